@@ -180,6 +180,66 @@ fn bench_parallel_hashing(c: &mut Criterion) {
     group.finish();
 }
 
+/// Async-worker versus blocking-pool hashing benchmark
+///
+/// Hashes many files at once on a runtime with few workers, once with
+/// digests computed on the async workers and once on the blocking pool
+/// (as the scanner does), to show how much the workers are held up
+fn bench_blocking_offload(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(2)
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let file_count = 64;
+    let file_size = FILE_SIZE_1MB;
+    let temp_files: Vec<NamedTempFile> = rt.block_on(async {
+        let mut files = Vec::new();
+        for _ in 0..file_count {
+            files.push(create_test_file(file_size).await);
+        }
+        files
+    });
+
+    let mut group = c.benchmark_group("blocking_offload");
+    group.throughput(Throughput::Bytes((file_count * file_size) as u64));
+
+    group.bench_function("async_workers", |b| {
+        b.iter(|| {
+            rt.block_on(async {
+                let tasks: Vec<_> = temp_files.iter()
+                    .map(|f| {
+                        let path = f.path().to_path_buf();
+                        tokio::spawn(async move { hash_with_sha256(&path).await })
+                    })
+                    .collect();
+                for task in tasks {
+                    black_box(task.await.unwrap().expect("SHA-256 failed"));
+                }
+            })
+        });
+    });
+
+    group.bench_function("blocking_pool", |b| {
+        b.iter(|| {
+            rt.block_on(async {
+                let tasks: Vec<_> = temp_files.iter()
+                    .map(|f| {
+                        let path = f.path().to_path_buf();
+                        tokio::spawn(async move { FileInfo::from_path(&path).await })
+                    })
+                    .collect();
+                for task in tasks {
+                    black_box(task.await.unwrap().expect("Failed to hash file"));
+                }
+            })
+        });
+    });
+
+    group.finish();
+}
+
 /// Comparative benchmark of different hashing algorithms
 ///
 /// Comparing SHA-256 with other algorithms to understand trade-offs
@@ -298,6 +358,7 @@ criterion_group!(
     bench_file_hashing_by_size,
     bench_buffer_sizes,
     bench_parallel_hashing,
+    bench_blocking_offload,
     bench_hash_algorithms
 );
 
@@ -0,0 +1,197 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chrono::{NaiveDate, TimeZone, Utc};
+use flate2::read::GzDecoder;
+use crate::hashing::{HashFunction, hash_reader};
+use crate::{ArchiveCopy, ArchiveFormat, ArchivedFile, DuplicateFinderError, DuplicateGroup, FileInfo};
+
+/// Detects the archive format of a file from its name
+pub fn archive_format(path: &Path) -> Option<ArchiveFormat> {
+    let name = path.file_name()?.to_str()?.to_lowercase();
+    if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Some(ArchiveFormat::TarGz)
+    } else if name.ends_with(".tar") {
+        Some(ArchiveFormat::Tar)
+    } else if name.ends_with(".zip") {
+        Some(ArchiveFormat::Zip)
+    } else {
+        None
+    }
+}
+
+/// Returns true when a path names a file inside an archive
+///
+/// Archive members are reported as `<archive>/<member path>`; such paths
+/// do not exist on disk and cannot be deleted on their own
+pub fn is_member(path: &Path) -> bool {
+    path.ancestors().skip(1).any(|ancestor| archive_format(ancestor).is_some())
+}
+
+/// Enumerates and hashes the files inside an archive
+///
+/// Members are streamed from the archive straight into the hasher, never
+/// extracted to disk. Each one keeps its own modification time; nested
+/// archives are hashed as plain files
+pub async fn archive_members(path: &Path, format: ArchiveFormat, function: Arc<dyn HashFunction>) -> Result<Vec<FileInfo>, DuplicateFinderError> {
+    let owned = path.to_path_buf();
+    tokio::task::spawn_blocking(move || read_members(&owned, format, function.as_ref()))
+        .await
+        .map_err(|e| DuplicateFinderError::Io(std::io::Error::other(e)))?
+        .map_err(DuplicateFinderError::Io)
+}
+
+/// Reads every regular file of an archive
+fn read_members(path: &Path, format: ArchiveFormat, function: &dyn HashFunction) -> std::io::Result<Vec<FileInfo>> {
+    let file = BufReader::new(File::open(path)?);
+    match format {
+        ArchiveFormat::Zip => zip_members(path, file, function),
+        ArchiveFormat::Tar => tar_members(path, file, function),
+        ArchiveFormat::TarGz => tar_members(path, GzDecoder::new(file), function),
+    }
+}
+
+fn zip_members(path: &Path, file: BufReader<File>, function: &dyn HashFunction) -> std::io::Result<Vec<FileInfo>> {
+    let mut archive = zip::ZipArchive::new(file).map_err(std::io::Error::other)?;
+    let mut members = Vec::new();
+
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index).map_err(std::io::Error::other)?;
+        // Entries escaping the archive root (e.g. "../x") are skipped
+        let Some(name) = entry.enclosed_name().filter(|_| entry.is_file()) else {
+            continue;
+        };
+        let modified = entry.last_modified().and_then(zip_time).unwrap_or(UNIX_EPOCH);
+        members.push(FileInfo {
+            path: path.join(name),
+            size: entry.size(),
+            hash: hash_reader(&mut entry, function)?,
+            modified,
+            created: None,
+            compression: None,
+        });
+    }
+    Ok(members)
+}
+
+fn tar_members(path: &Path, reader: impl Read, function: &dyn HashFunction) -> std::io::Result<Vec<FileInfo>> {
+    let mut archive = tar::Archive::new(reader);
+    let mut members = Vec::new();
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = entry.path()?.into_owned();
+        if !name.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir)) {
+            continue;
+        }
+        let modified = UNIX_EPOCH + Duration::from_secs(entry.header().mtime().unwrap_or(0));
+        members.push(FileInfo {
+            path: path.join(name),
+            size: entry.size(),
+            hash: hash_reader(&mut entry, function)?,
+            modified,
+            created: None,
+            compression: None,
+        });
+    }
+    Ok(members)
+}
+
+/// Converts a zip timestamp (local time without zone) to a system time
+fn zip_time(time: zip::DateTime) -> Option<SystemTime> {
+    let date = NaiveDate::from_ymd_opt(time.year().into(), time.month().into(), time.day().into())?;
+    let naive = date.and_hms_opt(time.hour().into(), time.minute().into(), time.second().into())?;
+    Some(Utc.from_utc_datetime(&naive).into())
+}
+
+/// Path of the archive holding a member, if the path names one
+pub fn containing_archive(path: &Path) -> Option<PathBuf> {
+    path.ancestors().skip(1).find(|ancestor| archive_format(ancestor).is_some()).map(Path::to_path_buf)
+}
+
+/// Lists loose files whose content also exists inside an archive
+///
+/// Every loose file of a group containing archive members is reported with
+/// all of those members, whichever copy the group treats as the original.
+/// Sorted by path
+pub fn find_archived_files(groups: &[DuplicateGroup]) -> Vec<ArchivedFile> {
+    let mut archived = Vec::new();
+    for group in groups {
+        let (members, loose): (Vec<&FileInfo>, Vec<&FileInfo>) = group.files.iter().partition(|f| is_member(&f.path));
+        if members.is_empty() {
+            continue;
+        }
+
+        let copies: Vec<ArchiveCopy> = members.iter()
+            .filter_map(|member| {
+                let archive = containing_archive(&member.path)?;
+                let inner = member.path.strip_prefix(&archive).ok()?.to_path_buf();
+                Some(ArchiveCopy { archive, member: inner })
+            })
+            .collect();
+        archived.extend(loose.into_iter().map(|file| ArchivedFile {
+            path: file.path.clone(),
+            size: file.size,
+            copies: copies.clone(),
+        }));
+    }
+
+    archived.sort_by(|a, b| a.path.cmp(&b.path));
+    archived
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HashAlgorithm;
+    use std::io::Write;
+    use flate2::write::GzEncoder;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_archive_members() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let zip_path = temp_dir.path().join("backup.zip");
+        let mut writer = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+        writer.add_directory("docs/", zip::write::SimpleFileOptions::default()).unwrap();
+        writer.start_file("docs/report.txt", zip::write::SimpleFileOptions::default()).unwrap();
+        writer.write_all(b"quarterly report").unwrap();
+        writer.finish().unwrap();
+
+        let tgz_path = temp_dir.path().join("old.tar.gz");
+        let mut builder = tar::Builder::new(GzEncoder::new(File::create(&tgz_path).unwrap(), Default::default()));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(16);
+        header.set_mtime(1_700_000_000);
+        header.set_cksum();
+        builder.append_data(&mut header, "report.txt", &b"quarterly report"[..]).unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let zipped = archive_members(&zip_path, ArchiveFormat::Zip, Arc::new(HashAlgorithm::Sha256)).await.unwrap();
+        let tarred = archive_members(&tgz_path, archive_format(&tgz_path).unwrap(), Arc::new(HashAlgorithm::Sha256)).await.unwrap();
+        assert_eq!(zipped.len(), 1);
+        assert_eq!(zipped[0].path, zip_path.join("docs/report.txt"));
+        assert_eq!(zipped[0].hash, tarred[0].hash);
+        assert_eq!(tarred[0].modified, UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+
+        assert!(is_member(&zipped[0].path) && !is_member(&zip_path));
+        assert_eq!(containing_archive(&tarred[0].path), Some(tgz_path.clone()));
+        assert!(archive_members(&temp_dir.path().join("missing.zip"), ArchiveFormat::Zip, Arc::new(HashAlgorithm::Sha256)).await.is_err());
+
+        // A loose copy is reported with both archives, even though it is the newest file
+        let loose = FileInfo { path: temp_dir.path().join("report.txt"), modified: SystemTime::now(), ..zipped[0].clone() };
+        let files = vec![zipped[0].clone(), tarred[0].clone(), loose.clone()];
+        let group = DuplicateGroup { id: String::new(), hash: loose.hash.clone(), size: 16, total_size: 48, wasted_space: 32, files };
+        let archived = find_archived_files(&[group]);
+        assert_eq!(archived.len(), 1);
+        assert_eq!(archived[0].path, loose.path);
+        assert_eq!(archived[0].copies[0], ArchiveCopy { archive: zip_path, member: PathBuf::from("docs/report.txt") });
+        assert_eq!(archived[0].copies[1].archive, tgz_path);
+    }
+}
@@ -0,0 +1,79 @@
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use tempfile::NamedTempFile;
+
+/// Writes `contents` to `path` so readers only ever see the old file or
+/// the complete new one
+///
+/// The contents go to a temporary file in the same directory, which is
+/// flushed to disk and then renamed over `path`. An interrupted run leaves
+/// the previous file in place instead of a truncated one
+pub fn write(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    write_with(path, |writer| writer.write_all(contents.as_ref()))
+}
+
+/// Like [`write`], with the contents produced by `write_contents` on a
+/// buffered writer, so they never have to be held in memory at once
+pub fn write_with(path: &Path, write_contents: impl FnOnce(&mut BufWriter<&mut NamedTempFile>) -> io::Result<()>) -> io::Result<()> {
+    let mut file = beside(path)?;
+    let mut writer = BufWriter::new(&mut file);
+    write_contents(&mut writer)?;
+    writer.flush()?;
+    drop(writer);
+    file.as_file().sync_data()?;
+    file.persist(path)?;
+    Ok(())
+}
+
+/// Like [`write`], on tokio's blocking pool
+pub async fn write_async(path: &Path, contents: Vec<u8>) -> io::Result<()> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || write(&path, contents)).await.map_err(io::Error::other)?
+}
+
+/// Temporary file next to `path`, to be persisted over it once complete
+///
+/// It is created with the permissions a new file at `path` would get
+pub(crate) fn beside(path: &Path) -> io::Result<NamedTempFile> {
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let mut builder = tempfile::Builder::new();
+    builder.prefix(".duplicate-finder-").suffix(".tmp");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        builder.permissions(std::fs::Permissions::from_mode(0o666));
+    }
+    builder.tempfile_in(directory)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_atomic_write() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let path = temp_dir.path().join("results.json");
+        std::fs::write(&path, "old").unwrap();
+
+        // An abandoned write leaves the old file alone
+        let mut pending = beside(&path).unwrap();
+        pending.write_all(b"half").unwrap();
+        drop(pending);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "old");
+
+        write(&path, "new").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+
+        // Permissions are those of any new file, not the owner-only ones
+        // of temporary files
+        let plain = temp_dir.path().join("plain");
+        std::fs::write(&plain, "").unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions(), std::fs::metadata(&plain).unwrap().permissions());
+    }
+}
@@ -0,0 +1,237 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+use crate::{DuplicateFinderError, FileInfo};
+
+/// Extensions of audio formats whose tags can be skipped
+pub const AUDIO_EXTENSIONS: &[&str] = &["flac", "m4a", "m4b", "mp3"];
+
+/// Returns true when the path looks like a supported audio file
+pub fn is_audio(path: &Path) -> bool {
+    extension(path).is_some_and(|ext| AUDIO_EXTENSIONS.contains(&ext.as_str()))
+}
+
+/// Reads an audio file's metadata and a SHA-256 digest of its audio data only
+///
+/// ID3v1/ID3v2/APE tags (MP3), metadata blocks such as Vorbis comments and
+/// pictures (FLAC) and every MP4 atom except `mdat` (M4A) are skipped, so
+/// the same rip with different tags or cover art gets the same digest
+pub async fn audio_file_info(path: &Path) -> Result<FileInfo, DuplicateFinderError> {
+    let mut file_info = FileInfo::stat(path).await?;
+
+    let owned = path.to_path_buf();
+    file_info.hash = tokio::task::spawn_blocking(move || audio_digest(&owned))
+        .await
+        .map_err(|e| DuplicateFinderError::Io(std::io::Error::other(e)))?
+        .map_err(|_| DuplicateFinderError::HashCalculationError {
+            path: path.to_path_buf(),
+        })?;
+
+    Ok(file_info)
+}
+
+/// Hashes the audio payload of a file
+fn audio_digest(path: &Path) -> std::io::Result<String> {
+    let mut file = File::open(path)?;
+    let length = file.metadata()?.len();
+
+    let ranges = match extension(path).as_deref() {
+        Some("flac") => flac_audio(&mut file, length)?,
+        Some("m4a" | "m4b") => mp4_audio(&mut file, length)?,
+        _ => mp3_audio(&mut file, length)?,
+    };
+
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 65536];
+    for (start, end) in ranges {
+        file.seek(SeekFrom::Start(start))?;
+        let mut remaining = end - start;
+        while remaining > 0 {
+            let chunk = remaining.min(buffer.len() as u64) as usize;
+            file.read_exact(&mut buffer[..chunk])?;
+            hasher.update(&buffer[..chunk]);
+            remaining -= chunk as u64;
+        }
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Byte range of MP3 frames between leading ID3v2 and trailing APE/ID3v1 tags
+fn mp3_audio(file: &mut File, length: u64) -> std::io::Result<Vec<(u64, u64)>> {
+    let start = skip_id3v2(file, 0)?;
+    let mut end = length;
+
+    // ID3v1 is a fixed 128-byte block at the very end
+    if end >= start + 128 && read_at(file, end - 128, 3)? == b"TAG" {
+        end -= 128;
+    }
+
+    // An APEv2 tag ends with a 32-byte footer carrying its size
+    if end >= start + 32 {
+        let footer = read_at(file, end - 32, 32)?;
+        if &footer[..8] == b"APETAGEX" {
+            let size = u32::from_le_bytes([footer[12], footer[13], footer[14], footer[15]]) as u64;
+            let has_header = footer[23] & 0x80 != 0;
+            let tag = size + if has_header { 32 } else { 0 };
+            end = end.saturating_sub(tag).max(start);
+        }
+    }
+
+    Ok(vec![(start, end)])
+}
+
+/// Byte range of FLAC frames following the metadata blocks
+fn flac_audio(file: &mut File, length: u64) -> std::io::Result<Vec<(u64, u64)>> {
+    // Some taggers prepend an ID3v2 tag to FLAC files as well
+    let mut position = skip_id3v2(file, 0)?;
+    if read_at(file, position, 4)? != b"fLaC" {
+        return Err(invalid("missing FLAC stream marker"));
+    }
+    position += 4;
+
+    loop {
+        let header = read_at(file, position, 4)?;
+        let size = u32::from_be_bytes([0, header[1], header[2], header[3]]) as u64;
+        position += 4 + size;
+        if header[0] & 0x80 != 0 {
+            break;
+        }
+    }
+
+    if position > length {
+        return Err(invalid("truncated FLAC metadata"));
+    }
+    Ok(vec![(position, length)])
+}
+
+/// Byte ranges of every `mdat` atom's payload in an MP4 container
+fn mp4_audio(file: &mut File, length: u64) -> std::io::Result<Vec<(u64, u64)>> {
+    let mut ranges = Vec::new();
+    let mut position = 0;
+
+    while position + 8 <= length {
+        let header = read_at(file, position, 8)?;
+        let kind = &header[4..8];
+        let (size, header_size) = match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+            0 => (length - position, 8),
+            1 => {
+                let large = read_at(file, position + 8, 8)?;
+                (u64::from_be_bytes(large.try_into().expect("8 bytes were read")), 16)
+            }
+            size => (size as u64, 8),
+        };
+        if size < header_size || position + size > length {
+            return Err(invalid("malformed MP4 atom"));
+        }
+
+        if kind == b"mdat" {
+            ranges.push((position + header_size, position + size));
+        }
+        position += size;
+    }
+
+    if ranges.is_empty() {
+        return Err(invalid("no audio data (mdat) in MP4 container"));
+    }
+    Ok(ranges)
+}
+
+/// Returns the offset after any ID3v2 tags starting at `position`
+fn skip_id3v2(file: &mut File, mut position: u64) -> std::io::Result<u64> {
+    loop {
+        let header = match read_at(file, position, 10) {
+            Ok(header) => header,
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(position),
+            Err(e) => return Err(e),
+        };
+        if &header[..3] != b"ID3" {
+            return Ok(position);
+        }
+
+        // Sizes are "syncsafe": 7 bits per byte
+        let size = header[6..10].iter().fold(0u64, |size, &b| (size << 7) | (b & 0x7f) as u64);
+        let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+        position += 10 + size + footer;
+    }
+}
+
+/// Reads `len` bytes at an absolute offset
+fn read_at(file: &mut File, position: u64, len: usize) -> std::io::Result<Vec<u8>> {
+    let mut buffer = vec![0u8; len];
+    file.seek(SeekFrom::Start(position))?;
+    file.read_exact(&mut buffer)?;
+    Ok(buffer)
+}
+
+/// Error for files whose container structure cannot be parsed
+fn invalid(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// Lowercased file extension
+fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn id3v2(text: &[u8]) -> Vec<u8> {
+        let mut tag = b"ID3\x04\x00\x00".to_vec();
+        let size = text.len() as u32;
+        tag.extend([(size >> 21) as u8 & 0x7f, (size >> 14) as u8 & 0x7f, (size >> 7) as u8 & 0x7f, size as u8 & 0x7f]);
+        tag.extend_from_slice(text);
+        tag
+    }
+
+    fn atom(kind: &[u8], payload: &[u8]) -> Vec<u8> {
+        let mut atom = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        atom.extend_from_slice(kind);
+        atom.extend_from_slice(payload);
+        atom
+    }
+
+    #[tokio::test]
+    async fn test_audio_digest_ignores_tags() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let frames = b"\xff\xfbaudio frames".repeat(10);
+        let write = |name: &str, parts: &[&[u8]]| {
+            let path = temp_dir.path().join(name);
+            std::fs::write(&path, parts.concat()).unwrap();
+            path
+        };
+        let digest = |path: std::path::PathBuf| audio_digest(&path).unwrap();
+
+        // MP3: different ID3v2 tags and a trailing ID3v1 tag
+        let mut id3v1 = b"TAG".to_vec();
+        id3v1.resize(128, b' ');
+        let plain = write("plain.mp3", &[&frames]);
+        let tagged = write("tagged.mp3", &[&id3v2(b"TIT2 Song title"), &frames, &id3v1]);
+        let other = write("other.mp3", &[&id3v2(b"TIT2 Song title"), b"\xff\xfbother frames"]);
+        assert_eq!(digest(plain.clone()), digest(tagged));
+        assert_ne!(digest(plain), digest(other));
+
+        // FLAC: STREAMINFO plus a Vorbis comment block that differs
+        let stream_info = [&[0x00, 0, 0, 34][..], &[7u8; 34]].concat();
+        let comment = |text: &[u8]| [&[0x84, 0, 0, text.len() as u8][..], text].concat();
+        let a = write("a.flac", &[b"fLaC", &stream_info, &comment(b"ARTIST=One"), &frames]);
+        let b = write("b.flac", &[b"fLaC", &stream_info, &comment(b"ARTIST=Someone else"), &frames]);
+        assert_eq!(digest(a), digest(b));
+        assert!(audio_digest(&write("bad.flac", &[b"OggS"])).is_err());
+
+        // M4A: only mdat counts, wherever it sits
+        let a = write("a.m4a", &[&atom(b"ftyp", b"M4A "), &atom(b"moov", b"tags one"), &atom(b"mdat", &frames)]);
+        let b = write("b.m4a", &[&atom(b"ftyp", b"M4A "), &atom(b"mdat", &frames), &atom(b"moov", b"other tags")]);
+        assert_eq!(digest(a), digest(b));
+
+        assert!(is_audio(Path::new("song.MP3")) && !is_audio(Path::new("song.ogg")));
+    }
+}
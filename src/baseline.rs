@@ -0,0 +1,60 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use crate::lock::StateLock;
+use crate::output::write_json;
+use crate::{ScanResult, atomic};
+
+/// Directory kept inside a scanned tree for its state
+///
+/// It is hidden, so scans only look inside it with `--include-hidden`
+pub const STATE_DIR: &str = ".duplicate-finder";
+
+/// Where the baseline of a scanned directory is kept
+/// (`--save-baseline`, `--against-baseline`)
+///
+/// The baseline is the full JSON result of the last scan saved as one, so
+/// `diff`, `report` and `combine` read it like any other saved result
+pub fn path(directory: &Path) -> PathBuf {
+    directory.join(STATE_DIR).join("baseline.json")
+}
+
+/// Loads the baseline of `directory`, if one was saved
+pub async fn load(directory: &Path) -> Result<Option<ScanResult>> {
+    let path = path(directory);
+    if !tokio::fs::try_exists(&path).await? {
+        return Ok(None);
+    }
+    Ok(Some(ScanResult::load(&path).await?))
+}
+
+/// Saves a scan result as the baseline of `directory`, replacing the
+/// previous one, and returns where it went
+///
+/// Every group is saved, whatever the report shows. The baseline is
+/// locked while it is written (see `--wait-for-lock`)
+pub async fn save(scan_result: &ScanResult, directory: &Path, wait_for_lock: bool) -> Result<PathBuf> {
+    let path = path(directory);
+    tokio::fs::create_dir_all(directory.join(STATE_DIR)).await?;
+    let owned = path.clone();
+    let _lock = tokio::task::spawn_blocking(move || StateLock::acquire(&owned, wait_for_lock)).await??;
+    atomic::write_with(&path, |writer| write_json(scan_result, writer))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_baseline_round_trip() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        assert!(load(temp_dir.path()).await.unwrap().is_none());
+
+        let scan_result = ScanResult { total_files: 3, scanned_directory: temp_dir.path().to_path_buf(), ..Default::default() };
+        let saved = save(&scan_result, temp_dir.path(), false).await.unwrap();
+        assert_eq!(saved, temp_dir.path().join(".duplicate-finder/baseline.json"));
+        assert_eq!(load(temp_dir.path()).await.unwrap().unwrap().total_files, 3);
+    }
+}
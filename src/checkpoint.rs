@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::atomic;
+use crate::lock::StateLock;
+use crate::scanner::comparison_settings;
+use crate::source::SourceEntry;
+use crate::spill::{read_record, write_record};
+use crate::{DuplicateFinderError, FileInfo, ScannerConfig};
+
+/// Version of the checkpoint file layout
+const CHECKPOINT_VERSION: u32 = 1;
+
+/// First line of a checkpoint file
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    version: u32,
+    /// Settings that decide the comparison keys (see [`comparison_settings`]);
+    /// a checkpoint is only resumed with the settings it was written with
+    comparison: String,
+}
+
+/// A hashed file as recorded in a checkpoint, with the size and
+/// modification time it had on disk when it was hashed
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    len: u64,
+    modified: Option<SystemTime>,
+    file: FileInfo,
+}
+
+/// Files hashed by a scan, appended to a checkpoint file as they finish
+///
+/// A checkpoint file starts with a JSON header line followed by one record
+/// per hashed file (see [`write_record`]). Records are buffered and written
+/// out every `--checkpoint-interval`, so a crash loses at most that much
+/// work. A resumed scan walks the tree again, which is cheap next to
+/// hashing, and only hashes the files missing from the checkpoint or
+/// changed since
+pub(crate) struct Checkpoint {
+    path: PathBuf,
+    completed: HashMap<PathBuf, Entry>,
+    interval: Duration,
+    writer: Mutex<(BufWriter<File>, Instant)>,
+    _lock: StateLock,
+}
+
+impl Checkpoint {
+    /// Starts a new checkpoint file, replacing any existing one
+    ///
+    /// The checkpoint stays locked while the scan runs, so two scans never
+    /// write to it at once (see `--wait-for-lock`)
+    pub(crate) fn create(path: &Path, config: &ScannerConfig) -> Result<Self, DuplicateFinderError> {
+        let lock = StateLock::acquire(path, config.wait_for_lock)?;
+        // The checkpoint of an earlier scan stays usable until the new one
+        // has its header
+        let mut file = atomic::beside(path)?;
+        let header = Header { version: CHECKPOINT_VERSION, comparison: comparison_settings(config) };
+        serde_json::to_writer(&mut file, &header).map_err(io::Error::from)?;
+        file.write_all(b"\n")?;
+        file.as_file().sync_data()?;
+        let file = file.persist(path).map_err(io::Error::from)?;
+        Ok(Self::append_to(path, file, HashMap::new(), config, lock))
+    }
+
+    /// Loads the checkpoint of an interrupted scan and keeps appending to it
+    ///
+    /// A record cut short by the interruption is dropped from the file
+    pub(crate) fn resume(path: &Path, config: &ScannerConfig) -> Result<Self, DuplicateFinderError> {
+        let lock = StateLock::acquire(path, config.wait_for_lock)?;
+        let mismatch = || DuplicateFinderError::CheckpointMismatch { path: path.to_path_buf() };
+        let mut reader = BufReader::new(File::open(path).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => DuplicateFinderError::PathNotFound { path: path.to_path_buf() },
+            _ => DuplicateFinderError::Io(e),
+        })?);
+
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let header: Header = serde_json::from_str(&line).map_err(|_| mismatch())?;
+        if header.version != CHECKPOINT_VERSION || header.comparison != comparison_settings(config) {
+            return Err(mismatch());
+        }
+
+        let mut completed = HashMap::new();
+        let mut valid_len = reader.stream_position()?;
+        loop {
+            match read_record::<Entry>(&mut reader) {
+                Ok(Some((path, entry))) => {
+                    completed.insert(path, entry);
+                    valid_len = reader.stream_position()?;
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    warn!("Dropping the last record of checkpoint {}: {}", path.display(), e);
+                    break;
+                }
+            }
+        }
+        info!("Resuming from {} files hashed before", completed.len());
+
+        let file = OpenOptions::new().write(true).open(path)?;
+        file.set_len(valid_len)?;
+        let file = OpenOptions::new().append(true).open(path)?;
+        Ok(Self::append_to(path, file, completed, config, lock))
+    }
+
+    fn append_to(path: &Path, file: File, completed: HashMap<PathBuf, Entry>, config: &ScannerConfig, lock: StateLock) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            completed,
+            interval: config.checkpoint_interval,
+            writer: Mutex::new((BufWriter::new(file), Instant::now())),
+            _lock: lock,
+        }
+    }
+
+    /// The file as hashed before the interruption, unless it changed since
+    pub(crate) fn completed(&self, discovered: &SourceEntry) -> Option<FileInfo> {
+        let entry = self.completed.get(&discovered.path)?;
+        (entry.len == discovered.size && entry.modified == Some(discovered.modified)).then(|| FileInfo {
+            path: discovered.path.clone(),
+            ..entry.file.clone()
+        })
+    }
+
+    /// Records a hashed file with the metadata it was discovered with
+    ///
+    /// Pending records are written out once the checkpoint interval has
+    /// passed since the last write. Failures are logged: a scan never fails
+    /// because its checkpoint could not be written
+    pub(crate) fn record(&self, file: &FileInfo, discovered: &SourceEntry) {
+        let entry = Entry {
+            len: discovered.size,
+            modified: Some(discovered.modified),
+            file: FileInfo { path: PathBuf::new(), ..file.clone() },
+        };
+        let mut writer = self.writer.lock().expect("checkpoint lock poisoned");
+        let (output, written) = &mut *writer;
+        let result = write_record(output, &file.path, &entry).and_then(|()| {
+            if written.elapsed() < self.interval {
+                return Ok(());
+            }
+            *written = Instant::now();
+            output.flush()?;
+            output.get_ref().sync_data()
+        });
+        if let Err(e) = result {
+            warn!("Cannot write checkpoint {}: {}", self.path.display(), e);
+        }
+    }
+
+    /// Writes out every pending record
+    pub(crate) fn flush(&self) {
+        let mut writer = self.writer.lock().expect("checkpoint lock poisoned");
+        let (output, written) = &mut *writer;
+        *written = Instant::now();
+        if let Err(e) = output.flush().and_then(|()| output.get_ref().sync_data()) {
+            warn!("Cannot write checkpoint {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkpoint_resume() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let data = temp_dir.path().join("data.txt");
+        std::fs::write(&data, "contents").unwrap();
+        let metadata = std::fs::metadata(&data).unwrap();
+        let discovered = crate::source::local_entry(data.clone(), &metadata);
+        let path = temp_dir.path().join("scan.checkpoint");
+        let config = ScannerConfig::default();
+
+        let file = FileInfo {
+            path: data.clone(),
+            size: metadata.len(),
+            hash: "abc".to_string(),
+            modified: metadata.modified().unwrap(),
+            created: None,
+            compression: None,
+        };
+        let checkpoint = Checkpoint::create(&path, &config).unwrap();
+        checkpoint.record(&file, &discovered);
+        checkpoint.flush();
+        drop(checkpoint);
+
+        // A crash in the middle of a record leaves a torn line behind
+        std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"/torn\0{\"len\"").unwrap();
+        let resumed = Checkpoint::resume(&path, &config).unwrap();
+        assert_eq!(resumed.completed(&discovered), Some(file.clone()));
+        let other = temp_dir.path().join("other");
+        resumed.record(&FileInfo { path: other.clone(), ..file.clone() }, &SourceEntry { path: other, ..discovered.clone() });
+        resumed.flush();
+        drop(resumed);
+        assert_eq!(Checkpoint::resume(&path, &config).unwrap().completed.len(), 2);
+
+        // Changed files are hashed again
+        std::fs::write(&data, "new contents").unwrap();
+        let changed = crate::source::local_entry(data.clone(), &std::fs::metadata(&data).unwrap());
+        assert_eq!(Checkpoint::resume(&path, &config).unwrap().completed(&changed), None);
+
+        let other_mode = ScannerConfig { mode: crate::Mode::Quick, ..ScannerConfig::default() };
+        assert!(matches!(Checkpoint::resume(&path, &other_mode), Err(DuplicateFinderError::CheckpointMismatch { .. })));
+    }
+}
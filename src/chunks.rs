@@ -0,0 +1,229 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use futures_util::{StreamExt, stream};
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use crate::source::is_remote_url;
+use crate::{ChunkAnalysis, FileInfo, SharedChunks, archives};
+
+/// Smallest chunk the chunker cuts (except at the end of a file)
+const MIN_CHUNK: usize = 16 * 1024;
+
+/// Largest chunk; a cut is forced once a chunk reaches this size
+const MAX_CHUNK: usize = 256 * 1024;
+
+/// Cut points are where these rolling-hash bits are all zero, which
+/// happens every 64 KiB on average past the minimum chunk size
+const CUT_MASK: u64 = 0xffff << 48;
+
+/// Pairs sharing less than this percentage of the smaller file are not listed
+const MIN_SHARED_PERCENT: u8 = 10;
+
+/// Chunks present in more files than this (e.g. runs of zeros in disk
+/// images) count towards the savings but not towards shared pairs
+const MAX_PAIR_FANOUT: usize = 64;
+
+/// Bytes read from a file at a time
+const READ_SIZE: usize = 1 << 20;
+
+/// Random values the rolling gear hash adds for each byte value
+const GEAR: [u64; 256] = gear_table();
+
+/// Fills the gear table from a fixed splitmix64 sequence, so chunk
+/// boundaries are the same on every run and machine
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut index = 0;
+    while index < table.len() {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[index] = z ^ (z >> 31);
+        index += 1;
+    }
+    table
+}
+
+/// A chunk: truncated SHA-256 digest and length
+type Chunk = (u128, u32);
+
+/// Splits a file into content-defined chunks
+///
+/// Boundaries depend only on the bytes just before them, so inserting or
+/// removing data early in a file only changes the chunks around the edit
+fn chunk_file(path: &Path) -> std::io::Result<Vec<Chunk>> {
+    let mut file = File::open(path)?;
+    let mut buffer = vec![0u8; READ_SIZE];
+    let mut chunks = Vec::new();
+    let mut hasher = Sha256::new();
+    let mut length = 0;
+    let mut gear = 0u64;
+
+    loop {
+        let bytes_read = file.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        let mut start = 0;
+        for (index, &byte) in buffer[..bytes_read].iter().enumerate() {
+            gear = (gear << 1).wrapping_add(GEAR[byte as usize]);
+            length += 1;
+            if (length >= MIN_CHUNK && gear & CUT_MASK == 0) || length >= MAX_CHUNK {
+                hasher.update(&buffer[start..=index]);
+                chunks.push((finish(&mut hasher), length as u32));
+                start = index + 1;
+                length = 0;
+                gear = 0;
+            }
+        }
+        hasher.update(&buffer[start..bytes_read]);
+    }
+    if length > 0 {
+        chunks.push((finish(&mut hasher), length as u32));
+    }
+    Ok(chunks)
+}
+
+/// Truncated digest of the chunk hashed so far; resets the hasher
+fn finish(hasher: &mut Sha256) -> u128 {
+    let digest = hasher.finalize_reset();
+    u128::from_le_bytes(digest[..16].try_into().expect("SHA-256 digests are 32 bytes"))
+}
+
+/// Measures how much data large files share at the chunk level
+///
+/// Every local file of at least `min_size` bytes is split into
+/// content-defined chunks, up to `concurrency` files at a time. With
+/// `distinct_contents` only one file per content hash is chunked, so the
+/// savings are those left after removing whole-file duplicates.
+///
+/// Returns the analysis and the number of files that could not be read
+pub async fn analyze(files: &[FileInfo], min_size: u64, distinct_contents: bool, concurrency: usize) -> (ChunkAnalysis, usize) {
+    let mut seen = HashSet::new();
+    let selected: Vec<&FileInfo> = files.iter()
+        .filter(|f| f.size >= min_size && !is_remote_url(&f.path) && !archives::is_member(&f.path))
+        .filter(|f| !distinct_contents || seen.insert(f.hash.as_str()))
+        .collect();
+    info!("Chunking {} large files", selected.len());
+
+    let results: Vec<(&FileInfo, std::io::Result<Vec<Chunk>>)> = stream::iter(selected)
+        .map(|file| async move {
+            let path = file.path.clone();
+            let chunks = tokio::task::spawn_blocking(move || chunk_file(&path))
+                .await
+                .unwrap_or_else(|e| Err(std::io::Error::other(e)));
+            (file, chunks)
+        })
+        .buffered(concurrency.max(1))
+        .collect()
+        .await;
+
+    let mut analyzed = Vec::new();
+    let mut errors = 0;
+    for (file, chunks) in results {
+        match chunks {
+            Ok(chunks) => analyzed.push((file, chunks)),
+            Err(e) => {
+                warn!("Cannot chunk {}: {}", file.path.display(), e);
+                errors += 1;
+            }
+        }
+    }
+    (summarize(&analyzed), errors)
+}
+
+/// Adds up the chunks of every analyzed file
+fn summarize(analyzed: &[(&FileInfo, Vec<Chunk>)]) -> ChunkAnalysis {
+    let mut owners: HashMap<u128, (u32, Vec<usize>)> = HashMap::new();
+    let mut total_bytes = 0;
+    for (index, (_, chunks)) in analyzed.iter().enumerate() {
+        for &(digest, length) in chunks {
+            total_bytes += u64::from(length);
+            let (_, files) = owners.entry(digest).or_insert((length, Vec::new()));
+            if files.last() != Some(&index) {
+                files.push(index);
+            }
+        }
+    }
+    let unique_bytes = owners.values().map(|(length, _)| u64::from(*length)).sum();
+
+    let mut shared: HashMap<(usize, usize), u64> = HashMap::new();
+    for (length, files) in owners.values().filter(|(_, files)| (2..=MAX_PAIR_FANOUT).contains(&files.len())) {
+        for (position, &first) in files.iter().enumerate() {
+            for &second in &files[position + 1..] {
+                *shared.entry((first, second)).or_default() += u64::from(*length);
+            }
+        }
+    }
+
+    let mut shared_pairs: Vec<SharedChunks> = shared.into_iter()
+        .filter_map(|((first, second), shared_bytes)| {
+            let (first, second) = (analyzed[first].0, analyzed[second].0);
+            let smaller = first.size.min(second.size).max(1);
+            let percent = (shared_bytes.saturating_mul(100) / smaller).min(100) as u8;
+            (percent >= MIN_SHARED_PERCENT).then(|| SharedChunks {
+                first: first.path.clone(),
+                second: second.path.clone(),
+                shared_bytes,
+                percent,
+            })
+        })
+        .collect();
+    shared_pairs.sort_by(|a, b| {
+        b.shared_bytes.cmp(&a.shared_bytes)
+            .then_with(|| a.first.cmp(&b.first))
+            .then_with(|| a.second.cmp(&b.second))
+    });
+
+    ChunkAnalysis { files_analyzed: analyzed.len(), total_bytes, unique_bytes, shared_pairs }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    #[tokio::test]
+    async fn test_chunk_analysis() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+
+        // Pseudo-random data so chunk boundaries fall at content-defined points
+        let mut state = 1u64;
+        let data: Vec<u8> = (0..2 * 1024 * 1024)
+            .map(|_| {
+                state = state.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+                (state >> 56) as u8
+            })
+            .collect();
+        // The snapshot has a few bytes inserted near the start
+        let mut snapshot = data[..1000].to_vec();
+        snapshot.extend_from_slice(b"inserted");
+        snapshot.extend_from_slice(&data[1000..]);
+
+        let mut files = Vec::new();
+        for (name, content) in [("base.img", &data), ("snapshot.img", &snapshot), ("copy.img", &data)] {
+            let path = temp_dir.path().join(name);
+            std::fs::write(&path, content).unwrap();
+            let hash = if name == "snapshot.img" { "b" } else { "a" };
+            files.push(FileInfo { path, size: content.len() as u64, hash: hash.to_string(), modified: UNIX_EPOCH, created: None, compression: None });
+        }
+
+        let (analysis, errors) = analyze(&files, 1024, true, 2).await;
+        assert_eq!(errors, 0);
+        assert_eq!(analysis.files_analyzed, 2);
+        assert_eq!(analysis.total_bytes, (data.len() + snapshot.len()) as u64);
+        // Only the chunks around the insertion differ
+        assert!(analysis.estimated_savings() > data.len() as u64 * 8 / 10);
+        assert_eq!(analysis.shared_pairs.len(), 1);
+        assert!(analysis.shared_pairs[0].percent >= 80);
+
+        let (skipped, _) = analyze(&files, u64::MAX, true, 2).await;
+        assert_eq!(skipped.files_analyzed, 0);
+    }
+}
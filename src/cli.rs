@@ -0,0 +1,232 @@
+use std::path::{Path, PathBuf};
+
+use clap::{Parser, Subcommand, ValueHint};
+
+use crate::ScannerConfig;
+
+/// CLI interface - structure for parsing command line arguments
+///
+/// This structure defines all possible parameters that a user
+/// can pass to the application via command line: the options of a
+/// [`ScannerConfig`] and the commands
+#[derive(Parser, Debug, Clone, Default)]
+#[command(name = "duplicate-finder")]
+#[command(about = "A blazingly fast duplicate file finder")]
+#[command(version = "0.1.0")]
+#[command(author = "Ilia Denisov <iodenisof@gmail.com>")]
+#[command(long_about = "
+Duplicate Finder recursively scans directories to identify duplicate files
+using SHA-256 hashing. It supports various filtering options and output formats
+to help you clean up your file system efficiently.
+
+Examples:
+  duplicate-finder /home/user/Documents
+  duplicate-finder scan ~/Pictures --mode image-pixels
+  duplicate-finder clean ~/Downloads --dry-run
+  duplicate-finder -s 1024 -e --output-format json
+  duplicate-finder -o json -O /path/to/output.json
+")]
+#[command(args_override_self = true)]
+// Options can follow the subcommand, e.g. `duplicate-finder scan ~/Photos --min-size 1MB`
+#[command(mut_args = |arg| if arg.is_positional() { arg } else { arg.global(true) })]
+pub struct Cli {
+    /// Options of the scan and of what is done with its results
+    #[command(flatten)]
+    pub config: ScannerConfig,
+
+    /// Directory to scan, so `duplicate-finder DIR` works like
+    /// `duplicate-finder scan DIR`
+    #[arg(value_name = "DIR", value_hint = ValueHint::DirPath)]
+    #[arg(help = "Directory to scan (same as --directory DIR)")]
+    pub path: Option<PathBuf>,
+
+    /// Print the JSON Schema of the result format and exit
+    #[arg(long)]
+    #[arg(help = "Print the JSON Schema describing JSON output and exit")]
+    pub print_schema: bool,
+
+    /// Named set of options applied before the command line: a built-in
+    /// one (photos, music, code, downloads) or one from the configuration
+    /// file (see [`crate::config::resolve_args`])
+    #[arg(long, value_name = "NAME")]
+    #[arg(help = "Apply a preset of options: photos, music, code, downloads or a profile from the config file")]
+    pub profile: Option<String>,
+
+    /// Configuration file to read instead of the default one (see
+    /// [`crate::config::ConfigFile`])
+    #[arg(value_name = "FILE")]
+    #[arg(help = "Read options and profiles from this JSON file (default: ~/.config/duplicate-finder/config.json)")]
+    #[arg(long = "config")]
+    pub config_file: Option<PathBuf>,
+
+    /// Action to perform instead of a plain duplicate scan
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Subcommands
+///
+/// Alternative actions that reuse the scanner's discovery and hashing
+/// pipeline. Without a subcommand the tool reports duplicates
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Scan for duplicates (what runs without a command)
+    Scan {
+        /// Directory to scan (same as --directory DIR)
+        #[arg(value_name = "DIR", value_hint = ValueHint::DirPath)]
+        path: Option<PathBuf>,
+    },
+
+    /// Scan, list the redundant copies to remove and remove empty directories
+    Clean {
+        /// Directory to clean up (same as --directory DIR)
+        #[arg(value_name = "DIR", value_hint = ValueHint::DirPath)]
+        path: Option<PathBuf>,
+
+        /// Only list the empty directories instead of removing them
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Inspect or clear the hashes kept in extended attributes by --xattr-cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+
+    /// Write a sha256sum-compatible checksum manifest of every scanned file
+    Manifest {
+        /// File to write the manifest to (prints to stdout if omitted)
+        output: Option<PathBuf>,
+    },
+
+    /// Re-check the scanned directory against a checksum manifest
+    Verify {
+        /// Manifest previously written by `manifest` or `sha256sum`
+        manifest: PathBuf,
+    },
+
+    /// Re-render previously saved JSON results without rescanning
+    Report {
+        /// JSON results written with `-o json -O <FILE>`
+        input: PathBuf,
+    },
+
+    /// Compare two saved JSON results and report what changed
+    Diff {
+        /// Results of the earlier scan, or a directory to use its baseline
+        old: PathBuf,
+
+        /// Results of the later scan
+        new: PathBuf,
+    },
+
+    /// List files whose content is not present in the reference directory
+    Missing,
+
+    /// Find every copy of specific files, hashing only same-size candidates
+    Find {
+        /// Files whose copies should be found
+        #[arg(required = true)]
+        needles: Vec<PathBuf>,
+
+        /// Directory to search (defaults to --directory)
+        #[arg(long = "in", value_name = "DIR", value_hint = ValueHint::DirPath)]
+        haystack: Option<PathBuf>,
+    },
+
+    /// Consolidate several directories into one, transferring only unique content
+    Merge {
+        /// Directories to consolidate, in priority order
+        #[arg(required = true, value_hint = ValueHint::DirPath)]
+        sources: Vec<PathBuf>,
+
+        /// Destination directory (created if missing)
+        #[arg(long, value_name = "DIR", value_hint = ValueHint::DirPath)]
+        into: PathBuf,
+
+        /// Move unique files instead of copying them
+        #[arg(long = "move")]
+        move_files: bool,
+
+        /// Hard-link content that already exists in the destination instead of skipping it
+        #[arg(long)]
+        link: bool,
+
+        /// Show what would be transferred without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Combine saved JSON results of separate scans and regroup their files
+    Combine {
+        /// JSON results written with `-o json -O <FILE>`
+        #[arg(required = true, num_args = 2..)]
+        inputs: Vec<PathBuf>,
+    },
+
+    /// Show how duplication changed over time from a history file
+    History {
+        /// History file written with `--history`
+        file: PathBuf,
+    },
+
+    /// Print a completion script for bash, zsh, fish, elvish or PowerShell
+    Completions {
+        /// Shell to complete in
+        shell: clap_complete::Shell,
+    },
+}
+
+/// Actions of the `cache` subcommand
+#[derive(Subcommand, Debug, Clone)]
+pub enum CacheAction {
+    /// Count the files with cached hashes for --hash-algo, current and outdated
+    Stats {
+        /// Directory to inspect (same as --directory DIR)
+        #[arg(value_name = "DIR", value_hint = ValueHint::DirPath)]
+        path: Option<PathBuf>,
+    },
+
+    /// Remove the cached hashes of every algorithm from the scanned files
+    Clear {
+        /// Directory to clear (same as --directory DIR)
+        #[arg(value_name = "DIR", value_hint = ValueHint::DirPath)]
+        path: Option<PathBuf>,
+    },
+}
+
+impl Command {
+    /// Directory given to a command that scans one, e.g. `scan DIR`
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Command::Scan { path } | Command::Clean { path, .. } => path.as_deref(),
+            Command::Cache { action: CacheAction::Stats { path } | CacheAction::Clear { path } } => path.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HashAlgorithm;
+
+    #[test]
+    fn test_subcommands() {
+        // Options may come before or after the command
+        let cli = Cli::try_parse_from(["duplicate-finder", "-e", "scan", "/data", "--min-size", "5"]).unwrap();
+        assert!(matches!(&cli.command, Some(Command::Scan { path: Some(path) }) if path == Path::new("/data")));
+        assert!(cli.config.exclude_empty && cli.config.min_size == 5);
+        assert_eq!(cli.command.unwrap().path(), Some(Path::new("/data")));
+
+        let cli = Cli::try_parse_from(["duplicate-finder", "/data", "-q"]).unwrap();
+        assert!(cli.command.is_none() && cli.config.quiet);
+        assert_eq!(cli.path.as_deref(), Some(Path::new("/data")));
+        let cli = Cli::try_parse_from(["duplicate-finder", "cache", "clear", "/data", "--hash-algo", "blake3"]).unwrap();
+        assert_eq!(cli.command.unwrap().path(), Some(Path::new("/data")));
+        assert_eq!(cli.config.hash_algo, HashAlgorithm::Blake3);
+        let cli = Cli::try_parse_from(["duplicate-finder", "completions", "powershell"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Completions { shell: clap_complete::Shell::PowerShell })));
+    }
+}
@@ -0,0 +1,232 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use tokio::fs;
+use tracing::{debug, info};
+
+use crate::scanner;
+use crate::{DuplicateGroup, ScanResult, Sidecar};
+
+/// Extensions of camera RAW formats
+pub const RAW_EXTENSIONS: &[&str] = &[
+    "3fr", "arw", "cr2", "cr3", "crw", "dng", "erf", "kdc", "mef", "mos", "mrw", "nef", "nrw", "orf", "pef", "raf",
+    "raw", "rw2", "rwl", "sr2", "srf", "srw", "x3f",
+];
+
+/// Extensions of developed images that usually accompany a RAW file
+pub const DEVELOPED_EXTENSIONS: &[&str] = &["heic", "heif", "jpeg", "jpg"];
+
+/// Extension of metadata sidecars written by photo editors
+pub const SIDECAR_EXTENSION: &str = "xmp";
+
+/// What role a file plays in a set of companion files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Role {
+    Raw,
+    Developed,
+    Sidecar,
+}
+
+/// Files in one directory sharing a base name, e.g. `IMG_1234.CR2`,
+/// `IMG_1234.JPG` and `IMG_1234.xmp`
+#[derive(Debug, Default)]
+struct PhotoSet {
+    raws: Vec<PathBuf>,
+    developed: Vec<PathBuf>,
+
+    /// Sidecars with the file they belong to: `IMG_1234.CR2.xmp` describes
+    /// one file, while `IMG_1234.xmp` (owner `None`) describes the whole set
+    sidecars: Vec<(PathBuf, Option<PathBuf>)>,
+}
+
+/// Set key of a file name and its role, if it is part of a photo workflow
+///
+/// The key is the lowercased name without its photo extensions, so
+/// `IMG_1234.CR2`, `img_1234.jpg` and `IMG_1234.CR2.xmp` share a key
+fn classify(name: &str) -> Option<(String, Role)> {
+    let lower = name.to_lowercase();
+    let (stem, ext) = lower.rsplit_once('.')?;
+
+    if ext == SIDECAR_EXTENSION {
+        let stem = match stem.rsplit_once('.') {
+            Some((base, inner)) if RAW_EXTENSIONS.contains(&inner) || DEVELOPED_EXTENSIONS.contains(&inner) => base,
+            _ => stem,
+        };
+        return Some((stem.to_string(), Role::Sidecar));
+    }
+    if RAW_EXTENSIONS.contains(&ext) {
+        return Some((stem.to_string(), Role::Raw));
+    }
+    if DEVELOPED_EXTENSIONS.contains(&ext) {
+        return Some((stem.to_string(), Role::Developed));
+    }
+    None
+}
+
+/// Lists the photo sets of a directory
+async fn photo_sets(dir: &Path) -> std::io::Result<HashMap<String, PhotoSet>> {
+    let mut sets: HashMap<String, PhotoSet> = HashMap::new();
+    let mut sidecars = Vec::new();
+
+    let mut read_dir = fs::read_dir(dir).await?;
+    while let Some(entry) = read_dir.next_entry().await? {
+        let path = entry.path();
+        let Some((key, role)) = path.file_name().and_then(|n| n.to_str()).and_then(classify) else {
+            continue;
+        };
+        let set = sets.entry(key).or_default();
+        match role {
+            Role::Raw => set.raws.push(path),
+            Role::Developed => set.developed.push(path),
+            Role::Sidecar => sidecars.push(path),
+        }
+    }
+
+    // Per-file sidecars are matched once all media files are known
+    for sidecar in sidecars {
+        let Some((key, _)) = sidecar.file_name().and_then(|n| n.to_str()).and_then(classify) else {
+            continue;
+        };
+        let set = sets.entry(key).or_default();
+        let owner = sidecar.file_stem().and_then(|stem| {
+            set.raws.iter().chain(&set.developed).find(|media| media.file_name() == Some(stem)).cloned()
+        });
+        set.sidecars.push((sidecar, owner));
+    }
+
+    Ok(sets)
+}
+
+/// Keeps RAW+JPEG pairs and their XMP sidecars together when choosing
+/// which copies are redundant
+///
+/// * A JPEG/HEIC copy whose RAW companion is kept is never redundant: the
+///   first such copy becomes the group's original and any others are
+///   dropped from the group.
+/// * XMP sidecars whose photos are all redundant are listed in
+///   [`ScanResult::sidecars`], so removing the copies never orphans them.
+///
+/// Totals and statistics are recomputed afterwards
+pub async fn apply(result: &mut ScanResult) {
+    // Photo sets of every directory holding a grouped file
+    let directories: HashSet<PathBuf> = result.duplicate_groups.iter()
+        .flat_map(|g| &g.files)
+        .filter_map(|f| f.path.parent().map(Path::to_path_buf))
+        .collect();
+    let mut sets: HashMap<(PathBuf, String), PhotoSet> = HashMap::new();
+    for dir in directories {
+        match photo_sets(&dir).await {
+            Ok(found) => sets.extend(found.into_iter().map(|(key, set)| ((dir.clone(), key), set))),
+            Err(e) => debug!("Cannot list companions in {}: {}", dir.display(), e),
+        }
+    }
+    let set_of = |path: &Path| {
+        let name = path.file_name()?.to_str()?;
+        let (key, _) = classify(name)?;
+        sets.get(&(path.parent()?.to_path_buf(), key))
+    };
+
+    // RAW files are never adjusted, so their fate is known up front
+    let redundant_raws: HashSet<PathBuf> = result.duplicate_groups.iter()
+        .flat_map(|g| g.redundant_files())
+        .filter(|f| matches!(f.path.file_name().and_then(|n| n.to_str()).and_then(classify), Some((_, Role::Raw))))
+        .map(|f| f.path.clone())
+        .collect();
+    let has_kept_raw = |path: &Path| {
+        let developed = matches!(path.file_name().and_then(|n| n.to_str()).and_then(classify), Some((_, Role::Developed)));
+        developed && set_of(path).is_some_and(|set| set.raws.iter().any(|raw| !redundant_raws.contains(raw)))
+    };
+
+    let mut kept_with_raw = 0;
+    for group in &mut result.duplicate_groups {
+        let (anchored, others): (Vec<_>, Vec<_>) = group.files.drain(..).partition(|f| has_kept_raw(&f.path));
+        let mut anchored = anchored.into_iter();
+        group.files.extend(anchored.next());
+        kept_with_raw += anchored.len();
+        group.files.extend(others);
+        reprice(group);
+    }
+    if kept_with_raw > 0 {
+        info!("Kept {} JPEG copies next to their RAW files", kept_with_raw);
+    }
+
+    // Sidecars whose photos all go away
+    let redundant: HashSet<&Path> = result.duplicate_groups.iter()
+        .flat_map(|g| g.redundant_files())
+        .map(|f| f.path.as_path())
+        .collect();
+    let mut sidecars: Vec<Sidecar> = Vec::new();
+    let mut seen = HashSet::new();
+    for path in &redundant {
+        let Some(set) = set_of(path) else {
+            continue;
+        };
+        let set_removed = set.raws.iter().chain(&set.developed).all(|media| redundant.contains(media.as_path()));
+        for (sidecar, owner) in &set.sidecars {
+            let orphaned = match owner {
+                Some(owner) => owner == path,
+                None => set_removed,
+            };
+            if orphaned && seen.insert(sidecar.clone()) {
+                sidecars.push(Sidecar { path: sidecar.clone(), companion: path.to_path_buf() });
+            }
+        }
+    }
+    sidecars.sort_by(|a, b| a.path.cmp(&b.path));
+
+    result.sidecars = sidecars;
+    scanner::refresh_statistics(result);
+}
+
+/// Recomputes a group's sizes after its files changed
+fn reprice(group: &mut DuplicateGroup) {
+    group.size = group.files.first().map(|f| f.size).unwrap_or(0);
+    group.total_size = group.files.iter().map(|f| f.size).sum();
+    group.wasted_space = group.total_size - group.size;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FileInfo;
+    use std::time::{Duration, UNIX_EPOCH};
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_companions_stay_together() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        for name in ["old/IMG_1.JPG", "old/IMG_2.CR2", "old/IMG_2.xmp", "shoot/IMG_1.CR2", "shoot/IMG_1.jpg", "shoot/IMG_1.CR2.xmp", "copy/IMG_2.CR2", "copy/IMG_2.xmp"] {
+            std::fs::create_dir_all(root.join(name).parent().unwrap()).unwrap();
+            std::fs::write(root.join(name), name).unwrap();
+        }
+
+        let file = |name: &str, age: u64| FileInfo {
+            path: root.join(name),
+            size: 100,
+            hash: String::new(),
+            modified: UNIX_EPOCH + Duration::from_secs(1000 - age),
+            created: None,
+            compression: None,
+        };
+        let group = |files: Vec<FileInfo>| DuplicateGroup { id: String::new(), hash: String::new(), size: 100, total_size: 200, wasted_space: 100, files };
+        let mut result = ScanResult {
+            duplicate_groups: vec![
+                // The older JPEG alone would be kept, leaving the shoot's RAW without its JPEG
+                group(vec![file("old/IMG_1.JPG", 2), file("shoot/IMG_1.jpg", 1)]),
+                group(vec![file("old/IMG_2.CR2", 2), file("copy/IMG_2.CR2", 1)]),
+            ],
+            ..ScanResult::default()
+        };
+
+        apply(&mut result).await;
+
+        assert_eq!(result.duplicate_groups[0].files[0].path, root.join("shoot/IMG_1.jpg"));
+        assert_eq!(
+            result.sidecars,
+            [Sidecar { path: root.join("copy/IMG_2.xmp"), companion: root.join("copy/IMG_2.CR2") }]
+        );
+        assert_eq!(result.total_duplicates, 2);
+        assert_eq!(classify("IMG_1.CR2.xmp"), Some(("img_1".to_string(), Role::Sidecar)));
+    }
+}
@@ -0,0 +1,106 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::sync::Arc;
+
+use flate2::read::MultiGzDecoder;
+use tracing::debug;
+use xz2::read::XzDecoder;
+
+use crate::hashing::{HashFunction, hash_reader};
+use crate::{Compression, DuplicateFinderError, FileInfo};
+
+/// Detects a single-file compression format from the file name
+pub fn compression_of(path: &Path) -> Option<Compression> {
+    let ext = path.extension()?.to_str()?.to_lowercase();
+    match ext.as_str() {
+        "gz" => Some(Compression::Gzip),
+        "xz" => Some(Compression::Xz),
+        "zst" => Some(Compression::Zstd),
+        _ => None,
+    }
+}
+
+/// Reads a compressed file's metadata and a digest of its
+/// decompressed content
+///
+/// `report.csv.gz` thus gets the same digest as `report.csv`. The size stays
+/// the on-disk size and [`FileInfo::compression`] records the wrapper, so a
+/// compressed copy is never mistaken for a plain file. Files that fail to
+/// decompress are hashed as they are
+pub async fn decompressed_file_info(path: &Path, compression: Compression, function: Arc<dyn HashFunction>) -> Result<FileInfo, DuplicateFinderError> {
+    let mut file_info = FileInfo::stat(path).await?;
+
+    let owned = path.to_path_buf();
+    let blocking_function = Arc::clone(&function);
+    let digest = tokio::task::spawn_blocking(move || decompressed_digest(&owned, compression, blocking_function.as_ref()))
+        .await
+        .map_err(|e| DuplicateFinderError::Io(std::io::Error::other(e)))?;
+
+    match digest {
+        Ok(hash) => {
+            file_info.hash = hash;
+            file_info.compression = Some(compression);
+            Ok(file_info)
+        }
+        Err(e) => {
+            debug!("Cannot decompress {}, hashing it as is: {}", path.display(), e);
+            FileInfo::from_path_with(path, function.as_ref()).await
+        }
+    }
+}
+
+/// Streams a compressed file through its decoder into the hasher
+fn decompressed_digest(path: &Path, compression: Compression, function: &dyn HashFunction) -> std::io::Result<String> {
+    let file = BufReader::new(File::open(path)?);
+    let mut reader: Box<dyn Read> = match compression {
+        Compression::Gzip => Box::new(MultiGzDecoder::new(file)),
+        Compression::Xz => Box::new(XzDecoder::new_multi_decoder(file)),
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::with_buffer(file)?),
+    };
+    hash_reader(&mut reader, function)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HashAlgorithm;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_decompressed_digest() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let data = b"date,amount\n2024-01-01,42\n".repeat(50);
+        let plain = temp_dir.path().join("report.csv");
+        std::fs::write(&plain, &data).unwrap();
+
+        let gz = temp_dir.path().join("report.csv.gz");
+        let mut encoder = flate2::write::GzEncoder::new(File::create(&gz).unwrap(), Default::default());
+        encoder.write_all(&data).unwrap();
+        encoder.finish().unwrap();
+
+        let xz = temp_dir.path().join("report.csv.xz");
+        let mut encoder = xz2::write::XzEncoder::new(File::create(&xz).unwrap(), 6);
+        encoder.write_all(&data).unwrap();
+        encoder.finish().unwrap();
+
+        let zst = temp_dir.path().join("report.csv.zst");
+        std::fs::write(&zst, zstd::encode_all(&data[..], 3).unwrap()).unwrap();
+
+        let broken = temp_dir.path().join("broken.gz");
+        std::fs::write(&broken, b"not gzip").unwrap();
+
+        let expected = FileInfo::from_path(&plain).await.unwrap().hash;
+        for path in [&gz, &xz, &zst] {
+            let info = decompressed_file_info(path, compression_of(path).unwrap(), Arc::new(HashAlgorithm::Sha256)).await.unwrap();
+            assert_eq!(info.hash, expected, "{}", path.display());
+            assert_eq!(info.compression, compression_of(path));
+            assert!(info.size < data.len() as u64);
+        }
+
+        let info = decompressed_file_info(&broken, Compression::Gzip, Arc::new(HashAlgorithm::Sha256)).await.unwrap();
+        assert_eq!(info.compression, None);
+        assert_eq!(compression_of(&plain), None);
+    }
+}
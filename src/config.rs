@@ -0,0 +1,296 @@
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+
+use clap::{ArgAction, CommandFactory};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{Cli, DuplicateFinderError};
+
+/// Options by long name without the leading dashes, e.g. `"min-size": 1024`
+///
+/// `true` passes a flag and `false` leaves it out, arrays repeat the option
+/// and numbers and strings are passed as its value
+pub type Options = BTreeMap<String, Value>;
+
+/// Contents of the configuration file
+///
+/// The file is JSON, read from `--config` or else from
+/// `duplicate-finder/config.json` in the user's configuration directory
+/// (see [`default_path`]):
+///
+/// ```json
+/// {
+///   "options": { "hash-algo": "blake3" },
+///   "profiles": { "nas": { "nice": true, "device-concurrency": 2 } }
+/// }
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    /// Options of every run
+    #[serde(default)]
+    pub options: Options,
+
+    /// Profiles selectable with `--profile`, replacing built-in ones of the
+    /// same name
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Options>,
+}
+
+/// Prefix of the environment variables setting options, e.g.
+/// `DUPLICATE_FINDER_MIN_SIZE` for `--min-size`
+pub const ENV_PREFIX: &str = "DUPLICATE_FINDER_";
+
+/// Profiles available without a configuration file, as command-line arguments
+const BUILTIN_PROFILES: &[(&str, &[&str])] = &[
+    // Photo libraries: same pictures with edited metadata, RAW+JPEG pairs kept together
+    ("photos", &["--mode", "image-pixels", "--companions", "--min-size", "10240", "--group-by", "directory"]),
+    // Music collections: same recordings with different tags or cover art
+    ("music", &["--mode", "audio", "--min-size", "102400"]),
+    // Source trees: same files checked out with other line endings
+    ("code", &["--normalize-text", "--trim-trailing-whitespace", "--exclude-empty", "--hash-algo", "blake3"]),
+    // Download folders: repeated downloads, also inside archives and compressed files
+    ("downloads", &["--decompress", "--scan-archives", "zip,tar,tar.gz", "--exclude-empty", "--top", "20"]),
+];
+
+/// Default location of the configuration file
+pub fn default_path() -> Option<PathBuf> {
+    let directory = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os(if cfg!(windows) { "APPDATA" } else { "HOME" }).map(|home| {
+            if cfg!(windows) { PathBuf::from(home) } else { Path::new(&home).join(".config") }
+        }))?;
+    Some(directory.join("duplicate-finder").join("config.json"))
+}
+
+/// Names `--profile` accepts: the built-in profiles and those of the
+/// configuration file given, named by `DUPLICATE_FINDER_CONFIG` or at the
+/// default location
+pub fn profile_names(config: Option<&Path>) -> Result<Vec<String>, DuplicateFinderError> {
+    let path = config
+        .map(Path::to_path_buf)
+        .or_else(|| std::env::var_os(format!("{}CONFIG", ENV_PREFIX)).map(PathBuf::from))
+        .or_else(|| default_path().filter(|path| path.is_file()));
+    let config = path.as_deref().map(ConfigFile::load).transpose()?.unwrap_or_default();
+    let mut names: Vec<String> = BUILTIN_PROFILES.iter().map(|(name, _)| name.to_string()).collect();
+    names.extend(config.profiles.into_keys().filter(|name| !BUILTIN_PROFILES.iter().any(|(builtin, _)| builtin == name)));
+    Ok(names)
+}
+
+impl ConfigFile {
+    /// Reads a configuration file
+    pub fn load(path: &Path) -> Result<Self, DuplicateFinderError> {
+        let content = std::fs::read_to_string(path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => DuplicateFinderError::PathNotFound { path: path.to_path_buf() },
+            _ => DuplicateFinderError::Io(e),
+        })?;
+        serde_json::from_str(&content).map_err(|e| DuplicateFinderError::InvalidConfig {
+            path: path.to_path_buf(),
+            reason: e.to_string(),
+        })
+    }
+}
+
+/// Expands the command line with the configuration file, `--profile` and
+/// the `DUPLICATE_FINDER_*` environment variables
+///
+/// Every source becomes command-line arguments placed before the given
+/// ones, so later sources override earlier ones. From lowest to highest
+/// precedence:
+///
+/// 1. built-in defaults
+/// 2. `options` of the configuration file
+/// 3. the profile's options
+/// 4. environment variables, e.g. `DUPLICATE_FINDER_HASH_ALGO=blake3`
+/// 5. the command line
+///
+/// `DUPLICATE_FINDER_CONFIG` and `DUPLICATE_FINDER_PROFILE` stand in for
+/// `--config` and `--profile` when they are not given. The file named by
+/// `--config` must exist; the default one is optional
+pub fn resolve_args(args: impl IntoIterator<Item = OsString>) -> Result<Vec<OsString>, DuplicateFinderError> {
+    resolve_args_with(args, std::env::vars_os())
+}
+
+/// [`resolve_args`] with the given environment instead of the process's
+pub fn resolve_args_with(
+    args: impl IntoIterator<Item = OsString>,
+    vars: impl IntoIterator<Item = (OsString, OsString)>,
+) -> Result<Vec<OsString>, DuplicateFinderError> {
+    let mut args = args.into_iter();
+    let program = args.next().unwrap_or_else(|| "duplicate-finder".into());
+    let given: Vec<OsString> = args.collect();
+    let mut env: BTreeMap<String, OsString> = vars
+        .into_iter()
+        .filter_map(|(name, value)| Some((name.into_string().ok()?.strip_prefix(ENV_PREFIX)?.to_string(), value)))
+        .collect();
+    let from_env = env.remove("CONFIG");
+    let profile = option_value(&given, "profile").or(env.remove("PROFILE"));
+
+    let path = option_value(&given, "config")
+        .or(from_env)
+        .map(PathBuf::from)
+        .or_else(|| default_path().filter(|path| path.is_file()));
+    let config = path.as_deref().map(ConfigFile::load).transpose()?.unwrap_or_default();
+
+    let known = long_options();
+    let to_args = |options: &Options, section: &str| {
+        to_args(options, &known).map_err(|reason| DuplicateFinderError::InvalidConfig {
+            path: path.clone().unwrap_or_default(),
+            reason: format!("{}: {}", section, reason),
+        })
+    };
+    let mut resolved = vec![program];
+    resolved.extend(to_args(&config.options, "options")?);
+    if let Some(name) = profile {
+        let name = name.to_string_lossy();
+        match (config.profiles.get(&*name), BUILTIN_PROFILES.iter().find(|(builtin, _)| *builtin == name)) {
+            (Some(options), _) => resolved.extend(to_args(options, &format!("profile {}", name))?),
+            (None, Some((_, builtin))) => resolved.extend(builtin.iter().map(OsString::from)),
+            (None, None) => return Err(DuplicateFinderError::UnknownProfile { name: name.into_owned() }),
+        }
+    }
+    resolved.extend(env_args(&env)?);
+    resolved.extend(given);
+    Ok(resolved)
+}
+
+/// Command-line arguments for `DUPLICATE_FINDER_*` variables, given without
+/// the prefix
+///
+/// Flags take `1`, `true`, `yes` or `on` and `0`, `false`, `no`, `off` or
+/// nothing; like `false` in the configuration file, the latter leave the
+/// flag to the sources below. Repeatable options without a delimiter of their own, such as
+/// `--root`, take a list separated like `PATH`
+fn env_args(env: &BTreeMap<String, OsString>) -> Result<Vec<OsString>, DuplicateFinderError> {
+    let command = Cli::command();
+    let mut args = Vec::new();
+    for (name, value) in env {
+        let invalid = |reason: &str| DuplicateFinderError::InvalidEnvironment {
+            variable: format!("{}{}", ENV_PREFIX, name),
+            reason: reason.to_string(),
+        };
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_long().is_some_and(|long| long.replace('-', "_").eq_ignore_ascii_case(name)))
+            .ok_or_else(|| invalid("no such option"))?;
+        let flag = format!("--{}", arg.get_long().unwrap_or_default());
+        if !arg.get_action().takes_values() {
+            match value.to_str().map(str::to_ascii_lowercase).as_deref() {
+                Some("1" | "true" | "yes" | "on") => args.push(flag.into()),
+                Some("0" | "false" | "no" | "off" | "") => {}
+                _ => return Err(invalid("expected true or false")),
+            }
+        } else if matches!(arg.get_action(), ArgAction::Append) && arg.get_value_delimiter().is_none() {
+            for value in std::env::split_paths(value) {
+                args.push(flag.clone().into());
+                args.push(value.into_os_string());
+            }
+        } else {
+            args.push(flag.into());
+            args.push(value.clone());
+        }
+    }
+    Ok(args)
+}
+
+/// Value given to `--<name>` on the command line, as `--name VALUE` or
+/// `--name=VALUE`
+fn option_value(args: &[OsString], name: &str) -> Option<OsString> {
+    let flag = format!("--{}", name);
+    let prefix = format!("--{}=", name);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == OsStr::new(&flag) {
+            return args.next().cloned();
+        }
+        if let Some(value) = arg.to_str().and_then(|arg| arg.strip_prefix(&prefix)) {
+            return Some(value.into());
+        }
+    }
+    None
+}
+
+/// Long names of all top-level options
+fn long_options() -> HashSet<String> {
+    Cli::command().get_arguments().filter_map(|arg| arg.get_long()).map(str::to_string).collect()
+}
+
+/// Command-line arguments for the options of the configuration file
+fn to_args(options: &Options, known: &HashSet<String>) -> Result<Vec<OsString>, String> {
+    let mut args = Vec::new();
+    for (name, value) in options {
+        if !known.contains(name) || name == "config" || name == "profile" {
+            return Err(format!("unknown option '{}'", name));
+        }
+        let values = match value {
+            Value::Array(values) => values.as_slice(),
+            value => std::slice::from_ref(value),
+        };
+        for value in values {
+            match value {
+                Value::Bool(true) => args.push(format!("--{}", name).into()),
+                Value::Bool(false) => {}
+                Value::Number(number) => args.push(format!("--{}={}", name, number).into()),
+                Value::String(text) => args.push(format!("--{}={}", name, text).into()),
+                _ => return Err(format!("'{}' must be a boolean, number, string or array of them", name)),
+            }
+        }
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn test_resolve_args() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let path = temp_dir.path().join("config.json");
+        std::fs::write(&path, r#"{
+            "options": { "hash-algo": "blake3", "min-size": 100 },
+            "profiles": { "nas": { "min-size": 4096, "nice": true, "root": ["/a", "/b"] } }
+        }"#).unwrap();
+        let config = path.to_str().unwrap();
+        let parse = |args: &[&str]| resolve_args(args.iter().map(OsString::from)).ok().and_then(|args| Cli::try_parse_from(args).ok());
+
+        // The command line wins over the profile, which wins over the options
+        let cli = parse(&["duplicate-finder", "--config", config, "--profile=nas", "-s", "10"]).unwrap();
+        assert_eq!((cli.config.hash_algo, cli.config.min_size, cli.config.nice), (crate::HashAlgorithm::Blake3, 10, true));
+        assert_eq!(cli.config.extra_roots, [PathBuf::from("/a"), PathBuf::from("/b")]);
+        let cli = parse(&["duplicate-finder", "--config", config, "--profile", "nas"]).unwrap();
+        assert_eq!(cli.config.min_size, 4096);
+
+        let cli = parse(&["duplicate-finder", "--config", config, "--profile", "music"]).unwrap();
+        assert_eq!((cli.config.mode, cli.config.min_size), (crate::Mode::Audio, 102400));
+        assert!(parse(&["duplicate-finder", "--config", config, "--profile", "films"]).is_none());
+
+        // The environment sits between the configuration file and the command line
+        let env = |vars: &[(&str, &str)]| vars.iter().map(|(name, value)| (OsString::from(name), OsString::from(value))).collect::<Vec<_>>();
+        let vars = env(&[
+            ("DUPLICATE_FINDER_CONFIG", config),
+            ("DUPLICATE_FINDER_PROFILE", "nas"),
+            ("DUPLICATE_FINDER_MIN_SIZE", "20"),
+            ("DUPLICATE_FINDER_NICE", "false"),
+            ("DUPLICATE_FINDER_EXCLUDE_EMPTY", "yes"),
+            ("DUPLICATE_FINDER_ROOT", "/c:/d"),
+            ("HOME", "/home/user"),
+        ]);
+        let resolved = resolve_args_with(["duplicate-finder".into(), "-S".into(), "99".into()], vars.clone()).unwrap();
+        let cli = Cli::try_parse_from(resolved).unwrap();
+        assert_eq!((cli.config.hash_algo, cli.config.min_size, cli.config.max_size), (crate::HashAlgorithm::Blake3, 20, 99));
+        assert!(cli.config.exclude_empty && cli.config.nice);
+        assert_eq!(cli.config.extra_roots.len(), 4);
+        let resolved = resolve_args_with(["duplicate-finder".into(), "--min-size=5".into()], vars).unwrap();
+        assert_eq!(Cli::try_parse_from(resolved).unwrap().config.min_size, 5);
+        let typo = env(&[("DUPLICATE_FINDER_MIN_SISE", "20")]);
+        assert!(matches!(resolve_args_with(["x".into()], typo), Err(DuplicateFinderError::InvalidEnvironment { .. })));
+
+        std::fs::write(&path, r#"{ "options": { "min-sise": 100 } }"#).unwrap();
+        assert!(matches!(resolve_args([OsString::from("x"), "--config".into(), path.into()]), Err(DuplicateFinderError::InvalidConfig { .. })));
+    }
+}
@@ -0,0 +1,181 @@
+use std::path::Path;
+use std::time::SystemTime;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, params};
+
+use crate::ScanResult;
+
+/// Schema for scan history databases
+///
+/// Every scan appends one row to `scans`; its duplicate groups and their
+/// member files reference it, so several runs can share one database
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS scans (
+    id                  INTEGER PRIMARY KEY,
+    scanned_at          TEXT    NOT NULL,
+    scanned_directory   TEXT    NOT NULL,
+    duration_ms         INTEGER NOT NULL,
+    total_files         INTEGER NOT NULL,
+    total_duplicates    INTEGER NOT NULL,
+    total_wasted_space  INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS groups (
+    id            INTEGER PRIMARY KEY,
+    scan_id       INTEGER NOT NULL REFERENCES scans(id) ON DELETE CASCADE,
+    stable_id     TEXT,
+    hash          TEXT    NOT NULL,
+    size          INTEGER NOT NULL,
+    file_count    INTEGER NOT NULL,
+    wasted_space  INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS files (
+    id           INTEGER PRIMARY KEY,
+    group_id     INTEGER NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+    path         TEXT    NOT NULL,
+    size         INTEGER NOT NULL,
+    modified     TEXT,
+    created      TEXT,
+    is_original  INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_groups_scan ON groups(scan_id);
+CREATE INDEX IF NOT EXISTS idx_groups_hash ON groups(hash);
+CREATE INDEX IF NOT EXISTS idx_files_group ON files(group_id);
+CREATE INDEX IF NOT EXISTS idx_files_path ON files(path);
+";
+
+/// Brings tables created by older versions up to [`SCHEMA`]
+///
+/// `groups.stable_id` (see [`DuplicateGroup::id`](crate::DuplicateGroup::id))
+/// stays empty for scans recorded before it existed
+fn migrate(conn: &Connection) -> Result<()> {
+    let has_stable_id: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM pragma_table_info('groups') WHERE name = 'stable_id'",
+        [],
+        |row| row.get(0),
+    )?;
+    if !has_stable_id {
+        conn.execute_batch("ALTER TABLE groups ADD COLUMN stable_id TEXT")?;
+    }
+    conn.execute_batch("CREATE INDEX IF NOT EXISTS idx_groups_stable_id ON groups(stable_id)")?;
+    Ok(())
+}
+
+/// Appends a scan result to a SQLite database, creating tables as needed
+///
+/// Everything is written in a single transaction, so an interrupted write
+/// never leaves a partially recorded scan. Returns the new scan's row id
+pub fn append_scan(scan_result: &ScanResult, db_path: &Path) -> Result<i64> {
+    let mut conn = Connection::open(db_path)?;
+    conn.execute_batch(SCHEMA)?;
+    migrate(&conn)?;
+
+    let tx = conn.transaction()?;
+
+    tx.execute(
+        "INSERT INTO scans (scanned_at, scanned_directory, duration_ms, total_files, total_duplicates, total_wasted_space)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            Utc::now().to_rfc3339(),
+            scan_result.scanned_directory.to_string_lossy(),
+            scan_result.scan_duration.as_millis() as i64,
+            scan_result.total_files as i64,
+            scan_result.total_duplicates as i64,
+            scan_result.total_wasted_space as i64,
+        ],
+    )?;
+    let scan_id = tx.last_insert_rowid();
+
+    {
+        let mut insert_group = tx.prepare(
+            "INSERT INTO groups (scan_id, stable_id, hash, size, file_count, wasted_space) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )?;
+        let mut insert_file = tx.prepare(
+            "INSERT INTO files (group_id, path, size, modified, created, is_original) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )?;
+
+        for group in &scan_result.duplicate_groups {
+            insert_group.execute(params![
+                scan_id,
+                group.id,
+                group.hash,
+                group.size as i64,
+                group.files.len() as i64,
+                group.wasted_space as i64,
+            ])?;
+            let group_id = tx.last_insert_rowid();
+
+            for (index, file) in group.files.iter().enumerate() {
+                insert_file.execute(params![
+                    group_id,
+                    file.path.to_string_lossy(),
+                    file.size as i64,
+                    to_rfc3339(file.modified),
+                    file.created.map(to_rfc3339),
+                    index == 0,
+                ])?;
+            }
+        }
+    }
+
+    tx.commit()?;
+    Ok(scan_id)
+}
+
+/// Formats a timestamp for storage as ISO 8601 text
+fn to_rfc3339(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DuplicateGroup, FileInfo};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_append_scan_accumulates_history() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let db_path = temp_dir.path().join("results.db");
+
+        let file = |path: &str| FileInfo {
+            path: PathBuf::from(path),
+            size: 10,
+            hash: "abc".to_string(),
+            modified: SystemTime::UNIX_EPOCH,
+            created: None,
+            compression: None,
+        };
+        let scan_result = ScanResult {
+            duplicate_groups: vec![DuplicateGroup {
+                id: "0123456789abcdef".to_string(),
+                hash: "abc".to_string(),
+                size: 10,
+                files: vec![file("/a"), file("/b")],
+                total_size: 20,
+                wasted_space: 10,
+            }],
+            ..Default::default()
+        };
+
+        let first = append_scan(&scan_result, &db_path).unwrap();
+        let second = append_scan(&scan_result, &db_path).unwrap();
+        assert_ne!(first, second);
+
+        let conn = Connection::open(&db_path).unwrap();
+        let files: i64 = conn.query_row("SELECT COUNT(*) FROM files", [], |row| row.get(0)).unwrap();
+        let originals: i64 = conn
+            .query_row("SELECT COUNT(*) FROM files WHERE is_original = 1", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(files, 4);
+        assert_eq!(originals, 2);
+        let tracked: i64 = conn
+            .query_row("SELECT COUNT(*) FROM groups WHERE stable_id = '0123456789abcdef'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(tracked, 2);
+    }
+}
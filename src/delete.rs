@@ -0,0 +1,446 @@
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use tracing::{debug, warn};
+
+use crate::{DuplicateGroup, FileInfo, ScanResult, ScannerConfig, ThumbnailProtocol, archives, format_bytes, parse_size, preview};
+
+/// Files of a group to keep, as answered at the `--interactive` prompt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Selection {
+    /// Keep the files at these positions (starting at 0), delete the rest
+    Keep(Vec<usize>),
+    /// Keep every file
+    All,
+    /// Delete every file
+    None,
+    /// Stop asking, leaving this and the remaining groups alone
+    Quit,
+}
+
+impl Selection {
+    /// Parses an answer for a group of `count` files
+    ///
+    /// Files are given by their number as listed (from 1), separated by
+    /// commas or spaces; `all`, `none` and `q` keep everything, nothing or
+    /// stop
+    pub fn parse(answer: &str, count: usize) -> Result<Self, String> {
+        match answer.trim().to_ascii_lowercase().as_str() {
+            "" => Err("type the numbers of the files to keep, all, none or q".to_string()),
+            "all" | "a" => Ok(Selection::All),
+            "none" | "n" => Ok(Selection::None),
+            "q" | "quit" => Ok(Selection::Quit),
+            numbers => {
+                let mut keep = Vec::new();
+                for number in numbers.split([',', ' ']).filter(|number| !number.is_empty()) {
+                    match number.parse::<usize>() {
+                        Ok(n) if (1..=count).contains(&n) => keep.push(n - 1),
+                        _ => return Err(format!("{} is not a file number between 1 and {}", number, count)),
+                    }
+                }
+                keep.sort_unstable();
+                keep.dedup();
+                Ok(Selection::Keep(keep))
+            }
+        }
+    }
+
+    /// Files of `group` this selection deletes
+    pub fn doomed<'g>(&self, group: &'g DuplicateGroup) -> Vec<&'g FileInfo> {
+        match self {
+            Selection::All | Selection::Quit => Vec::new(),
+            Selection::None => group.files.iter().collect(),
+            Selection::Keep(keep) => group.files.iter().enumerate().filter(|(i, _)| !keep.contains(i)).map(|(_, file)| file).collect(),
+        }
+    }
+}
+
+/// Options of the `--interactive` prompt
+#[derive(Debug, Clone, Default)]
+pub struct PromptOptions {
+    /// Delete once every group was answered instead of after each answer
+    pub delete_at_end: bool,
+
+    /// Protocol drawing image thumbnails in previews, if the terminal has
+    /// one (see [`preview::thumbnail_protocol`])
+    pub thumbnails: Option<ThumbnailProtocol>,
+
+    /// Command previews also run on the file (see [`preview::exec_preview`])
+    pub exec_preview: Option<String>,
+
+    /// Groups may hold files with different contents, as in name, unverified
+    /// and similarity modes (see [`crate::Mode::finds_equal_contents`]), so
+    /// each one is listed with a warning
+    pub may_differ: bool,
+}
+
+/// Narrows the groups shown at the `--interactive` prompt, typed as `/QUERY`
+///
+/// Every word must match: `.jpg` a file extension, `>10MB` and `<1GB` the
+/// file size and anything else a path, fuzzily (its characters in order,
+/// e.g. `phvac` for `photos/vacation`). A group matches when its files do
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GroupFilter {
+    terms: Vec<Term>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Term {
+    Extension(String),
+    LargerThan(u64),
+    SmallerThan(u64),
+    Path(String),
+}
+
+impl GroupFilter {
+    /// Parses a query; an empty one matches every group
+    pub fn parse(query: &str) -> Result<Self, String> {
+        let terms = query
+            .split_whitespace()
+            .map(|word| {
+                Ok(if let Some(size) = word.strip_prefix('>') {
+                    Term::LargerThan(parse_size(size)?)
+                } else if let Some(size) = word.strip_prefix('<') {
+                    Term::SmallerThan(parse_size(size)?)
+                } else if let Some(extension) = word.strip_prefix('.') {
+                    Term::Extension(extension.to_lowercase())
+                } else {
+                    Term::Path(word.to_lowercase())
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self { terms })
+    }
+
+    pub fn matches(&self, group: &DuplicateGroup) -> bool {
+        self.terms.iter().all(|term| match term {
+            Term::LargerThan(size) => group.size > *size,
+            Term::SmallerThan(size) => group.size < *size,
+            Term::Extension(wanted) => group.files.iter().any(|file| {
+                file.path.extension().is_some_and(|extension| extension.to_string_lossy().to_lowercase() == *wanted)
+            }),
+            Term::Path(pattern) => group.files.iter().any(|file| fuzzy_match(pattern, &file.path.to_string_lossy().to_lowercase())),
+        })
+    }
+}
+
+/// Whether the characters of `pattern` appear in `text` in order
+fn fuzzy_match(pattern: &str, text: &str) -> bool {
+    let mut text = text.chars();
+    pattern.chars().all(|wanted| text.any(|c| c == wanted))
+}
+
+/// Outcome of deleting duplicates
+#[derive(Debug, Default)]
+pub struct Deletion {
+    /// Files removed
+    pub deleted: usize,
+    /// Bytes those files held
+    pub freed: u64,
+    /// Files that could not be removed
+    pub failed: usize,
+}
+
+impl Deletion {
+    /// Deletes `files`, reporting each one to `output`
+    ///
+    /// A file is only deleted if its size is still the one the scan found.
+    /// Files inside archives are never deleted
+    #[tracing::instrument(name = "delete", skip_all, fields(files = files.len()))]
+    pub fn delete(&mut self, files: &[&FileInfo], output: &mut impl Write) -> io::Result<()> {
+        for file in files {
+            if archives::is_member(&file.path) {
+                writeln!(output, "   Kept {} (inside an archive)", file.path.display())?;
+                continue;
+            }
+            let result = std::fs::symlink_metadata(&file.path).and_then(|metadata| {
+                if metadata.len() != file.size {
+                    return Err(io::Error::other("changed since the scan"));
+                }
+                std::fs::remove_file(&file.path)
+            });
+            match result {
+                Ok(()) => {
+                    writeln!(output, "   Deleted {}", file.path.display())?;
+                    debug!(path = %file.path.display(), bytes = file.size, "Deleted file");
+                    self.deleted += 1;
+                    self.freed += file.size;
+                }
+                Err(e) => {
+                    warn!(path = %file.path.display(), error = %e, "Cannot delete file");
+                    self.failed += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Files `--auto` deletes: the redundant copies of every group, keeping
+/// its original (see [`DuplicateGroup::redundant_files`]), followed by the
+/// XMP sidecars of those copies (see [`ScanResult::sidecars`])
+///
+/// Files inside archives are left out, they cannot be deleted. Nothing is
+/// selected unless `config`, the configuration of the scan, groups
+/// byte-identical copies (see [`ScannerConfig::finds_identical_bytes`])
+pub fn automatic(scan_result: &ScanResult, config: &ScannerConfig) -> io::Result<Vec<FileInfo>> {
+    let mut doomed = Vec::new();
+    if !config.finds_identical_bytes() {
+        return Ok(doomed);
+    }
+    scan_result.for_each_group(|group| {
+        doomed.extend(group.redundant_files().filter(|file| !archives::is_member(&file.path)).cloned());
+        Ok(())
+    })?;
+    let mut sidecars = Vec::new();
+    for sidecar in scan_result.sidecars.iter().filter(|sidecar| doomed.iter().any(|file| file.path == sidecar.companion)) {
+        // Sizes are taken now, so a sidecar edited before the deletion is kept
+        match std::fs::symlink_metadata(&sidecar.path) {
+            Ok(metadata) => sidecars.push(FileInfo {
+                path: sidecar.path.clone(),
+                size: metadata.len(),
+                hash: String::new(),
+                modified: metadata.modified()?,
+                created: None,
+                compression: None,
+            }),
+            Err(e) => debug!(path = %sidecar.path.display(), error = %e, "Sidecar already gone"),
+        }
+    }
+    doomed.extend(sidecars);
+    Ok(doomed)
+}
+
+/// Asks to type `delete` before deleting without `--yes`
+pub fn confirm(files: usize, bytes: u64, input: &mut impl BufRead, output: &mut impl Write) -> io::Result<bool> {
+    write!(output, "Type 'delete' to delete {} files ({}): ", files, format_bytes(bytes))?;
+    output.flush()?;
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    Ok(answer.trim() == "delete")
+}
+
+/// Walks through the duplicate groups asking which files to keep, like
+/// `fdupes --delete`
+///
+/// Each group is listed with numbered files, oldest first, and the answer
+/// read from `input` (see [`Selection::parse`]). Besides an answer,
+/// `p N` previews file N (see [`preview::describe`], with a thumbnail of
+/// images where the terminal can draw one), `o N` opens it with its
+/// default application, `r N` reveals it in the file manager and `/QUERY`
+/// skips the groups not matching a [`GroupFilter`] from then on.
+/// Deletions happen right after each answer or once every group was
+/// answered. The end of `input` stops like `q`
+pub fn interactive(scan_result: &ScanResult, input: &mut impl BufRead, output: &mut impl Write, options: &PromptOptions) -> io::Result<Deletion> {
+    let total = scan_result.group_count();
+    let mut deletion = Deletion::default();
+    let mut pending: Vec<FileInfo> = Vec::new();
+    let mut filter = GroupFilter::default();
+    let mut number = 0;
+    let mut quit = false;
+    scan_result.for_each_group(|group| {
+        number += 1;
+        if quit || !filter.matches(group) {
+            return Ok(());
+        }
+        writeln!(output)?;
+        writeln!(output, "[{}/{}] {} files of {} each (ID {})", number, total, group.files.len(), format_bytes(group.size), group.id)?;
+        if options.may_differ {
+            writeln!(output, "  Warning: these files may differ in content, check them before deleting any")?;
+        }
+        for (i, file) in group.files.iter().enumerate() {
+            writeln!(output, "  [{}] {}", i + 1, file.path.display())?;
+        }
+        let selection = ask(group, input, output, &mut filter, options)?;
+        quit = selection == Selection::Quit;
+        let doomed = selection.doomed(group);
+        if options.delete_at_end {
+            pending.extend(doomed.into_iter().cloned());
+            Ok(())
+        } else {
+            deletion.delete(&doomed, output)
+        }
+    })?;
+    deletion.delete(&pending.iter().collect::<Vec<_>>(), output)?;
+    Ok(deletion)
+}
+
+/// Asks which files of `group` to keep until the answer is one
+fn ask(
+    group: &DuplicateGroup,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    filter: &mut GroupFilter,
+    options: &PromptOptions,
+) -> io::Result<Selection> {
+    let count = group.files.len();
+    loop {
+        write!(output, "Keep which files? (1-{}, all, none, p/o/r N to preview/open/reveal, /filter, q): ", count)?;
+        output.flush()?;
+        let mut answer = String::new();
+        if input.read_line(&mut answer)? == 0 {
+            return Ok(Selection::Quit);
+        }
+        let answer = answer.trim();
+
+        if let Some(query) = answer.strip_prefix('/') {
+            match GroupFilter::parse(query) {
+                // Leaving a group out keeps all of its files
+                Ok(parsed) if !parsed.matches(group) => {
+                    *filter = parsed;
+                    return Ok(Selection::All);
+                }
+                Ok(parsed) => *filter = parsed,
+                Err(reason) => writeln!(output, "   {}", reason)?,
+            }
+            continue;
+        }
+
+        let (action, number) = answer.split_at_checked(1).map_or((answer, ""), |(action, number)| (action, number.trim()));
+        if matches!(action, "p" | "o" | "r") && !number.is_empty() {
+            let Some(n) = number.parse::<usize>().ok().filter(|n| (1..=count).contains(n)) else {
+                writeln!(output, "   {} is not a file number between 1 and {}", number, count)?;
+                continue;
+            };
+            let path = &group.files[n - 1].path;
+            let result = match action {
+                "o" => preview::open(path),
+                "r" => preview::reveal(path),
+                _ => show_preview(path, output, options),
+            };
+            if let Err(e) = result {
+                writeln!(output, "      Cannot show {}: {}", path.display(), e)?;
+            }
+            continue;
+        }
+
+        match Selection::parse(answer, count) {
+            Ok(selection) => return Ok(selection),
+            Err(reason) => writeln!(output, "   {}", reason)?,
+        }
+    }
+}
+
+/// Describes a file below its listing, draws its thumbnail and runs the
+/// `--exec-preview` command on it
+fn show_preview(path: &Path, output: &mut impl Write, options: &PromptOptions) -> io::Result<()> {
+    for line in preview::describe(path)? {
+        writeln!(output, "      {}", line)?;
+    }
+    if let Some(thumbnail) = options.thumbnails.and_then(|protocol| preview::thumbnail(path, protocol)) {
+        output.write_all(&thumbnail)?;
+    }
+    output.flush()?;
+    match &options.exec_preview {
+        Some(command) => preview::exec_preview(command, path),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Mode;
+
+    #[test]
+    fn test_selection_parse() {
+        assert_eq!(Selection::parse("1", 3), Ok(Selection::Keep(vec![0])));
+        assert_eq!(Selection::parse(" 3, 1 3\n", 3), Ok(Selection::Keep(vec![0, 2])));
+        assert_eq!(Selection::parse("ALL", 3), Ok(Selection::All));
+        assert_eq!(Selection::parse("none", 3), Ok(Selection::None));
+        assert!(Selection::parse("4", 3).is_err());
+        assert!(Selection::parse("", 3).is_err());
+    }
+
+    #[test]
+    fn test_interactive_deletion() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let file = |name: &str| {
+            let path = temp_dir.path().join(name);
+            std::fs::write(&path, "same").unwrap();
+            FileInfo { path, size: 4, hash: "h".to_string(), modified: std::time::SystemTime::now(), created: None, compression: None }
+        };
+        let group = |files: Vec<FileInfo>| DuplicateGroup { id: String::new(), hash: String::new(), size: 4, total_size: 0, wasted_space: 0, files };
+        let first = group(vec![file("a"), file("b"), file("c")]);
+        let second = group(vec![file("d"), file("e")]);
+        let scan_result = ScanResult { duplicate_groups: vec![first, second], ..Default::default() };
+
+        // An invalid answer is asked again; the end of the input stops
+        let mut output = Vec::new();
+        let deletion = interactive(&scan_result, &mut &b"9\n2\n"[..], &mut output, &PromptOptions::default()).unwrap();
+        assert_eq!((deletion.deleted, deletion.freed), (2, 8));
+        assert!(String::from_utf8(output).unwrap().contains("9 is not a file number between 1 and 3"));
+        assert!(!temp_dir.path().join("a").exists() && temp_dir.path().join("b").exists());
+        assert!(temp_dir.path().join("d").exists() && temp_dir.path().join("e").exists());
+
+        // Filtered out groups are skipped; previews show the file
+        let mut output = Vec::new();
+        let deletion = interactive(&scan_result, &mut &b"p 2\n/ >1KB\n"[..], &mut output, &PromptOptions::default()).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(deletion.deleted, 0);
+        assert!(output.contains("      | same") && !output.contains("[2/2]"));
+        assert!(!output.contains("Warning"));
+        let filter = GroupFilter::parse(".TXT phot <1MB").unwrap();
+        let photo = |path: &str| FileInfo { path: path.into(), ..scan_result.duplicate_groups[0].files[1].clone() };
+        assert!(filter.matches(&group(vec![photo("/photos/a.txt")])));
+        assert!(!filter.matches(&group(vec![photo("/docs/a.txt")])));
+        assert!(GroupFilter::parse(">ten").is_err());
+
+        // Groups of modes not comparing contents carry a warning
+        let mut output = Vec::new();
+        let options = PromptOptions { may_differ: true, ..PromptOptions::default() };
+        interactive(&scan_result, &mut &b"all\n"[..], &mut output, &options).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap().matches("may differ in content").count(), 2);
+
+        // --auto keeps the first file of each group
+        let doomed = automatic(&scan_result, &ScannerConfig::default()).unwrap();
+        assert_eq!(doomed.iter().map(|file| file.path.file_name().unwrap().to_str().unwrap()).collect::<Vec<_>>(), ["b", "c", "e"]);
+        assert!(!confirm(3, 12, &mut &b"yes\n"[..], &mut Vec::new()).unwrap());
+        assert!(confirm(3, 12, &mut &b"delete\n"[..], &mut Vec::new()).unwrap());
+    }
+
+    #[test]
+    fn test_automatic_needs_identical_bytes() {
+        let file = |path: &str| FileInfo { path: path.into(), size: 4, hash: "h".to_string(), modified: std::time::SystemTime::UNIX_EPOCH, created: None, compression: None };
+        let group = DuplicateGroup { id: String::new(), hash: "h".to_string(), size: 4, total_size: 8, wasted_space: 4, files: vec![file("a"), file("b")] };
+        let scan_result = ScanResult { duplicate_groups: vec![group], ..Default::default() };
+        assert_eq!(automatic(&scan_result, &ScannerConfig::default()).unwrap().len(), 1);
+
+        // Unverified, similarity and name groups, and groups of decoded contents, are never deleted from
+        for mode in [Mode::Quick, Mode::Metadata, Mode::ImageSimilar, Mode::Video, Mode::TextSimilar, Mode::Name, Mode::NameConflict, Mode::ImagePixels] {
+            let config = ScannerConfig { mode, ..ScannerConfig::default() };
+            assert!(automatic(&scan_result, &config).unwrap().is_empty(), "{:?}", mode);
+        }
+        let decompressed = ScannerConfig { decompress: true, ..ScannerConfig::default() };
+        assert!(automatic(&scan_result, &decompressed).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_automatic_deletes_sidecars() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        for name in ["old/IMG_2.CR2", "old/IMG_2.xmp", "copy/IMG_2.CR2", "copy/IMG_2.xmp"] {
+            std::fs::create_dir_all(root.join(name).parent().unwrap()).unwrap();
+            std::fs::write(root.join(name), "raw").unwrap();
+        }
+        let file = |name: &str, age: u64| FileInfo {
+            path: root.join(name),
+            size: 3,
+            hash: "h".to_string(),
+            modified: std::time::UNIX_EPOCH + std::time::Duration::from_secs(1000 - age),
+            created: None,
+            compression: None,
+        };
+        let group = DuplicateGroup { id: String::new(), hash: "h".to_string(), size: 3, total_size: 6, wasted_space: 3, files: vec![file("old/IMG_2.CR2", 2), file("copy/IMG_2.CR2", 1)] };
+        let mut scan_result = ScanResult { duplicate_groups: vec![group], ..Default::default() };
+        crate::companions::apply(&mut scan_result).await;
+
+        let doomed = automatic(&scan_result, &ScannerConfig::default()).unwrap();
+        assert_eq!(doomed.iter().map(|file| file.path.clone()).collect::<Vec<_>>(), [root.join("copy/IMG_2.CR2"), root.join("copy/IMG_2.xmp")]);
+        let mut deletion = Deletion::default();
+        deletion.delete(&doomed.iter().collect::<Vec<_>>(), &mut Vec::new()).unwrap();
+        assert_eq!((deletion.deleted, deletion.freed), (2, 6));
+        assert!(!root.join("copy/IMG_2.xmp").exists());
+        assert!(root.join("old/IMG_2.CR2").exists() && root.join("old/IMG_2.xmp").exists());
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{DuplicateGroup, ScanResult};
+
+/// A duplicate group whose set of copies differs between two scans
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangedGroup {
+    /// Stable ID shared by both versions of the group
+    pub id: String,
+
+    /// Content hash shared by both versions of the group
+    pub hash: String,
+
+    /// Size of each file in the group
+    pub size: u64,
+
+    /// Copies present only in the newer scan
+    pub added: Vec<PathBuf>,
+
+    /// Copies present only in the older scan
+    pub removed: Vec<PathBuf>,
+
+    /// Change in wasted bytes for this group (positive means growth)
+    pub wasted_space_delta: i64,
+}
+
+/// Differences between two scan results
+///
+/// Groups are matched by their stable ID (see [`DuplicateGroup::id`]), so a
+/// group whose copies moved around is reported as changed rather than as
+/// resolved plus new
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ScanDiff {
+    /// Groups that only exist in the newer scan
+    pub new_groups: Vec<DuplicateGroup>,
+
+    /// Groups from the older scan that no longer have duplicates
+    pub resolved_groups: Vec<DuplicateGroup>,
+
+    /// Groups present in both scans with a different set of copies
+    pub changed_groups: Vec<ChangedGroup>,
+
+    /// Total wasted bytes in the older scan
+    pub old_wasted_space: u64,
+
+    /// Total wasted bytes in the newer scan
+    pub new_wasted_space: u64,
+}
+
+impl ScanDiff {
+    /// Change in total wasted bytes (positive means more waste)
+    pub fn wasted_space_delta(&self) -> i64 {
+        self.new_wasted_space as i64 - self.old_wasted_space as i64
+    }
+
+    /// Returns true when both scans found exactly the same duplicates
+    pub fn is_unchanged(&self) -> bool {
+        self.new_groups.is_empty() && self.resolved_groups.is_empty() && self.changed_groups.is_empty()
+    }
+}
+
+/// Compares two scan results
+///
+/// Every list in the returned diff is sorted by wasted space (largest first)
+pub fn diff(old: &ScanResult, new: &ScanResult) -> ScanDiff {
+    let old_groups: HashMap<&str, &DuplicateGroup> = old
+        .duplicate_groups
+        .iter()
+        .map(|g| (g.id.as_str(), g))
+        .collect();
+    let new_groups: HashMap<&str, &DuplicateGroup> = new
+        .duplicate_groups
+        .iter()
+        .map(|g| (g.id.as_str(), g))
+        .collect();
+
+    let mut result = ScanDiff {
+        old_wasted_space: old.total_wasted_space,
+        new_wasted_space: new.total_wasted_space,
+        ..Default::default()
+    };
+
+    for group in &new.duplicate_groups {
+        match old_groups.get(group.id.as_str()) {
+            None => result.new_groups.push(group.clone()),
+            Some(previous) => {
+                if let Some(changed) = compare_group(previous, group) {
+                    result.changed_groups.push(changed);
+                }
+            }
+        }
+    }
+
+    for group in &old.duplicate_groups {
+        if !new_groups.contains_key(group.id.as_str()) {
+            result.resolved_groups.push(group.clone());
+        }
+    }
+
+    result.new_groups.sort_by_key(|g| std::cmp::Reverse(g.wasted_space));
+    result.resolved_groups.sort_by_key(|g| std::cmp::Reverse(g.wasted_space));
+    result.changed_groups.sort_by_key(|g| std::cmp::Reverse(g.wasted_space_delta.unsigned_abs()));
+    result
+}
+
+/// Describes how the copies of one group changed, if they did
+fn compare_group(old: &DuplicateGroup, new: &DuplicateGroup) -> Option<ChangedGroup> {
+    let added: Vec<PathBuf> = new
+        .files
+        .iter()
+        .filter(|f| !old.files.iter().any(|o| o.path == f.path))
+        .map(|f| f.path.clone())
+        .collect();
+    let removed: Vec<PathBuf> = old
+        .files
+        .iter()
+        .filter(|f| !new.files.iter().any(|n| n.path == f.path))
+        .map(|f| f.path.clone())
+        .collect();
+
+    if added.is_empty() && removed.is_empty() {
+        return None;
+    }
+
+    Some(ChangedGroup {
+        id: new.id.clone(),
+        hash: new.hash.clone(),
+        size: new.size,
+        added,
+        removed,
+        wasted_space_delta: new.wasted_space as i64 - old.wasted_space as i64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FileInfo;
+
+    fn group(hash: &str, paths: &[&str]) -> DuplicateGroup {
+        let files: Vec<FileInfo> = paths
+            .iter()
+            .map(|path| FileInfo {
+                path: PathBuf::from(path),
+                size: 10,
+                hash: hash.to_string(),
+                modified: std::time::UNIX_EPOCH,
+                created: None,
+                compression: None,
+            })
+            .collect();
+        DuplicateGroup {
+            id: format!("id-{}", hash),
+            hash: hash.to_string(),
+            size: 10,
+            total_size: 10 * files.len() as u64,
+            wasted_space: 10 * (files.len() as u64 - 1),
+            files,
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = ScanResult {
+            duplicate_groups: vec![group("kept", &["/a", "/b"]), group("gone", &["/c", "/d"])],
+            total_wasted_space: 20,
+            ..Default::default()
+        };
+        let new = ScanResult {
+            duplicate_groups: vec![group("kept", &["/a", "/b", "/e"]), group("fresh", &["/f", "/g"])],
+            total_wasted_space: 30,
+            ..Default::default()
+        };
+
+        let result = diff(&old, &new);
+        assert_eq!(result.new_groups[0].hash, "fresh");
+        assert_eq!(result.resolved_groups[0].hash, "gone");
+        assert_eq!(result.changed_groups[0].id, "id-kept");
+        assert_eq!(result.changed_groups[0].added, vec![PathBuf::from("/e")]);
+        assert_eq!(result.changed_groups[0].wasted_space_delta, 10);
+        assert_eq!(result.wasted_space_delta(), 10);
+        assert!(!result.is_unchanged());
+        assert!(diff(&old, &old).is_unchanged());
+    }
+}
@@ -0,0 +1,385 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use sha2::{Digest, Sha256};
+
+use crate::{DirectoryGroup, DirectoryOverlap, DuplicateGroup, FileInfo, GroupScope, ScannerConfig};
+
+/// Contents shared by more directories than this are ignored for overlaps
+///
+/// Boilerplate such as license files or `.gitkeep` copies can live in
+/// thousands of directories; pairing all of them would be quadratic while
+/// telling nothing about which trees are copies of each other
+const MAX_OVERLAP_FANOUT: usize = 256;
+
+/// Contents of one directory while digests are computed bottom-up
+#[derive(Default)]
+struct DirNode {
+    /// One line per child: kind, name and content hash (or child digest)
+    entries: Vec<String>,
+
+    /// Bytes of every file below this directory
+    size: u64,
+
+    /// Number of files below this directory
+    file_count: usize,
+
+    /// Most recent modification of any file below this directory
+    newest: Option<SystemTime>,
+}
+
+/// Runs the directory analyses enabled in the configuration
+///
+/// Returns identical directory trees (`--duplicate-dirs`) and overlapping
+/// directories (`--dir-overlap`); disabled analyses yield empty lists
+///
+/// With `--group-scope per-root` directories are only compared with
+/// directories of the same root
+pub(crate) fn analyze(files: &[FileInfo], config: &ScannerConfig) -> (Vec<DirectoryGroup>, Vec<DirectoryOverlap>) {
+    let roots: Vec<&Path> = config.roots().collect();
+    let scopes: Vec<&[&Path]> = match config.group_scope {
+        GroupScope::Combined => vec![&roots],
+        GroupScope::PerRoot => roots.chunks(1).collect(),
+    };
+
+    let mut groups = Vec::new();
+    let mut overlaps = Vec::new();
+    for scope in scopes {
+        if config.duplicate_dirs {
+            groups.extend(find_duplicate_directories(files, scope));
+        }
+        if let Some(min_percent) = config.dir_overlap {
+            overlaps.extend(find_overlapping_directories(files, scope, min_percent));
+        }
+    }
+    (groups, overlaps)
+}
+
+/// Returns true when a directory lies strictly inside one of the roots
+fn inside_roots(dir: &Path, roots: &[&Path]) -> bool {
+    !roots.contains(&dir) && roots.iter().any(|root| dir.starts_with(root))
+}
+
+/// A directory whose digest is known
+#[derive(Clone)]
+struct Tree {
+    path: PathBuf,
+    size: u64,
+    file_count: usize,
+    newest: Option<SystemTime>,
+}
+
+/// Finds directory trees below the `roots` whose contents are identical
+///
+/// Every directory gets a composite digest built from the names and hashes
+/// of its files and the digests of its subdirectories, so two directories
+/// match only when their whole trees match. Only hashed files take part:
+/// files excluded by filters (hidden, size limits) are invisible here.
+///
+/// Nested matches are collapsed: when `/a` and `/b` are identical, their
+/// identical subdirectories are not reported again. Within a group the
+/// directory whose newest file is oldest comes first and is treated as the
+/// original. Groups are sorted by wasted space (descending)
+pub fn find_duplicate_directories(files: &[FileInfo], roots: &[&Path]) -> Vec<DirectoryGroup> {
+    let mut nodes: BTreeMap<PathBuf, DirNode> = BTreeMap::new();
+
+    for file in files {
+        let Some(parent) = file.path.parent() else {
+            continue;
+        };
+        if !inside_roots(parent, roots) {
+            continue;
+        }
+
+        let node = nodes.entry(parent.to_path_buf()).or_default();
+        node.entries.push(format!("F\0{}\0{}", file_name(&file.path), file.hash));
+        node.size += file.size;
+        node.file_count += 1;
+        node.newest = node.newest.max(Some(file.modified));
+
+        // Make sure every ancestor up to the root exists, even without files
+        for ancestor in parent.ancestors().skip(1) {
+            if !inside_roots(ancestor, roots) {
+                break;
+            }
+            nodes.entry(ancestor.to_path_buf()).or_default();
+        }
+    }
+
+    // Children are finished before their parents when walking deepest first
+    let mut paths: Vec<PathBuf> = nodes.keys().cloned().collect();
+    paths.sort_by_key(|p| std::cmp::Reverse(p.components().count()));
+
+    let mut by_digest: HashMap<String, Vec<Tree>> = HashMap::new();
+    for path in paths {
+        let mut node = nodes.remove(&path).unwrap_or_default();
+        node.entries.sort();
+
+        let mut hasher = Sha256::new();
+        for entry in &node.entries {
+            hasher.update(entry.as_bytes());
+            hasher.update(b"\n");
+        }
+        let digest = format!("{:x}", hasher.finalize());
+
+        if let Some(parent) = path.parent()
+            && let Some(parent_node) = nodes.get_mut(parent)
+        {
+            parent_node.entries.push(format!("D\0{}\0{}", file_name(&path), digest));
+            parent_node.size += node.size;
+            parent_node.file_count += node.file_count;
+            parent_node.newest = parent_node.newest.max(node.newest);
+        }
+
+        by_digest
+            .entry(digest)
+            .or_default()
+            .push(Tree {
+                path,
+                size: node.size,
+                file_count: node.file_count,
+                newest: node.newest,
+            });
+    }
+
+    let duplicated: Vec<(String, Vec<Tree>)> = by_digest
+        .into_iter()
+        .filter(|(_, dirs)| dirs.len() > 1)
+        .collect();
+
+    // A match is redundant when all of its directories sit inside other matches
+    let grouped: HashSet<&Path> = duplicated
+        .iter()
+        .flat_map(|(_, trees)| trees.iter().map(|t| t.path.as_path()))
+        .collect();
+
+    let mut groups: Vec<DirectoryGroup> = duplicated
+        .iter()
+        .filter(|(_, trees)| {
+            !trees.iter().all(|t| t.path.parent().is_some_and(|p| grouped.contains(p)))
+        })
+        .map(|(digest, trees)| {
+            let mut trees = trees.clone();
+            trees.sort_by(|a, b| a.newest.cmp(&b.newest).then_with(|| a.path.cmp(&b.path)));
+
+            DirectoryGroup {
+                digest: digest.clone(),
+                size: trees[0].size,
+                file_count: trees[0].file_count,
+                wasted_space: trees[0].size * (trees.len() as u64 - 1),
+                directories: trees.into_iter().map(|t| t.path).collect(),
+            }
+        })
+        .collect();
+
+    groups.sort_by(|a, b| {
+        b.wasted_space
+            .cmp(&a.wasted_space)
+            .then_with(|| a.directories.cmp(&b.directories))
+    });
+    groups
+}
+
+/// Finds directories whose contents are largely contained in another directory
+///
+/// Each directory is treated as the set of distinct (non-empty) contents
+/// anywhere below it. A pair is reported when at least `min_percent` of a
+/// directory's contents also exist below the other one, ignoring file
+/// names and layout, e.g. "Backup2021 is 94% contained in Archive/2021".
+///
+/// Directories with fewer than two distinct contents are skipped, as are
+/// pairs where one directory lies inside the other and directories whose
+/// contents all live in a single subdirectory. When two directories
+/// overlap, the same overlap between their parents hides the pair so only
+/// the outermost matching trees are listed. Identical trees are reported
+/// once. Results are sorted by percentage, then shared bytes (descending)
+pub fn find_overlapping_directories(files: &[FileInfo], roots: &[&Path], min_percent: u8) -> Vec<DirectoryOverlap> {
+    let mut contents: BTreeMap<&Path, HashSet<&str>> = BTreeMap::new();
+    let mut sizes: HashMap<&str, u64> = HashMap::new();
+
+    for file in files.iter().filter(|f| f.size > 0) {
+        sizes.insert(&file.hash, file.size);
+        for dir in file.path.ancestors().skip(1) {
+            if !inside_roots(dir, roots) {
+                break;
+            }
+            contents.entry(dir).or_default().insert(&file.hash);
+        }
+    }
+
+    // A directory holding nothing but one subdirectory would only repeat its child's pairs
+    let wrappers: HashSet<&Path> = contents
+        .iter()
+        .filter_map(|(dir, hashes)| {
+            let parent = dir.parent()?;
+            (contents.get(parent)?.len() == hashes.len()).then_some(parent)
+        })
+        .collect();
+
+    let mut owners: HashMap<&str, Vec<&Path>> = HashMap::new();
+    for (dir, hashes) in contents.iter().filter(|(dir, _)| !wrappers.contains(*dir)) {
+        for hash in hashes {
+            owners.entry(hash).or_default().push(dir);
+        }
+    }
+
+    // Shared content count and bytes for every ordered pair of unrelated directories
+    let mut shared: HashMap<(&Path, &Path), (usize, u64)> = HashMap::new();
+    for (hash, dirs) in &owners {
+        if dirs.len() > MAX_OVERLAP_FANOUT {
+            continue;
+        }
+        for &a in dirs {
+            for &b in dirs {
+                if a.starts_with(b) || b.starts_with(a) {
+                    continue;
+                }
+                let entry = shared.entry((a, b)).or_default();
+                entry.0 += 1;
+                entry.1 += sizes[hash];
+            }
+        }
+    }
+
+    let candidates: HashMap<(&Path, &Path), (usize, u64)> = shared
+        .into_iter()
+        .filter(|((a, b), (count, _))| {
+            let total = contents[a].len();
+            let identical = *count == total && contents[b].len() == total;
+            total >= 2 && count * 100 >= min_percent as usize * total && !(identical && a > b)
+        })
+        .collect();
+
+    let mut overlaps: Vec<DirectoryOverlap> = candidates
+        .iter()
+        .filter(|((a, b), _)| match (a.parent(), b.parent()) {
+            (Some(pa), Some(pb)) => !candidates.contains_key(&(pa, pb)) && !candidates.contains_key(&(pb, pa)),
+            _ => true,
+        })
+        .map(|((a, b), (count, bytes))| DirectoryOverlap {
+            directory: a.to_path_buf(),
+            container: b.to_path_buf(),
+            shared_files: *count,
+            total_files: contents[a].len(),
+            shared_bytes: *bytes,
+        })
+        .collect();
+
+    overlaps.sort_by(|a, b| {
+        (b.shared_files * a.total_files)
+            .cmp(&(a.shared_files * b.total_files))
+            .then_with(|| b.shared_bytes.cmp(&a.shared_bytes))
+            .then_with(|| a.directory.cmp(&b.directory))
+    });
+    overlaps
+}
+
+/// Checks whether a file group is fully explained by one duplicate directory group
+///
+/// True when every file of the group lies inside a directory of the same
+/// directory group; such file groups add nothing once the directories are
+/// reported
+pub fn is_covered(group: &DuplicateGroup, directory_groups: &[DirectoryGroup]) -> bool {
+    directory_groups.iter().any(|dirs| {
+        group
+            .files
+            .iter()
+            .all(|f| dirs.directories.iter().any(|d| f.path.starts_with(d)))
+    })
+}
+
+/// Final path component as text
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, hash: &str) -> FileInfo {
+        FileInfo {
+            path: PathBuf::from(path),
+            size: 10,
+            hash: hash.to_string(),
+            modified: std::time::UNIX_EPOCH,
+            created: None,
+            compression: None,
+        }
+    }
+
+    #[test]
+    fn test_find_duplicate_directories() {
+        let files = [
+            file("/root/a/1.txt", "x"),
+            file("/root/a/sub/2.txt", "y"),
+            file("/root/b/1.txt", "x"),
+            file("/root/b/sub/2.txt", "y"),
+            // Same content under a different name is not the same tree
+            file("/root/c/renamed.txt", "x"),
+            file("/root/c/sub/2.txt", "y"),
+            file("/root/top.txt", "x"),
+        ];
+
+        let groups = find_duplicate_directories(&files, &[Path::new("/root")]);
+
+        // a/sub, b/sub and c/sub match too, but a/sub and b/sub are covered by a and b
+        assert_eq!(groups.len(), 2);
+        let trees = groups.iter().find(|g| g.directories.len() == 2).unwrap();
+        assert_eq!(trees.directories, [PathBuf::from("/root/a"), PathBuf::from("/root/b")]);
+        assert_eq!(trees.file_count, 2);
+        assert_eq!(trees.wasted_space, 20);
+
+        let subs = groups.iter().find(|g| g.directories.len() == 3).unwrap();
+        assert_eq!(subs.size, 10);
+
+        let group = DuplicateGroup {
+            id: String::new(),
+            hash: "y".to_string(),
+            size: 10,
+            files: vec![file("/root/a/sub/2.txt", "y"), file("/root/b/sub/2.txt", "y")],
+            total_size: 20,
+            wasted_space: 10,
+        };
+        assert!(is_covered(&group, &groups));
+
+        let group = DuplicateGroup {
+            id: String::new(),
+            files: vec![file("/root/a/1.txt", "x"), file("/root/top.txt", "x")],
+            ..group
+        };
+        assert!(!is_covered(&group, &groups));
+    }
+
+    #[test]
+    fn test_find_overlapping_directories() {
+        let files = [
+            file("/root/backup/1.txt", "a"),
+            file("/root/backup/2.txt", "b"),
+            file("/root/backup/nested/3.txt", "c"),
+            file("/root/backup/4.txt", "d"),
+            // The archive holds three of the four contents, under other names
+            file("/root/archive/2021/one.txt", "a"),
+            file("/root/archive/2021/two.txt", "b"),
+            file("/root/archive/2021/deep/three.txt", "c"),
+            file("/root/archive/2021/other.txt", "e"),
+        ];
+
+        let overlaps = find_overlapping_directories(&files, &[Path::new("/root")], 70);
+        let pairs: Vec<(&Path, &Path, f64)> = overlaps
+            .iter()
+            .map(|o| (o.directory.as_path(), o.container.as_path(), o.percent()))
+            .collect();
+
+        // 75% each way; the archive itself also reaches 75% against the backup
+        assert!(pairs.contains(&(Path::new("/root/backup"), Path::new("/root/archive/2021"), 75.0)));
+        assert!(pairs.contains(&(Path::new("/root/archive/2021"), Path::new("/root/backup"), 75.0)));
+        assert!(pairs.iter().all(|(a, _, _)| *a != Path::new("/root/archive")));
+        assert_eq!(overlaps[0].shared_bytes, 30);
+
+        assert!(find_overlapping_directories(&files, &[Path::new("/root")], 80).is_empty());
+    }
+}
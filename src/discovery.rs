@@ -0,0 +1,261 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::SystemTime;
+
+use futures_util::{Stream, StreamExt};
+use futures_util::stream;
+use tokio::sync::mpsc;
+
+use crate::pause::PauseSwitch;
+use crate::source::{ListOptions, LocalSource, SourceEntry};
+use crate::{Mode, ScannerConfig, audio, documents, similarity, video};
+
+/// Directories whose files may wait for the consumer of a discovery stream
+const PENDING_DIRECTORIES: usize = 64;
+
+/// A regular file found by a [`FileDiscovery`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredFile {
+    /// Path of the file, below one of the roots
+    pub path: PathBuf,
+    pub metadata: FileMetadata,
+}
+
+/// Metadata read while discovering a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileMetadata {
+    /// Size in bytes
+    pub size: u64,
+    /// Last modification time
+    pub modified: SystemTime,
+    /// Creation time, where the filesystem records it
+    pub created: Option<SystemTime>,
+}
+
+impl From<SourceEntry> for DiscoveredFile {
+    fn from(entry: SourceEntry) -> Self {
+        Self {
+            path: entry.path,
+            metadata: FileMetadata { size: entry.size, modified: entry.modified, created: entry.created },
+        }
+    }
+}
+
+/// Size and comparison-mode filters applied to discovered files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct FileFilter {
+    pub min_size: u64,
+    /// 0 for no limit
+    pub max_size: u64,
+    pub exclude_empty: bool,
+    pub mode: Mode,
+}
+
+impl FileFilter {
+    pub(crate) fn from_config(config: &ScannerConfig) -> Self {
+        Self { min_size: config.min_size, max_size: config.max_size, exclude_empty: config.exclude_empty, mode: config.mode }
+    }
+
+    /// Checks if a file passes the size filters
+    pub(crate) fn passes_size(&self, size: u64) -> bool {
+        // Check empty files exclusion
+        if self.exclude_empty && size == 0 {
+            return false;
+        }
+
+        // Check minimum size
+        if size < self.min_size {
+            return false;
+        }
+
+        // Check maximum size (0 means no limit)
+        if self.max_size > 0 && size > self.max_size {
+            return false;
+        }
+
+        true
+    }
+
+    /// Returns true when the comparison mode can compare a file, judged by
+    /// its extension
+    pub(crate) fn mode_accepts(&self, path: &Path) -> bool {
+        match self.mode {
+            Mode::Exact | Mode::TextSimilar | Mode::Name | Mode::NameConflict | Mode::Quick | Mode::Metadata => true,
+            Mode::ImageSimilar | Mode::ImagePixels => similarity::is_image(path),
+            Mode::Audio => audio::is_audio(path),
+            Mode::Video => video::is_video(path),
+            Mode::Document => documents::is_document(path),
+        }
+    }
+}
+
+/// Walks local directory trees the way a scan does, without hashing
+///
+/// Applies the hidden-file, symlink and depth rules of discovery and the
+/// size and comparison-mode filters, and yields every file that passes as
+/// soon as its directory is listed. Files below a nested root are yielded
+/// once, by the walk of the deepest root holding them
+///
+/// ```rust,no_run
+/// use duplicate_finder::FileDiscovery;
+/// use futures_util::StreamExt;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let discovery = FileDiscovery::new("/srv/photos").with_size_range(1024, 0);
+/// let mut files = std::pin::pin!(discovery.stream());
+/// while let Some(file) = files.next().await {
+///     println!("{} ({} bytes)", file.path.display(), file.metadata.size);
+/// }
+/// println!("{} unreadable entries", discovery.errors());
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct FileDiscovery {
+    roots: Vec<PathBuf>,
+    options: ListOptions,
+    filter: FileFilter,
+    pause: PauseSwitch,
+    errors: Arc<AtomicUsize>,
+}
+
+impl FileDiscovery {
+    /// Walks the tree below `root` with the default rules of a scan:
+    /// hidden files and symbolic links skipped, no depth or size limit
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        let config = ScannerConfig::default();
+        Self { roots: vec![root.into()], ..Self::from_config(&config) }
+    }
+
+    /// Walks every root of a scan configuration with its discovery rules
+    /// and filters
+    pub fn from_config(config: &ScannerConfig) -> Self {
+        Self {
+            roots: config.roots().map(Path::to_path_buf).collect(),
+            options: ListOptions::from_config(config),
+            filter: FileFilter::from_config(config),
+            pause: PauseSwitch::default(),
+            errors: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Walks another tree as well
+    pub fn with_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.roots.push(root.into());
+        self
+    }
+
+    /// Replaces the hidden-file, symlink, depth and concurrency rules
+    pub fn with_options(mut self, options: ListOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Yields files of `min` to `max` bytes (0 for no upper limit)
+    pub fn with_size_range(mut self, min: u64, max: u64) -> Self {
+        self.filter.min_size = min;
+        self.filter.max_size = max;
+        self
+    }
+
+    /// Skips empty files
+    pub fn exclude_empty(mut self, exclude: bool) -> Self {
+        self.filter.exclude_empty = exclude;
+        self
+    }
+
+    /// Yields only the files `mode` can compare, e.g. images for
+    /// [`Mode::ImageSimilar`]
+    pub fn with_mode(mut self, mode: Mode) -> Self {
+        self.filter.mode = mode;
+        self
+    }
+
+    /// Lists no further directories while `pause` is paused
+    pub fn with_pause(mut self, pause: PauseSwitch) -> Self {
+        self.pause = pause;
+        self
+    }
+
+    /// Number of directories and entries that could not be read by the
+    /// streams of this discovery so far
+    pub fn errors(&self) -> usize {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    /// Walks the roots one after another on a background task
+    ///
+    /// Must be called from within a tokio runtime. The walk waits while
+    /// the consumer lags behind, and stops when the stream is dropped
+    pub fn stream(&self) -> impl Stream<Item = DiscoveredFile> + Send + 'static {
+        let (sender, receiver) = mpsc::channel(PENDING_DIRECTORIES);
+        let discovery = self.clone();
+        tokio::spawn(async move {
+            for root in &discovery.roots {
+                let source = LocalSource::new(root.clone()).with_pause(discovery.pause.clone());
+                let errors = source.walk_each(0, &discovery.options, |entries| {
+                    let files: Vec<DiscoveredFile> = entries.into_iter()
+                        .filter(|entry| discovery.accepts(root, entry))
+                        .map(DiscoveredFile::from)
+                        .collect();
+                    let sender = sender.clone();
+                    // The receiver is only gone when the stream was dropped
+                    async move { files.is_empty() || sender.send(files).await.is_ok() }
+                }).await;
+                discovery.errors.fetch_add(errors, Ordering::Relaxed);
+                if sender.is_closed() {
+                    break;
+                }
+            }
+        });
+
+        stream::unfold(receiver, |mut receiver| async move {
+            receiver.recv().await.map(|files| (stream::iter(files), receiver))
+        })
+        .flatten()
+    }
+
+    /// Returns true when a file found below `root` passes the filters and
+    /// is not left to the walk of a nested root
+    fn accepts(&self, root: &Path, entry: &SourceEntry) -> bool {
+        let deepest = self.roots.iter()
+            .filter(|other| entry.path.starts_with(other))
+            .max_by_key(|other| other.components().count());
+        deepest.is_none_or(|deepest| deepest == root)
+            && self.filter.passes_size(entry.size)
+            && self.filter.mode_accepts(&entry.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_file_discovery() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let nested = temp_dir.path().join("nested");
+        std::fs::create_dir_all(nested.join("deeper")).unwrap();
+        std::fs::write(temp_dir.path().join("a.jpg"), "image").unwrap();
+        std::fs::write(temp_dir.path().join("b.txt"), "text").unwrap();
+        std::fs::write(temp_dir.path().join("empty.jpg"), "").unwrap();
+        std::fs::write(temp_dir.path().join(".hidden.jpg"), "image").unwrap();
+        std::fs::write(nested.join("c.jpg"), "image").unwrap();
+        std::fs::write(nested.join("deeper/d.jpg"), "image").unwrap();
+
+        let discovery = FileDiscovery::new(temp_dir.path())
+            .with_root(&nested)
+            .with_mode(Mode::ImageSimilar)
+            .exclude_empty(true);
+        let mut found: Vec<PathBuf> = discovery.stream().map(|file| file.path).collect().await;
+        found.sort();
+        assert_eq!(found, [temp_dir.path().join("a.jpg"), nested.join("c.jpg"), nested.join("deeper/d.jpg")]);
+        assert_eq!(discovery.errors(), 0);
+
+        let options = ListOptions { max_depth: 1, ..ListOptions::default() };
+        let shallow: Vec<DiscoveredFile> = FileDiscovery::new(&nested).with_options(options).stream().collect().await;
+        assert_eq!(shallow.len(), 1);
+        assert_eq!(shallow[0].metadata.size, 5);
+    }
+}
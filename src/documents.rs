@@ -0,0 +1,293 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use lopdf::{Dictionary, Document, Object};
+use sha2::{Digest, Sha256};
+use tracing::debug;
+
+use crate::{DuplicateFinderError, FileInfo};
+
+/// Extensions of document formats whose content can be extracted
+pub const DOCUMENT_EXTENSIONS: &[&str] = &["docx", "odp", "ods", "odt", "pdf", "pptx", "xlsx"];
+
+/// Zip entries holding document properties (author, timestamps, revision
+/// counters, editing time) rather than content
+const PROPERTY_ENTRIES: &[&str] = &["docProps/", "meta.xml"];
+
+/// XML attributes regenerated on every save: Word revision session IDs,
+/// Excel object and document GUIDs, paragraph IDs
+const VOLATILE_ATTRIBUTES: &[&str] = &["w:rsid", "w14:paraId", "w14:textId", "xr:uid", "xr2:uid", "xr3:uid", "xr10:uid", "xr16:uid", "documentId"];
+
+/// XML elements that only record revision sessions
+const VOLATILE_ELEMENTS: &[&str] = &["w:rsid", "w:rsidRoot", "xr:revisionPtr"];
+
+/// Stream dictionary entries describing the encoding rather than the content
+const ENCODING_KEYS: &[&[u8]] = &[b"Length", b"Filter", b"DecodeParms"];
+
+/// Returns true when the path looks like a supported document
+pub fn is_document(path: &Path) -> bool {
+    extension(path).is_some_and(|ext| DOCUMENT_EXTENSIONS.contains(&ext.as_str()))
+}
+
+/// Reads a document's metadata and a SHA-256 digest of its content only
+///
+/// For PDFs the document information dictionary, the XMP metadata stream
+/// and the trailer `/ID` are skipped and streams are hashed decompressed.
+/// For Office Open XML and OpenDocument files the zip container (entry
+/// order, timestamps, compression) is ignored along with the document
+/// properties and revision IDs. Exporting the same document twice thus
+/// yields the same digest
+pub async fn document_file_info(path: &Path) -> Result<FileInfo, DuplicateFinderError> {
+    let mut file_info = FileInfo::stat(path).await?;
+
+    let owned = path.to_path_buf();
+    file_info.hash = tokio::task::spawn_blocking(move || document_digest(&owned))
+        .await
+        .map_err(|e| DuplicateFinderError::Io(std::io::Error::other(e)))?
+        .inspect_err(|e| debug!("Cannot extract document content from {}: {}", path.display(), e))
+        .map_err(|_| DuplicateFinderError::HashCalculationError {
+            path: path.to_path_buf(),
+        })?;
+
+    Ok(file_info)
+}
+
+/// Hashes the content of a document, dispatching on its format
+fn document_digest(path: &Path) -> anyhow::Result<String> {
+    let mut hasher = Sha256::new();
+    match extension(path).as_deref() {
+        Some("pdf") => pdf_content(path, &mut hasher)?,
+        _ => zip_content(path, &mut hasher)?,
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Feeds every PDF object except metadata into the hasher
+fn pdf_content(path: &Path, hasher: &mut Sha256) -> anyhow::Result<()> {
+    let document = Document::load(path)?;
+    let info = document.trailer.get(b"Info").and_then(Object::as_reference).ok();
+    let metadata = document.catalog().and_then(|c| c.get(b"Metadata")).and_then(Object::as_reference).ok();
+
+    for (&id, object) in &document.objects {
+        if Some(id) == info || Some(id) == metadata {
+            continue;
+        }
+        // Cross-reference and object streams only describe the file layout
+        if let Object::Stream(stream) = object
+            && (stream.dict.has_type(b"XRef") || stream.dict.has_type(b"ObjStm"))
+        {
+            continue;
+        }
+
+        hasher.update(id.0.to_le_bytes());
+        hasher.update(id.1.to_le_bytes());
+        hash_object(hasher, object);
+    }
+    Ok(())
+}
+
+/// Feeds a PDF object into the hasher in a layout-independent encoding
+fn hash_object(hasher: &mut Sha256, object: &Object) {
+    match object {
+        Object::Null => hasher.update(b"n"),
+        Object::Boolean(value) => hasher.update(if *value { b"t" } else { b"f" }),
+        Object::Integer(value) => {
+            hasher.update(b"i");
+            hasher.update(value.to_le_bytes());
+        }
+        Object::Real(value) => {
+            hasher.update(b"r");
+            hasher.update(value.to_le_bytes());
+        }
+        Object::Name(name) => hash_bytes(hasher, b"/", name),
+        Object::String(text, _) => hash_bytes(hasher, b"s", text),
+        Object::Array(items) => {
+            hasher.update(b"[");
+            items.iter().for_each(|item| hash_object(hasher, item));
+            hasher.update(b"]");
+        }
+        Object::Dictionary(dict) => hash_dictionary(hasher, dict, &[]),
+        Object::Stream(stream) => match stream.decompressed_content() {
+            Ok(content) => {
+                hash_dictionary(hasher, &stream.dict, ENCODING_KEYS);
+                hash_bytes(hasher, b"S", &content);
+            }
+            Err(_) => {
+                hash_dictionary(hasher, &stream.dict, &[b"Length"]);
+                hash_bytes(hasher, b"S", &stream.content);
+            }
+        },
+        Object::Reference((number, generation)) => {
+            hasher.update(b"R");
+            hasher.update(number.to_le_bytes());
+            hasher.update(generation.to_le_bytes());
+        }
+    }
+}
+
+/// Feeds a dictionary into the hasher with its keys sorted, leaving out `skip`
+fn hash_dictionary(hasher: &mut Sha256, dict: &Dictionary, skip: &[&[u8]]) {
+    let mut entries: Vec<(&Vec<u8>, &Object)> = dict.iter().filter(|(key, _)| !skip.contains(&key.as_slice())).collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+
+    hasher.update(b"<<");
+    for (key, value) in entries {
+        hash_bytes(hasher, b"/", key);
+        hash_object(hasher, value);
+    }
+    hasher.update(b">>");
+}
+
+/// Feeds a length-prefixed byte string into the hasher
+fn hash_bytes(hasher: &mut Sha256, tag: &[u8], bytes: &[u8]) {
+    hasher.update(tag);
+    hasher.update((bytes.len() as u64).to_le_bytes());
+    hasher.update(bytes);
+}
+
+/// Feeds the content entries of a zip-based document into the hasher
+///
+/// Entries are hashed by name in sorted order from their decompressed data
+fn zip_content(path: &Path, hasher: &mut Sha256) -> anyhow::Result<()> {
+    let mut archive = zip::ZipArchive::new(BufReader::new(File::open(path)?))?;
+
+    let mut names: Vec<String> = archive.file_names()
+        .filter(|name| !name.ends_with('/') && !PROPERTY_ENTRIES.iter().any(|p| name.starts_with(p)))
+        .map(str::to_string)
+        .collect();
+    names.sort();
+
+    for name in names {
+        let mut data = Vec::new();
+        archive.by_name(&name)?.read_to_end(&mut data)?;
+        if name.ends_with(".xml") || name.ends_with(".rels") {
+            data = strip_volatile_xml(&data);
+        }
+        hash_bytes(hasher, name.as_bytes(), &data);
+    }
+    Ok(())
+}
+
+/// Removes revision IDs and GUIDs that change on every save from an XML part
+///
+/// A lightweight byte-level pass: attributes listed in
+/// [`VOLATILE_ATTRIBUTES`] (matched by name prefix) are dropped from every
+/// tag and self-closing elements listed in [`VOLATILE_ELEMENTS`] are
+/// removed entirely
+fn strip_volatile_xml(xml: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(xml.len());
+    let mut position = 0;
+
+    while position < xml.len() {
+        let Some(offset) = xml[position..].iter().position(|&b| b == b'<') else {
+            out.extend_from_slice(&xml[position..]);
+            break;
+        };
+        out.extend_from_slice(&xml[position..position + offset]);
+        let start = position + offset;
+        let end = match xml[start..].iter().position(|&b| b == b'>') {
+            Some(length) => start + length + 1,
+            None => xml.len(),
+        };
+        let tag = &xml[start..end];
+
+        let name_end = tag.iter().position(|b| b.is_ascii_whitespace() || *b == b'/' || *b == b'>').unwrap_or(tag.len());
+        let name = &tag[1..name_end];
+        if !(tag.ends_with(b"/>") && VOLATILE_ELEMENTS.iter().any(|e| name == e.as_bytes())) {
+            out.extend_from_slice(&strip_attributes(tag));
+        }
+        position = end;
+    }
+    out
+}
+
+/// Drops volatile attributes from a single XML tag
+fn strip_attributes(tag: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(tag.len());
+    let mut position = 0;
+
+    while position < tag.len() {
+        let byte = tag[position];
+        if byte.is_ascii_whitespace() {
+            let name_start = position + 1;
+            let rest = &tag[name_start..];
+            if VOLATILE_ATTRIBUTES.iter().any(|a| rest.starts_with(a.as_bytes()))
+                && let Some(quote) = rest.iter().position(|&b| b == b'"' || b == b'\'')
+                && rest[..quote].ends_with(b"=")
+                && let Some(close) = rest[quote + 1..].iter().position(|&b| b == rest[quote])
+            {
+                position = name_start + quote + 1 + close + 1;
+                continue;
+            }
+        }
+        out.push(byte);
+        position += 1;
+    }
+    out
+}
+
+/// Lowercased file extension
+fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use lopdf::{Stream, dictionary};
+    use tempfile::tempdir;
+    use zip::write::SimpleFileOptions;
+
+    fn docx(path: &Path, entries: &[(&str, &str)]) {
+        let mut writer = zip::ZipWriter::new(File::create(path).unwrap());
+        for (name, content) in entries {
+            writer.start_file(*name, SimpleFileOptions::default()).unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    fn pdf(path: &Path, text: &str, created: &str) {
+        let mut document = Document::with_version("1.7");
+        let content = document.add_object(Stream::new(dictionary! {}, text.as_bytes().to_vec()));
+        let catalog = document.add_object(dictionary! { "Type" => "Catalog", "Content" => content });
+        let info = document.add_object(dictionary! { "CreationDate" => Object::string_literal(created) });
+        document.trailer.set("Root", catalog);
+        document.trailer.set("Info", info);
+        document.trailer.set("ID", vec![Object::string_literal(created), Object::string_literal(created)]);
+        document.save(path).unwrap();
+    }
+
+    #[test]
+    fn test_document_digest_ignores_metadata() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let path = |name: &str| temp_dir.path().join(name);
+
+        docx(&path("a.docx"), &[
+            ("word/document.xml", r#"<w:p w:rsidR="00A1B2C3" w:rsidRDefault="00D4E5F6"><w:t>Hello</w:t></w:p>"#),
+            ("docProps/core.xml", "<dcterms:created>2024-01-01</dcterms:created>"),
+        ]);
+        docx(&path("b.docx"), &[
+            ("docProps/core.xml", "<dcterms:created>2025-06-30</dcterms:created>"),
+            ("word/document.xml", r#"<w:p w:rsidR="00FFFFFF"><w:t>Hello</w:t></w:p>"#),
+        ]);
+        docx(&path("c.docx"), &[("word/document.xml", "<w:p><w:t>Goodbye</w:t></w:p>")]);
+        let a = document_digest(&path("a.docx")).unwrap();
+        assert_eq!(a, document_digest(&path("b.docx")).unwrap());
+        assert_ne!(a, document_digest(&path("c.docx")).unwrap());
+
+        pdf(&path("a.pdf"), "BT (Report) Tj ET", "D:20240101120000");
+        pdf(&path("b.pdf"), "BT (Report) Tj ET", "D:20250630080000");
+        pdf(&path("c.pdf"), "BT (Draft) Tj ET", "D:20240101120000");
+        let a = document_digest(&path("a.pdf")).unwrap();
+        assert_eq!(a, document_digest(&path("b.pdf")).unwrap());
+        assert_ne!(a, document_digest(&path("c.pdf")).unwrap());
+
+        assert!(document_digest(&path("missing.xlsx")).is_err());
+        assert!(is_document(Path::new("Report.PDF")) && !is_document(Path::new("notes.txt")));
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use tokio::fs;
+use tracing::{debug, info, warn};
+
+use crate::EmptyDirectory;
+
+/// One directory seen while walking a tree
+struct WalkedDir {
+    path: PathBuf,
+
+    /// Files directly inside the directory
+    files: Vec<PathBuf>,
+
+    /// Set when the directory holds anything that always counts as content:
+    /// symlinks, special files, skipped hidden entries or unreadable entries
+    pinned: bool,
+
+    /// Indexes of subdirectories in the walk
+    children: Vec<usize>,
+}
+
+/// Walks every directory below `root` (excluding `root` itself), parents first
+///
+/// Hidden entries are never descended into unless `include_hidden` is set;
+/// they still count as content, so their parents are never considered empty
+async fn walk(root: &Path, include_hidden: bool) -> Result<Vec<WalkedDir>> {
+    let mut walked: Vec<WalkedDir> = Vec::new();
+    let mut stack: Vec<(PathBuf, Option<usize>)> = vec![(root.to_path_buf(), None)];
+
+    while let Some((dir, parent)) = stack.pop() {
+        let index = walked.len();
+        walked.push(WalkedDir {
+            path: dir.clone(),
+            files: Vec::new(),
+            pinned: false,
+            children: Vec::new(),
+        });
+        if let Some(parent) = parent {
+            walked[parent].children.push(index);
+        }
+
+        let mut read_dir = match fs::read_dir(&dir).await {
+            Ok(read_dir) => read_dir,
+            Err(e) => {
+                warn!("Cannot read directory {}: {}", dir.display(), e);
+                walked[index].pinned = true;
+                continue;
+            }
+        };
+
+        while let Some(entry) = read_dir.next_entry().await.transpose() {
+            let Ok(entry) = entry else {
+                walked[index].pinned = true;
+                continue;
+            };
+            let path = entry.path();
+            let hidden = path.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.starts_with('.'));
+
+            match entry.file_type().await {
+                Ok(kind) if kind.is_dir() && (include_hidden || !hidden) => stack.push((path, Some(index))),
+                Ok(kind) if kind.is_file() => walked[index].files.push(path),
+                _ => walked[index].pinned = true,
+            }
+        }
+    }
+
+    Ok(walked)
+}
+
+/// Computes which walked directories are empty once `removed` files are gone
+///
+/// A directory is empty when it holds no files other than removed ones and
+/// all of its subdirectories are empty
+fn emptiness(walked: &[WalkedDir], removed: &HashSet<&Path>) -> Vec<bool> {
+    let mut empty = vec![false; walked.len()];
+
+    // Children always come after their parent in the walk
+    for index in (0..walked.len()).rev() {
+        let dir = &walked[index];
+        empty[index] = !dir.pinned
+            && dir.files.iter().all(|f| removed.contains(f.as_path()))
+            && dir.children.iter().all(|&child| empty[child]);
+    }
+    empty
+}
+
+/// Finds empty directories below `root`, including those that would be empty
+/// after the `removed` files are deleted
+///
+/// Only the outermost empty directory of each empty subtree is returned,
+/// sorted by path. The root itself is never reported
+pub async fn find_empty_directories(
+    root: &Path,
+    include_hidden: bool,
+    removed: &HashSet<&Path>,
+) -> Result<Vec<EmptyDirectory>> {
+    let walked = walk(root, include_hidden).await?;
+    let empty_now = emptiness(&walked, &HashSet::new());
+    let empty_after = emptiness(&walked, removed);
+
+    let mut found = Vec::new();
+    let mut stack = vec![0];
+    while let Some(index) = stack.pop() {
+        if index != 0 && empty_after[index] {
+            found.push(EmptyDirectory {
+                path: walked[index].path.clone(),
+                after_cleanup: !empty_now[index],
+            });
+            continue;
+        }
+        stack.extend(&walked[index].children);
+    }
+
+    found.sort_by(|a, b| a.path.cmp(&b.path));
+    info!(root = %root.display(), directories = found.len(), "Found empty directories");
+    Ok(found)
+}
+
+/// Removes every empty directory below `root`, deepest first
+///
+/// Uses plain `rmdir` semantics, so a directory that gained content in the
+/// meantime is left alone. The root itself is kept. Returns the outermost
+/// removed directories, sorted by path
+#[tracing::instrument(name = "prune_empty_directories", skip_all, fields(root = %root.display()))]
+pub async fn prune_empty_directories(root: &Path, include_hidden: bool) -> Result<Vec<PathBuf>> {
+    let walked = walk(root, include_hidden).await?;
+    let empty = emptiness(&walked, &HashSet::new());
+
+    let mut removed = HashSet::new();
+    for index in (1..walked.len()).rev().filter(|&i| empty[i]) {
+        let path = &walked[index].path;
+        match fs::remove_dir(path).await {
+            Ok(()) => {
+                debug!(path = %path.display(), "Removed empty directory");
+                removed.insert(path.clone());
+            }
+            Err(e) => warn!("Cannot remove directory {}: {}", path.display(), e),
+        }
+    }
+
+    let mut outermost: Vec<PathBuf> = removed
+        .iter()
+        .filter(|path| !path.parent().is_some_and(|p| removed.contains(p)))
+        .cloned()
+        .collect();
+    outermost.sort();
+    Ok(outermost)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_find_and_prune_empty_directories() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        std::fs::create_dir_all(root.join("empty/nested")).unwrap();
+        std::fs::create_dir_all(root.join("copies")).unwrap();
+        std::fs::create_dir_all(root.join("kept/.config")).unwrap();
+        std::fs::write(root.join("copies/a.txt"), "a").unwrap();
+        std::fs::write(root.join("kept/b.txt"), "b").unwrap();
+
+        let copy = root.join("copies/a.txt");
+        let removed = HashSet::from([copy.as_path()]);
+        let found = find_empty_directories(root, false, &removed).await.unwrap();
+        assert_eq!(
+            found,
+            [
+                EmptyDirectory { path: root.join("copies"), after_cleanup: true },
+                EmptyDirectory { path: root.join("empty"), after_cleanup: false },
+            ]
+        );
+
+        // The hidden directory is content, so "kept" survives even without b.txt
+        std::fs::remove_file(root.join("kept/b.txt")).unwrap();
+        let pruned = prune_empty_directories(root, false).await.unwrap();
+        assert_eq!(pruned, [root.join("empty")]);
+        assert!(root.join("kept/.config").exists());
+        assert!(root.join("copies").exists());
+    }
+}
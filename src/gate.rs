@@ -0,0 +1,88 @@
+use std::fmt;
+
+use crate::{ScanResult, ScannerConfig, format_bytes};
+
+/// A CI threshold that a scan result exceeded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// More bytes are wasted by duplicates than allowed
+    WastedSpace { actual: u64, limit: u64 },
+
+    /// More duplicate groups were found than allowed
+    Groups { actual: usize, limit: usize },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::WastedSpace { actual, limit } => write!(
+                f,
+                "wasted space {} exceeds the limit of {}",
+                format_bytes(*actual),
+                format_bytes(*limit)
+            ),
+            Violation::Groups { actual, limit } => {
+                write!(f, "{} duplicate groups exceed the limit of {}", actual, limit)
+            }
+        }
+    }
+}
+
+/// Returns true when any CI threshold is configured
+pub fn is_enabled(config: &ScannerConfig) -> bool {
+    config.fail_if_wasted.is_some() || config.fail_if_groups.is_some()
+}
+
+/// Checks a scan result against the configured CI thresholds
+///
+/// A threshold is violated only when the result is strictly above it, so
+/// `--fail-if-groups 0` fails on any duplicate at all
+pub fn check(config: &ScannerConfig, scan_result: &ScanResult) -> Vec<Violation> {
+    let mut violations = Vec::new();
+
+    if let Some(limit) = config.fail_if_wasted
+        && scan_result.total_wasted_space > limit
+    {
+        violations.push(Violation::WastedSpace {
+            actual: scan_result.total_wasted_space,
+            limit,
+        });
+    }
+
+    if let Some(limit) = config.fail_if_groups
+        && scan_result.group_count() > limit
+    {
+        violations.push(Violation::Groups {
+            actual: scan_result.group_count(),
+            limit,
+        });
+    }
+
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let scan_result = ScanResult {
+            total_wasted_space: 2048,
+            ..Default::default()
+        };
+
+        let mut config = ScannerConfig::default();
+        assert!(!is_enabled(&config));
+
+        config.fail_if_wasted = Some(2048);
+        config.fail_if_groups = Some(0);
+        assert!(is_enabled(&config));
+        assert!(check(&config, &scan_result).is_empty());
+
+        config.fail_if_wasted = Some(1024);
+        let violations = check(&config, &scan_result);
+        assert_eq!(violations, vec![Violation::WastedSpace { actual: 2048, limit: 1024 }]);
+        assert_eq!(violations[0].to_string(), "wasted space 2.00 KB exceeds the limit of 1.00 KB");
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Instant;
+
+use crate::scanner::{default_group_id, result_from_groups};
+use crate::{DuplicateGroup, FileInfo, ScanResult};
+
+/// Groups files whose hashes are already known, fed one at a time
+///
+/// Files with the same [`FileInfo::hash`] form a group, oldest first, as
+/// in an exact-mode scan. Hashes are compared as they are, so they should
+/// all come from the same hash function; groups get the IDs a default
+/// SHA-256 scan would give them
+///
+/// ```rust
+/// use duplicate_finder::{DuplicateGrouper, FileInfo};
+///
+/// let file = |path: &str, hash: &str| FileInfo {
+///     path: path.into(),
+///     size: 4,
+///     hash: hash.to_string(),
+///     modified: std::time::SystemTime::UNIX_EPOCH,
+///     created: None,
+///     compression: None,
+/// };
+/// let mut grouper = DuplicateGrouper::new();
+/// grouper.push(file("a.txt", "d1"));
+/// grouper.extend([file("b.txt", "d1"), file("c.txt", "d2")]);
+///
+/// let result = grouper.into_result("/imported".as_ref());
+/// assert_eq!((result.total_files, result.duplicate_groups.len()), (3, 1));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DuplicateGrouper {
+    by_hash: HashMap<String, Vec<FileInfo>>,
+    files: usize,
+}
+
+impl DuplicateGrouper {
+    /// Grouper without any files yet
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a file to the group of its hash
+    pub fn push(&mut self, file: FileInfo) {
+        self.files += 1;
+        self.by_hash.entry(file.hash.clone()).or_default().push(file);
+    }
+
+    /// Number of files fed so far
+    pub fn len(&self) -> usize {
+        self.files
+    }
+
+    /// Returns true when no file was fed yet
+    pub fn is_empty(&self) -> bool {
+        self.files == 0
+    }
+
+    /// Groups of two or more files with the same hash
+    pub fn groups(self) -> Vec<DuplicateGroup> {
+        self.all_groups().into_iter()
+            .filter(|group| group.files.len() > 1)
+            .map(|group| DuplicateGroup { id: default_group_id(&group.hash), ..group })
+            .collect()
+    }
+
+    /// Scan result holding the groups with their statistics, for the
+    /// output formatters; `directory` is reported as the scanned directory
+    pub fn into_result(self, directory: &Path) -> ScanResult {
+        let total_files = self.files;
+        result_from_groups(self.groups(), total_files, directory, 0, Instant::now())
+    }
+
+    /// Every group, single files included, without IDs
+    pub(crate) fn all_groups(self) -> Vec<DuplicateGroup> {
+        self.by_hash.into_iter()
+            .map(|(hash, mut files)| {
+                // Sort files by modification time (oldest first)
+                files.sort_by_key(|f| f.modified);
+
+                // Sizes only differ in content-key modes (e.g. same pixels, other metadata)
+                let size = files.first().map(|f| f.size).unwrap_or(0);
+                let total_size: u64 = files.iter().map(|f| f.size).sum();
+                let wasted_space = total_size - size;
+
+                DuplicateGroup {
+                    id: String::new(),
+                    hash,
+                    size,
+                    files,
+                    total_size,
+                    wasted_space,
+                }
+            })
+            .collect()
+    }
+}
+
+impl Extend<FileInfo> for DuplicateGrouper {
+    fn extend<T: IntoIterator<Item = FileInfo>>(&mut self, files: T) {
+        files.into_iter().for_each(|file| self.push(file));
+    }
+}
+
+impl FromIterator<FileInfo> for DuplicateGrouper {
+    fn from_iter<T: IntoIterator<Item = FileInfo>>(files: T) -> Self {
+        let mut grouper = Self::new();
+        grouper.extend(files);
+        grouper
+    }
+}
+
+/// Groups files whose hashes are already known (see [`DuplicateGrouper`])
+pub fn group_duplicates(files: impl IntoIterator<Item = FileInfo>) -> Vec<DuplicateGroup> {
+    files.into_iter().collect::<DuplicateGrouper>().groups()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    fn file(path: &str, hash: &str, age: u64) -> FileInfo {
+        FileInfo {
+            path: path.into(),
+            size: 10,
+            hash: hash.to_string(),
+            modified: SystemTime::UNIX_EPOCH + Duration::from_secs(1000 - age),
+            created: None,
+            compression: None,
+        }
+    }
+
+    #[test]
+    fn test_group_duplicates() {
+        let groups = group_duplicates([file("new", "d1", 1), file("old", "d1", 2), file("single", "d2", 3)]);
+        assert_eq!(groups.len(), 1);
+        let paths: Vec<&Path> = groups[0].files.iter().map(|f| f.path.as_path()).collect();
+        assert_eq!(paths, [Path::new("old"), Path::new("new")]);
+        assert_eq!((groups[0].total_size, groups[0].wasted_space), (20, 10));
+        assert_eq!(groups[0].id, default_group_id("d1"));
+    }
+}
@@ -0,0 +1,751 @@
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use futures_util::{StreamExt, stream};
+use memmap2::MmapOptions;
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tracing::{debug, warn};
+use xxhash_rust::xxh3::Xxh3;
+
+use crate::io_backend::LocalIo;
+use crate::progress::ProgressObserver;
+use crate::source::is_remote_url;
+use crate::storage::StorageKind;
+use crate::throttle::Throttle;
+use crate::{DuplicateFinderError, DuplicateGroup, FileInfo, HashAlgorithm, IoMode, archives};
+
+/// Bytes read from a file at a time when hashing or comparing it
+pub(crate) const BUFFER_SIZE: usize = 65536;
+
+/// Largest read when hashing, whatever `--buffer-size` says
+const MAX_BUFFER_SIZE: usize = 64 * 1024 * 1024;
+
+/// Small files are read with a buffer of their size rounded up to this
+const PAGE_SIZE: u64 = 4096;
+
+/// Bytes of a huge file handed to [`ContentHasher::update_parallel`] at a time
+const PARALLEL_BLOCK_SIZE: usize = 16 * 1024 * 1024;
+
+/// Bytes of a file mapped at a time; a multiple of every page size
+const MAP_WINDOW: u64 = 256 * 1024 * 1024;
+
+/// Incremental digest of file contents
+///
+/// Contents are fed in chunks of arbitrary size; the digest must only
+/// depend on the bytes, not on how they were split. Implement this (and
+/// [`HashFunction`]) to compare files with a digest of your own, e.g. an
+/// HMAC or the hash your storage system already keeps
+pub trait ContentHasher: Send {
+    /// Feeds the next chunk of content
+    fn update(&mut self, data: &[u8]);
+
+    /// Feeds a large chunk of a huge file, spreading the work over several
+    /// threads where the digest allows it
+    ///
+    /// Must give the same digest as [`ContentHasher::update`], which it
+    /// calls by default
+    fn update_parallel(&mut self, data: &[u8]) {
+        self.update(data);
+    }
+
+    /// Digest of everything fed so far, as a string
+    ///
+    /// Files are duplicates when their digests are equal
+    fn finalize(self: Box<Self>) -> String;
+}
+
+/// A digest algorithm: creates a fresh [`ContentHasher`] for every file
+///
+/// Hand one to the scanner with [`FileScanner::with_hasher`](crate::FileScanner::with_hasher).
+/// [`HashAlgorithm`] provides the built-in algorithms
+pub trait HashFunction: Send + Sync {
+    /// Starts the digest of one file
+    fn hasher(&self) -> Box<dyn ContentHasher>;
+
+    /// Whether [`ContentHasher::update_parallel`] actually uses several
+    /// threads, so huge files are worth hashing one at a time
+    fn hashes_in_parallel(&self) -> bool {
+        false
+    }
+}
+
+impl HashFunction for HashAlgorithm {
+    fn hasher(&self) -> Box<dyn ContentHasher> {
+        match self {
+            HashAlgorithm::Sha256 => Box::new(Sha256::new()),
+            HashAlgorithm::Blake3 => Box::new(blake3::Hasher::new()),
+            HashAlgorithm::Xxh3 => Box::new(Xxh3::new()),
+        }
+    }
+
+    /// BLAKE3 hashes the chunks of its tree on the rayon thread pool; the
+    /// SHA-256 and XXH3 digests are inherently sequential
+    fn hashes_in_parallel(&self) -> bool {
+        *self == HashAlgorithm::Blake3
+    }
+}
+
+/// Lowercase hex, 64 digits
+impl ContentHasher for Sha256 {
+    fn update(&mut self, data: &[u8]) {
+        Digest::update(self, data);
+    }
+
+    fn finalize(self: Box<Self>) -> String {
+        format!("{:x}", Digest::finalize(*self))
+    }
+}
+
+/// Lowercase hex, 64 digits
+impl ContentHasher for blake3::Hasher {
+    fn update(&mut self, data: &[u8]) {
+        blake3::Hasher::update(self, data);
+    }
+
+    fn update_parallel(&mut self, data: &[u8]) {
+        self.update_rayon(data);
+    }
+
+    fn finalize(self: Box<Self>) -> String {
+        blake3::Hasher::finalize(&self).to_hex().to_string()
+    }
+}
+
+/// Lowercase hex of the 128-bit digest, 32 digits
+impl ContentHasher for Xxh3 {
+    fn update(&mut self, data: &[u8]) {
+        Xxh3::update(self, data);
+    }
+
+    fn finalize(self: Box<Self>) -> String {
+        format!("{:032x}", self.digest128())
+    }
+}
+
+/// How the files of a scan are read for hashing
+#[derive(Debug, Clone)]
+pub(crate) struct ReadOptions {
+    /// Bytes per read (`--buffer-size`); 0 picks them by storage
+    configured: u64,
+    storage: StorageKind,
+    /// Whether the OS is told that files are read once, start to end
+    /// (see [`open_sequential`]); off with `--keep-cache`
+    pub hints: bool,
+    /// Rate limits the reads are accounted to
+    pub throttle: Throttle,
+    /// Stops the reads of a file that ran out of time
+    pub cancel: Cancel,
+    /// Where the bytes read are reported, if anywhere
+    pub progress: Option<Arc<ReadProgress>>,
+}
+
+impl ReadOptions {
+    pub(crate) fn new(configured: u64, storage: StorageKind, hints: bool) -> Self {
+        Self { configured, storage, hints, throttle: Throttle::default(), cancel: Cancel::default(), progress: None }
+    }
+
+    /// Accounts reads to rate limits
+    pub(crate) fn with_throttle(mut self, throttle: Throttle) -> Self {
+        self.throttle = throttle;
+        self
+    }
+
+    /// Stops reading once `cancel` is set
+    pub(crate) fn with_cancel(mut self, cancel: Cancel) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Reports the bytes read to a progress observer
+    pub(crate) fn with_progress(mut self, progress: Option<Arc<ReadProgress>>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Accounts `bytes` just read to the rate limits and the progress observer
+    ///
+    /// Called after every read; fails with [`ErrorKind::TimedOut`] once
+    /// the reads were cancelled, so a blocking task reading a file that was
+    /// given up on stops at its next read
+    pub(crate) fn account(&self, bytes: usize) -> std::io::Result<()> {
+        self.cancel.check()?;
+        self.throttle.consume(bytes);
+        if let Some(progress) = &self.progress {
+            progress.add(bytes as u64);
+        }
+        Ok(())
+    }
+
+    /// Bytes per read for a file of `size` bytes
+    ///
+    /// Up to [`MAX_BUFFER_SIZE`], and no larger than the file rounded up
+    /// to whole pages, so small files do not allocate large buffers
+    pub(crate) fn buffer_size(&self, size: u64) -> usize {
+        let fitting = size.max(1).checked_next_multiple_of(PAGE_SIZE).and_then(|s| usize::try_from(s).ok()).unwrap_or(usize::MAX);
+        self.preferred_buffer_size().min(fitting)
+    }
+
+    /// Returns true when a file of `size` bytes fits a single read
+    pub(crate) fn reads_whole(&self, size: u64) -> bool {
+        size < self.preferred_buffer_size() as u64
+    }
+
+    /// Bytes per read of `--buffer-size` or the storage kind
+    fn preferred_buffer_size(&self) -> usize {
+        match self.configured {
+            0 => self.storage.default_buffer_size(),
+            configured => usize::try_from(configured).unwrap_or(MAX_BUFFER_SIZE).min(MAX_BUFFER_SIZE),
+        }
+    }
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        Self::new(0, StorageKind::Unknown, true)
+    }
+}
+
+/// Flag that tells the reads of a file to stop (`--file-timeout`)
+///
+/// Hashing runs on blocking threads that cannot be aborted; they check the
+/// flag after every read instead
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Cancel(Arc<AtomicBool>);
+
+impl Cancel {
+    pub(crate) fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    fn check(&self) -> std::io::Result<()> {
+        if self.0.load(Ordering::Relaxed) {
+            return Err(std::io::Error::new(ErrorKind::TimedOut, "reading was cancelled"));
+        }
+        Ok(())
+    }
+}
+
+/// The bytes of one file reported to the progress observer as they are read
+///
+/// No more than the size of the file is reported, however often it is read
+/// (retries) or however much it grew; [`ReadProgress::finish`] reports the
+/// rest of a file that was not read through, e.g. when its digest was cached
+pub(crate) struct ReadProgress {
+    observer: Arc<dyn ProgressObserver>,
+    size: u64,
+    shown: AtomicU64,
+}
+
+impl ReadProgress {
+    pub(crate) fn new(observer: Arc<dyn ProgressObserver>, size: u64) -> Self {
+        Self { observer, size, shown: AtomicU64::new(0) }
+    }
+
+    fn add(&self, bytes: u64) {
+        let before = self.shown.fetch_add(bytes, Ordering::Relaxed);
+        let new = bytes.min(self.size.saturating_sub(before));
+        if new > 0 {
+            self.observer.read(new);
+        }
+    }
+
+    /// Reports whatever part of the file was not read
+    pub(crate) fn finish(&self) {
+        self.add(self.size);
+    }
+}
+
+impl std::fmt::Debug for ReadProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReadProgress").field("size", &self.size).field("shown", &self.shown).finish_non_exhaustive()
+    }
+}
+
+/// Opens a file that is about to be read once from start to end
+///
+/// With `hints` the OS reads ahead more aggressively: on Linux through
+/// `POSIX_FADV_SEQUENTIAL`, on Windows through `FILE_FLAG_SEQUENTIAL_SCAN`
+pub(crate) fn open_sequential(path: &Path, hints: bool) -> std::io::Result<File> {
+    let mut options = std::fs::OpenOptions::new();
+    options.read(true);
+    #[cfg(windows)]
+    if hints {
+        use std::os::windows::fs::OpenOptionsExt;
+        const FILE_FLAG_SEQUENTIAL_SCAN: u32 = 0x0800_0000;
+        options.custom_flags(FILE_FLAG_SEQUENTIAL_SCAN);
+    }
+    let file = options.open(path)?;
+    #[cfg(target_os = "linux")]
+    if hints {
+        use std::os::fd::AsRawFd;
+        // SAFETY: plain system call on a descriptor owned by `file`; the
+        // advice is best effort, so its result is ignored
+        unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_SEQUENTIAL) };
+    }
+    Ok(file)
+}
+
+/// Lets the OS drop the cached pages of a file that was just hashed
+///
+/// A scan reads every file once, so without this a large scan would push
+/// everything else out of the page cache. Linux only; Windows already
+/// recycles the pages of files opened with `FILE_FLAG_SEQUENTIAL_SCAN` first
+pub(crate) fn release_cache(file: &File, hints: bool) {
+    #[cfg(target_os = "linux")]
+    if hints {
+        use std::os::fd::AsRawFd;
+        // SAFETY: as in open_sequential
+        unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) };
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = (file, hints);
+}
+
+/// Digest of a byte slice
+pub(crate) fn digest(function: &dyn HashFunction, data: &[u8]) -> String {
+    let mut hasher = function.hasher();
+    hasher.update(data);
+    hasher.finalize()
+}
+
+/// Digest of everything a reader yields
+pub(crate) fn hash_reader(reader: &mut impl Read, function: &dyn HashFunction) -> std::io::Result<String> {
+    feed_reader(reader, function.hasher(), BUFFER_SIZE, &ReadOptions::default())
+}
+
+/// Hashes a local file on tokio's blocking pool
+///
+/// The whole file is read and digested by one blocking task, so digests
+/// never run on the async workers that walk directories and drive
+/// progress, and a file costs one hand-off instead of one per read
+pub(crate) async fn hash_file_blocking(path: &Path, function: &dyn HashFunction, buffer_size: usize, options: ReadOptions) -> std::io::Result<String> {
+    let path = path.to_path_buf();
+    let hasher = function.hasher();
+    tokio::task::spawn_blocking(move || hash_local(&path, hasher, buffer_size, &options))
+        .await
+        .map_err(std::io::Error::other)?
+}
+
+/// Hashes a local file listed with `size` bytes on tokio's blocking pool,
+/// picking the reads by size
+///
+/// Files smaller than a read buffer are read whole (see [`hash_whole`]);
+/// larger ones stream through reads of [`ReadOptions::buffer_size`]. Files
+/// large enough to be mapped or hashed with several threads never get here
+pub(crate) async fn hash_sized_file(path: &Path, function: &dyn HashFunction, size: u64, options: ReadOptions) -> std::io::Result<String> {
+    if !options.reads_whole(size) {
+        return hash_file_blocking(path, function, options.buffer_size(size), options).await;
+    }
+    let path = path.to_path_buf();
+    let hasher = function.hasher();
+    tokio::task::spawn_blocking(move || hash_whole(&path, hasher, size, &options))
+        .await
+        .map_err(std::io::Error::other)?
+}
+
+/// Like [`hash_sized_file`], on the calling thread
+#[cfg(feature = "threads")]
+pub(crate) fn hash_sized_local(path: &Path, function: &dyn HashFunction, size: u64, options: &ReadOptions) -> std::io::Result<String> {
+    if options.reads_whole(size) {
+        hash_whole(path, function.hasher(), size, options)
+    } else {
+        hash_local(path, function.hasher(), options.buffer_size(size), options)
+    }
+}
+
+/// Hashes a small file with a single read of one byte more than its size
+///
+/// Getting exactly `size` bytes means the file ended there, so neither a
+/// readahead hint nor a read that confirms the end is needed. A file that
+/// changed since it was listed is read on like any other
+fn hash_whole(path: &Path, mut hasher: Box<dyn ContentHasher>, size: u64, options: &ReadOptions) -> std::io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut buffer = vec![0u8; size as usize + 1];
+    let bytes_read = file.read(&mut buffer)?;
+    options.account(bytes_read)?;
+    hasher.update(&buffer[..bytes_read]);
+    let digest = if bytes_read as u64 == size {
+        hasher.finalize()
+    } else {
+        feed_reader(&mut file, hasher, options.buffer_size(size), options)?
+    };
+    release_cache(&file, options.hints);
+    Ok(digest)
+}
+
+fn hash_local(path: &Path, hasher: Box<dyn ContentHasher>, buffer_size: usize, options: &ReadOptions) -> std::io::Result<String> {
+    let mut file = open_sequential(path, options.hints)?;
+    let digest = feed_reader(&mut file, hasher, buffer_size, options)?;
+    release_cache(&file, options.hints);
+    Ok(digest)
+}
+
+fn feed_reader(reader: &mut impl Read, mut hasher: Box<dyn ContentHasher>, buffer_size: usize, options: &ReadOptions) -> std::io::Result<String> {
+    let mut buffer = vec![0u8; buffer_size];
+    loop {
+        let bytes_read = reader.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        options.account(bytes_read)?;
+        hasher.update(&buffer[..bytes_read]);
+    }
+    Ok(hasher.finalize())
+}
+
+/// Digest of everything an async reader yields, with the number of bytes
+/// it yielded
+///
+/// For content that has no path of its own, such as archive members or
+/// network streams; see [`crate::FileInfo::from_reader`] for a complete
+/// file record
+pub async fn hash_stream(reader: &mut (impl AsyncRead + Unpin), function: &dyn HashFunction) -> std::io::Result<(String, u64)> {
+    let mut hasher = function.hasher();
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    let mut size = 0;
+    loop {
+        let bytes_read = reader.read(&mut buffer).await?;
+        if bytes_read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..bytes_read]);
+        size += bytes_read as u64;
+    }
+    Ok((hasher.finalize(), size))
+}
+
+/// Fills in the digest of a file's raw contents, given its metadata
+///
+/// Files that `io_mode` maps are hashed from a memory map on the blocking
+/// pool; the others are read through `io` as `options` say
+pub(crate) async fn raw_file_info(mut file_info: FileInfo, function: &Arc<dyn HashFunction>, io_mode: IoMode, io: &dyn LocalIo, options: ReadOptions) -> Result<FileInfo, DuplicateFinderError> {
+    let path = file_info.path.clone();
+    let hash = if io_mode.maps(file_info.size) {
+        let (owned, function) = (path.clone(), Arc::clone(function));
+        tokio::task::spawn_blocking(move || hash_mapped_or_read(&owned, function.as_ref(), false, &options))
+            .await
+            .map_err(|e| DuplicateFinderError::Io(std::io::Error::other(e)))?
+    } else {
+        io.hash_file(&path, file_info.size, Arc::clone(function), options).await
+    };
+    file_info.hash = hash.map_err(|e| read_failed(&path, e))?;
+    Ok(file_info)
+}
+
+/// Fills in the digest of a huge file, hashed with several threads
+///
+/// The file is mapped or read in large blocks on the blocking pool, each
+/// one handed to [`ContentHasher::update_parallel`]
+pub(crate) async fn parallel_file_info(mut file_info: FileInfo, function: Arc<dyn HashFunction>, io_mode: IoMode, options: ReadOptions) -> Result<FileInfo, DuplicateFinderError> {
+    let path = file_info.path.clone();
+    let owned = path.clone();
+    let mapped = io_mode.maps(file_info.size);
+    file_info.hash = tokio::task::spawn_blocking(move || {
+        if mapped {
+            hash_mapped_or_read(&owned, function.as_ref(), true, &options)
+        } else {
+            hash_file_parallel(&owned, function.as_ref(), &options)
+        }
+    })
+        .await
+        .map_err(|e| DuplicateFinderError::Io(std::io::Error::other(e)))?
+        .map_err(|e| read_failed(&path, e))?;
+    Ok(file_info)
+}
+
+/// Error of a failed read, which may be retried if it was transient
+fn read_failed(path: &Path, error: std::io::Error) -> DuplicateFinderError {
+    if crate::is_transient_io(&error) {
+        DuplicateFinderError::TransientRead { path: path.to_path_buf(), source: error }
+    } else {
+        DuplicateFinderError::HashCalculationError { path: path.to_path_buf() }
+    }
+}
+
+/// Hashes a memory-mapped file, or reads it again if it changed meanwhile
+fn hash_mapped_or_read(path: &Path, function: &dyn HashFunction, parallel: bool, options: &ReadOptions) -> std::io::Result<String> {
+    match hash_mapped(path, function, parallel, options) {
+        Err(e) if e.kind() == ErrorKind::Interrupted => {
+            debug!("{} changed while mapped, reading it instead", path.display());
+            if parallel {
+                hash_file_parallel(path, function, options)
+            } else {
+                hash_local(path, function.hasher(), BUFFER_SIZE, options)
+            }
+        }
+        result => result,
+    }
+}
+
+/// Hashes a file through memory maps of up to [`MAP_WINDOW`] bytes
+///
+/// A mapped file that is truncated faults on access instead of returning
+/// an error, so the length is checked again before each window is mapped,
+/// which keeps the race to a single window. Files whose length or
+/// modification time changed during hashing fail with
+/// [`ErrorKind::Interrupted`]
+fn hash_mapped(path: &Path, function: &dyn HashFunction, parallel: bool, options: &ReadOptions) -> std::io::Result<String> {
+    let changed = || std::io::Error::new(ErrorKind::Interrupted, "file changed while mapped");
+    let file = open_sequential(path, options.hints)?;
+    let before = file.metadata()?;
+    let length = before.len();
+    let mut hasher = function.hasher();
+    let mut offset = 0;
+
+    while offset < length {
+        if file.metadata()?.len() < length {
+            return Err(changed());
+        }
+        let window = (length - offset).min(MAP_WINDOW);
+        // SAFETY: the map is read-only and dropped before the next window;
+        // concurrent modification is detected by the checks around it
+        let map = unsafe { MmapOptions::new().offset(offset).len(window as usize).map(&file)? };
+        #[cfg(unix)]
+        map.advise(memmap2::Advice::Sequential)?;
+        // Pages are read as they are hashed, so the rate limits are
+        // accounted a block at a time
+        for block in map.chunks(PARALLEL_BLOCK_SIZE) {
+            options.account(block.len())?;
+            if parallel {
+                hasher.update_parallel(block);
+            } else {
+                hasher.update(block);
+            }
+        }
+        offset += window;
+    }
+
+    let after = file.metadata()?;
+    if after.len() != length || after.modified().ok() != before.modified().ok() {
+        return Err(changed());
+    }
+    release_cache(&file, options.hints);
+    Ok(hasher.finalize())
+}
+
+fn hash_file_parallel(path: &Path, function: &dyn HashFunction, options: &ReadOptions) -> std::io::Result<String> {
+    let mut file = open_sequential(path, options.hints)?;
+    let mut hasher = function.hasher();
+    let mut buffer = vec![0u8; PARALLEL_BLOCK_SIZE];
+    loop {
+        let bytes_read = read_full(&mut file, &mut buffer)?;
+        options.account(bytes_read)?;
+        hasher.update_parallel(&buffer[..bytes_read]);
+        if bytes_read < buffer.len() {
+            release_cache(&file, options.hints);
+            return Ok(hasher.finalize());
+        }
+    }
+}
+
+/// Splits duplicate groups into sets of byte-identical files
+///
+/// Used by `--verify-matches`: every file is compared byte by byte with
+/// the first file of each set found so far. Sets split off a group get the
+/// group's hash with a `#<n>` suffix. Groups with remote files or archive
+/// members cannot be re-read cheaply and are kept as they are.
+///
+/// Returns the groups and the number of files that could not be read
+pub(crate) async fn verify_groups(groups: Vec<DuplicateGroup>, concurrency: usize) -> (Vec<DuplicateGroup>, usize) {
+    let results: Vec<(Vec<DuplicateGroup>, usize)> = stream::iter(groups)
+        .map(|group| async move {
+            if group.files.iter().any(|f| is_remote_url(&f.path) || archives::is_member(&f.path)) {
+                return (vec![group], 0);
+            }
+            let fallback = group.clone();
+            tokio::task::spawn_blocking(move || split_group(group))
+                .await
+                .unwrap_or_else(|e| {
+                    warn!("Cannot verify group {}: {}", fallback.hash, e);
+                    (vec![fallback], 0)
+                })
+        })
+        .buffered(concurrency.max(1))
+        .collect()
+        .await;
+
+    let mut verified = Vec::new();
+    let mut errors = 0;
+    for (groups, group_errors) in results {
+        verified.extend(groups);
+        errors += group_errors;
+    }
+    (verified, errors)
+}
+
+/// Splits one group into sets of identical files, dropping unreadable ones
+fn split_group(group: DuplicateGroup) -> (Vec<DuplicateGroup>, usize) {
+    let mut sets: Vec<Vec<FileInfo>> = Vec::new();
+    let mut errors = 0;
+
+    'files: for file in group.files {
+        for set in &mut sets {
+            match same_contents(&set[0].path, &file.path) {
+                Ok(true) => {
+                    set.push(file);
+                    continue 'files;
+                }
+                Ok(false) => {}
+                Err(e) => {
+                    warn!("Cannot verify {}: {}", file.path.display(), e);
+                    errors += 1;
+                    continue 'files;
+                }
+            }
+        }
+        sets.push(vec![file]);
+    }
+
+    if sets.len() > 1 {
+        debug!("Hash {} matched {} different contents", group.hash, sets.len());
+    }
+    let groups = sets.into_iter()
+        .enumerate()
+        .map(|(index, files)| {
+            let size = files[0].size;
+            let total_size: u64 = files.iter().map(|f| f.size).sum();
+            DuplicateGroup {
+                id: if index == 0 { group.id.clone() } else { format!("{}#{}", group.id, index + 1) },
+                hash: if index == 0 { group.hash.clone() } else { format!("{}#{}", group.hash, index + 1) },
+                size,
+                total_size,
+                wasted_space: total_size - size,
+                files,
+            }
+        })
+        .collect();
+    (groups, errors)
+}
+
+/// Compares two files byte by byte
+fn same_contents(first: &Path, second: &Path) -> std::io::Result<bool> {
+    let (mut first, mut second) = (File::open(first)?, File::open(second)?);
+    if first.metadata()?.len() != second.metadata()?.len() {
+        return Ok(false);
+    }
+    let (mut first_buffer, mut second_buffer) = (vec![0u8; BUFFER_SIZE], vec![0u8; BUFFER_SIZE]);
+    loop {
+        let bytes_read = read_full(&mut first, &mut first_buffer)?;
+        if read_full(&mut second, &mut second_buffer)? != bytes_read
+            || first_buffer[..bytes_read] != second_buffer[..bytes_read]
+        {
+            return Ok(false);
+        }
+        if bytes_read < BUFFER_SIZE {
+            return Ok(true);
+        }
+    }
+}
+
+/// Fills `buffer` unless the reader ends first; returns the bytes read
+fn read_full(reader: &mut impl Read, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..])? {
+            0 => break,
+            bytes_read => filled += bytes_read,
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    #[tokio::test]
+    async fn test_verify_groups() {
+        for (algorithm, length) in [(HashAlgorithm::Sha256, 64), (HashAlgorithm::Blake3, 64), (HashAlgorithm::Xxh3, 32)] {
+            assert_eq!(digest(&algorithm, b"hello").len(), length);
+            // Chunk boundaries do not change the digest
+            let mut hasher = algorithm.hasher();
+            hasher.update(b"hel");
+            hasher.update(b"lo");
+            assert_eq!(hasher.finalize(), hash_reader(&mut &b"hello"[..], &algorithm).unwrap());
+        }
+
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let mut files = Vec::new();
+        for (name, content) in [("a", "same"), ("b", "same"), ("c", "diff"), ("d", "diff"), ("e", "lone")] {
+            let path = temp_dir.path().join(name);
+            std::fs::write(&path, content).unwrap();
+            files.push(FileInfo { path, size: 4, hash: "collision".to_string(), modified: UNIX_EPOCH, created: None, compression: None });
+        }
+        files.push(FileInfo { path: temp_dir.path().join("missing"), ..files[0].clone() });
+        let group = DuplicateGroup { id: String::new(), hash: "collision".to_string(), size: 4, total_size: 24, wasted_space: 20, files };
+
+        let (groups, errors) = verify_groups(vec![group], 2).await;
+        assert_eq!(errors, 1);
+        let sizes: Vec<usize> = groups.iter().map(|g| g.files.len()).collect();
+        assert_eq!(sizes, [2, 2, 1]);
+        assert_eq!(groups[0].hash, "collision");
+        assert_eq!(groups[1].hash, "collision#2");
+        assert_eq!(groups[1].wasted_space, 4);
+    }
+
+    #[tokio::test]
+    async fn test_read_buffer() {
+        let automatic = ReadOptions::new(0, StorageKind::Rotational, true);
+        assert_eq!(automatic.buffer_size(10 << 30), 1024 * 1024);
+        assert_eq!(automatic.buffer_size(100), 4096);
+        assert_eq!(automatic.buffer_size(0), 4096);
+        assert_eq!(ReadOptions::new(0, StorageKind::SolidState, true).buffer_size(10 << 30), 256 * 1024);
+        assert_eq!(ReadOptions::new(8192, StorageKind::Rotational, true).buffer_size(10 << 30), 8192);
+        assert_eq!(ReadOptions::new(u64::MAX, StorageKind::Unknown, false).buffer_size(u64::MAX), MAX_BUFFER_SIZE);
+        assert!(automatic.reads_whole(100) && !automatic.reads_whole(1024 * 1024));
+
+        // Small files are read whole, even when their listed size is stale
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let path = temp_dir.path().join("small.txt");
+        std::fs::write(&path, "hello").unwrap();
+        let function = HashAlgorithm::Sha256;
+        for size in [5, 0, 3, 4096] {
+            let hash = hash_sized_file(&path, &function, size, automatic.clone()).await.unwrap();
+            assert_eq!(hash, digest(&function, b"hello"), "listed as {} bytes", size);
+        }
+
+        // The observer is told the bytes read, never more than the listed size
+        #[derive(Default)]
+        struct Bytes(AtomicU64);
+        impl ProgressObserver for Bytes {
+            fn read(&self, bytes: u64) {
+                self.0.fetch_add(bytes, Ordering::Relaxed);
+            }
+        }
+        let bytes = Arc::new(Bytes::default());
+        let progress = Arc::new(ReadProgress::new(Arc::clone(&bytes) as Arc<dyn ProgressObserver>, 3));
+        hash_sized_file(&path, &function, 5, automatic.clone().with_progress(Some(Arc::clone(&progress)))).await.unwrap();
+        assert_eq!(bytes.0.load(Ordering::Relaxed), 3);
+        let progress = ReadProgress::new(Arc::clone(&bytes) as Arc<dyn ProgressObserver>, 10);
+        progress.finish();
+        progress.finish();
+        assert_eq!(bytes.0.load(Ordering::Relaxed), 13);
+    }
+
+    #[tokio::test]
+    async fn test_parallel_hashing() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let path = temp_dir.path().join("disk.img");
+        // More than one block, with a partial last block
+        let data: Vec<u8> = (0..PARALLEL_BLOCK_SIZE + 12345).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+
+        for algorithm in [HashAlgorithm::Blake3, HashAlgorithm::Sha256] {
+            for io_mode in [IoMode::Read, IoMode::Mmap] {
+                let parallel = parallel_file_info(FileInfo::stat(&path).await.unwrap(), Arc::new(algorithm), io_mode, ReadOptions::default()).await.unwrap();
+                assert_eq!(parallel.hash, digest(&algorithm, &data));
+                assert_eq!(parallel.size, data.len() as u64);
+                let function: Arc<dyn HashFunction> = Arc::new(algorithm);
+                let single = raw_file_info(FileInfo::stat(&path).await.unwrap(), &function, io_mode, &crate::io_backend::TokioIo, ReadOptions::default()).await.unwrap();
+                assert_eq!(single.hash, parallel.hash);
+            }
+        }
+        assert!(HashAlgorithm::Blake3.hashes_in_parallel());
+        assert!(!HashAlgorithm::Sha256.hashes_in_parallel());
+    }
+}
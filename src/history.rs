@@ -0,0 +1,277 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+use crate::scanner::refresh_statistics;
+use crate::{DuplicateFinderError, ScanResult, format_bytes};
+
+/// Summary of one scan, as stored in a history file
+///
+/// History files are JSON Lines: one entry per scan, appended in order,
+/// so they stay small and can be inspected or trimmed with standard tools
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// When the scan finished
+    pub scanned_at: DateTime<Utc>,
+
+    /// Root directory that was scanned
+    pub scanned_directory: PathBuf,
+
+    /// Total number of files processed
+    pub total_files: usize,
+
+    /// Number of duplicate groups found
+    pub duplicate_groups: usize,
+
+    /// Number of redundant copies found
+    pub total_duplicates: usize,
+
+    /// Bytes taken by redundant copies
+    pub total_wasted_space: u64,
+
+    /// Number of copies in each duplicate group found, by stable ID (see
+    /// [`DuplicateGroup::id`](crate::DuplicateGroup::id)); empty in entries
+    /// written before groups had IDs
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub groups: BTreeMap<String, usize>,
+}
+
+impl HistoryEntry {
+    /// Summarizes a scan result finished at `scanned_at`
+    pub fn from_result(scan_result: &ScanResult, scanned_at: DateTime<Utc>) -> Self {
+        Self {
+            scanned_at,
+            scanned_directory: scan_result.scanned_directory.clone(),
+            total_files: scan_result.total_files,
+            duplicate_groups: scan_result.group_count(),
+            total_duplicates: scan_result.total_duplicates,
+            total_wasted_space: scan_result.total_wasted_space,
+            groups: group_copies(scan_result),
+        }
+    }
+
+    /// Number of groups in `self` missing from `earlier`, and of groups of
+    /// `earlier` missing from `self`; `None` unless both entries have IDs
+    pub fn group_changes(&self, earlier: &HistoryEntry) -> Option<(usize, usize)> {
+        if self.groups.is_empty() || earlier.groups.is_empty() {
+            return None;
+        }
+        let missing = |ids: &BTreeMap<String, usize>, from: &BTreeMap<String, usize>| {
+            ids.keys().filter(|id| !from.contains_key(*id)).count()
+        };
+        Some((missing(&self.groups, &earlier.groups), missing(&earlier.groups, &self.groups)))
+    }
+}
+
+/// Copies per group of a scan; spilled groups that cannot be read back
+/// leave the map empty, as in older entries
+fn group_copies(scan_result: &ScanResult) -> BTreeMap<String, usize> {
+    let mut groups = BTreeMap::new();
+    if let Err(e) = scan_result.for_each_group(|group| {
+        groups.insert(group.id.clone(), group.files.len());
+        Ok(())
+    }) {
+        tracing::warn!("Cannot read duplicate groups for the history: {}", e);
+        return BTreeMap::new();
+    }
+    groups
+}
+
+/// Keeps the duplicate groups of `scan_result` that are new since the
+/// `previous` scan or have more copies than then (`--changes-only`)
+///
+/// Totals and statistics cover the kept groups only. Spilled groups are
+/// read back, and the kept ones held in memory
+pub fn changes_since(scan_result: &ScanResult, previous: &HistoryEntry) -> std::io::Result<ScanResult> {
+    let mut changes = Vec::new();
+    scan_result.for_each_group(|group| {
+        if previous.groups.get(&group.id).is_none_or(|&copies| group.files.len() > copies) {
+            changes.push(group.clone());
+        }
+        Ok(())
+    })?;
+    let mut result = ScanResult { duplicate_groups: changes, spilled_groups: None, ..scan_result.clone() };
+    refresh_statistics(&mut result);
+    Ok(result)
+}
+
+/// The latest entry for `directory`, which a new scan of it is compared to
+pub fn previous_scan<'a>(entries: &'a [HistoryEntry], directory: &Path) -> Option<&'a HistoryEntry> {
+    entries.iter().rfind(|e| e.scanned_directory == directory)
+}
+
+/// Appends one entry to a history file, creating it if needed
+pub async fn append(path: &Path, entry: &HistoryEntry) -> Result<()> {
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(path).await?;
+    file.write_all(line.as_bytes()).await?;
+    file.flush().await?;
+    Ok(())
+}
+
+/// Reads every entry of a history file, oldest first
+pub async fn load(path: &Path) -> Result<Vec<HistoryEntry>> {
+    let content = fs::read_to_string(path).await.map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => DuplicateFinderError::PathNotFound {
+            path: path.to_path_buf(),
+        },
+        _ => DuplicateFinderError::Io(e),
+    })?;
+    parse(&content)
+}
+
+/// Parses history file contents, skipping blank lines
+pub fn parse(content: &str) -> Result<Vec<HistoryEntry>> {
+    let mut entries = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str(line)
+            .map_err(|_| DuplicateFinderError::InvalidHistory { line: index + 1 })?;
+        entries.push(entry);
+    }
+    entries.sort_by_key(|e: &HistoryEntry| e.scanned_at);
+    Ok(entries)
+}
+
+/// Describes how duplication changed from `from` to `to` in one sentence
+///
+/// For example: "wasted space grew 12.00 GB since 2024-05-01 (30 days ago),
+/// 2 more groups (5 new, 3 resolved)"
+pub fn describe_change(from: &HistoryEntry, to: &HistoryEntry) -> String {
+    let delta = to.total_wasted_space as i64 - from.total_wasted_space as i64;
+    let days = (to.scanned_at - from.scanned_at).num_days();
+    let since = format!(
+        "since {} ({})",
+        from.scanned_at.format("%Y-%m-%d"),
+        match days {
+            0 => "earlier today".to_string(),
+            1 => "1 day ago".to_string(),
+            n => format!("{} days ago", n),
+        }
+    );
+
+    let groups = to.duplicate_groups as i64 - from.duplicate_groups as i64;
+    let groups = match groups {
+        0 => "same number of groups".to_string(),
+        1 => "1 more group".to_string(),
+        -1 => "1 fewer group".to_string(),
+        n if n > 0 => format!("{} more groups", n),
+        n => format!("{} fewer groups", -n),
+    };
+    let groups = match to.group_changes(from) {
+        Some((0, 0)) | None => groups,
+        Some((new, resolved)) => format!("{} ({} new, {} resolved)", groups, new, resolved),
+    };
+
+    match delta {
+        0 => format!("wasted space unchanged {}, {}", since, groups),
+        d if d > 0 => format!("wasted space grew {} {}, {}", format_bytes(d.unsigned_abs()), since, groups),
+        d => format!("wasted space shrank {} {}, {}", format_bytes(d.unsigned_abs()), since, groups),
+    }
+}
+
+/// Picks the entries a trend report compares the latest scan against
+///
+/// Returns the previous scan of the same directory and the scan closest to
+/// `window` before the latest one (if that is a different, older scan)
+pub fn baselines<'a>(
+    entries: &'a [HistoryEntry],
+    latest: &HistoryEntry,
+    window: Duration,
+) -> (Option<&'a HistoryEntry>, Option<&'a HistoryEntry>) {
+    let earlier: Vec<&HistoryEntry> = entries
+        .iter()
+        .filter(|e| e.scanned_directory == latest.scanned_directory && e.scanned_at < latest.scanned_at)
+        .collect();
+
+    let previous = earlier.last().copied();
+    let target = latest.scanned_at - window;
+    let window_start = earlier
+        .iter()
+        .min_by_key(|e| (e.scanned_at - target).num_seconds().abs())
+        .copied()
+        .filter(|e| Some(*e) != previous);
+
+    (previous, window_start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(days_ago: i64, wasted: u64, groups: usize) -> HistoryEntry {
+        let now = DateTime::parse_from_rfc3339("2024-06-01T00:00:00Z").unwrap().with_timezone(&Utc);
+        HistoryEntry {
+            scanned_at: now - Duration::days(days_ago),
+            scanned_directory: PathBuf::from("/data"),
+            total_files: 100,
+            duplicate_groups: groups,
+            total_duplicates: groups,
+            total_wasted_space: wasted,
+            groups: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_parse_and_baselines() {
+        let content = [entry(0, 3072, 3), entry(31, 1024, 1), entry(7, 2048, 2)]
+            .iter()
+            .map(|e| serde_json::to_string(e).unwrap())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        let entries = parse(&content).unwrap();
+        assert_eq!(entries[0], entry(31, 1024, 1));
+
+        let latest = entries.last().unwrap();
+        let (previous, month) = baselines(&entries, latest, Duration::days(30));
+        assert_eq!(previous, Some(&entry(7, 2048, 2)));
+        assert_eq!(month, Some(&entry(31, 1024, 1)));
+
+        assert_eq!(
+            describe_change(month.unwrap(), latest),
+            "wasted space grew 2.00 KB since 2024-05-01 (31 days ago), 2 more groups"
+        );
+        assert!(parse("not json").is_err());
+
+        // Groups that came and went are counted even when the total stays
+        let ids = |ids: &[&str]| ids.iter().map(|id| (id.to_string(), 2)).collect();
+        let before = HistoryEntry { groups: ids(&["a", "b"]), ..entry(7, 2048, 2) };
+        let after = HistoryEntry { groups: ids(&["b", "c"]), ..entry(0, 2048, 2) };
+        assert_eq!(
+            describe_change(&before, &after),
+            "wasted space unchanged since 2024-05-25 (7 days ago), same number of groups (1 new, 1 resolved)"
+        );
+
+        // Only new groups and groups with more copies are reported as changes
+        let group = |id: &str, copies: usize| crate::DuplicateGroup {
+            id: id.to_string(),
+            hash: id.to_string(),
+            size: 10,
+            files: (0..copies).map(|n| crate::FileInfo {
+                path: PathBuf::from(format!("/data/{}{}", id, n)),
+                size: 10,
+                hash: id.to_string(),
+                modified: std::time::UNIX_EPOCH,
+                created: None,
+                compression: None,
+            }).collect(),
+            total_size: 10 * copies as u64,
+            wasted_space: 10 * (copies as u64 - 1),
+        };
+        let scan = ScanResult { duplicate_groups: vec![group("a", 2), group("b", 3), group("c", 2)], ..Default::default() };
+        let changes = changes_since(&scan, &before).unwrap();
+        let ids: Vec<&str> = changes.duplicate_groups.iter().map(|g| g.id.as_str()).collect();
+        assert_eq!(ids, ["b", "c"]);
+        assert_eq!((changes.total_duplicates, changes.total_wasted_space), (3, 30));
+    }
+}
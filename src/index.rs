@@ -0,0 +1,234 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::{DuplicateFinderError, FileInfo, ScanResult};
+
+/// Current version of the persisted index format
+const INDEX_FORMAT_VERSION: u32 = 1;
+
+/// On-disk representation of a [`HashIndex`]
+#[derive(Serialize, Deserialize)]
+struct IndexFile {
+    format_version: u32,
+    files: Vec<FileInfo>,
+}
+
+/// Lookup table of hashed files, queryable by path and by content hash
+///
+/// An index can be filled by the scanner ([`crate::FileScanner::build_index`]),
+/// merged with indexes from other scans or machines, and saved to disk so
+/// later comparisons don't have to re-hash anything
+#[derive(Debug, Clone, Default)]
+pub struct HashIndex {
+    /// Every indexed file, by path
+    files: BTreeMap<PathBuf, FileInfo>,
+
+    /// Paths of all files sharing a content hash
+    by_hash: HashMap<String, Vec<PathBuf>>,
+}
+
+impl HashIndex {
+    /// Creates an empty index
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds an index from hashed files (later entries win on equal paths)
+    pub fn from_files(files: impl IntoIterator<Item = FileInfo>) -> Self {
+        let mut index = Self::new();
+        for file in files {
+            index.insert(file);
+        }
+        index
+    }
+
+    /// Builds an index from the files listed in a scan result
+    ///
+    /// Scan results only contain files that have duplicates, so unique
+    /// files of that scan are not part of the index
+    pub fn from_scan(scan_result: &ScanResult) -> Self {
+        Self::from_files(
+            scan_result
+                .duplicate_groups
+                .iter()
+                .flat_map(|g| g.files.iter().cloned()),
+        )
+    }
+
+    /// Adds or replaces a file, returning the previous entry for its path
+    pub fn insert(&mut self, file: FileInfo) -> Option<FileInfo> {
+        let previous = self.remove(&file.path);
+        self.by_hash
+            .entry(file.hash.clone())
+            .or_default()
+            .push(file.path.clone());
+        self.files.insert(file.path.clone(), file);
+        previous
+    }
+
+    /// Removes a file by path
+    pub fn remove(&mut self, path: &Path) -> Option<FileInfo> {
+        let file = self.files.remove(path)?;
+        if let Some(paths) = self.by_hash.get_mut(&file.hash) {
+            paths.retain(|p| p != path);
+            if paths.is_empty() {
+                self.by_hash.remove(&file.hash);
+            }
+        }
+        Some(file)
+    }
+
+    /// Removes a path and every file below it, returning how many were removed
+    pub fn remove_below(&mut self, path: &Path) -> usize {
+        let below: Vec<PathBuf> = self
+            .files
+            .range(path.to_path_buf()..)
+            .take_while(|(indexed, _)| indexed.starts_with(path))
+            .map(|(indexed, _)| indexed.clone())
+            .collect();
+
+        for indexed in &below {
+            self.remove(indexed);
+        }
+        below.len()
+    }
+
+    /// Adds every file of another index, replacing entries with equal paths
+    pub fn merge(&mut self, other: HashIndex) {
+        for file in other.files.into_values() {
+            self.insert(file);
+        }
+    }
+
+    /// Looks up a file by path
+    pub fn get(&self, path: &Path) -> Option<&FileInfo> {
+        self.files.get(path)
+    }
+
+    /// Returns every file with the given content hash
+    pub fn find_hash<'a>(&'a self, hash: &str) -> impl Iterator<Item = &'a FileInfo> + 'a {
+        self.by_hash
+            .get(hash)
+            .into_iter()
+            .flatten()
+            .filter_map(|path| self.files.get(path))
+    }
+
+    /// Returns true when any indexed file has the given content hash
+    pub fn contains_hash(&self, hash: &str) -> bool {
+        self.by_hash.contains_key(hash)
+    }
+
+    /// Number of indexed files
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Returns true when the index holds no files
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Iterates over all files in path order
+    pub fn files(&self) -> impl Iterator<Item = &FileInfo> {
+        self.files.values()
+    }
+
+    /// Consumes the index, returning all files in path order
+    pub fn into_files(self) -> Vec<FileInfo> {
+        self.files.into_values().collect()
+    }
+
+    /// Serializes the index to JSON
+    pub fn to_json(&self) -> Result<String> {
+        let file = IndexFile {
+            format_version: INDEX_FORMAT_VERSION,
+            files: self.files.values().cloned().collect(),
+        };
+        Ok(serde_json::to_string(&file)?)
+    }
+
+    /// Deserializes an index written by [`HashIndex::to_json`]
+    pub fn from_json(json: &str) -> Result<Self> {
+        let file: IndexFile = serde_json::from_str(json)?;
+        if file.format_version > INDEX_FORMAT_VERSION {
+            return Err(DuplicateFinderError::UnsupportedFormatVersion {
+                found: file.format_version,
+                supported: INDEX_FORMAT_VERSION,
+            }
+            .into());
+        }
+        Ok(Self::from_files(file.files))
+    }
+
+    /// Saves the index to a JSON file, replacing it in one step
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        crate::atomic::write_async(path, self.to_json()?.into_bytes()).await?;
+        Ok(())
+    }
+
+    /// Loads an index saved with [`HashIndex::save`]
+    pub async fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path).await.map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => DuplicateFinderError::PathNotFound {
+                path: path.to_path_buf(),
+            },
+            _ => DuplicateFinderError::Io(e),
+        })?;
+        Self::from_json(&content)
+    }
+}
+
+impl FromIterator<FileInfo> for HashIndex {
+    fn from_iter<I: IntoIterator<Item = FileInfo>>(iter: I) -> Self {
+        Self::from_files(iter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, hash: &str) -> FileInfo {
+        FileInfo {
+            path: PathBuf::from(path),
+            size: 10,
+            hash: hash.to_string(),
+            modified: std::time::UNIX_EPOCH,
+            created: None,
+            compression: None,
+        }
+    }
+
+    #[test]
+    fn test_index_queries_and_merge() {
+        let mut index = HashIndex::from_files([file("/a/1", "x"), file("/a/2", "x"), file("/b/3", "y")]);
+        assert_eq!(index.find_hash("x").count(), 2);
+        assert_eq!(index.get(Path::new("/b/3")).map(|f| f.hash.as_str()), Some("y"));
+
+        // Re-inserting a path with new content moves it to the new hash
+        index.insert(file("/a/2", "z"));
+        assert_eq!(index.find_hash("x").count(), 1);
+        assert!(index.contains_hash("z"));
+
+        let other: HashIndex = [file("/c/4", "y")].into_iter().collect();
+        index.merge(other);
+        assert_eq!(index.find_hash("y").count(), 2);
+
+        assert_eq!(index.remove_below(Path::new("/a")), 2);
+        assert!(!index.contains_hash("x"));
+        assert_eq!(index.len(), 2);
+    }
+
+    #[test]
+    fn test_index_json_round_trip() {
+        let index = HashIndex::from_files([file("/a/1", "x"), file("/b/2", "y")]);
+        let reloaded = HashIndex::from_json(&index.to_json().unwrap()).unwrap();
+        assert_eq!(reloaded.files().collect::<Vec<_>>(), index.files().collect::<Vec<_>>());
+        assert!(HashIndex::from_json(r#"{"format_version": 99, "files": []}"#).is_err());
+    }
+}
@@ -0,0 +1,89 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use tracing::warn;
+
+use crate::IoBackend;
+use crate::hashing::{self, HashFunction, ReadOptions};
+
+/// Metadata of a directory entry, as far as discovery needs it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct EntryStat {
+    pub is_dir: bool,
+    pub is_file: bool,
+    pub len: u64,
+    pub modified: SystemTime,
+    pub created: Option<SystemTime>,
+}
+
+impl From<std::fs::Metadata> for EntryStat {
+    fn from(metadata: std::fs::Metadata) -> Self {
+        Self {
+            is_dir: metadata.is_dir(),
+            is_file: metadata.is_file(),
+            len: metadata.len(),
+            modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            created: metadata.created().ok(),
+        }
+    }
+}
+
+/// How local files and their metadata are read
+///
+/// Discovery stats the entries of a directory as one batch and hashing
+/// reads each file through here, so a backend can cut per-file system calls
+#[async_trait]
+pub(crate) trait LocalIo: Send + Sync {
+    /// Metadata of every path, without following symbolic links
+    ///
+    /// Fails as a whole only when the batch could not be issued
+    async fn stat_batch(&self, paths: Vec<PathBuf>) -> std::io::Result<Vec<std::io::Result<EntryStat>>>;
+
+    /// Digest of a file's contents, read as `options` say; `size` is its
+    /// expected length
+    async fn hash_file(&self, path: &Path, size: u64, function: Arc<dyn HashFunction>, options: ReadOptions) -> std::io::Result<String>;
+}
+
+/// Regular system calls on tokio's blocking pool, where files are hashed too
+pub(crate) struct TokioIo;
+
+#[async_trait]
+impl LocalIo for TokioIo {
+    async fn stat_batch(&self, paths: Vec<PathBuf>) -> std::io::Result<Vec<std::io::Result<EntryStat>>> {
+        tokio::task::spawn_blocking(move || {
+            paths.iter().map(|path| std::fs::symlink_metadata(path).map(EntryStat::from)).collect()
+        })
+        .await
+        .map_err(std::io::Error::other)
+    }
+
+    async fn hash_file(&self, path: &Path, size: u64, function: Arc<dyn HashFunction>, options: ReadOptions) -> std::io::Result<String> {
+        hashing::hash_sized_file(path, function.as_ref(), size, options).await
+    }
+}
+
+/// Creates the backend selected with `--io-backend`
+///
+/// Falls back to regular system calls where io_uring is unavailable: on
+/// other systems, old kernels, or when a seccomp policy (e.g. in a
+/// container) forbids it
+pub(crate) fn open_backend(backend: IoBackend) -> Arc<dyn LocalIo> {
+    match backend {
+        IoBackend::Tokio => Arc::new(TokioIo),
+        #[cfg(target_os = "linux")]
+        IoBackend::Uring => match crate::uring::UringIo::new() {
+            Ok(io) => Arc::new(io),
+            Err(e) => {
+                warn!("io_uring is not available ({}), using regular reads", e);
+                Arc::new(TokioIo)
+            }
+        },
+        #[cfg(not(target_os = "linux"))]
+        IoBackend::Uring => {
+            warn!("io_uring is only available on Linux, using regular reads");
+            Arc::new(TokioIo)
+        }
+    }
+}
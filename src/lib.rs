@@ -15,12 +15,12 @@
 //! ## Example
 //!
 //! ```rust,no_run
-//! use duplicate_finder::{Cli, FileScanner};
+//! use duplicate_finder::{FileScanner, ScannerConfig};
 //! use std::path::PathBuf;
 //!
 //! #[tokio::main]
 //! async fn main() -> anyhow::Result<()> {
-//!     let config = Cli {
+//!     let config = ScannerConfig {
 //!         directory: PathBuf::from("/path/to/scan"),
 //!         min_size: 1024, // Skip files smaller than 1KB
 //!         ..Default::default()
@@ -33,135 +33,1005 @@
 //!     Ok(())
 //! }
 //! ```
+//!
+//! Applications without an async runtime call the blocking counterparts,
+//! which run one of their own:
+//!
+//! ```rust,no_run
+//! use duplicate_finder::{FileScanner, ScannerConfig};
+//!
+//! let results = FileScanner::new(ScannerConfig::default()).scan_blocking()?;
+//! println!("Found {} duplicate groups", results.duplicate_groups.len());
+//! # anyhow::Ok(())
+//! ```
+//!
+//! With the `threads` feature, scans of local files that compare raw bytes
+//! run on a thread pool instead: discovery and hashing go without tokio,
+//! and the blocking counterparts start no runtime for them. Scans using
+//! options that need the async pipeline (remote roots, reference
+//! directories, archives, checkpoints and the like) run on tokio as before
+//!
+//! ## Tracing
+//!
+//! The library logs through [`tracing`]; only
+//! [`OutputFormatter::display_results`] writes to stdout. A scan runs in a
+//! `scan` span with `discovery`, `hashing` and `grouping` spans for its
+//! phases and a debug-level `hash_file` span per file; deletions and merges
+//! run in `delete` and `merge` spans. Counts and sizes are span and event
+//! fields, so an application's own subscriber can aggregate them. With the
+//! `otel` feature, `otel::Telemetry` exports them over OTLP
 
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::SystemTime;
 
 use anyhow::Result;
-use clap::Parser;
+#[cfg(feature = "cli")]
+use clap::ValueHint;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
 use tokio::fs;
+use tokio::io::AsyncRead;
 
 // Public modules - available for external usage
 pub mod scanner;
+pub mod archives;
+pub mod chunks;
+pub mod audio;
+pub mod companions;
+pub mod compression;
 pub mod output;
+pub mod style;
+pub mod manifest;
+pub mod diff;
+pub mod directories;
+pub mod discovery;
+pub mod documents;
+pub mod empty;
+pub mod grouping;
+pub mod hashing;
+pub mod watch;
+pub mod metrics;
+pub mod gate;
+pub mod history;
+pub mod baseline;
+#[cfg(feature = "cli")]
+pub mod cli;
+#[cfg(feature = "cli")]
+pub mod config;
+pub mod similarity;
+pub mod source;
+pub mod text;
+pub mod video;
+pub mod index;
+mod io_backend;
+pub mod storage;
+pub mod priority;
+mod throttle;
+mod checkpoint;
+mod spill;
+mod prefilter;
+pub mod lock;
+pub mod atomic;
+pub mod xattr;
+pub mod merge;
+pub mod delete;
+pub mod preview;
+pub mod pause;
+pub mod progress;
+pub mod names;
+#[cfg(feature = "sqlite")]
+pub mod database;
+#[cfg(feature = "s3")]
+pub mod s3;
+#[cfg(feature = "sftp")]
+pub mod sftp;
+#[cfg(feature = "otel")]
+pub mod otel;
+#[cfg(feature = "threads")]
+mod threads;
+#[cfg(target_os = "linux")]
+mod uring;
 
+#[cfg(feature = "cli")]
+pub use cli::{CacheAction, Cli, Command};
 pub use output::OutputFormatter;
+pub use pause::PauseSwitch;
+pub use progress::ProgressObserver;
+pub use style::TextStyle;
 // Re-export of main types for convenient library usage
+pub use discovery::{DiscoveredFile, FileDiscovery};
+pub use grouping::{DuplicateGrouper, group_duplicates};
+pub use hashing::{ContentHasher, HashFunction};
+pub use index::HashIndex;
 pub use scanner::FileScanner;
+pub use source::{FileSource, LocalSource};
+pub use spill::SpilledGroups;
+pub use watch::WatchEngine;
 
-/// CLI interface - structure for parsing command line arguments
+/// Configuration of a scan and of what is done with its results
 ///
-/// This structure defines all possible parameters that a user
-/// can pass to the application via command line
-#[derive(Parser, Debug, Clone)]
-#[command(name = "duplicate-finder")]
-#[command(about = "A blazingly fast duplicate file finder")]
-#[command(version = "0.1.0")]
-#[command(author = "Ilia Denisov <iodenisof@gmail.com>")]
-#[command(long_about = "
-Duplicate Finder recursively scans directories to identify duplicate files
-using SHA-256 hashing. It supports various filtering options and output formats
-to help you clean up your file system efficiently.
-
-Examples:
-  duplicate-finder -d /home/user/Documents
-  duplicate-finder -s 1024 -e --output-format json
-  duplicate-finder -o json -O /path/to/output.json
-")]
-pub struct Cli {
+/// Every option of the command line except the commands themselves; with
+/// the `cli` feature, [`cli::Cli`] parses it from the command line
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "cli", derive(clap::Args))]
+pub struct ScannerConfig {
     /// Directory to scan for duplicates (defaults to current directory)
-    #[arg(short, long, default_value = ".")]
-    #[arg(help = "Path to the directory to scan")]
+    ///
+    /// An `s3://bucket/prefix` or `sftp://[user@]host/path` URL scans
+    /// remote storage instead
+    #[cfg_attr(feature = "cli", arg(short, long, default_value = ".", value_hint = ValueHint::DirPath))]
+    #[cfg_attr(feature = "cli", arg(help = "Path to the directory to scan (or an s3://bucket/prefix or sftp://[user@]host/path URL)"))]
     pub directory: PathBuf,
 
+    /// Further local directories scanned alongside `--directory`
+    ///
+    /// All roots are walked concurrently; `--group-scope` decides whether
+    /// copies in different roots count as duplicates
+    #[cfg_attr(feature = "cli", arg(long = "root", value_name = "DIR", value_hint = ValueHint::DirPath, conflicts_with_all = ["files_from", "watch"]))]
+    #[cfg_attr(feature = "cli", arg(help = "Also scan this directory (repeatable)"))]
+    pub extra_roots: Vec<PathBuf>,
+
+    /// Whether files are compared across all roots or only within each root
+    #[cfg_attr(feature = "cli", arg(long, default_value = "combined"))]
+    #[cfg_attr(feature = "cli", arg(help = "Group duplicates across all roots (combined) or within each root only (per-root)"))]
+    pub group_scope: GroupScope,
+
     /// Minimum file size in bytes (files smaller than this will be ignored)
-    #[arg(short = 's', long, default_value = "0")]
-    #[arg(help = "Minimum file size in bytes")]
+    #[cfg_attr(feature = "cli", arg(short = 's', long, default_value = "0"))]
+    #[cfg_attr(feature = "cli", arg(help = "Minimum file size in bytes"))]
     pub min_size: u64,
 
     /// Maximum file size in bytes (0 = no limit)
-    #[arg(short = 'S', long, default_value = "0")]
-    #[arg(help = "Maximum file size in bytes (0 for no limit)")]
+    #[cfg_attr(feature = "cli", arg(short = 'S', long, default_value = "0"))]
+    #[cfg_attr(feature = "cli", arg(help = "Maximum file size in bytes (0 for no limit)"))]
     pub max_size: u64,
 
     /// Include hidden files and directories in the scan
-    #[arg(short = 'H', long)]
-    #[arg(help = "Include hidden files and directories")]
+    #[cfg_attr(feature = "cli", arg(short = 'H', long))]
+    #[cfg_attr(feature = "cli", arg(help = "Include hidden files and directories"))]
     pub include_hidden: bool,
 
     /// Exclude empty files from the scan
-    #[arg(short = 'e', long)]
-    #[arg(help = "Exclude empty files")]
+    #[cfg_attr(feature = "cli", arg(short = 'e', long))]
+    #[cfg_attr(feature = "cli", arg(help = "Exclude empty files"))]
     pub exclude_empty: bool,
 
+    /// How files are compared: byte-identical contents, identical media data or visual similarity
+    #[cfg_attr(feature = "cli", arg(short = 'm', long, default_value = "exact"))]
+    #[cfg_attr(feature = "cli", arg(help = "Comparison mode"))]
+    pub mode: Mode,
+
+    /// Largest perceptual hash distance (in bits, out of 64) still considered similar
+    #[cfg_attr(feature = "cli", arg(long, default_value = "6", value_name = "BITS", value_parser = clap::value_parser!(u32).range(0..=64)))]
+    #[cfg_attr(feature = "cli", arg(help = "Maximum perceptual hash distance for --mode image-similar and video (0 = visually identical)"))]
+    pub max_distance: u32,
+
+    /// Smallest estimated share of common content for two text files to be grouped
+    #[cfg_attr(feature = "cli", arg(long, default_value = "80", value_name = "PERCENT", value_parser = clap::value_parser!(u8).range(1..=100)))]
+    #[cfg_attr(feature = "cli", arg(help = "Minimum similarity percentage for --mode text-similar"))]
+    pub min_similarity: u8,
+
+    /// Content hash used by exact comparisons
+    #[cfg_attr(feature = "cli", arg(long, default_value = "sha256"))]
+    #[cfg_attr(feature = "cli", arg(help = "Hash algorithm for exact mode (blake3 is faster; xxh3 is fastest but only guards against accidental collisions)"))]
+    pub hash_algo: HashAlgorithm,
+
+    /// Compare the files of every exact-mode group byte by byte before reporting it
+    #[cfg_attr(feature = "cli", arg(long))]
+    #[cfg_attr(feature = "cli", arg(help = "Byte-compare the members of each duplicate group and split any that differ (local files, exact mode)"))]
+    pub verify_matches: bool,
+
+    /// Files at least this large are hashed with all cores, one at a time,
+    /// when the hash algorithm supports it (blake3)
+    #[cfg_attr(feature = "cli", arg(long, value_name = "SIZE", default_value = "1GiB", value_parser = parse_size))]
+    #[cfg_attr(feature = "cli", arg(help = "Hash files of at least SIZE with multiple threads (--hash-algo blake3)"))]
+    pub parallel_hash_min_size: u64,
+
+    /// Hash only the files whose size and first and last 64 KiB match
+    /// another file's; the others are never read in full
+    ///
+    /// Every file is listed before any is hashed, so discovery no longer
+    /// overlaps hashing and the listing is held in memory (exact mode,
+    /// local files)
+    #[cfg_attr(feature = "cli", arg(long))]
+    #[cfg_attr(feature = "cli", arg(help = "Hash only files whose size, head and tail chunks match another file (exact mode, local files)"))]
+    pub prefilter: bool,
+
+    /// Same-size files smaller than this are hashed without comparing their
+    /// head and tail chunks first
+    #[cfg_attr(feature = "cli", arg(long, value_name = "SIZE", default_value = "1MiB", value_parser = parse_size, requires = "prefilter"))]
+    #[cfg_attr(feature = "cli", arg(help = "Compare head and tail chunks with --prefilter only for files of at least SIZE"))]
+    pub prefilter_min_size: u64,
+
+    /// Bytes read from a file at a time when hashing it (0 = automatic)
+    ///
+    /// Automatic picks larger reads for spinning disks and network mounts
+    /// and smaller ones for small files
+    #[cfg_attr(feature = "cli", arg(long, value_name = "SIZE", default_value = "0", value_parser = parse_size))]
+    #[cfg_attr(feature = "cli", arg(help = "Read SIZE bytes at a time when hashing (0 to pick by storage and file size)"))]
+    pub buffer_size: u64,
+
+    /// Leave hashed files in the page cache
+    ///
+    /// By default the OS is told that files are read once from start to
+    /// end, so it reads ahead further and drops the pages after hashing
+    #[cfg_attr(feature = "cli", arg(long))]
+    #[cfg_attr(feature = "cli", arg(help = "Keep hashed files in the OS page cache instead of hinting sequential, read-once access"))]
+    pub keep_cache: bool,
+
+    /// How file contents are read when hashing them in exact mode
+    #[cfg_attr(feature = "cli", arg(long, default_value = "auto"))]
+    #[cfg_attr(feature = "cli", arg(help = "Read files for hashing with buffered reads, memory maps, or maps for large files only (auto)"))]
+    pub io_mode: IoMode,
+
+    /// System interface used to stat and read local files
+    #[cfg_attr(feature = "cli", arg(long, default_value = "tokio"))]
+    #[cfg_attr(feature = "cli", arg(help = "Read local files with regular system calls (tokio) or batched through io_uring (uring, Linux 5.6+)"))]
+    pub io_backend: IoBackend,
+
+    /// Hash exactly the files named in a list instead of traversing the directory
+    #[cfg_attr(feature = "cli", arg(long, value_name = "FILE", conflicts_with = "watch"))]
+    #[cfg_attr(feature = "cli", arg(help = "Scan only the files listed in FILE, one per line ('-' reads stdin)"))]
+    pub files_from: Option<PathBuf>,
+
+    /// Archive formats whose members are hashed and compared like regular files
+    #[cfg_attr(feature = "cli", arg(long, value_name = "FORMATS", value_delimiter = ','))]
+    #[cfg_attr(feature = "cli", arg(help = "Also scan files inside archives (comma-separated: zip, tar, tar.gz)"))]
+    pub scan_archives: Vec<ArchiveFormat>,
+
+    /// List loose files whose content is already stored in a scanned archive
+    #[cfg_attr(feature = "cli", arg(long, requires = "scan_archives"))]
+    #[cfg_attr(feature = "cli", arg(help = "Report loose files already contained in scanned archives (--print-duplicates then lists them)"))]
+    pub report_archived: bool,
+
+    /// Measure data shared between large files that are not identical, e.g. successive VM image snapshots
+    #[cfg_attr(feature = "cli", arg(long))]
+    #[cfg_attr(feature = "cli", arg(help = "Estimate chunk-level dedup savings between large files that differ as a whole"))]
+    pub chunk_analysis: bool,
+
+    /// Files smaller than this are left out of `--chunk-analysis`
+    #[cfg_attr(feature = "cli", arg(long, value_name = "SIZE", default_value = "64MiB", value_parser = parse_size))]
+    #[cfg_attr(feature = "cli", arg(help = "Minimum file size for --chunk-analysis"))]
+    pub chunk_min_size: u64,
+
+    /// Compare file names case-insensitively in the name modes
+    #[cfg_attr(feature = "cli", arg(long))]
+    #[cfg_attr(feature = "cli", arg(help = "Ignore letter case when comparing names (--mode name and name-conflict)"))]
+    pub ignore_case: bool,
+
+    /// Treat composed and decomposed Unicode spellings of a name as equal in the name modes
+    #[cfg_attr(feature = "cli", arg(long))]
+    #[cfg_attr(feature = "cli", arg(help = "Compare names after Unicode NFC normalization (--mode name and name-conflict)"))]
+    pub normalize_unicode: bool,
+
+    /// Compare compressed files by their decompressed content
+    #[cfg_attr(feature = "cli", arg(long))]
+    #[cfg_attr(feature = "cli", arg(help = "Hash the decompressed content of .gz, .xz and .zst files (exact mode)"))]
+    pub decompress: bool,
+
+    /// Hash text files in canonical form: no UTF-8 BOM and LF line endings
+    #[cfg_attr(feature = "cli", arg(long))]
+    #[cfg_attr(feature = "cli", arg(help = "Ignore byte order marks and CRLF/LF differences in text files"))]
+    pub normalize_text: bool,
+
+    /// Also ignore whitespace at line ends when normalizing text files
+    #[cfg_attr(feature = "cli", arg(long, requires = "normalize_text"))]
+    #[cfg_attr(feature = "cli", arg(help = "Ignore trailing whitespace in text files (with --normalize-text)"))]
+    pub trim_trailing_whitespace: bool,
+
     /// Output format: text or JSON
-    #[arg(short, long, default_value = "text")]
-    #[arg(help = "Output format")]
+    #[cfg_attr(feature = "cli", arg(short, long, default_value = "text"))]
+    #[cfg_attr(feature = "cli", arg(help = "Output format"))]
     pub output_format: OutputFormat,
 
     /// Save results to a file instead of printing to stdout
-    #[arg(short = 'O', long)]
-    #[arg(help = "Output file path")]
+    #[cfg_attr(feature = "cli", arg(short = 'O', long))]
+    #[cfg_attr(feature = "cli", arg(help = "Output file path"))]
     pub output_file: Option<PathBuf>,
 
-    /// Number of threads for file processing (0 = automatic)
-    #[arg(short = 'j', long, default_value = "0")]
-    #[arg(help = "Number of processing threads (0 for auto-detect)")]
-    pub threads: usize,
+    /// Also record files without duplicates, so results of separate scans
+    /// can be combined with `combine`
+    #[cfg_attr(feature = "cli", arg(long))]
+    #[cfg_attr(feature = "cli", arg(help = "Also list files without duplicates in the results (for combining scans)"))]
+    pub include_unique: bool,
+
+    /// Number of files read and hashed at the same time (0 = automatic)
+    ///
+    /// Automatic picks a count suited to the storage of the scan roots
+    /// (see [`storage::detect`])
+    #[cfg_attr(feature = "cli", arg(short = 'j', long, visible_alias = "threads", value_name = "COUNT", default_value = "0"))]
+    #[cfg_attr(feature = "cli", arg(help = "Files hashed at the same time (0 to pick by storage: 2 for spinning disks, more for SSDs and network mounts)"))]
+    pub hash_concurrency: usize,
+
+    /// Number of directories listed at the same time
+    #[cfg_attr(feature = "cli", arg(long, value_name = "COUNT", default_value = "4"))]
+    #[cfg_attr(feature = "cli", arg(help = "Directories listed at the same time; raise it for high-latency network mounts"))]
+    pub walk_concurrency: usize,
+
+    /// Most files read at the same time from one device (0 = automatic)
+    ///
+    /// Applies on top of `--hash-concurrency`, so a spinning disk scanned
+    /// together with an SSD is not overwhelmed
+    #[cfg_attr(feature = "cli", arg(long, value_name = "COUNT", default_value = "0"))]
+    #[cfg_attr(feature = "cli", arg(help = "Files read at the same time from one device (0 to pick by its storage kind)"))]
+    pub device_concurrency: usize,
+
+    /// Most bytes per second read from local files, across all devices
+    #[cfg_attr(feature = "cli", arg(long, value_name = "RATE", value_parser = parse_rate))]
+    #[cfg_attr(feature = "cli", arg(help = "Read local files at no more than RATE overall, e.g. 50MB/s"))]
+    pub max_throughput: Option<u64>,
+
+    /// Most bytes per second read from any one device
+    #[cfg_attr(feature = "cli", arg(long, value_name = "RATE", value_parser = parse_rate))]
+    #[cfg_attr(feature = "cli", arg(help = "Read each device at no more than RATE, e.g. 20MB/s"))]
+    pub device_max_throughput: Option<u64>,
+
+    /// Run with low CPU and I/O priority and fewer concurrent reads
+    ///
+    /// Meant for scans during working hours. The binary lowers the process
+    /// priority (see [`priority::lower_priority`]); the scanner itself only
+    /// reads fewer files at a time when `--hash-concurrency` is automatic
+    #[cfg_attr(feature = "cli", arg(long))]
+    #[cfg_attr(feature = "cli", arg(help = "Low-impact mode: lowest CPU and I/O priority and fewer concurrent reads"))]
+    pub nice: bool,
+
+    /// Hashed files kept in memory before they are spilled to temporary files
+    ///
+    /// Only applies to scans that can be grouped piece by piece (exact-key
+    /// modes without reference trees, unique-file reports, chunk or
+    /// directory analysis); other scans keep every file in memory. Duplicate
+    /// groups holding more files than this are spilled too when the results
+    /// are written out as they are read back: JSON, fdupes and path lists,
+    /// or reports narrowed down with `--top`
+    #[cfg_attr(feature = "cli", arg(long, value_name = "COUNT", default_value = "1000000"))]
+    #[cfg_attr(feature = "cli", arg(help = "Hashed files kept in memory before spilling them to temporary files"))]
+    pub spill_threshold: usize,
+
+    /// Directory for spilled files (the system temporary directory by default)
+    #[cfg_attr(feature = "cli", arg(long, value_name = "DIR", value_hint = ValueHint::DirPath))]
+    #[cfg_attr(feature = "cli", arg(help = "Directory for temporary files of large scans (default: system temp directory)"))]
+    pub spill_dir: Option<PathBuf>,
+
+    /// Record every hashed file in this checkpoint file
+    #[cfg_attr(feature = "cli", arg(long, value_name = "FILE"))]
+    #[cfg_attr(feature = "cli", arg(help = "Record hashed files in FILE so an interrupted scan can be continued with --resume"))]
+    pub checkpoint: Option<PathBuf>,
+
+    /// Continue an interrupted scan from its checkpoint file
+    ///
+    /// Files recorded in the checkpoint and unchanged since are not hashed
+    /// again; newly hashed files are appended to the same checkpoint
+    #[cfg_attr(feature = "cli", arg(long, value_name = "FILE", conflicts_with = "checkpoint"))]
+    #[cfg_attr(feature = "cli", arg(help = "Continue an interrupted scan from its checkpoint FILE, hashing only files it lacks"))]
+    pub resume: Option<PathBuf>,
+
+    /// How often hashed files are written to the checkpoint file
+    #[cfg_attr(feature = "cli", arg(long, value_name = "INTERVAL", value_parser = parse_interval, default_value = "30s"))]
+    #[cfg_attr(feature = "cli", arg(help = "Write hashed files to the checkpoint at least every INTERVAL"))]
+    pub checkpoint_interval: std::time::Duration,
+
+    /// Give up on a file that takes longer than this to fingerprint
+    ///
+    /// Guards against hung network mounts and failing disk sectors; the
+    /// file is skipped and counted as a file error
+    #[cfg_attr(feature = "cli", arg(long, value_name = "INTERVAL", value_parser = parse_interval))]
+    #[cfg_attr(feature = "cli", arg(help = "Skip files that take longer than INTERVAL to hash, e.g. 60s (hung mounts, bad sectors)"))]
+    pub file_timeout: Option<std::time::Duration>,
+
+    /// Times a file is read again after a transient error
+    ///
+    /// Transient errors (e.g. `EAGAIN`, network filesystem timeouts or
+    /// Windows sharing violations) are retried with a doubling delay;
+    /// other errors skip the file right away
+    #[cfg_attr(feature = "cli", arg(long, value_name = "COUNT", default_value = "3"))]
+    #[cfg_attr(feature = "cli", arg(help = "Retry files failing with transient errors (timeouts, EAGAIN, sharing violations) up to COUNT times"))]
+    pub retries: u32,
+
+    /// Keep each file's digest in an extended attribute and reuse it
+    ///
+    /// The attribute records the device, inode, size and modification time
+    /// the digest belongs to, so later scans only hash files that changed or
+    /// were restored since. It travels with the file when it is renamed or
+    /// moved within a filesystem
+    #[cfg_attr(feature = "cli", arg(long))]
+    #[cfg_attr(feature = "cli", arg(help = "Store hashes in a user.duplicate-finder.<algo> extended attribute and trust them while inode, size and mtime match"))]
+    pub xattr_cache: bool,
+
+    /// Percentage of cached hashes checked by hashing the files anyway
+    ///
+    /// A different random sample is checked on every run; stale entries
+    /// are logged and replaced
+    #[cfg_attr(feature = "cli", arg(long, value_name = "PERCENT", value_parser = parse_percentage, default_value = "0"))]
+    #[cfg_attr(feature = "cli", arg(help = "Re-hash a random PERCENT of files with cached hashes to catch stale entries, e.g. 1%"))]
+    pub cache_verify: f64,
+
+    /// Wait for other runs using the same checkpoint, output or history file
+    ///
+    /// Runs lock these files while they use them; by default a second run
+    /// fails right away instead (e.g. when cron starts a scan before the
+    /// last one finished)
+    #[cfg_attr(feature = "cli", arg(long))]
+    #[cfg_attr(feature = "cli", arg(help = "Wait for other runs to release the checkpoint, output and history files instead of failing"))]
+    pub wait_for_lock: bool,
+
+    /// Wrap up the scan after this long and report what was hashed so far
+    #[cfg_attr(feature = "cli", arg(long, value_name = "INTERVAL", value_parser = parse_interval))]
+    #[cfg_attr(feature = "cli", arg(help = "Stop hashing after INTERVAL (e.g. 2h) and report the groups found so far as partial"))]
+    pub deadline: Option<std::time::Duration>,
+
+    /// Wrap up the scan at this local time of day (the next time it comes)
+    #[cfg_attr(feature = "cli", arg(long, value_name = "HH:MM", value_parser = parse_time_of_day, conflicts_with = "deadline"))]
+    #[cfg_attr(feature = "cli", arg(help = "Stop hashing at local time HH:MM (e.g. 06:00) and report the groups found so far as partial"))]
+    pub stop_at: Option<chrono::NaiveTime>,
+
+    /// How often a status line is logged while stderr is not a terminal
+    ///
+    /// Takes the place of the progress bar in cron jobs and CI logs; off
+    /// with `--quiet` or `--verbose`
+    #[cfg_attr(feature = "cli", arg(long, value_name = "INTERVAL", value_parser = parse_interval, default_value = "1m"))]
+    #[cfg_attr(feature = "cli", arg(help = "Log a progress line every INTERVAL when stderr is not a terminal (cron, CI)"))]
+    pub heartbeat: std::time::Duration,
 
     /// Enable verbose output with detailed progress information
-    #[arg(short, long)]
-    #[arg(help = "Verbose output")]
+    #[cfg_attr(feature = "cli", arg(short, long))]
+    #[cfg_attr(feature = "cli", arg(help = "Verbose output"))]
     pub verbose: bool,
 
+    /// Also write the log to this file, whatever the console shows
+    ///
+    /// The file gets debug-level detail (every file hashed, skipped or
+    /// deleted) and is appended to, so repeated runs keep one history
+    #[cfg_attr(feature = "cli", arg(long, value_name = "FILE", value_hint = ValueHint::FilePath))]
+    #[cfg_attr(feature = "cli", arg(help = "Append a detailed log (every file hashed, skipped or deleted) to FILE"))]
+    pub log_file: Option<PathBuf>,
+
+    /// Format of the `--log-file` lines
+    #[cfg_attr(feature = "cli", arg(long, value_name = "FORMAT", default_value = "text", requires = "log_file"))]
+    #[cfg_attr(feature = "cli", arg(help = "Format of --log-file lines: text, or json with one object per line"))]
+    pub log_format: LogFormat,
+
     /// Follow symbolic links (be careful with this option!)
-    #[arg(short = 'L', long)]
-    #[arg(help = "Follow symbolic links (can cause infinite loops!)")]
+    #[cfg_attr(feature = "cli", arg(short = 'L', long))]
+    #[cfg_attr(feature = "cli", arg(help = "Follow symbolic links (can cause infinite loops!)"))]
     pub follow_symlinks: bool,
 
     /// Maximum depth for directory recursion (0 = unlimited)
-    #[arg(short = 'D', long, default_value = "0")]
-    #[arg(help = "Maximum directory depth (0 for unlimited)")]
+    #[cfg_attr(feature = "cli", arg(short = 'D', long, default_value = "0"))]
+    #[cfg_attr(feature = "cli", arg(help = "Maximum directory depth (0 for unlimited)"))]
     pub max_depth: usize,
+
+    /// Only report files that duplicate content of this reference tree
+    ///
+    /// May be an `s3://` or `sftp://` URL, e.g. to find local files already backed up
+    #[cfg_attr(feature = "cli", arg(short = 'R', long, value_name = "DIR", value_hint = ValueHint::DirPath, conflicts_with = "watch"))]
+    #[cfg_attr(feature = "cli", arg(help = "Report only files that already exist in this reference directory or s3:// / sftp:// URL (never listed for deletion)"))]
+    pub reference: Option<PathBuf>,
+
+    /// Which duplicate groups are reported, depending on where their copies live
+    #[cfg_attr(feature = "cli", arg(long, default_value = "all"))]
+    #[cfg_attr(feature = "cli", arg(help = "Report all duplicates, only copies spread over different directories (cross-dir) or only copies within one directory (same-dir)"))]
+    pub scope: Scope,
+
+    /// Directory level at which `--scope` compares locations (0 = each file's own directory)
+    #[cfg_attr(feature = "cli", arg(long, value_name = "DEPTH", default_value = "0"))]
+    #[cfg_attr(feature = "cli", arg(help = "Compare directories DEPTH levels below the root for --scope (0 for each file's own directory)"))]
+    pub scope_depth: usize,
+
+    /// Also detect whole directory trees with identical contents
+    #[cfg_attr(feature = "cli", arg(long, conflicts_with = "reference"))]
+    #[cfg_attr(feature = "cli", arg(help = "Report identical directory trees as single groups"))]
+    pub duplicate_dirs: bool,
+
+    /// Report directories whose contents are at least this percent contained in another directory
+    #[cfg_attr(feature = "cli", arg(long, value_name = "PERCENT", value_parser = clap::value_parser!(u8).range(1..=100), conflicts_with = "reference"))]
+    #[cfg_attr(feature = "cli", arg(help = "Report directories at least PERCENT contained in another directory (e.g. 90)"))]
+    pub dir_overlap: Option<u8>,
+
+    /// List directories that are empty or would be after removing redundant copies
+    #[cfg_attr(feature = "cli", arg(long))]
+    #[cfg_attr(feature = "cli", arg(help = "Report empty directories, including those emptied by removing duplicates"))]
+    pub report_empty_dirs: bool,
+
+    /// Treat RAW+JPEG pairs and their XMP sidecars as units when choosing redundant copies
+    #[cfg_attr(feature = "cli", arg(long, conflicts_with = "reference"))]
+    #[cfg_attr(feature = "cli", arg(help = "Keep JPEGs next to their RAW files and list XMP sidecars of redundant photos"))]
+    pub companions: bool,
+
+    /// Remove empty directories after the scan (and after `merge --move`)
+    #[cfg_attr(feature = "cli", arg(long))]
+    #[cfg_attr(feature = "cli", arg(help = "Remove empty directories left below the scanned or merged directories"))]
+    pub prune_empty_dirs: bool,
+
+    /// Delete duplicates after the scan, choosing the files to keep with
+    /// `--interactive` or `--auto`
+    #[cfg_attr(feature = "cli", arg(long, conflicts_with_all = ["watch", "every", "print_duplicates"]))]
+    #[cfg_attr(feature = "cli", arg(help = "Delete duplicate files after the scan (with --interactive or --auto)"))]
+    pub delete: bool,
+
+    /// Ask which files of each group to keep, like `fdupes --delete`
+    #[cfg_attr(feature = "cli", arg(long, requires = "delete"))]
+    #[cfg_attr(feature = "cli", arg(help = "Ask which files of each duplicate group to keep, by number, all or none"))]
+    pub interactive: bool,
+
+    /// Delete the files chosen with `--interactive` once every group was
+    /// answered instead of after each answer
+    #[cfg_attr(feature = "cli", arg(long, requires = "interactive"))]
+    #[cfg_attr(feature = "cli", arg(help = "Delete only after every group was answered instead of after each answer"))]
+    pub delete_at_end: bool,
+
+    /// How `--interactive` previews draw image thumbnails
+    #[cfg_attr(feature = "cli", arg(long, default_value = "auto", value_name = "PROTOCOL"))]
+    #[cfg_attr(feature = "cli", arg(help = "Image thumbnails in --interactive previews: auto, kitty, iterm, sixel or off"))]
+    pub thumbnails: ThumbnailProtocol,
+
+    /// Command run on a file when `--interactive` previews it, e.g.
+    /// `qlmanage -p` or `less`; `{}` stands for the path
+    #[cfg_attr(feature = "cli", arg(long, value_name = "COMMAND", requires = "interactive"))]
+    #[cfg_attr(feature = "cli", arg(help = "Also run COMMAND on files previewed in --interactive ({} stands for the path, else it is appended)"))]
+    pub exec_preview: Option<String>,
+
+    /// Keep the original of every group (the oldest file) and delete its
+    /// redundant copies without asking group by group; only for scans whose
+    /// groups hold byte-identical copies (see
+    /// [`ScannerConfig::finds_identical_bytes`])
+    #[cfg_attr(feature = "cli", arg(long, requires = "delete", conflicts_with = "interactive"))]
+    #[cfg_attr(feature = "cli", arg(help = "Keep the oldest file of every group and delete the other copies without prompting (exact mode)"))]
+    pub auto: bool,
+
+    /// Proceed with `--auto` without typing the confirmation
+    #[cfg_attr(feature = "cli", arg(short = 'y', long, requires = "auto"))]
+    #[cfg_attr(feature = "cli", arg(help = "Delete with --auto without asking for confirmation (for scripts)"))]
+    pub yes: bool,
+
+    /// Report layout: per duplicate group or per directory
+    #[cfg_attr(feature = "cli", arg(short = 'g', long, default_value = "hash"))]
+    #[cfg_attr(feature = "cli", arg(help = "Organize the report by duplicate group (hash) or by directory"))]
+    pub group_by: GroupBy,
+
+    /// Print only the results: no banner, progress bar, summary or recommendations
+    #[cfg_attr(feature = "cli", arg(short = 'q', long, conflicts_with = "verbose"))]
+    #[cfg_attr(feature = "cli", arg(help = "Quiet mode: print only the results"))]
+    pub quiet: bool,
+
+    /// Replace emoji and box-drawing characters with plain ASCII
+    #[cfg_attr(feature = "cli", arg(long, visible_alias = "no-emoji"))]
+    #[cfg_attr(feature = "cli", arg(help = "Plain ASCII output without emoji or Unicode decoration"))]
+    pub ascii: bool,
+
+    /// When to use colors in text output
+    #[cfg_attr(feature = "cli", arg(long, default_value = "auto", value_name = "WHEN"))]
+    #[cfg_attr(feature = "cli", arg(help = "Colorize text output: auto, always or never"))]
+    pub color: ColorChoice,
+
+    /// Print only the paths of redundant copies (originals are never listed)
+    ///
+    /// Only for modes that find equal contents (see
+    /// [`Mode::finds_equal_contents`]), as the list is meant for `rm`
+    #[cfg_attr(feature = "cli", arg(long))]
+    #[cfg_attr(feature = "cli", arg(help = "Print only paths of redundant duplicate copies, one per line"))]
+    pub print_duplicates: bool,
+
+    /// Separate printed and listed paths with NUL bytes instead of newlines
+    #[cfg_attr(feature = "cli", arg(short = '0', long = "null"))]
+    #[cfg_attr(feature = "cli", arg(help = "Separate printed paths and --files-from entries with NUL instead of newline (for xargs -0, find -print0)"))]
+    pub null_separated: bool,
+
+    /// List each group on a single line in fdupes output (like `fdupes -1`)
+    #[cfg_attr(feature = "cli", arg(short = '1', long))]
+    #[cfg_attr(feature = "cli", arg(help = "Fdupes format: list each group on a single line"))]
+    pub sameline: bool,
+
+    /// Limit reports to the N groups wasting the most space (0 = all)
+    #[cfg_attr(feature = "cli", arg(long, default_value = "0", value_name = "N"))]
+    #[cfg_attr(feature = "cli", arg(help = "Show only the N groups wasting the most space (0 for all)"))]
+    pub top: usize,
+
+    /// Keep running after the first scan and report again whenever files change
+    #[cfg_attr(feature = "cli", arg(short = 'w', long))]
+    #[cfg_attr(feature = "cli", arg(help = "Watch for filesystem changes and re-emit updated results"))]
+    pub watch: bool,
+
+    /// Serve Prometheus metrics on this address while the process runs
+    #[cfg_attr(feature = "cli", arg(long, value_name = "ADDR"))]
+    #[cfg_attr(feature = "cli", arg(help = "Expose Prometheus metrics at http://ADDR/metrics (e.g. 127.0.0.1:9184)"))]
+    pub metrics_addr: Option<std::net::SocketAddr>,
+
+    /// Export scan spans and metrics to this OpenTelemetry collector
+    #[cfg_attr(feature = "cli", arg(long, value_name = "URL"))]
+    #[cfg_attr(feature = "cli", arg(help = "Export spans and metrics over OTLP/HTTP to URL (e.g. http://localhost:4318; needs the otel feature)"))]
+    pub otel_endpoint: Option<String>,
+
+    /// Append a summary of every scan to this history file (JSON Lines)
+    #[cfg_attr(feature = "cli", arg(long, value_name = "FILE"))]
+    #[cfg_attr(feature = "cli", arg(help = "Append a summary of each scan to a history file"))]
+    pub history: Option<PathBuf>,
+
+    /// Only report groups that are new or have more copies than in the
+    /// previous scan of the same directory recorded in the history file
+    ///
+    /// The history still records every group, and the exit status only
+    /// reflects the reported ones
+    #[cfg_attr(feature = "cli", arg(long, requires = "history"))]
+    #[cfg_attr(feature = "cli", arg(help = "Only report duplicate groups that are new or grew since the previous scan in the history file"))]
+    pub changes_only: bool,
+
+    /// Save the full result as the baseline of the scanned directory, in
+    /// `.duplicate-finder/baseline.json` (see [`baseline::path`])
+    #[cfg_attr(feature = "cli", arg(long))]
+    #[cfg_attr(feature = "cli", arg(help = "Save the result as the scanned directory's baseline (.duplicate-finder/baseline.json)"))]
+    pub save_baseline: bool,
+
+    /// Print what changed since the baseline of the scanned directory
+    /// after the report
+    #[cfg_attr(feature = "cli", arg(long))]
+    #[cfg_attr(feature = "cli", arg(help = "Show what changed since the scanned directory's saved baseline"))]
+    pub against_baseline: bool,
+
+    /// Repeat the scan at this interval until interrupted
+    #[cfg_attr(feature = "cli", arg(long, value_name = "INTERVAL", value_parser = parse_interval, conflicts_with = "watch"))]
+    #[cfg_attr(feature = "cli", arg(help = "Rescan every INTERVAL (e.g. 30m, 24h, 7d) until interrupted"))]
+    pub every: Option<std::time::Duration>,
+
+    /// Fail (exit status 1) when duplicates waste more than this many bytes
+    #[cfg_attr(feature = "cli", arg(long, value_name = "SIZE", value_parser = parse_size))]
+    #[cfg_attr(feature = "cli", arg(help = "CI gate: fail if wasted space exceeds SIZE (e.g. 100MB)"))]
+    pub fail_if_wasted: Option<u64>,
+
+    /// Fail (exit status 1) when more than this many duplicate groups are found
+    #[cfg_attr(feature = "cli", arg(long, value_name = "COUNT"))]
+    #[cfg_attr(feature = "cli", arg(help = "CI gate: fail if more than COUNT duplicate groups are found"))]
+    pub fail_if_groups: Option<usize>,
+
+    /// Exit with status 0 even when duplicates are found
+    #[cfg_attr(feature = "cli", arg(long))]
+    #[cfg_attr(feature = "cli", arg(help = "Exit with status 0 when duplicates are found (errors still fail)"))]
+    pub no_fail_on_duplicates: bool,
 }
 
-/// Default implementation for Cli - useful for testing and programmatic usage
-impl Default for Cli {
+/// Default implementation for ScannerConfig - useful for testing and programmatic usage
+impl Default for ScannerConfig {
     fn default() -> Self {
         Self {
             directory: PathBuf::from("."),
+            extra_roots: Vec::new(),
+            group_scope: GroupScope::Combined,
             min_size: 0,
             max_size: 0,
             include_hidden: false,
             exclude_empty: false,
+            mode: Mode::Exact,
+            max_distance: 6,
+            min_similarity: 80,
+            hash_algo: HashAlgorithm::Sha256,
+            verify_matches: false,
+            parallel_hash_min_size: 1024 * 1024 * 1024,
+            prefilter: false,
+            prefilter_min_size: 1024 * 1024,
+            buffer_size: 0,
+            keep_cache: false,
+            io_mode: IoMode::Auto,
+            io_backend: IoBackend::Tokio,
+            files_from: None,
+            scan_archives: Vec::new(),
+            report_archived: false,
+            chunk_analysis: false,
+            chunk_min_size: 64 * 1024 * 1024,
+            ignore_case: false,
+            normalize_unicode: false,
+            decompress: false,
+            normalize_text: false,
+            trim_trailing_whitespace: false,
             output_format: OutputFormat::Text,
             output_file: None,
-            threads: 0,
+            include_unique: false,
+            hash_concurrency: 0,
+            walk_concurrency: 4,
+            device_concurrency: 0,
+            max_throughput: None,
+            device_max_throughput: None,
+            nice: false,
+            spill_threshold: 1_000_000,
+            spill_dir: None,
+            checkpoint: None,
+            resume: None,
+            checkpoint_interval: std::time::Duration::from_secs(30),
+            file_timeout: None,
+            retries: 3,
+            xattr_cache: false,
+            cache_verify: 0.0,
+            wait_for_lock: false,
+            deadline: None,
+            stop_at: None,
+            heartbeat: std::time::Duration::from_secs(60),
             verbose: false,
+            log_file: None,
+            log_format: LogFormat::Text,
             follow_symlinks: false,
             max_depth: 0,
+            reference: None,
+            scope: Scope::All,
+            scope_depth: 0,
+            duplicate_dirs: false,
+            dir_overlap: None,
+            report_empty_dirs: false,
+            companions: false,
+            prune_empty_dirs: false,
+            delete: false,
+            interactive: false,
+            delete_at_end: false,
+            thumbnails: ThumbnailProtocol::Auto,
+            exec_preview: None,
+            auto: false,
+            yes: false,
+            group_by: GroupBy::Hash,
+            quiet: false,
+            ascii: false,
+            color: ColorChoice::Auto,
+            print_duplicates: false,
+            null_separated: false,
+            sameline: false,
+            top: 0,
+            watch: false,
+            metrics_addr: None,
+            otel_endpoint: None,
+            history: None,
+            changes_only: false,
+            save_baseline: false,
+            against_baseline: false,
+            every: None,
+            fail_if_wasted: None,
+            fail_if_groups: None,
+            no_fail_on_duplicates: false,
+        }
+    }
+}
+
+impl ScannerConfig {
+    /// Directories to scan: `--directory` followed by every `--root`
+    pub fn roots(&self) -> impl Iterator<Item = &Path> {
+        std::iter::once(self.directory.as_path()).chain(self.extra_roots.iter().map(PathBuf::as_path))
+    }
+
+    /// Root a path was found below (the deepest one when roots are nested)
+    pub fn root_of(&self, path: &Path) -> Option<&Path> {
+        self.roots()
+            .filter(|root| path.starts_with(root))
+            .max_by_key(|root| root.components().count())
+    }
+
+    /// Returns true when every group holds byte-identical copies: exact
+    /// mode hashing files as stored, not decompressed or normalized
+    pub fn finds_identical_bytes(&self) -> bool {
+        self.mode == Mode::Exact && !self.decompress && !self.normalize_text
+    }
+}
+
+/// Comparison mode
+///
+/// Decides what makes two files duplicates of each other
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Mode {
+    /// Byte-identical contents (SHA-256, or the --hash-algo digest)
+    #[default]
+    Exact,
+    /// Visually similar images (perceptual hash), e.g. resized or re-encoded copies
+    ImageSimilar,
+    /// Images with identical pixels, ignoring EXIF/XMP and other metadata
+    ImagePixels,
+    /// MP3/FLAC/M4A files with identical audio data, ignoring tags and cover art
+    Audio,
+    /// Videos with matching sampled frames and duration, e.g. re-containered or re-encoded copies (needs FFmpeg)
+    Video,
+    /// Text files sharing most of their content, e.g. lightly edited documents or configs (MinHash)
+    TextSimilar,
+    /// PDF and Office documents with identical content, ignoring timestamps, revision IDs and other metadata
+    Document,
+    /// Files with the same name, whatever their content (see --ignore-case and --normalize-unicode)
+    Name,
+    /// Files with the same name but different content, which clash when synced onto one filesystem
+    NameConflict,
+    /// Files with the same name and size, without reading them (a heuristic first pass)
+    Quick,
+    /// Files with the same size and modification time, without reading them (unverified candidates)
+    Metadata,
+}
+
+impl Mode {
+    /// Returns true for modes that group files without reading them, so
+    /// their groups are only candidates
+    pub fn is_unverified(self) -> bool {
+        matches!(self, Mode::Quick | Mode::Metadata)
+    }
+
+    /// Returns true for modes that group files with equal contents, raw or
+    /// decoded; name, unverified and similarity modes group files whose
+    /// contents may differ
+    pub fn finds_equal_contents(self) -> bool {
+        matches!(self, Mode::Exact | Mode::ImagePixels | Mode::Audio | Mode::Document)
+    }
+}
+
+/// Content hash of exact comparisons
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum HashAlgorithm {
+    /// SHA-256: collision-resistant even against deliberately crafted files
+    #[default]
+    Sha256,
+    /// BLAKE3: as collision-resistant as SHA-256 and much faster
+    Blake3,
+    /// XXH3-128: several times faster, but only guards against accidental collisions
+    Xxh3,
+}
+
+/// How file contents are read for hashing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum IoMode {
+    /// Buffered reads, which work on every filesystem
+    Read,
+    /// Memory-mapped files, avoiding the copy into a read buffer (fastest on local SSDs)
+    Mmap,
+    /// Memory maps for files of at least 64 MiB, buffered reads for the rest
+    ///
+    /// Files smaller than a read buffer take a single read and huge files
+    /// are hashed with several threads when the digest allows it
+    #[default]
+    Auto,
+}
+
+impl IoMode {
+    /// Smallest file `auto` maps; below this, setting up the mapping costs
+    /// more than the copy it saves
+    pub const AUTO_MAP_MIN_SIZE: u64 = 64 * 1024 * 1024;
+
+    /// Returns true when a file of `size` bytes is memory-mapped
+    ///
+    /// Empty files cannot be mapped
+    pub fn maps(self, size: u64) -> bool {
+        match self {
+            IoMode::Read => false,
+            IoMode::Mmap => size > 0,
+            IoMode::Auto => size >= Self::AUTO_MAP_MIN_SIZE,
         }
     }
 }
 
+/// System interface for reading local files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum IoBackend {
+    /// One system call per operation, run on tokio's blocking pool
+    #[default]
+    Tokio,
+    /// Batched submissions through io_uring, for trees of millions of small files (Linux only)
+    Uring,
+}
+
 /// Output format
 ///
 /// Supported formats for scan result presentation
-#[derive(Debug, Clone, clap::ValueEnum, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
 pub enum OutputFormat {
     /// Human-readable text format with Unicode characters
     Text,
     /// Machine-readable JSON format
     Json,
+    /// Plain path groups separated by blank lines, compatible with `fdupes`
+    Fdupes,
+    /// SQLite database (requires an output file; new scans are appended)
+    Sqlite,
+    /// Markdown report for GitHub issues, wikis and merge request descriptions
+    Markdown,
+}
+
+/// Archive format whose members can be scanned
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum ArchiveFormat {
+    /// Zip archives (.zip)
+    Zip,
+    /// Uncompressed tar archives (.tar)
+    Tar,
+    /// Gzip-compressed tar archives (.tar.gz, .tgz)
+    #[cfg_attr(feature = "cli", value(name = "tar.gz"))]
+    TarGz,
+}
+
+/// Single-file compression format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    /// gzip (.gz)
+    Gzip,
+    /// XZ (.xz)
+    Xz,
+    /// Zstandard (.zst)
+    Zstd,
+}
+
+/// Report layout
+///
+/// Controls how duplicates are organized in human-readable reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum GroupBy {
+    /// One section per duplicate group (files sharing the same hash)
+    Hash,
+    /// One section per directory, showing where its duplicates have copies
+    Directory,
+}
+
+/// Which files of a multi-root scan are compared with each other
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum GroupScope {
+    /// Files of every root are compared with each other
+    Combined,
+    /// Files are only compared with files of the same root
+    PerRoot,
+}
+
+/// Which duplicate groups are reported, depending on where their copies live
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Scope {
+    /// Every duplicate group
+    All,
+    /// Only groups with copies in at least two different directories
+    CrossDir,
+    /// Only copies that share a directory, e.g. `photo.jpg` and `photo (1).jpg`
+    SameDir,
+}
+
+/// Color mode for text output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum ColorChoice {
+    /// Colorize when stdout is a terminal and NO_COLOR is not set
+    Auto,
+    /// Always emit ANSI color codes
+    Always,
+    /// Never emit ANSI color codes
+    Never,
+}
+
+/// Terminal graphics protocol for image thumbnails in `--interactive`
+/// previews
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum ThumbnailProtocol {
+    /// Detect kitty or iTerm2-compatible terminals, else show no thumbnails
+    Auto,
+    /// Kitty graphics protocol (kitty, Ghostty, Konsole)
+    Kitty,
+    /// iTerm2 inline images (iTerm2, WezTerm)
+    Iterm,
+    /// Sixel graphics (foot, mlterm, xterm -ti vt340, Windows Terminal)
+    Sixel,
+    /// No thumbnails, only dimensions and EXIF fields
+    Off,
+}
+
+/// Format of the `--log-file` lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum LogFormat {
+    /// Timestamped lines as on the console, without colors
+    Text,
+    /// One JSON object per event, with its fields and spans
+    Json,
 }
 
 /// File metadata
 ///
 /// Contains all necessary information about a file for duplicate detection
 /// and result presentation to the user
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
 pub struct FileInfo {
     /// Full path to the file
     pub path: PathBuf,
@@ -177,13 +1047,29 @@ pub struct FileInfo {
 
     /// Creation time (if available on the filesystem)
     pub created: Option<SystemTime>,
+
+    /// Compression wrapper, set when `hash` describes the decompressed
+    /// content (only with `--decompress`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Compression>,
 }
 
 /// Group of duplicate files
 ///
 /// Represents a set of files with identical contents (same hash)
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct DuplicateGroup {
+    /// Identity of the group that stays the same across scans
+    ///
+    /// Derived from the comparison settings (mode, hash algorithm and
+    /// content normalization) and the group's key, so the group keeps it
+    /// while copies come and go. Groups confined to one root or location by
+    /// `--group-scope per-root` or `--scope same-dir` also depend on that
+    /// root or location. Similarity modes key a group by one of its files,
+    /// so their IDs only last while that file stays in the group
+    #[serde(default)]
+    pub id: String,
+
     /// SHA-256 hash that identifies this group
     pub hash: String,
 
@@ -200,17 +1086,284 @@ pub struct DuplicateGroup {
     pub wasted_space: u64,
 }
 
+/// Per-directory duplicate statistics
+///
+/// Rolls duplicate files up to the directory that directly contains them
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct DirStat {
+    /// Directory containing the duplicate files
+    pub path: PathBuf,
+
+    /// Number of files in this directory that belong to a duplicate group
+    pub duplicate_files: usize,
+
+    /// Bytes taken by redundant copies (everything except the original) in this directory
+    pub wasted_bytes: u64,
+}
+
+/// Per-extension duplicate statistics
+///
+/// Summarizes which file types are responsible for duplicates
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct ExtensionStat {
+    /// Lowercased file extension, or "(no extension)"
+    pub extension: String,
+
+    /// Number of files with this extension that belong to a duplicate group
+    pub files: usize,
+
+    /// Number of redundant copies (files minus one original per group)
+    pub duplicate_files: usize,
+
+    /// Bytes taken by redundant copies with this extension
+    pub wasted_bytes: u64,
+}
+
+/// Group of directories with identical contents
+///
+/// Two directories match when they contain the same file names with the
+/// same contents, recursively. Deleting all but one copy of the tree
+/// recovers `wasted_space`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct DirectoryGroup {
+    /// Composite SHA-256 digest of the tree's names and contents
+    pub digest: String,
+
+    /// Bytes of all files in one copy of the tree
+    pub size: u64,
+
+    /// Number of files in one copy of the tree
+    pub file_count: usize,
+
+    /// Identical directories; the first one is treated as the original
+    pub directories: Vec<PathBuf>,
+
+    /// Space that could be saved by removing all but one copy
+    pub wasted_space: u64,
+}
+
+/// A directory whose contents largely exist in another directory
+///
+/// Contents are compared by hash regardless of file names or layout, so a
+/// reorganized copy of a backup still shows up
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct DirectoryOverlap {
+    /// Directory whose contents were looked up
+    pub directory: PathBuf,
+
+    /// Directory that holds copies of those contents
+    pub container: PathBuf,
+
+    /// Distinct contents of `directory` also found in `container`
+    pub shared_files: usize,
+
+    /// Distinct contents of `directory`
+    pub total_files: usize,
+
+    /// Bytes of the shared contents (one copy of each)
+    pub shared_bytes: u64,
+}
+
+/// Two text files with largely the same content (only with `--mode text-similar`)
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct SimilarPair {
+    /// One file of the pair
+    pub first: PathBuf,
+
+    /// The other file of the pair
+    pub second: PathBuf,
+
+    /// Estimated percentage of shared content
+    pub similarity: u8,
+}
+
+/// An XMP sidecar that would be orphaned by removing redundant photos (only with `--companions`)
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct Sidecar {
+    /// The sidecar file, to be removed along with its photo
+    pub path: PathBuf,
+
+    /// Redundant photo the sidecar belongs to
+    pub companion: PathBuf,
+}
+
+/// A file inside an archive
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct ArchiveCopy {
+    /// The archive on disk
+    pub archive: PathBuf,
+
+    /// Path of the member within the archive
+    pub member: PathBuf,
+}
+
+/// A loose file whose content is already stored in an archive (only with `--report-archived`)
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct ArchivedFile {
+    /// The file on disk, safe to delete while the archive is kept
+    pub path: PathBuf,
+
+    /// Size of the file in bytes
+    pub size: u64,
+
+    /// Archive members with the same content
+    pub copies: Vec<ArchiveCopy>,
+}
+
+/// Result of the chunk-level analysis (only with `--chunk-analysis`)
+///
+/// Large files are split into content-defined chunks of about 80 KiB, so
+/// data that moved within a file is still recognized
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct ChunkAnalysis {
+    /// Number of files that were chunked (one per distinct content)
+    pub files_analyzed: usize,
+
+    /// Combined size of the chunked files in bytes
+    pub total_bytes: u64,
+
+    /// Bytes needed to store every distinct chunk once
+    pub unique_bytes: u64,
+
+    /// Pairs of files sharing at least a tenth of the smaller file, by shared bytes (descending)
+    pub shared_pairs: Vec<SharedChunks>,
+}
+
+impl ChunkAnalysis {
+    /// Bytes a chunk-level deduplicating store would save on the chunked files
+    pub fn estimated_savings(&self) -> u64 {
+        self.total_bytes - self.unique_bytes
+    }
+}
+
+/// Two files sharing chunks
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct SharedChunks {
+    /// One file of the pair
+    pub first: PathBuf,
+
+    /// The other file of the pair
+    pub second: PathBuf,
+
+    /// Bytes of the chunks both files contain
+    pub shared_bytes: u64,
+
+    /// Shared bytes as a percentage of the smaller file
+    pub percent: u8,
+}
+
+/// A directory without content
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct EmptyDirectory {
+    /// Outermost empty directory (everything below it is empty too)
+    pub path: PathBuf,
+
+    /// True when the directory only becomes empty once redundant copies are removed
+    pub after_cleanup: bool,
+}
+
+impl DirectoryOverlap {
+    /// Percentage of `directory` contained in `container`
+    pub fn percent(&self) -> f64 {
+        if self.total_files == 0 {
+            return 0.0;
+        }
+        self.shared_files as f64 * 100.0 / self.total_files as f64
+    }
+}
+
+impl DuplicateGroup {
+    /// Files that are redundant copies of the group's original
+    ///
+    /// The first file (oldest by modification time) is treated as the original
+    pub fn redundant_files(&self) -> impl Iterator<Item = &FileInfo> {
+        self.files.iter().skip(1)
+    }
+
+    /// Returns true when the files beyond the first are copies of it
+    ///
+    /// False for groups of files that only share a name (`--mode name` and
+    /// `name-conflict`), which waste no space although they hold data; such
+    /// groups count towards no duplicate totals or statistics
+    pub fn holds_copies(&self) -> bool {
+        self.wasted_space > 0 || self.total_size == 0
+    }
+}
+
+/// Current version of the serialized `ScanResult` format
+///
+/// Bump this whenever the JSON shape changes and teach
+/// [`ScanResult::from_json`] how to upgrade the previous version.
+///
+/// * 1 - initial format (no `format_version` field)
+/// * 2 - adds `format_version`, `directory_stats` and `extension_stats`
+/// * 3 - adds `file_errors`
+/// * 4 - adds `directory_groups`
+/// * 5 - adds `directory_overlaps`
+/// * 6 - adds `empty_directories`
+/// * 7 - adds `similar_pairs`
+/// * 8 - adds `sidecars`
+/// * 9 - adds `archived_files`
+/// * 10 - adds `compression` to files
+/// * 11 - adds `unique_files`
+/// * 12 - adds `chunk_analysis`
+/// * 13 - adds `partial`
+/// * 14 - adds `id` to duplicate groups
+/// * 15 - adds `mode` and `hash_algo`
+pub const FORMAT_VERSION: u32 = 15;
+
+/// Format version assumed for documents written before versioning existed
+fn legacy_format_version() -> u32 {
+    1
+}
+
 /// Full scan result
 ///
 /// Contains aggregated statistics and all found duplicate groups
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ScanResult {
+    /// Version of the serialized format (see [`FORMAT_VERSION`])
+    #[serde(default = "legacy_format_version")]
+    pub format_version: u32,
+
     /// Total number of files processed
     pub total_files: usize,
 
     /// Groups of duplicate files
     pub duplicate_groups: Vec<DuplicateGroup>,
 
+    /// Identical directory trees (only with `--duplicate-dirs`)
+    #[serde(default)]
+    pub directory_groups: Vec<DirectoryGroup>,
+
+    /// Directories largely contained in other directories (only with `--dir-overlap`)
+    #[serde(default)]
+    pub directory_overlaps: Vec<DirectoryOverlap>,
+
+    /// Empty directories (only with `--report-empty-dirs`)
+    #[serde(default)]
+    pub empty_directories: Vec<EmptyDirectory>,
+
+    /// Pairs of similar text files within the groups (only with `--mode text-similar`)
+    #[serde(default)]
+    pub similar_pairs: Vec<SimilarPair>,
+
+    /// XMP sidecars of redundant photos (only with `--companions`)
+    #[serde(default)]
+    pub sidecars: Vec<Sidecar>,
+
+    /// Loose files already contained in scanned archives (only with `--report-archived`)
+    #[serde(default)]
+    pub archived_files: Vec<ArchivedFile>,
+
+    /// Files without duplicates (only with `--include-unique`)
+    #[serde(default)]
+    pub unique_files: Vec<FileInfo>,
+
+    /// Data shared between large files (only with `--chunk-analysis`)
+    #[serde(default)]
+    pub chunk_analysis: Option<ChunkAnalysis>,
+
     /// Total number of duplicate files across all groups
     pub total_duplicates: usize,
 
@@ -222,6 +1375,39 @@ pub struct ScanResult {
 
     /// Root directory that was scanned
     pub scanned_directory: PathBuf,
+
+    /// Comparison mode of the scan (`--mode`)
+    #[serde(default)]
+    pub mode: Mode,
+
+    /// Content hash of the scan (`--hash-algo`)
+    #[serde(default)]
+    pub hash_algo: HashAlgorithm,
+
+    /// Files and directories that were skipped because they could not be read
+    #[serde(default)]
+    pub file_errors: usize,
+
+    /// The scan wrapped up early at `--deadline` or `--stop-at`; groups only
+    /// hold the files hashed until then
+    #[serde(default)]
+    pub partial: bool,
+
+    /// Duplicate statistics per directory, sorted by wasted bytes (descending)
+    #[serde(default)]
+    pub directory_stats: Vec<DirStat>,
+
+    /// Duplicate statistics per file extension, sorted by file count (descending)
+    #[serde(default)]
+    pub extension_stats: Vec<ExtensionStat>,
+
+    /// Duplicate groups of a scan that found more of them than fit in memory
+    /// (see `--spill-threshold`); `duplicate_groups` is empty then
+    ///
+    /// Use [`ScanResult::for_each_group`] and [`ScanResult::group_count`] to
+    /// cover both. Serializing the result leaves these groups out
+    #[serde(skip)]
+    pub spilled_groups: Option<Arc<SpilledGroups>>,
 }
 
 /// Application custom errors
@@ -241,6 +1427,10 @@ pub enum DuplicateFinderError {
     #[error("Path does not exist: {path}")]
     PathNotFound { path: PathBuf },
 
+    /// Serialized results are newer than this build understands
+    #[error("Unsupported result format version {found} (this build supports up to {supported})")]
+    UnsupportedFormatVersion { found: u32, supported: u32 },
+
     /// Invalid configuration: min_size > max_size
     #[error("Invalid size filter: min_size ({min}) > max_size ({max})")]
     InvalidSizeFilter { min: u64, max: u64 },
@@ -249,6 +1439,14 @@ pub enum DuplicateFinderError {
     #[error("Failed to calculate file hash for: {path}")]
     HashCalculationError { path: PathBuf },
 
+    /// A file took longer than `--file-timeout` to fingerprint
+    #[error("Timed out after {}s: {path}", .after.as_secs())]
+    FileTimeout { path: PathBuf, after: std::time::Duration },
+
+    /// Reading a file failed in a way that may go away on its own
+    #[error("Temporary failure reading {path}: {source}")]
+    TransientRead { path: PathBuf, source: std::io::Error },
+
     /// Symbolic link loop detected
     #[error("Symbolic link loop detected at: {path}")]
     SymlinkLoop { path: PathBuf },
@@ -256,6 +1454,300 @@ pub enum DuplicateFinderError {
     /// Maximum recursion depth exceeded
     #[error("Maximum directory depth ({max_depth}) exceeded at: {path}")]
     MaxDepthExceeded { path: PathBuf, max_depth: usize },
+
+    /// Malformed checksum manifest line
+    #[error("Invalid manifest entry at line {line}")]
+    InvalidManifest { line: usize },
+
+    /// A command that compares against a reference tree was run without one
+    #[error("This command requires a reference directory (--reference <DIR>)")]
+    ReferenceRequired,
+
+    /// Another run holds the lock of a shared file
+    #[error("{path} is in use by another duplicate-finder run")]
+    Locked { path: PathBuf },
+
+    /// Saved results of scans comparing files differently cannot be combined
+    #[error("Cannot combine results of {mode:?} scans using {hash_algo:?} with results of {other_mode:?} scans using {other_hash_algo:?} (--mode and --hash-algo must match)")]
+    IncompatibleResults { mode: Mode, hash_algo: HashAlgorithm, other_mode: Mode, other_hash_algo: HashAlgorithm },
+
+    /// A checkpoint was written by a scan with other comparison settings
+    #[error("Checkpoint {path} was written with other comparison settings (--mode, --hash-algo, ...)")]
+    CheckpointMismatch { path: PathBuf },
+
+    /// No built-in or configured profile has this name
+    #[error("Unknown profile '{name}' (built-in: photos, music, code, downloads)")]
+    UnknownProfile { name: String },
+
+    /// The configuration file cannot be understood
+    #[error("Invalid configuration file {path}: {reason}")]
+    InvalidConfig { path: PathBuf, reason: String },
+
+    /// A `DUPLICATE_FINDER_*` environment variable cannot be understood
+    #[error("Invalid environment variable {variable}: {reason}")]
+    InvalidEnvironment { variable: String, reason: String },
+
+    /// Malformed scan history line
+    #[error("Invalid history entry at line {line}")]
+    InvalidHistory { line: usize },
+
+    /// Archive members can only be compared by their raw contents
+    #[error("--scan-archives only works with exact comparison (--mode exact without --normalize-text)")]
+    ArchivesRequireExactMode,
+
+    /// Remote files can only be compared by their raw contents
+    #[error("s3:// and sftp:// locations only work with exact comparison (--mode exact without --normalize-text)")]
+    RemoteRequiresExactMode,
+
+    /// Additional roots cannot be combined with remote storage
+    #[error("--root only works with local directories: {path}")]
+    RemoteRoot { path: PathBuf },
+
+    /// Listing or reading S3-compatible object storage failed
+    #[error("Object storage error: {0}")]
+    ObjectStorage(String),
+
+    /// Connecting to, authenticating with or reading from an SFTP server failed
+    #[error("SFTP error: {0}")]
+    Sftp(String),
+
+    /// An external program needed by the comparison mode is not installed
+    #[error("Required tool not found: {tool}")]
+    ToolNotFound { tool: String },
+}
+
+impl DuplicateFinderError {
+    /// Returns true when repeating the failed operation may succeed
+    pub fn is_transient(&self) -> bool {
+        match self {
+            DuplicateFinderError::Io(e) | DuplicateFinderError::TransientRead { source: e, .. } => is_transient_io(e),
+            _ => false,
+        }
+    }
+}
+
+/// Returns true for I/O errors that tend to go away when retried: busy or
+/// interrupted calls, network filesystem timeouts and, on Windows, files
+/// another program holds open without sharing
+pub(crate) fn is_transient_io(error: &std::io::Error) -> bool {
+    use std::io::ErrorKind;
+    #[cfg(windows)]
+    {
+        const ERROR_SHARING_VIOLATION: i32 = 32;
+        const ERROR_LOCK_VIOLATION: i32 = 33;
+        if matches!(error.raw_os_error(), Some(ERROR_SHARING_VIOLATION | ERROR_LOCK_VIOLATION)) {
+            return true;
+        }
+    }
+    matches!(
+        error.kind(),
+        ErrorKind::WouldBlock
+            | ErrorKind::Interrupted
+            | ErrorKind::TimedOut
+            | ErrorKind::ResourceBusy
+            | ErrorKind::StaleNetworkFileHandle
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::NetworkDown
+            | ErrorKind::NetworkUnreachable
+            | ErrorKind::HostUnreachable
+    )
+}
+
+impl Default for ScanResult {
+    fn default() -> Self {
+        Self {
+            format_version: FORMAT_VERSION,
+            total_files: 0,
+            duplicate_groups: Vec::new(),
+            directory_groups: Vec::new(),
+            directory_overlaps: Vec::new(),
+            empty_directories: Vec::new(),
+            similar_pairs: Vec::new(),
+            sidecars: Vec::new(),
+            archived_files: Vec::new(),
+            unique_files: Vec::new(),
+            chunk_analysis: None,
+            total_duplicates: 0,
+            total_wasted_space: 0,
+            scan_duration: std::time::Duration::ZERO,
+            scanned_directory: PathBuf::new(),
+            mode: Mode::default(),
+            hash_algo: HashAlgorithm::default(),
+            file_errors: 0,
+            partial: false,
+            directory_stats: Vec::new(),
+            extension_stats: Vec::new(),
+            spilled_groups: None,
+        }
+    }
+}
+
+impl ScanResult {
+    /// Number of duplicate groups, including spilled ones
+    pub fn group_count(&self) -> usize {
+        self.duplicate_groups.len() + self.spilled_groups.as_ref().map_or(0, |spilled| spilled.len())
+    }
+
+    /// Calls `visit` with every duplicate group, reading spilled groups
+    /// back from disk one at a time
+    pub fn for_each_group(&self, mut visit: impl FnMut(&DuplicateGroup) -> std::io::Result<()>) -> std::io::Result<()> {
+        for group in &self.duplicate_groups {
+            visit(group)?;
+        }
+        if let Some(spilled) = &self.spilled_groups {
+            for group in spilled.read()? {
+                visit(&group?)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Deserializes a JSON scan result written by this or any older version
+    ///
+    /// Documents from older format versions are upgraded in memory (missing
+    /// statistics are recomputed, groups without an ID get the one a scan
+    /// with default settings would give them) so callers always get a current-format
+    /// result. Documents from newer versions are rejected instead of being
+    /// silently misread
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(json)?;
+        let found = value
+            .get("format_version")
+            .and_then(|v| v.as_u64())
+            .map(|v| v as u32)
+            .unwrap_or_else(legacy_format_version);
+
+        if found > FORMAT_VERSION {
+            return Err(DuplicateFinderError::UnsupportedFormatVersion {
+                found,
+                supported: FORMAT_VERSION,
+            }
+            .into());
+        }
+
+        let mut result: ScanResult = serde_json::from_value(value)?;
+
+        if found < 2 {
+            result.directory_stats = scanner::compute_directory_stats(&result.duplicate_groups);
+            result.extension_stats = scanner::compute_extension_stats(&result.duplicate_groups);
+        }
+        if found < 14 {
+            // The settings of the scan are unknown; most scans use the defaults
+            for group in &mut result.duplicate_groups {
+                group.id = scanner::default_group_id(&group.hash);
+            }
+        }
+        if found < 15 {
+            // Likewise, the comparison is taken for the default one
+            result.mode = Mode::default();
+            result.hash_algo = HashAlgorithm::default();
+        }
+
+        result.format_version = FORMAT_VERSION;
+        Ok(result)
+    }
+
+    /// Loads a JSON scan result previously saved with `-o json -O <FILE>`
+    ///
+    /// Accepts every format version understood by [`ScanResult::from_json`]
+    pub async fn load(path: &Path) -> anyhow::Result<Self> {
+        let content = fs::read_to_string(path).await.map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => DuplicateFinderError::PathNotFound {
+                path: path.to_path_buf(),
+            },
+            _ => DuplicateFinderError::Io(e),
+        })?;
+        Self::from_json(&content)
+    }
+
+    /// Combines the result of another scan into this one
+    ///
+    /// Grouped and unique files of both results are regrouped, so files
+    /// whose only copies were found by the other scan form new groups. Groups
+    /// join when their hashes match, and unique files join the group sharing
+    /// their hash; the oldest file of a group stays its original. A path
+    /// present in both results keeps the entry from `other`, so scans of
+    /// different machines should not report the same paths. Groups keep the
+    /// ID of a group they were formed from.
+    ///
+    /// File counts, errors and durations are added up. Per-scan analyses
+    /// (directory groups and overlaps, empty directories, similar pairs,
+    /// sidecars, archived files) are kept as they are; of the chunk
+    /// analyses only the first one is kept.
+    ///
+    /// Hashes of scans with another mode or hash algorithm cannot be
+    /// compared, so such results are refused and this one is left as it is
+    pub fn merge(&mut self, other: ScanResult) -> Result<(), DuplicateFinderError> {
+        if (self.mode, self.hash_algo) != (other.mode, other.hash_algo) {
+            return Err(DuplicateFinderError::IncompatibleResults {
+                mode: self.mode,
+                hash_algo: self.hash_algo,
+                other_mode: other.mode,
+                other_hash_algo: other.hash_algo,
+            });
+        }
+        let mut files: HashMap<PathBuf, (String, FileInfo)> = HashMap::new();
+        let mut ids: HashMap<String, String> = HashMap::new();
+        let mut overlapping = 0;
+        let sides = [
+            (std::mem::take(&mut self.duplicate_groups), std::mem::take(&mut self.unique_files)),
+            (other.duplicate_groups, other.unique_files),
+        ];
+        for (groups, unique) in sides {
+            let grouped = groups.into_iter().flat_map(|g| {
+                ids.entry(g.hash.clone()).or_insert(g.id);
+                let key = g.hash;
+                g.files.into_iter().map(move |f| (key.clone(), f))
+            });
+            for (key, file) in grouped.chain(unique.into_iter().map(|f| (f.hash.clone(), f))) {
+                if files.insert(file.path.clone(), (key, file)).is_some() {
+                    overlapping += 1;
+                }
+            }
+        }
+
+        // Files are regrouped by the hash of the group they came from
+        let mut regrouped: HashMap<String, Vec<FileInfo>> = HashMap::new();
+        for (key, file) in files.into_values() {
+            regrouped.entry(key).or_default().push(file);
+        }
+        for (hash, mut files) in regrouped {
+            if files.len() == 1 {
+                self.unique_files.extend(files);
+                continue;
+            }
+            files.sort_by(|a, b| a.modified.cmp(&b.modified).then_with(|| a.path.cmp(&b.path)));
+            let size = files[0].size;
+            let total_size: u64 = files.iter().map(|f| f.size).sum();
+            let id = ids.remove(&hash).unwrap_or_else(|| scanner::default_group_id(&hash));
+            self.duplicate_groups.push(DuplicateGroup { id, hash, size, total_size, wasted_space: total_size - size, files });
+        }
+        self.duplicate_groups.sort_by(|a, b| b.wasted_space.cmp(&a.wasted_space).then_with(|| a.hash.cmp(&b.hash)));
+        self.unique_files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        self.total_files = (self.total_files + other.total_files).saturating_sub(overlapping);
+        self.file_errors += other.file_errors;
+        self.partial |= other.partial;
+        self.scan_duration += other.scan_duration;
+        self.directory_groups.extend(other.directory_groups);
+        self.directory_overlaps.extend(other.directory_overlaps);
+        self.empty_directories.extend(other.empty_directories);
+        self.similar_pairs.extend(other.similar_pairs);
+        self.sidecars.extend(other.sidecars);
+        self.archived_files.extend(other.archived_files);
+        if self.chunk_analysis.is_none() {
+            self.chunk_analysis = other.chunk_analysis;
+        }
+        scanner::refresh_statistics(self);
+        Ok(())
+    }
+
+    /// Returns the JSON Schema describing the serialized result format
+    pub fn json_schema() -> serde_json::Value {
+        serde_json::to_value(schemars::schema_for!(ScanResult))
+            .expect("JSON Schema is always serializable")
+    }
 }
 
 impl FileInfo {
@@ -287,6 +1779,95 @@ impl FileInfo {
     /// }
     /// ```
     pub async fn from_path(path: &Path) -> Result<Self, DuplicateFinderError> {
+        Self::from_path_with(path, &HashAlgorithm::Sha256).await
+    }
+
+    /// Creates a new FileInfo whose contents are hashed with `function`
+    /// instead of SHA-256
+    pub async fn from_path_with(path: &Path, function: &dyn HashFunction) -> Result<Self, DuplicateFinderError> {
+        let mut file_info = Self::stat(path).await?;
+
+        // Calculate file hash - this is the most expensive operation
+        file_info.hash = Self::calculate_file_hash(path, function).await
+            .map_err(|_| DuplicateFinderError::HashCalculationError {
+                path: path.to_path_buf(),
+            })?;
+
+        Ok(file_info)
+    }
+
+    /// Creates a new FileInfo from content read from `reader` rather than a
+    /// file, such as an archive member or a network stream
+    ///
+    /// The content is hashed with SHA-256 in the buffers files are read in;
+    /// `path` only labels it. The size is the number of bytes read, and the
+    /// modification time is unknown (the Unix epoch)
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use duplicate_finder::FileInfo;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let file_info = FileInfo::from_reader("backup.tar/notes.txt", &b"hello"[..]).await?;
+    /// assert_eq!(file_info.size, 5);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn from_reader(path: impl Into<PathBuf>, reader: impl AsyncRead + Unpin) -> Result<Self, DuplicateFinderError> {
+        Self::from_reader_with(path, reader, &HashAlgorithm::Sha256).await
+    }
+
+    /// Creates a new FileInfo from content read from `reader`, hashed with
+    /// `function` instead of SHA-256
+    pub async fn from_reader_with(
+        path: impl Into<PathBuf>,
+        mut reader: impl AsyncRead + Unpin,
+        function: &dyn HashFunction,
+    ) -> Result<Self, DuplicateFinderError> {
+        let path = path.into();
+        let (hash, size) = hashing::hash_stream(&mut reader, function).await
+            .map_err(|_| DuplicateFinderError::HashCalculationError { path: path.clone() })?;
+        Ok(FileInfo { path, size, hash, modified: SystemTime::UNIX_EPOCH, created: None, compression: None })
+    }
+
+    /// Blocking version of [`FileInfo::from_path`], for callers without an
+    /// async runtime
+    ///
+    /// # Panics
+    ///
+    /// When called from within an async runtime
+    pub fn from_path_blocking(path: &Path) -> Result<Self, DuplicateFinderError> {
+        block_on(Self::from_path(path))?
+    }
+
+    /// Blocking version of [`FileInfo::from_path_with`]
+    ///
+    /// # Panics
+    ///
+    /// When called from within an async runtime
+    pub fn from_path_with_blocking(path: &Path, function: &dyn HashFunction) -> Result<Self, DuplicateFinderError> {
+        block_on(Self::from_path_with(path, function))?
+    }
+
+    /// A discovered file with the metadata its source reported, leaving
+    /// the hash empty
+    pub(crate) fn from_entry(entry: &source::SourceEntry) -> Self {
+        FileInfo {
+            path: entry.path.clone(),
+            size: entry.size,
+            hash: String::new(),
+            modified: entry.modified,
+            created: entry.created,
+            compression: None,
+        }
+    }
+
+    /// Reads a file's metadata, leaving the hash empty
+    ///
+    /// Comparison modes that derive their own content key fill in `hash`
+    pub(crate) async fn stat(path: &Path) -> Result<Self, DuplicateFinderError> {
         // Get file metadata with detailed error mapping
         let metadata = fs::metadata(path)
             .await
@@ -308,47 +1889,37 @@ impl FileInfo {
         let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
         let created = metadata.created().ok();
 
-        // Calculate file hash - this is the most expensive operation
-        let hash = Self::calculate_file_hash(path).await
-            .map_err(|_| DuplicateFinderError::HashCalculationError {
-                path: path.to_path_buf(),
-            })?;
-
         Ok(FileInfo {
             path: path.to_path_buf(),
             size,
-            hash,
+            hash: String::new(),
             modified,
             created,
+            compression: None,
         })
     }
 
-    /// Calculates SHA-256 hash of file contents using streaming
+    /// Calculates the hash of file contents using streaming
     ///
     /// This function reads the file in chunks to handle large files efficiently
-    /// without loading the entire file into memory
-    async fn calculate_file_hash(path: &Path) -> Result<String, std::io::Error> {
-        use tokio::io::AsyncReadExt;
-
-        let mut file = fs::File::open(path).await?;
-        let mut hasher = Sha256::new();
-        let mut buffer = vec![0u8; 65536]; // 64KB buffer for optimal performance
-
-        loop {
-            let bytes_read = file.read(&mut buffer).await?;
-            if bytes_read == 0 {
-                break; // End of a file reached
-            }
-            hasher.update(&buffer[..bytes_read]);
-        }
-
-        // Convert hash to hexadecimal string
-        Ok(format!("{:x}", hasher.finalize()))
+    /// without loading the entire file into memory. Reading and hashing run
+    /// on the blocking pool, away from the async workers
+    async fn calculate_file_hash(path: &Path, function: &dyn HashFunction) -> Result<String, std::io::Error> {
+        hashing::hash_file_blocking(path, function, hashing::BUFFER_SIZE, hashing::ReadOptions::default()).await
     }
 }
 
 // Utility functions for the library
 
+/// Runs `future` to completion on a runtime of its own, for the blocking
+/// counterparts of async functions
+///
+/// The runtime is multi-threaded, so files are still hashed concurrently
+pub(crate) fn block_on<F: std::future::Future>(future: F) -> std::io::Result<F::Output> {
+    let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build()?;
+    Ok(runtime.block_on(future))
+}
+
 /// Formats byte count into human-readable string
 ///
 /// Converts large numbers into appropriate units (B, KB, MB, GB, TB)
@@ -382,6 +1953,109 @@ pub fn format_bytes(bytes: u64) -> String {
     }
 }
 
+/// Parses a human-readable size such as `512`, `10K`, `100MB` or `1.5 GiB`
+///
+/// Units are binary (1 KB = 1024 bytes) to match [`format_bytes`]; the
+/// suffix is case-insensitive and a missing unit means bytes
+///
+/// # Examples
+///
+/// ```rust
+/// use duplicate_finder::parse_size;
+///
+/// assert_eq!(parse_size("100MB"), Ok(100 * 1024 * 1024));
+/// assert_eq!(parse_size("1.5k"), Ok(1536));
+/// ```
+pub fn parse_size(input: &str) -> Result<u64, String> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(input.len());
+    let (number, unit) = input.split_at(split);
+
+    let value: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid size '{}': expected a number like 100MB", input))?;
+
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1024,
+        "m" | "mb" | "mib" => 1024 * 1024,
+        "g" | "gb" | "gib" => 1024 * 1024 * 1024,
+        "t" | "tb" | "tib" => 1024 * 1024 * 1024 * 1024,
+        other => return Err(format!("invalid size unit '{}': use B, KB, MB, GB or TB", other)),
+    };
+
+    Ok((value * multiplier as f64).round() as u64)
+}
+
+/// Parses a transfer rate such as `50MB/s` or `1.5GiB`
+///
+/// A size per second as accepted by [`parse_size`]; the `/s` suffix is
+/// optional. Zero rates are rejected
+///
+/// # Examples
+///
+/// ```rust
+/// use duplicate_finder::parse_rate;
+///
+/// assert_eq!(parse_rate("50MB/s"), Ok(50 * 1024 * 1024));
+/// ```
+pub fn parse_rate(input: &str) -> Result<u64, String> {
+    let trimmed = input.trim();
+    let size = trimmed.strip_suffix("/s").or_else(|| trimmed.strip_suffix("/S")).unwrap_or(trimmed);
+    match parse_size(size)? {
+        0 => Err(format!("invalid rate '{}': must be above zero", input)),
+        rate => Ok(rate),
+    }
+}
+
+/// Parses a percentage such as `5%`, `0.5` or `100%`
+pub fn parse_percentage(input: &str) -> Result<f64, String> {
+    let trimmed = input.trim();
+    let number = trimmed.strip_suffix('%').unwrap_or(trimmed);
+    match number.trim().parse::<f64>() {
+        Ok(value) if (0.0..=100.0).contains(&value) => Ok(value),
+        _ => Err(format!("invalid percentage '{}': expected a value from 0% to 100%", input)),
+    }
+}
+
+/// Parses a scheduling interval such as `90s`, `30m`, `24h` or `7d`
+///
+/// A bare number means seconds; zero intervals are rejected
+pub fn parse_interval(input: &str) -> Result<std::time::Duration, String> {
+    let input = input.trim();
+    let split = input.find(|c: char| !c.is_ascii_digit()).unwrap_or(input.len());
+    let (number, unit) = input.split_at(split);
+
+    let value: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid interval '{}': expected a value like 24h", input))?;
+
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 60 * 60 * 24,
+        other => return Err(format!("invalid interval unit '{}': use s, m, h or d", other)),
+    };
+    let seconds = value
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("interval too large: '{}'", input))?;
+
+    if seconds == 0 {
+        return Err("interval must be greater than zero".to_string());
+    }
+
+    Ok(std::time::Duration::from_secs(seconds))
+}
+
+/// Parses a local time of day such as `06:00` or `23:30`
+pub fn parse_time_of_day(input: &str) -> Result<chrono::NaiveTime, String> {
+    chrono::NaiveTime::parse_from_str(input.trim(), "%H:%M")
+        .map_err(|_| format!("invalid time '{}': expected HH:MM, e.g. 06:00", input))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -410,6 +2084,18 @@ mod tests {
         assert_eq!(file_info.hash, file_info2.hash);
     }
 
+    #[tokio::test]
+    async fn test_file_info_from_reader() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let file_path = temp_dir.path().join("test_file.txt");
+        std::fs::write(&file_path, b"Hello, World!").unwrap();
+
+        let from_path = FileInfo::from_path_with(&file_path, &HashAlgorithm::Xxh3).await.unwrap();
+        let from_reader = FileInfo::from_reader_with("archive.tar/test_file.txt", &b"Hello, World!"[..], &HashAlgorithm::Xxh3).await.unwrap();
+        assert_eq!(from_reader.path, Path::new("archive.tar/test_file.txt"));
+        assert_eq!((from_reader.size, &from_reader.hash), (from_path.size, &from_path.hash));
+    }
+
     #[test]
     fn test_format_bytes() {
         assert_eq!(format_bytes(0), "0 B");
@@ -421,11 +2107,148 @@ mod tests {
     }
 
     #[test]
-    fn test_cli_default() {
-        let cli = Cli::default();
+    fn test_parse_size() {
+        assert_eq!(parse_size("0"), Ok(0));
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("10K"), Ok(10 * 1024));
+        assert_eq!(parse_size("100MB"), Ok(100 * 1024 * 1024));
+        assert_eq!(parse_size("2 GiB"), Ok(2 * 1024 * 1024 * 1024));
+        assert!(parse_size("ten").is_err());
+        assert!(parse_size("10XB").is_err());
+    }
+
+    #[test]
+    fn test_parse_rate() {
+        assert_eq!(parse_rate("50MB/s"), Ok(50 * 1024 * 1024));
+        assert_eq!(parse_rate("512k"), Ok(512 * 1024));
+        assert!(parse_rate("0/s").is_err());
+        assert!(parse_rate("fast").is_err());
+    }
+
+    #[test]
+    fn test_transient_errors() {
+        use std::io::{Error, ErrorKind};
+        let transient = DuplicateFinderError::Io(Error::from(ErrorKind::WouldBlock));
+        assert!(transient.is_transient());
+        let read = DuplicateFinderError::TransientRead { path: PathBuf::from("/a"), source: Error::from(ErrorKind::TimedOut) };
+        assert!(read.is_transient());
+        assert!(!DuplicateFinderError::Io(Error::from(ErrorKind::PermissionDenied)).is_transient());
+        assert!(!DuplicateFinderError::HashCalculationError { path: PathBuf::from("/a") }.is_transient());
+    }
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(parse_interval("90"), Ok(std::time::Duration::from_secs(90)));
+        assert_eq!(parse_interval("30m"), Ok(std::time::Duration::from_secs(30 * 60)));
+        assert_eq!(parse_interval("7d"), Ok(std::time::Duration::from_secs(7 * 24 * 60 * 60)));
+        assert!(parse_interval("0h").is_err());
+        assert!(parse_interval("1w").is_err());
+        assert_eq!(parse_interval("999999999999999d"), Err("interval too large: '999999999999999d'".to_string()));
+        assert_eq!(parse_time_of_day("06:00"), Ok(chrono::NaiveTime::from_hms_opt(6, 0, 0).unwrap()));
+        assert!(parse_time_of_day("25:00").is_err());
+        assert_eq!(parse_percentage("2.5%"), Ok(2.5));
+        assert_eq!(parse_percentage("100"), Ok(100.0));
+        assert!(parse_percentage("101%").is_err());
+    }
+
+    #[test]
+    fn test_scan_result_merge() {
+        let file = |path: &str, hash: &str, age: u64| FileInfo {
+            path: PathBuf::from(path),
+            size: 10,
+            hash: hash.to_string(),
+            modified: std::time::UNIX_EPOCH + std::time::Duration::from_secs(1000 - age),
+            created: None,
+            compression: None,
+        };
+        let group = |files: Vec<FileInfo>| DuplicateGroup { id: String::new(), hash: files[0].hash.clone(), size: 10, total_size: 20, wasted_space: 10, files };
+
+        let mut server_a = ScanResult {
+            total_files: 4,
+            duplicate_groups: vec![group(vec![file("/a/1", "x", 1), file("/a/2", "x", 2)])],
+            unique_files: vec![file("/a/3", "y", 3), file("/a/4", "z", 1)],
+            file_errors: 1,
+            ..Default::default()
+        };
+        let server_b = ScanResult {
+            total_files: 3,
+            duplicate_groups: vec![group(vec![file("/b/1", "x", 5), file("/b/2", "x", 1)])],
+            unique_files: vec![file("/b/3", "y", 1)],
+            ..Default::default()
+        };
+        server_a.merge(server_b).unwrap();
+
+        assert_eq!(server_a.total_files, 7);
+        assert_eq!(server_a.file_errors, 1);
+        assert_eq!(server_a.duplicate_groups.len(), 2);
+        // The oldest copy across both servers is the original
+        assert_eq!(server_a.duplicate_groups[0].files.len(), 4);
+        assert_eq!(server_a.duplicate_groups[0].files[0].path, Path::new("/b/1"));
+        assert_eq!(server_a.duplicate_groups[1].files[0].path, Path::new("/a/3"));
+        assert_eq!(server_a.unique_files.len(), 1);
+        assert_eq!(server_a.total_duplicates, 4);
+        assert_eq!(server_a.total_wasted_space, 40);
+
+        // Hashes of another comparison cannot be regrouped with these
+        let names = ScanResult { mode: Mode::Name, ..Default::default() };
+        assert!(server_a.merge(names).is_err());
+        let blake3 = ScanResult { hash_algo: HashAlgorithm::Blake3, ..Default::default() };
+        assert!(server_a.merge(blake3).is_err());
+        assert_eq!(server_a.total_files, 7);
+    }
+
+    #[test]
+    fn test_scan_result_from_legacy_json() {
+        // Version 1 documents have no format_version and no statistics
+        let legacy = r#"{
+            "total_files": 2,
+            "duplicate_groups": [{
+                "hash": "abc",
+                "size": 5,
+                "files": [
+                    {"path": "/a/x.txt", "size": 5, "hash": "abc",
+                     "modified": {"secs_since_epoch": 0, "nanos_since_epoch": 0}, "created": null},
+                    {"path": "/b/x.txt", "size": 5, "hash": "abc",
+                     "modified": {"secs_since_epoch": 0, "nanos_since_epoch": 0}, "created": null}
+                ],
+                "total_size": 10,
+                "wasted_space": 5
+            }],
+            "total_duplicates": 1,
+            "total_wasted_space": 5,
+            "scan_duration": {"secs": 1, "nanos": 0},
+            "scanned_directory": "/"
+        }"#;
+
+        let result = ScanResult::from_json(legacy).expect("Legacy JSON should load");
+        assert_eq!(result.format_version, FORMAT_VERSION);
+        assert_eq!((result.mode, result.hash_algo), (Mode::Exact, HashAlgorithm::Sha256));
+        assert_eq!(result.directory_stats.len(), 2);
+        assert_eq!(result.extension_stats[0].extension, "txt");
+
+        // Round trip through the current format
+        let json = serde_json::to_string(&result).unwrap();
+        let reloaded = ScanResult::from_json(&json).unwrap();
+        assert_eq!(reloaded.directory_stats, result.directory_stats);
+
+        let future = r#"{"format_version": 999}"#;
+        assert!(ScanResult::from_json(future).is_err());
+    }
+
+    #[test]
+    fn test_json_schema_describes_result() {
+        let schema = ScanResult::json_schema();
+        let properties = &schema["properties"];
+        assert!(properties.get("format_version").is_some());
+        assert!(properties.get("duplicate_groups").is_some());
+    }
+
+    #[test]
+    fn test_config_default() {
+        let cli = ScannerConfig::default();
         assert_eq!(cli.directory, PathBuf::from("."));
         assert_eq!(cli.min_size, 0);
-        assert_eq!(cli.threads, 0);
+        assert_eq!(cli.hash_concurrency, 0);
         assert!(!cli.verbose);
     }
 }
\ No newline at end of file
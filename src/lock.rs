@@ -0,0 +1,68 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::path::{Path, PathBuf};
+
+use tracing::info;
+
+use crate::DuplicateFinderError;
+
+/// Advisory lock on a file shared between runs, e.g. a checkpoint, an
+/// output file or a history file
+///
+/// The lock is taken on a `<name>.lock` file next to the shared file, so it
+/// also covers files that are replaced rather than rewritten in place. It
+/// is released when dropped or when the process ends, however it ends. The
+/// lock file itself is left behind: removing it would let a third run lock
+/// a new file while the second still waits on the old one
+#[derive(Debug)]
+pub struct StateLock {
+    _file: File,
+}
+
+impl StateLock {
+    /// Locks `path` for this run
+    ///
+    /// When another run holds the lock this waits for it with `wait`, and
+    /// fails with [`DuplicateFinderError::Locked`] otherwise. Blocks the
+    /// thread while waiting
+    pub fn acquire(path: &Path, wait: bool) -> Result<Self, DuplicateFinderError> {
+        let lock_path = lock_path(path);
+        let file = OpenOptions::new().create(true).truncate(false).write(true).open(&lock_path)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) if wait => {
+                info!("Waiting for another run to release {}", lock_path.display());
+                file.lock()?;
+            }
+            Err(TryLockError::WouldBlock) => return Err(DuplicateFinderError::Locked { path: path.to_path_buf() }),
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+        Ok(Self { _file: file })
+    }
+}
+
+fn lock_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or(path.as_os_str()).to_os_string();
+    name.push(".lock");
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_lock() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let path = temp_dir.path().join("results.json");
+        let lock = StateLock::acquire(&path, false).unwrap();
+        assert!(temp_dir.path().join("results.json.lock").exists());
+        assert!(matches!(StateLock::acquire(&path, false), Err(DuplicateFinderError::Locked { .. })));
+        // Other files are not affected
+        StateLock::acquire(&temp_dir.path().join("history.jsonl"), false).unwrap();
+
+        let waiter = std::thread::spawn(move || StateLock::acquire(&path, true).is_ok());
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        drop(lock);
+        assert!(waiter.join().unwrap());
+    }
+}
@@ -5,189 +5,1261 @@
 //! and find duplicate files.
 
 use anyhow::Result;
-use clap::Parser;
-use tracing::{error, info};
+use clap::{CommandFactory, Parser};
+use tracing::{error, info, warn};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::Layer;
 
 // Use our library
-use duplicate_finder::{Cli, FileScanner, OutputFormatter};
+use duplicate_finder::metrics::{self, Metrics};
+use duplicate_finder::empty;
+use duplicate_finder::history::{self, HistoryEntry};
+use duplicate_finder::atomic;
+use duplicate_finder::lock::StateLock;
+use duplicate_finder::merge::{self, MergeAction, MergeEntry, MergeOptions, TransferMode};
+use duplicate_finder::{baseline, delete, diff, gate, manifest};
+use duplicate_finder::xattr;
+use duplicate_finder::{CacheAction, Cli, Command, FileScanner, GroupScope, HashAlgorithm, LogFormat, Mode, OutputFormat, OutputFormatter, PauseSwitch, ScanResult, ScannerConfig, TextStyle, WatchEngine};
+use std::borrow::Cow;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Exit status: the scan completed and found no duplicates
+const EXIT_CLEAN: i32 = 0;
+
+/// Exit status: the scan completed and found duplicates (or `verify` found differences)
+const EXIT_DUPLICATES: i32 = 1;
+
+/// Exit status: the scan completed, but some files or directories could not
+/// be read or it stopped at its deadline
+const EXIT_PARTIAL: i32 = 2;
+
+/// Exit status: the scan could not be completed (bad arguments, unreadable root, ...)
+const EXIT_FATAL: i32 = 3;
 
 /// Main entry point for the CLI application
 ///
 /// This function coordinates the entire duplicate finding process:
-/// 1. Initializes logging system
-/// 2. Parses command line arguments
+/// 1. Parses command line arguments
+/// 2. Initializes logging system
 /// 3. Creates and runs the file scanner
 /// 4. Formats and displays results
 /// 5. Handles errors gracefully
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize a logging system with level based on verbose flag
-    // We check for a verbose flag before parsing to set up logging correctly
-    let verbose = std::env::args().any(|arg| arg == "-v" || arg == "--verbose");
+    // Parse command line arguments using clap, keeping usage errors out of
+    // the exit codes that describe scan outcomes. The configuration file,
+    // --profile and DUPLICATE_FINDER_* variables come in as arguments
+    // placed before the given ones
+    let args = duplicate_finder::config::resolve_args(std::env::args_os()).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        exit(EXIT_FATAL);
+    });
+    let mut cli = Cli::try_parse_from(args).unwrap_or_else(|e| {
+        let _ = e.print();
+        exit(if e.use_stderr() { EXIT_FATAL } else { EXIT_CLEAN });
+    });
 
-    let subscriber = tracing_subscriber::fmt()
-        .with_max_level(if verbose {
-            tracing::Level::DEBUG
-        } else {
-            tracing::Level::INFO
-        })
+    // The schema is static, so print it without any scanning or logging
+    if cli.print_schema {
+        println!("{}", serde_json::to_string_pretty(&ScanResult::json_schema())?);
+        return Ok(());
+    }
+
+    // Completion scripts only depend on the command line definition and the
+    // profiles, which --profile completes to
+    if let Some(Command::Completions { shell }) = cli.command {
+        let profiles = duplicate_finder::config::profile_names(cli.config_file.as_deref())?;
+        let mut command = Cli::command().mut_arg("profile", |arg| arg.value_parser(clap::builder::PossibleValuesParser::new(profiles)));
+        clap_complete::generate(shell, &mut command, "duplicate-finder", &mut std::io::stdout());
+        return Ok(());
+    }
+
+    // `duplicate-finder DIR`, `scan DIR` and the like are shorthands for --directory DIR
+    if let Some(path) = cli.path.clone().or_else(|| cli.command.as_ref().and_then(Command::path).map(Path::to_path_buf)) {
+        cli.config.directory = path;
+    }
+    if matches!(cli.command, Some(Command::Scan { .. })) {
+        cli.command = None;
+    }
+
+    // `find --in DIR` is shorthand for scanning DIR
+    if let Some(Command::Find { haystack: Some(haystack), .. }) = &cli.command {
+        cli.config.directory = haystack.clone();
+    }
+
+    // Machine-readable output on stdout must not be mixed with banners and summaries
+    if cli.config.print_duplicates || matches!(cli.command, Some(Command::Manifest { output: None })) {
+        cli.config.quiet = true;
+    }
+
+    // Initialize a logging system with level based on verbose/quiet flags
+    let console = tracing_subscriber::fmt::layer()
         .with_target(false) // Don't show module names in logs for cleaner output
-        .finish();
+        .with_writer(std::io::stderr) // Keep stdout clean for results
+        .with_ansi(duplicate_finder::style::color_enabled(cli.config.color))
+        .with_filter(if cli.config.verbose {
+            LevelFilter::DEBUG
+        } else if cli.config.quiet {
+            LevelFilter::WARN
+        } else {
+            LevelFilter::INFO
+        });
+
+    // The log file gets every detail, whatever the console shows
+    let log_file = match &cli.config.log_file {
+        Some(path) => match std::fs::OpenOptions::new().create(true).append(true).open(path) {
+            Ok(file) => Some(file_log_layer(file, cli.config.log_format)),
+            Err(e) => {
+                eprintln!("Cannot open the log file {}: {}", path.display(), e);
+                exit(EXIT_FATAL);
+            }
+        },
+        None => None,
+    };
 
-    tracing::subscriber::set_global_default(subscriber)
+    // Metrics are served and exported for the lifetime of the process (most
+    // useful with --watch)
+    let metrics = Metrics::new();
+    let otel = otel_layer(&cli.config, &metrics);
+
+    tracing::subscriber::set_global_default(tracing_subscriber::registry().with(console).with(log_file).with(otel))
         .expect("Failed to set global logger");
 
-    // Parse command line arguments using clap
-    let cli = Cli::parse();
+    let style = TextStyle::from_config(&cli.config);
+
+    // Business-hours scans yield the CPU and disks to everything else
+    if cli.config.nice
+        && let Err(e) = duplicate_finder::priority::lower_priority()
+    {
+        warn!("Cannot lower the process priority: {}", e);
+    }
 
     // Log startup information
-    info!("🚀 Starting Duplicate Finder v{}", env!("CARGO_PKG_VERSION"));
-    info!("📁 Target directory: {}", cli.directory.display());
+    info!("{}Starting Duplicate Finder v{}", style.icon("🚀 "), env!("CARGO_PKG_VERSION"));
+    info!("{}Target directory: {}", style.icon("📁 "), cli.config.directory.display());
 
-    // Display a welcome message to the user
-    display_welcome_banner(&cli);
+    // Display a welcome message to the user (reports and diffs don't scan
+    // anything, and clean keeps stdout to its path list)
+    let scans = !matches!(
+        cli.command,
+        Some(
+            Command::Clean { .. }
+                | Command::Report { .. }
+                | Command::Diff { .. }
+                | Command::Combine { .. }
+                | Command::History { .. }
+                | Command::Merge { .. }
+                | Command::Cache { .. }
+        )
+    );
+    if !cli.config.quiet && scans {
+        display_welcome_banner(&cli.config, &style);
+    }
 
-    // Create and configure the file scanner
-    let mut scanner = FileScanner::new(cli.clone());
+    // NUL separators only apply to path lists
+    if cli.config.null_separated && !cli.config.print_duplicates && cli.config.files_from.is_none() {
+        eprintln!("{}--null only applies to --print-duplicates and --files-from", style.icon("❌ "));
+        exit(EXIT_FATAL);
+    }
 
-    // Execute the main scanning process
-    match scanner.scan().await {
-        Ok(scan_result) => {
-            info!("📊 Scan completed successfully");
+    // Path lists are piped into rm, so they only list copies with equal
+    // contents; saved results are checked by the mode they were scanned with
+    let saved = matches!(cli.command, Some(Command::Report { .. } | Command::Combine { .. }));
+    if cli.config.print_duplicates && !saved && !cli.config.mode.finds_equal_contents() {
+        eprintln!("{}--print-duplicates does not apply to --mode {}, whose groups may differ in content", style.icon("❌ "), mode_name(cli.config.mode));
+        exit(EXIT_FATAL);
+    }
+    if matches!(cli.command, Some(Command::Clean { .. })) && !cli.config.mode.finds_equal_contents() {
+        eprintln!("{}clean does not apply to --mode {}, whose groups may differ in content", style.icon("❌ "), mode_name(cli.config.mode));
+        exit(EXIT_FATAL);
+    }
+
+    // Deleting needs a choice of the files to keep, and files on this machine
+    if cli.config.delete && !cli.config.interactive && !cli.config.auto {
+        eprintln!("{}--delete needs --interactive or --auto to choose the files to keep", style.icon("❌ "));
+        exit(EXIT_FATAL);
+    }
+    if cli.config.auto && !cli.config.finds_identical_bytes() {
+        eprintln!(
+            "{}--auto only deletes byte-identical copies, found by --mode exact without --decompress or --normalize-text; use --interactive",
+            style.icon("❌ ")
+        );
+        exit(EXIT_FATAL);
+    }
+    if cli.config.delete && (cli.command.is_some() || cli.config.roots().any(duplicate_finder::source::is_remote_url)) {
+        eprintln!("{}--delete only applies to scans of local directories", style.icon("❌ "));
+        exit(EXIT_FATAL);
+    }
 
-            // Create a formatter for displaying results
-            let formatter = OutputFormatter::new(&cli);
+    // A database can only be written to a file, so fail before scanning
+    if matches!(cli.config.output_format, OutputFormat::Sqlite) && cli.config.output_file.is_none() {
+        eprintln!("{}SQLite output requires an output file (-O results.db)", style.icon("❌ "));
+        exit(EXIT_FATAL);
+    }
 
-            // Display results in the requested format
-            if let Err(e) = formatter.display_results(&scan_result).await {
-                error!("Failed to display results: {}", e);
-                std::process::exit(1);
+    // Subcommands reuse the scanner but produce their own output
+    if let Some(command) = cli.command.clone() {
+        let outcome = match command {
+            Command::Scan { .. } => unreachable!("scan runs as a plain scan"),
+            Command::Clean { dry_run, .. } => run_clean(&cli.config, dry_run, &style).await,
+            Command::Cache { action } => run_cache(&cli.config, &action, &style).await,
+            Command::Manifest { output } => run_manifest(&cli.config, output.as_deref(), &style).await,
+            Command::Verify { manifest } => run_verify(&cli.config, &manifest, &style).await,
+            Command::Report { input } => run_report(&cli.config, &input, &style).await,
+            Command::Diff { old, new } => run_diff(&cli.config, &old, &new, &style).await,
+            Command::Combine { inputs } => run_combine(&cli.config, &inputs, &style).await,
+            Command::History { file } => run_history(&file, &style).await,
+            Command::Completions { .. } => unreachable!("completions are printed before scanning"),
+            Command::Missing => run_missing(&cli.config, &style).await,
+            Command::Find { needles, .. } => run_find(&cli.config, &needles, &style).await,
+            Command::Merge { sources, into, move_files, link, dry_run } => {
+                let options = MergeOptions {
+                    mode: if move_files { TransferMode::Move } else { TransferMode::Copy },
+                    link_existing: link,
+                    dry_run,
+                };
+                run_merge(&cli.config, &sources, &into, &options, &style).await
             }
+        };
 
-            // Save to a file if requested
-            if let Some(output_file) = &cli.output_file {
-                match formatter.save_to_file(&scan_result, output_file).await {
-                    Ok(()) => {
-                        println!("💾 Results saved to: {}", output_file.display());
-                        info!("Results saved to file: {}", output_file.display());
-                    }
+        match outcome {
+            Ok(code) => exit(code),
+            Err(e) => {
+                error!("Command failed: {}", e);
+                eprintln!("{}{}", style.icon("❌ "), e);
+                if !cli.config.quiet {
+                    display_error_help(&e, &style);
+                }
+                exit(EXIT_FATAL);
+            }
+        }
+    }
+
+    if let Some(addr) = cli.config.metrics_addr
+        && let Err(e) = metrics::serve(addr, Arc::clone(&metrics)).await
+    {
+        eprintln!("{}Cannot serve metrics on {}: {}", style.icon("❌ "), addr, e);
+        exit(EXIT_FATAL);
+    }
+
+    // SIGUSR1 pauses and resumes scans; so does `p` while a scan shows its
+    // progress in a terminal
+    let pause = PauseSwitch::new();
+    #[cfg(unix)]
+    if let Err(e) = pause.toggle_on_sigusr1(report_pause) {
+        warn!("Cannot pause scans on SIGUSR1: {}", e);
+    }
+
+    // Watch mode keeps the process alive and re-emits results on changes
+    if cli.config.watch {
+        if let Err(e) = run_watch(&cli.config, &metrics, &pause, &style).await {
+            error!("Watch failed: {}", e);
+            eprintln!("{}Watch failed: {}", style.icon("❌ "), e);
+            if !cli.config.quiet {
+                display_error_help(&e, &style);
+            }
+            exit(EXIT_FATAL);
+        }
+        exit(EXIT_CLEAN);
+    }
+
+    // Scheduled mode rescans at a fixed interval until interrupted
+    if let Some(interval) = cli.config.every {
+        run_schedule(&cli.config, interval, &metrics, &pause, &style).await;
+        exit(EXIT_CLEAN);
+    }
+
+    // Create and configure the file scanner
+    let mut scanner = FileScanner::new(cli.config.clone()).with_metrics(Arc::clone(&metrics)).with_pause(pause.clone());
+
+    // Execute the main scanning process; the terminal reads lines again
+    // once it is over
+    #[cfg(unix)]
+    let keys = (!cli.config.quiet && std::io::stdin().is_terminal() && std::io::stderr().is_terminal())
+        .then(|| duplicate_finder::pause::KeyListener::start(pause.clone(), b'p', report_pause).ok())
+        .flatten();
+    let scanned = scanner.scan().await;
+    #[cfg(unix)]
+    drop(keys);
+
+    let code = match scanned {
+        Ok(scan_result) => {
+            info!("{}Scan completed successfully", style.icon("📊 "));
+            metrics.record_result(&scan_result);
+
+            let reported = match reported_changes(&cli.config, &scan_result, &style).await {
+                Ok(reported) => reported,
+                Err(e) => {
+                    error!("Failed to compare with the history: {}", e);
+                    eprintln!("{}Failed to compare with the history: {}", style.icon("❌ "), e);
+                    exit(EXIT_FATAL);
+                }
+            };
+
+            // Deleting walks through the groups instead of reporting them
+            let deletion = if cli.config.delete {
+                match delete_duplicates(&cli.config, &reported, &style) {
+                    Ok(deletion) => Some(deletion),
                     Err(e) => {
-                        error!("Failed to save results to file: {}", e);
-                        eprintln!("❌ Failed to save results: {}", e);
-                        std::process::exit(1);
+                        error!("Failed to delete duplicates: {}", e);
+                        eprintln!("{}Failed to delete duplicates: {}", style.icon("❌ "), e);
+                        exit(EXIT_FATAL);
                     }
                 }
+            } else {
+                if let Err(e) = emit_results(&cli.config, &reported, &style).await {
+                    error!("{}", e);
+                    eprintln!("{}{}", style.icon("❌ "), e);
+                    exit(EXIT_FATAL);
+                }
+                None
+            };
+
+            if let Err(e) = record_history(&cli.config, &scan_result, &style).await {
+                error!("Failed to update history: {}", e);
+                eprintln!("{}Failed to update history: {}", style.icon("❌ "), e);
+                exit(EXIT_FATAL);
+            }
+
+            if let Err(e) = update_baseline(&cli.config, &scan_result, &style).await {
+                error!("Failed to update the baseline: {}", e);
+                eprintln!("{}Failed to update the baseline: {}", style.icon("❌ "), e);
+                exit(EXIT_FATAL);
+            }
+
+            for root in cli.config.roots().filter(|_| cli.config.prune_empty_dirs) {
+                if let Err(e) = prune_empty_dirs(&cli.config, root, &style).await {
+                    error!("Failed to prune empty directories: {}", e);
+                    eprintln!("{}Failed to prune empty directories: {}", style.icon("❌ "), e);
+                    exit(EXIT_FATAL);
+                }
             }
 
-            // Display final summary
-            display_completion_summary(&scan_result);
+            // Deletions were reported as they happened; some failing leaves
+            // duplicates behind
+            if let Some(deletion) = deletion {
+                if deletion.failed > 0 { EXIT_PARTIAL } else { EXIT_CLEAN }
+            } else {
+                // Display final summary
+                if !cli.config.quiet {
+                    display_completion_summary(&reported, &style);
+                    if cli.config.mode.is_unverified() && reported.group_count() > 0 {
+                        display_unverified_note(cli.config.mode, &style);
+                    }
+                }
+
+                exit_status(&cli.config, &reported)
+            }
         }
         Err(e) => {
             error!("Scan failed: {}", e);
-            eprintln!("❌ Scan failed: {}", e);
+            eprintln!("{}Scan failed: {}", style.icon("❌ "), e);
 
             // Provide helpful error context
-            display_error_help(&e);
-            std::process::exit(1);
+            if !cli.config.quiet {
+                display_error_help(&e, &style);
+            }
+            EXIT_FATAL
+        }
+    };
+
+    exit(code)
+}
+
+/// The OpenTelemetry exporter of `--otel-endpoint`, shut down on exit
+#[cfg(feature = "otel")]
+static TELEMETRY: std::sync::OnceLock<duplicate_finder::otel::Telemetry> = std::sync::OnceLock::new();
+
+/// Ends the process with `code`, exporting the spans and metrics not sent yet
+fn exit(code: i32) -> ! {
+    #[cfg(feature = "otel")]
+    if let Some(telemetry) = TELEMETRY.get() {
+        telemetry.shutdown();
+    }
+    std::process::exit(code)
+}
+
+/// Spans of scans and their phases sent to `--otel-endpoint`, whose metrics
+/// are exported from `metrics` too
+#[cfg(feature = "otel")]
+fn otel_layer<S>(cli: &ScannerConfig, metrics: &Arc<Metrics>) -> Option<Box<dyn Layer<S> + Send + Sync>>
+where
+    S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span> + Send + Sync,
+{
+    let endpoint = cli.otel_endpoint.as_deref()?;
+    match duplicate_finder::otel::Telemetry::start(endpoint, Arc::clone(metrics)) {
+        Ok(telemetry) => {
+            // Per-file spans are left to the log file
+            let layer = telemetry.layer().with_filter(LevelFilter::INFO).boxed();
+            let _ = TELEMETRY.set(telemetry);
+            Some(layer)
+        }
+        Err(e) => {
+            eprintln!("Cannot export to {}: {}", endpoint, e);
+            exit(EXIT_FATAL);
+        }
+    }
+}
+
+#[cfg(not(feature = "otel"))]
+fn otel_layer<S>(cli: &ScannerConfig, _metrics: &Arc<Metrics>) -> Option<Box<dyn Layer<S> + Send + Sync>>
+where
+    S: tracing::Subscriber,
+{
+    if cli.otel_endpoint.is_some() {
+        eprintln!("OpenTelemetry export is not available: rebuild with the `otel` feature enabled");
+        exit(EXIT_FATAL);
+    }
+    None
+}
+
+/// Log lines written to `--log-file`, in `format`, at debug level
+fn file_log_layer<S>(file: std::fs::File, format: LogFormat) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+{
+    let layer = tracing_subscriber::fmt::layer().with_writer(std::sync::Mutex::new(file)).with_ansi(false);
+    match format {
+        LogFormat::Text => layer.with_filter(LevelFilter::DEBUG).boxed(),
+        LogFormat::Json => layer.json().with_filter(LevelFilter::DEBUG).boxed(),
+    }
+}
+
+/// Logs a scan being paused or resumed; the progress bar shows it too
+#[cfg(unix)]
+fn report_pause(paused: bool) {
+    if paused {
+        info!("Scan paused");
+    } else {
+        info!("Scan resumed");
+    }
+}
+
+/// Maps a completed scan to its exit status
+///
+/// Unreadable files take precedence over duplicates: the results are
+/// incomplete, which scripts must not mistake for a clean tree. When CI
+/// thresholds are configured they replace the plain "any duplicate" rule
+fn exit_status(cli: &ScannerConfig, scan_result: &ScanResult) -> i32 {
+    let violations = gate::check(cli, scan_result);
+    for violation in &violations {
+        eprintln!("Threshold exceeded: {}", violation);
+    }
+
+    if scan_result.file_errors > 0 || scan_result.partial {
+        EXIT_PARTIAL
+    } else if gate::is_enabled(cli) {
+        if violations.is_empty() { EXIT_CLEAN } else { EXIT_DUPLICATES }
+    } else if scan_result.group_count() > 0 && !cli.no_fail_on_duplicates {
+        EXIT_DUPLICATES
+    } else {
+        EXIT_CLEAN
+    }
+}
+
+/// Displays results in the requested format and saves them if asked to
+///
+/// Shared by live scans and the `report` subcommand, so saved results
+/// render exactly like fresh ones
+async fn emit_results(cli: &ScannerConfig, scan_result: &ScanResult, style: &TextStyle) -> Result<()> {
+    let formatter = OutputFormatter::new(cli);
+
+    // Display results in the requested format
+    if cli.print_duplicates {
+        formatter
+            .write_duplicate_paths(scan_result, &mut std::io::stdout().lock())
+            .map_err(|e| anyhow::anyhow!("Failed to display results: {}", e))?;
+    } else {
+        formatter
+            .display_results(scan_result)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to display results: {}", e))?;
+    }
+
+    // Save to a file if requested
+    if let Some(output_file) = &cli.output_file {
+        formatter
+            .save_to_file(scan_result, output_file)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to save results: {}", e))?;
+
+        if !cli.quiet {
+            println!("{}Results saved to: {}", style.icon("💾 "), output_file.display());
+        }
+        info!("Results saved to file: {}", output_file.display());
+    }
+
+    Ok(())
+}
+
+/// The part of a scan to report: all of it, or with `--changes-only` the
+/// groups that are new or grew since the previous scan in the history file
+///
+/// The first scan of a directory reports every group
+async fn reported_changes<'a>(cli: &ScannerConfig, scan_result: &'a ScanResult, style: &TextStyle) -> Result<Cow<'a, ScanResult>> {
+    let Some(path) = cli.history.as_ref().filter(|_| cli.changes_only) else {
+        return Ok(Cow::Borrowed(scan_result));
+    };
+    let entries = if path.exists() { history::load(path).await? } else { Vec::new() };
+    let Some(previous) = history::previous_scan(&entries, &scan_result.scanned_directory) else {
+        return Ok(Cow::Borrowed(scan_result));
+    };
+    if previous.groups.is_empty() && previous.duplicate_groups > 0 {
+        warn!("The previous scan in {} predates group IDs, reporting every group", path.display());
+    }
+
+    let changes = history::changes_since(scan_result, previous)?;
+    if !cli.quiet {
+        println!(
+            "{}Reporting {} of {} groups: new or grown since {}",
+            style.icon("🆕 "),
+            changes.group_count(),
+            scan_result.group_count(),
+            previous.scanned_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M")
+        );
+    }
+    Ok(Cow::Owned(changes))
+}
+
+/// Appends the scan to the history file (if configured) and prints the trend
+async fn record_history(cli: &ScannerConfig, scan_result: &ScanResult, style: &TextStyle) -> Result<()> {
+    let Some(path) = &cli.history else {
+        return Ok(());
+    };
+
+    // Runs finishing together must not interleave their entries
+    let (owned, wait) = (path.clone(), cli.wait_for_lock);
+    let _lock = tokio::task::spawn_blocking(move || StateLock::acquire(&owned, wait)).await??;
+    let mut entries = if path.exists() { history::load(path).await? } else { Vec::new() };
+    let entry = HistoryEntry::from_result(scan_result, chrono::Utc::now());
+    history::append(path, &entry).await?;
+    info!("Scan summary appended to history: {}", path.display());
+
+    if !cli.quiet {
+        entries.push(entry.clone());
+        let (previous, month) = history::baselines(&entries, &entry, TREND_WINDOW);
+        for baseline in previous.into_iter().chain(month) {
+            println!("{}Trend: {}", style.icon("📈 "), history::describe_change(baseline, &entry));
         }
     }
 
     Ok(())
 }
 
+/// How far back trend reports look in addition to the previous scan
+const TREND_WINDOW: chrono::Duration = chrono::Duration::days(30);
+
+/// Prints the duplication trend of every directory in a history file
+async fn run_history(path: &Path, style: &TextStyle) -> Result<i32> {
+    let entries = history::load(path).await?;
+
+    // Every directory gets its own trend, in order of first appearance
+    let mut directories: Vec<&Path> = Vec::new();
+    for entry in &entries {
+        if !directories.contains(&entry.scanned_directory.as_path()) {
+            directories.push(&entry.scanned_directory);
+        }
+    }
+
+    if directories.is_empty() {
+        println!("{}No scans recorded in {}", style.icon("📭 "), path.display());
+    }
+
+    for directory in directories {
+        let scans: Vec<&HistoryEntry> = entries.iter().filter(|e| e.scanned_directory == directory).collect();
+        let Some(latest) = scans.last() else {
+            continue;
+        };
+
+        println!();
+        println!("{}{} ({} scans)", style.icon("📂 "), directory.display(), scans.len());
+        println!("   {}Latest scan: {}", style.icon("🕒 "), latest.scanned_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S"));
+        println!("   {}Duplicate groups: {}", style.icon("📦 "), latest.duplicate_groups);
+        println!("   {}Wasted space: {}", style.icon("💾 "), duplicate_finder::format_bytes(latest.total_wasted_space));
+
+        let (previous, month) = history::baselines(&entries, latest, TREND_WINDOW);
+        for baseline in previous.into_iter().chain(month) {
+            println!("   {}{}", style.icon("📈 "), history::describe_change(baseline, latest));
+        }
+    }
+
+    Ok(EXIT_CLEAN)
+}
+
+/// Rescans at a fixed interval until interrupted with Ctrl+C
+///
+/// Failed scans are reported and retried at the next interval instead of
+/// stopping the schedule
+async fn run_schedule(cli: &ScannerConfig, interval: std::time::Duration, metrics: &Arc<Metrics>, pause: &PauseSwitch, style: &TextStyle) {
+    loop {
+        if !cli.quiet {
+            println!("{}Scheduled scan started at {}", style.icon("⏰ "), chrono::Local::now().format("%Y-%m-%d %H:%M:%S"));
+        }
+
+        let mut scanner = FileScanner::new(cli.clone()).with_metrics(Arc::clone(metrics)).with_pause(pause.clone());
+        let outcome = match scanner.scan().await {
+            Ok(scan_result) => {
+                metrics.record_result(&scan_result);
+                async {
+                    let reported = reported_changes(cli, &scan_result, style).await?;
+                    emit_results(cli, &reported, style).await?;
+                    record_history(cli, &scan_result, style).await?;
+                    update_baseline(cli, &scan_result, style).await
+                }
+                .await
+            }
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = outcome {
+            error!("Scheduled scan failed: {}", e);
+            eprintln!("{}Scheduled scan failed: {}", style.icon("❌ "), e);
+        }
+
+        if !cli.quiet {
+            println!("{}Next scan in {}", style.icon("💤 "), style.duration(interval));
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+
+    if !cli.quiet {
+        println!("{}Schedule stopped", style.icon("👋 "));
+    }
+}
+
+/// Scans once, then keeps results up to date until interrupted with Ctrl+C
+async fn run_watch(cli: &ScannerConfig, metrics: &Arc<Metrics>, pause: &PauseSwitch, style: &TextStyle) -> Result<()> {
+    let scanner = FileScanner::new(cli.clone()).with_metrics(Arc::clone(metrics)).with_pause(pause.clone());
+    let (mut engine, scan_result) = WatchEngine::start(scanner).await?;
+    metrics.record_result(&scan_result);
+    emit_results(cli, &scan_result, style).await?;
+
+    if !cli.quiet {
+        println!("{}Watching {} for changes (press Ctrl+C to stop)", style.icon("👀 "), cli.directory.display());
+    }
+
+    loop {
+        let update = tokio::select! {
+            update = engine.next_update() => update?,
+            _ = tokio::signal::ctrl_c() => None,
+        };
+
+        let Some(scan_result) = update else {
+            break;
+        };
+        metrics.record_result(&scan_result);
+
+        if !cli.quiet {
+            println!();
+            println!("{}Files changed at {}, updated results:", style.icon("🔁 "), chrono::Local::now().format("%Y-%m-%d %H:%M:%S"));
+        }
+        emit_results(cli, &scan_result, style).await?;
+    }
+
+    if !cli.quiet {
+        println!("{}Stopped watching", style.icon("👋 "));
+    }
+    Ok(())
+}
+
+/// Loads saved results and renders them without rescanning
+async fn run_report(cli: &ScannerConfig, input: &Path, style: &TextStyle) -> Result<i32> {
+    let scan_result = ScanResult::load(input).await?;
+    info!("Loaded {} duplicate groups from {}", scan_result.duplicate_groups.len(), input.display());
+    check_path_list(cli, &scan_result)?;
+
+    emit_results(cli, &scan_result, style).await?;
+    Ok(exit_status(cli, &scan_result))
+}
+
+/// Refuses `--print-duplicates` for a saved result whose mode groups files
+/// that may differ in content, as path lists are piped into rm
+fn check_path_list(cli: &ScannerConfig, scan_result: &ScanResult) -> Result<()> {
+    if cli.print_duplicates && !scan_result.mode.finds_equal_contents() {
+        anyhow::bail!("--print-duplicates does not apply to results of --mode {}, whose groups may differ in content", mode_name(scan_result.mode));
+    }
+    Ok(())
+}
+
+/// Merges saved results of separate scans and renders the combined result
+async fn run_combine(cli: &ScannerConfig, inputs: &[PathBuf], style: &TextStyle) -> Result<i32> {
+    let mut combined: Option<ScanResult> = None;
+    for input in inputs {
+        let result = ScanResult::load(input).await?;
+        if result.unique_files.is_empty() {
+            warn!("{} lists no unique files; rescan with --include-unique to find all copies across scans", input.display());
+        }
+        match &mut combined {
+            Some(combined) => combined.merge(result)?,
+            None => combined = Some(result),
+        }
+    }
+    let combined = combined.unwrap_or_default();
+    info!("Combined {} results into {} duplicate groups", inputs.len(), combined.duplicate_groups.len());
+    check_path_list(cli, &combined)?;
+
+    emit_results(cli, &combined, style).await?;
+    Ok(exit_status(cli, &combined))
+}
+
+/// Compares two saved results and prints what changed between them
+///
+/// A directory as the older result stands for its baseline
+async fn run_diff(cli: &ScannerConfig, old_path: &Path, new_path: &Path, style: &TextStyle) -> Result<i32> {
+    let old_path = &if old_path.is_dir() { baseline::path(old_path) } else { old_path.to_path_buf() };
+    let old = ScanResult::load(old_path).await?;
+    let new = ScanResult::load(new_path).await?;
+    let report = diff::diff(&old, &new);
+
+    if matches!(cli.output_format, OutputFormat::Json) {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(EXIT_CLEAN);
+    }
+
+    print_diff(&report, &old_path.display().to_string(), &new_path.display().to_string(), style);
+    Ok(EXIT_CLEAN)
+}
+
+/// Prints the changes between two results in text form
+fn print_diff(report: &diff::ScanDiff, old: &str, new: &str, style: &TextStyle) {
+    let bullet = style.bullet();
+    let delta = report.wasted_space_delta();
+    let sign = if delta < 0 { "-" } else { "+" };
+
+    println!();
+    println!("{}Comparing {} -> {}", style.icon("📋 "), old, new);
+    println!("{}Wasted space: {} -> {} ({}{})",
+             style.icon("💾 "),
+             duplicate_finder::format_bytes(report.old_wasted_space),
+             duplicate_finder::format_bytes(report.new_wasted_space),
+             sign,
+             duplicate_finder::format_bytes(delta.unsigned_abs())
+    );
+
+    println!("{}New groups: {}", style.icon("🆕 "), report.new_groups.len());
+    for group in &report.new_groups {
+        println!("   {} {} copies, {} wasted", bullet, group.files.len(), duplicate_finder::format_bytes(group.wasted_space));
+        for file in &group.files {
+            println!("      {}", file.path.display());
+        }
+    }
+
+    println!("{}Resolved groups: {}", style.icon("✅ "), report.resolved_groups.len());
+    for group in &report.resolved_groups {
+        let first = group.files.first().map(|f| f.path.display().to_string()).unwrap_or_default();
+        println!("   {} {} ({} reclaimed)", bullet, first, duplicate_finder::format_bytes(group.wasted_space));
+    }
+
+    println!("{}Changed groups: {}", style.icon("🔄 "), report.changed_groups.len());
+    for group in &report.changed_groups {
+        println!("   {} {}", bullet, group.id);
+        for path in &group.added {
+            println!("      + {}", path.display());
+        }
+        for path in &group.removed {
+            println!("      - {}", path.display());
+        }
+    }
+
+    if report.is_unchanged() {
+        println!("{}No changes between the two scans", style.icon("🎉 "));
+    }
+}
+
+/// Prints what changed since the directory's baseline and saves the scan
+/// as the new one, as configured
+async fn update_baseline(cli: &ScannerConfig, scan_result: &ScanResult, style: &TextStyle) -> Result<()> {
+    if cli.against_baseline {
+        match baseline::load(&cli.directory).await? {
+            Some(previous) => {
+                let since = format!("baseline of {}", previous.scanned_directory.display());
+                print_diff(&diff::diff(&previous, scan_result), &since, "this scan", style);
+            }
+            None => println!("{}No baseline saved for {} yet", style.icon("📋 "), cli.directory.display()),
+        }
+    }
+
+    if cli.save_baseline {
+        let path = baseline::save(scan_result, &cli.directory, cli.wait_for_lock).await?;
+        info!("Baseline saved to: {}", path.display());
+        if !cli.quiet {
+            println!("{}Baseline saved to: {}", style.icon("💾 "), path.display());
+        }
+    }
+
+    Ok(())
+}
+
+/// Reports every copy of the needle files in the haystack directory
+///
+/// Results go through the regular formatters, so `--print-duplicates`
+/// lists exactly the copies (never the needles themselves)
+async fn run_find(cli: &ScannerConfig, needles: &[PathBuf], style: &TextStyle) -> Result<i32> {
+    let mut scanner = FileScanner::new(cli.clone());
+    let scan_result = scanner.find_copies(needles).await?;
+
+    emit_results(cli, &scan_result, style).await?;
+    Ok(exit_status(cli, &scan_result))
+}
+
+/// Consolidates source directories into a destination and reports the outcome
+///
+/// Exits with [`EXIT_DUPLICATES`] when conflicts were left for the user to
+/// resolve, and with [`EXIT_PARTIAL`] when some transfers failed
+async fn run_merge(cli: &ScannerConfig, sources: &[PathBuf], destination: &Path, options: &MergeOptions, style: &TextStyle) -> Result<i32> {
+    let report = merge::merge(cli, sources, destination, options).await?;
+
+    // Moving files out leaves the source trees behind as empty skeletons
+    if cli.prune_empty_dirs && options.mode == TransferMode::Move && !options.dry_run {
+        for source in sources {
+            prune_empty_dirs(cli, source, style).await?;
+        }
+    }
+
+    if matches!(cli.output_format, OutputFormat::Json) {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        let verb = match (options.dry_run, options.mode) {
+            (true, _) => "Would transfer",
+            (false, TransferMode::Copy) => "Copied",
+            (false, TransferMode::Move) => "Moved",
+        };
+
+        println!();
+        println!("{}Merge into {}{}", style.icon("📥 "), destination.display(), if options.dry_run { " (dry run)" } else { "" });
+
+        let transferred: Vec<&MergeEntry> = report.with_action(|a| matches!(a, MergeAction::Copied | MergeAction::Moved)).collect();
+        println!("{}{} {} files ({})", style.icon("✅ "), verb, transferred.len(), duplicate_finder::format_bytes(report.transferred_bytes()));
+        if cli.verbose {
+            for entry in &transferred {
+                println!("   {} {} -> {}", style.bullet(), entry.source.display(), entry.target.display());
+            }
+        }
+
+        let linked = report.with_action(|a| matches!(a, MergeAction::Linked { .. })).count();
+        let skipped = report.with_action(|a| matches!(a, MergeAction::Skipped { .. })).count();
+        println!("{}Already present: {} skipped, {} linked", style.icon("♻️  "), skipped, linked);
+
+        println!("{}Conflicts: {}", style.icon("⚠️  "), report.conflicts());
+        for entry in report.with_action(|a| matches!(a, MergeAction::Conflict)) {
+            println!("   {} {} (different content already at {})", style.bullet(), entry.source.display(), entry.target.display());
+        }
+
+        if report.failures() > 0 {
+            println!("{}Failed: {}", style.icon("❌ "), report.failures());
+            for entry in &report.entries {
+                if let MergeAction::Failed { reason } = &entry.action {
+                    println!("   {} {}: {}", style.bullet(), entry.source.display(), reason);
+                }
+            }
+        }
+    }
+
+    Ok(if report.failures() > 0 {
+        EXIT_PARTIAL
+    } else if report.conflicts() > 0 {
+        EXIT_DUPLICATES
+    } else {
+        EXIT_CLEAN
+    })
+}
+
+/// Removes empty directories below `root` and reports what was removed
+///
+/// The report goes to stderr, leaving stdout to path lists
+async fn prune_empty_dirs(cli: &ScannerConfig, root: &Path, style: &TextStyle) -> Result<()> {
+    let removed = empty::prune_empty_directories(root, cli.include_hidden).await?;
+    if !cli.quiet && !removed.is_empty() {
+        eprintln!("{}Removed {} empty directories below {}", style.icon("🧹 "), removed.len(), root.display());
+        for dir in &removed {
+            eprintln!("   {} {}", style.bullet(), dir.display());
+        }
+    }
+    Ok(())
+}
+
+/// Deletes duplicates, asking which files of each group to keep or, with
+/// `--auto`, keeping every group's original
+///
+/// `--auto` shows what it is about to delete first and proceeds only with
+/// `--yes` or once `delete` is typed at the terminal
+fn delete_duplicates(cli: &ScannerConfig, scan_result: &ScanResult, style: &TextStyle) -> Result<delete::Deletion> {
+    let deletion = if cli.auto {
+        let doomed = delete::automatic(scan_result, cli)?;
+        let bytes = doomed.iter().map(|file| file.size).sum();
+        let sidecars = doomed.iter().filter(|file| scan_result.sidecars.iter().any(|sidecar| sidecar.path == file.path)).count();
+        println!(
+            "{}About to delete {} redundant copies and {} XMP sidecars ({}) from {} groups",
+            style.icon("🗑️ "),
+            doomed.len() - sidecars,
+            sidecars,
+            duplicate_finder::format_bytes(bytes),
+            scan_result.group_count()
+        );
+        if !cli.yes {
+            if !std::io::stdin().is_terminal() {
+                anyhow::bail!("refusing to delete without --yes when not run from a terminal");
+            }
+            if !delete::confirm(doomed.len(), bytes, &mut std::io::stdin().lock(), &mut std::io::stdout().lock())? {
+                println!("{}Nothing deleted", style.icon("ℹ️ "));
+                return Ok(delete::Deletion::default());
+            }
+        }
+        let mut deletion = delete::Deletion::default();
+        deletion.delete(&doomed.iter().collect::<Vec<_>>(), &mut std::io::stdout().lock())?;
+        deletion
+    } else {
+        let options = delete::PromptOptions {
+            delete_at_end: cli.delete_at_end,
+            thumbnails: duplicate_finder::preview::thumbnail_protocol(cli.thumbnails),
+            exec_preview: cli.exec_preview.clone(),
+            may_differ: !cli.mode.finds_equal_contents(),
+        };
+        delete::interactive(scan_result, &mut std::io::stdin().lock(), &mut std::io::stdout().lock(), &options)?
+    };
+    println!();
+    println!(
+        "{}Deleted {} files ({} freed)",
+        style.icon("🗑️ "),
+        deletion.deleted,
+        duplicate_finder::format_bytes(deletion.freed)
+    );
+    if deletion.failed > 0 {
+        eprintln!("{}{} files could not be deleted", style.icon("⚠️ "), deletion.failed);
+    }
+    Ok(deletion)
+}
+
+/// Scans, lists the redundant copies and removes empty directories
+///
+/// Directories that only become empty once the copies are gone are listed
+/// instead; a later `clean` removes them. Only the copies are listed on
+/// stdout, so they can be piped to `xargs rm`; everything else goes to stderr
+async fn run_clean(cli: &ScannerConfig, dry_run: bool, style: &TextStyle) -> Result<i32> {
+    let config = ScannerConfig { report_empty_dirs: true, ..cli.clone() };
+    let scan_result = FileScanner::new(config.clone()).scan().await?;
+
+    if !cli.quiet {
+        eprintln!();
+        eprintln!(
+            "{}Redundant copies: {} ({} reclaimable)",
+            style.icon("🗑️ "),
+            scan_result.total_duplicates,
+            duplicate_finder::format_bytes(scan_result.total_wasted_space)
+        );
+    }
+    OutputFormatter::new(&config).write_duplicate_paths(&scan_result, &mut std::io::stdout().lock())?;
+
+    let (later, now): (Vec<_>, Vec<_>) = scan_result.empty_directories.iter().partition(|dir| dir.after_cleanup);
+    if dry_run {
+        eprintln!("{}Empty directories to remove: {}", style.icon("🧹 "), now.len());
+        for dir in &now {
+            eprintln!("   {} {}", style.bullet(), dir.path.display());
+        }
+    } else {
+        for root in cli.roots() {
+            prune_empty_dirs(cli, root, style).await?;
+        }
+    }
+    if !cli.quiet && !later.is_empty() {
+        eprintln!("{}Directories left empty once the copies are removed: {}", style.icon("📂 "), later.len());
+        for dir in &later {
+            eprintln!("   {} {}", style.bullet(), dir.path.display());
+        }
+    }
+
+    Ok(exit_status(cli, &scan_result))
+}
+
+/// Reports or clears the hashes `--xattr-cache` keeps with the scanned files
+async fn run_cache(cli: &ScannerConfig, action: &CacheAction, style: &TextStyle) -> Result<i32> {
+    let paths = FileScanner::new(cli.clone()).list_files().await?;
+    match action {
+        CacheAction::Stats { .. } => {
+            let algorithm = cli.hash_algo;
+            let summary = tokio::task::spawn_blocking(move || xattr::summarize(&paths, algorithm)).await?;
+            if matches!(cli.output_format, OutputFormat::Json) {
+                println!("{}", serde_json::to_string_pretty(&summary)?);
+                return Ok(EXIT_CLEAN);
+            }
+            println!("{}Files: {}", style.icon("📁 "), summary.files);
+            println!("{}Cached {:?} hashes: {} current, {} outdated", style.icon("💾 "), algorithm, summary.current, summary.stale);
+        }
+        CacheAction::Clear { .. } => {
+            let cleared = tokio::task::spawn_blocking(move || xattr::clear(&paths)).await?;
+            println!("{}Removed cached hashes from {} files", style.icon("🧹 "), cleared);
+        }
+    }
+    Ok(EXIT_CLEAN)
+}
+
+/// Lists files that have no copy in the reference directory
+///
+/// Exits with [`EXIT_DUPLICATES`] when unique files exist, so a script can
+/// refuse to delete a tree that still holds something not backed up
+async fn run_missing(cli: &ScannerConfig, style: &TextStyle) -> Result<i32> {
+    let mut scanner = FileScanner::new(cli.clone());
+    let missing = scanner.find_missing().await?;
+
+    if matches!(cli.output_format, OutputFormat::Json) {
+        println!("{}", serde_json::to_string_pretty(&missing)?);
+    } else {
+        if !cli.quiet {
+            let reference = cli.reference.as_deref().unwrap_or(Path::new(""));
+            println!();
+            println!("{}Files not present in {}: {}", style.icon("🔎 "), reference.display(), missing.len());
+        }
+        for file in &missing {
+            println!("{}", file.path.display());
+        }
+        if !cli.quiet {
+            let total: u64 = missing.iter().map(|f| f.size).sum();
+            if missing.is_empty() {
+                println!("{}Every file has a copy in the reference directory", style.icon("🎉 "));
+            } else {
+                println!("{}Unique data: {}", style.icon("💾 "), duplicate_finder::format_bytes(total));
+            }
+        }
+    }
+
+    Ok(if scanner.file_errors() > 0 {
+        EXIT_PARTIAL
+    } else if missing.is_empty() {
+        EXIT_CLEAN
+    } else {
+        EXIT_DUPLICATES
+    })
+}
+
+/// Hashes every file and writes a sha256sum-compatible manifest
+async fn run_manifest(cli: &ScannerConfig, output: Option<&Path>, style: &TextStyle) -> Result<i32> {
+    // Manifests always hold SHA-256 checksums of the raw bytes, whatever the comparison mode
+    let mut scanner = FileScanner::new(ScannerConfig { mode: Mode::Exact, hash_algo: HashAlgorithm::Sha256, normalize_text: false, decompress: false, scan_archives: Vec::new(), ..cli.clone() });
+    let files = scanner.collect_files().await?;
+    let content = manifest::format_manifest(&files, &cli.directory);
+
+    match output {
+        Some(path) => {
+            atomic::write_async(path, content.into_bytes()).await?;
+            println!("{}Manifest with {} entries written to: {}", style.icon("💾 "), files.len(), path.display());
+        }
+        None => print!("{}", content),
+    }
+
+    Ok(if scanner.file_errors() > 0 { EXIT_PARTIAL } else { EXIT_CLEAN })
+}
+
+/// Re-hashes the directory and compares it against a manifest
+///
+/// Exits with [`EXIT_DUPLICATES`] when any file is missing, changed or unexpected
+async fn run_verify(cli: &ScannerConfig, manifest_path: &Path, style: &TextStyle) -> Result<i32> {
+    let content = tokio::fs::read_to_string(manifest_path).await?;
+    let entries = manifest::parse_manifest(&content)?;
+
+    let mut scanner = FileScanner::new(ScannerConfig { mode: Mode::Exact, hash_algo: HashAlgorithm::Sha256, normalize_text: false, decompress: false, scan_archives: Vec::new(), ..cli.clone() });
+    let files = scanner.collect_files().await?;
+    let report = manifest::verify(&entries, &files, &cli.directory);
+
+    println!();
+    println!("{}Verified against: {}", style.icon("📋 "), manifest_path.display());
+    println!("{}Matching files: {}", style.icon("✅ "), report.matched);
+
+    let sections = [
+        ("❌ ", "Changed", &report.changed),
+        ("🕳️  ", "Missing", &report.missing),
+        ("➕ ", "Extra", &report.extra),
+    ];
+    for (icon, label, paths) in sections {
+        println!("{}{} files: {}", style.icon(icon), label, paths.len());
+        for path in paths {
+            println!("   {} {}", style.bullet(), path.display());
+        }
+    }
+
+    if report.is_clean() {
+        println!("{}Directory matches the manifest", style.icon("🎉 "));
+    }
+
+    Ok(if scanner.file_errors() > 0 {
+        EXIT_PARTIAL
+    } else if report.is_clean() {
+        EXIT_CLEAN
+    } else {
+        EXIT_DUPLICATES
+    })
+}
+
 /// Displays a welcome banner with configuration information
-fn display_welcome_banner(cli: &Cli) {
-    println!("🔍 Duplicate Finder v{}", env!("CARGO_PKG_VERSION"));
-    println!("📂 Scanning directory: {}", cli.directory.display());
+fn display_welcome_banner(cli: &ScannerConfig, style: &TextStyle) {
+    println!("{}Duplicate Finder v{}", style.icon("🔍 "), env!("CARGO_PKG_VERSION"));
+    println!("{}Scanning directory: {}", style.icon("📂 "), cli.directory.display());
+    for root in &cli.extra_roots {
+        println!("{}Also scanning: {}", style.icon("📂 "), root.display());
+    }
+    if !cli.extra_roots.is_empty() && cli.group_scope == GroupScope::PerRoot {
+        println!("{}Duplicates are only grouped within each root", style.icon("🧩 "));
+    }
+    if let Some(reference) = &cli.reference {
+        println!("{}Reference directory: {}", style.icon("📚 "), reference.display());
+    }
+    if cli.mode != Mode::Exact {
+        println!("{}Comparison mode: {:?}", style.icon("🧪 "), cli.mode);
+    }
+    if cli.normalize_text && cli.mode == Mode::Exact {
+        println!("{}Text normalization: on", style.icon("🧹 "));
+    }
+    if cli.hash_algo != HashAlgorithm::Sha256 && cli.mode == Mode::Exact {
+        let verification = if cli.verify_matches { "matches verified byte by byte" } else { "no byte verification" };
+        println!("{}Hash algorithm: {:?} ({})", style.icon("⚡ "), cli.hash_algo, verification);
+    }
 
     if cli.verbose {
         println!();
-        println!("🔧 Configuration:");
+        println!("{}Configuration:", style.icon("🔧 "));
 
         if cli.min_size > 0 {
-            println!("   📏 Min size: {}", duplicate_finder::format_bytes(cli.min_size));
+            println!("   {}Min size: {}", style.icon("📏 "), duplicate_finder::format_bytes(cli.min_size));
         }
 
         if cli.max_size > 0 {
-            println!("   📐 Max size: {}", duplicate_finder::format_bytes(cli.max_size));
+            println!("   {}Max size: {}", style.icon("📐 "), duplicate_finder::format_bytes(cli.max_size));
         }
 
-        println!("   👁️  Include hidden: {}", cli.include_hidden);
-        println!("   🚫 Exclude empty: {}", cli.exclude_empty);
-        println!("   🔗 Follow symlinks: {}", cli.follow_symlinks);
+        println!("   {}Include hidden: {}", style.icon("👁️  "), cli.include_hidden);
+        println!("   {}Exclude empty: {}", style.icon("🚫 "), cli.exclude_empty);
+        println!("   {}Follow symlinks: {}", style.icon("🔗 "), cli.follow_symlinks);
 
         if cli.max_depth > 0 {
-            println!("   📊 Max depth: {}", cli.max_depth);
+            println!("   {}Max depth: {}", style.icon("📊 "), cli.max_depth);
         }
 
-        println!("   🧵 Threads: {}",
-                 if cli.threads == 0 {
+        println!("   {}Hash concurrency: {}",
+                 style.icon("🧵 "),
+                 if cli.hash_concurrency == 0 {
                      "auto".to_string()
                  } else {
-                     cli.threads.to_string()
+                     cli.hash_concurrency.to_string()
                  }
         );
 
-        println!("   📄 Output format: {:?}", cli.output_format);
+        println!("   {}Output format: {:?}", style.icon("📄 "), cli.output_format);
+        println!("   {}Group by: {:?}", style.icon("🗂️  "), cli.group_by);
     }
 
     println!();
 }
 
 /// Displays a completion summary with key metrics
-fn display_completion_summary(scan_result: &duplicate_finder::ScanResult) {
+fn display_completion_summary(scan_result: &ScanResult, style: &TextStyle) {
     println!();
-    println!("✅ Scan completed!");
-    println!("⏱️  Duration: {:?}", scan_result.scan_duration);
+    println!("{}Scan completed!", style.icon("✅ "));
+    println!("{}Duration: {}", style.icon("⏱️  "), style.duration(scan_result.scan_duration));
+
+    if scan_result.file_errors > 0 {
+        println!("{}{} files or directories could not be read (see warnings above)", style.icon("⚠️  "), scan_result.file_errors);
+    }
+    if scan_result.partial {
+        println!("{}The scan stopped at its deadline: only files hashed until then were compared", style.icon("⚠️  "));
+    }
 
-    if scan_result.duplicate_groups.is_empty() {
-        println!("🎉 No duplicates found - your files are perfectly organized!");
+    if scan_result.group_count() == 0 {
+        println!("{}No duplicates found - your files are perfectly organized!", style.icon("🎉 "));
     } else {
-        println!("📊 Summary:");
-        println!("   📁 Files scanned: {}", scan_result.total_files);
-        println!("   🔄 Duplicate files: {}", scan_result.total_duplicates);
-        println!("   📦 Duplicate groups: {}", scan_result.duplicate_groups.len());
-        println!("   💾 Space wasted: {}", duplicate_finder::format_bytes(scan_result.total_wasted_space));
+        println!("{}Summary:", style.icon("📊 "));
+        println!("   {}Files scanned: {}", style.icon("📁 "), scan_result.total_files);
+        println!("   {}Duplicate files: {}", style.icon("🔄 "), scan_result.total_duplicates);
+        println!("   {}Duplicate groups: {}", style.icon("📦 "), scan_result.group_count());
+        println!("   {}Space wasted: {}", style.icon("💾 "), duplicate_finder::format_bytes(scan_result.total_wasted_space));
 
         // Calculate potential savings percentage
         if scan_result.total_files > 0 {
-            let largest_group = scan_result.duplicate_groups
-                .iter()
-                .max_by_key(|g| g.wasted_space);
-
-            if let Some(group) = largest_group {
-                println!("   🏆 Largest group: {} files, {} wasted",
-                         group.files.len(),
-                         duplicate_finder::format_bytes(group.wasted_space)
+            // Spilled groups are read back one at a time for this
+            let mut largest_group = None;
+            let read = scan_result.for_each_group(|group| {
+                if largest_group.is_none_or(|(_, wasted_space)| group.wasted_space >= wasted_space) {
+                    largest_group = Some((group.files.len(), group.wasted_space));
+                }
+                Ok(())
+            });
+
+            if let (Ok(()), Some((files, wasted_space))) = (read, largest_group) {
+                println!("   {}Largest group: {} files, {} wasted",
+                         style.icon("🏆 "),
+                         files,
+                         duplicate_finder::format_bytes(wasted_space)
                 );
             }
         }
     }
 }
 
+/// Name of a mode as given to `--mode`
+fn mode_name(mode: Mode) -> String {
+    clap::ValueEnum::to_possible_value(&mode).map_or_else(|| format!("{:?}", mode), |value| value.get_name().to_string())
+}
+
+/// Explains that a mode without reads only found candidates and how to confirm them
+fn display_unverified_note(mode: Mode, style: &TextStyle) {
+    let (name, matched) = match mode {
+        Mode::Metadata => ("metadata", "sizes and modification times"),
+        _ => ("quick", "names and sizes"),
+    };
+    let bullet = style.bullet();
+    println!();
+    println!("{}UNVERIFIED: {} mode matched {} only; these are candidates, not confirmed duplicates.", style.icon("⚠️  "), name, matched);
+    println!("   Confirm them by hashing just the candidates:");
+    println!("   {} duplicate-finder --mode {} -o fdupes -O candidates.txt", bullet, name);
+    println!("   {} duplicate-finder --files-from candidates.txt", bullet);
+}
+
 /// Provides helpful error context and suggestions
-fn display_error_help(error: &anyhow::Error) {
+fn display_error_help(error: &anyhow::Error, style: &TextStyle) {
+    let bullet = style.bullet();
+
     println!();
-    println!("💡 Troubleshooting tips:");
+    println!("{}Troubleshooting tips:", style.icon("💡 "));
 
     let error_str = error.to_string().to_lowercase();
 
     if error_str.contains("permission denied") {
-        println!("   • Try running with elevated permissions (sudo)");
-        println!("   • Check that you have read access to the directory");
-        println!("   • Use -H flag to skip hidden directories that might cause permission issues");
+        println!("   {} Try running with elevated permissions (sudo)", bullet);
+        println!("   {} Check that you have read access to the directory", bullet);
+        println!("   {} Use -H flag to skip hidden directories that might cause permission issues", bullet);
+    } else if error_str.contains("required tool not found") {
+        println!("   {} Install FFmpeg (ffmpeg and ffprobe) and make sure it is on your PATH", bullet);
+    } else if error_str.contains("object storage error") {
+        println!("   {} Set AWS_ACCESS_KEY_ID, AWS_SECRET_ACCESS_KEY and AWS_REGION for the bucket", bullet);
+        println!("   {} For S3-compatible services also set AWS_ENDPOINT (and AWS_ALLOW_HTTP=true for plain HTTP)", bullet);
+    } else if error_str.contains("sftp error") {
+        println!("   {} Check that you can log in with: ssh user@host", bullet);
+        println!("   {} Load your key into ssh-agent or keep it in ~/.ssh (id_ed25519, id_ecdsa or id_rsa)", bullet);
     } else if error_str.contains("not found") {
-        println!("   • Verify the directory path exists");
-        println!("   • Use absolute paths to avoid confusion");
-        println!("   • Check for typos in the path");
+        println!("   {} Verify the directory path exists", bullet);
+        println!("   {} Use absolute paths to avoid confusion", bullet);
+        println!("   {} Check for typos in the path", bullet);
+    } else if error_str.contains("requires a reference directory") {
+        println!("   {} Pass the reference tree before the command, e.g. --reference ~/Archive missing", bullet);
+    } else if error_str.contains("--scan-archives") {
+        println!("   {} Archive members are compared byte for byte; drop --mode and --normalize-text", bullet);
+    } else if error_str.contains("sftp:// locations") {
+        println!("   {} Remote files are compared byte for byte; drop --mode and --normalize-text", bullet);
+    } else if error_str.contains("in use by another duplicate-finder run") {
+        println!("   {} Wait for the other run to finish, or pass --wait-for-lock to queue behind it", bullet);
     } else if error_str.contains("invalid size filter") {
-        println!("   • Make sure min-size is less than max-size");
-        println!("   • Use 0 for max-size to remove the upper limit");
+        println!("   {} Make sure min-size is less than max-size", bullet);
+        println!("   {} Use 0 for max-size to remove the upper limit", bullet);
     } else {
-        println!("   • Try running with -v flag for more detailed error information");
-        println!("   • Check that the target directory is accessible");
-        println!("   • Ensure you have sufficient disk space for temporary operations");
+        println!("   {} Try running with -v flag for more detailed error information", bullet);
+        println!("   {} Check that the target directory is accessible", bullet);
+        println!("   {} Ensure you have sufficient disk space for temporary operations", bullet);
     }
 
-    println!("   • Run 'duplicate-finder --help' for usage information");
-}
\ No newline at end of file
+    println!("   {} Run 'duplicate-finder --help' for usage information", bullet);
+}
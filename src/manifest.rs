@@ -0,0 +1,215 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{DuplicateFinderError, FileInfo};
+
+/// One line of a checksum manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// Hex-encoded SHA-256 digest
+    pub hash: String,
+
+    /// Path relative to the manifest root
+    pub path: PathBuf,
+}
+
+/// Outcome of checking a directory tree against a manifest
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct VerifyReport {
+    /// Number of files whose contents match the manifest
+    pub matched: usize,
+
+    /// Files listed in the manifest that no longer exist
+    pub missing: Vec<PathBuf>,
+
+    /// Files whose contents differ from the manifest
+    pub changed: Vec<PathBuf>,
+
+    /// Files present on disk but not listed in the manifest
+    pub extra: Vec<PathBuf>,
+}
+
+impl VerifyReport {
+    /// Returns true when the tree matches the manifest exactly
+    pub fn is_clean(&self) -> bool {
+        self.missing.is_empty() && self.changed.is_empty() && self.extra.is_empty()
+    }
+}
+
+/// Renders scanned files as a `sha256sum`-compatible manifest
+///
+/// Paths are written relative to `root` and sorted, so running
+/// `sha256sum -c` from inside `root` checks the same files. Names
+/// containing backslashes or newlines use GNU coreutils' escaped form
+pub fn format_manifest(files: &[FileInfo], root: &Path) -> String {
+    let mut entries: Vec<(String, &str)> = files
+        .iter()
+        .map(|f| (relative_path(&f.path, root).to_string_lossy().into_owned(), f.hash.as_str()))
+        .collect();
+    entries.sort();
+
+    let mut output = String::new();
+    for (path, hash) in entries {
+        if path.contains('\\') || path.contains('\n') {
+            let escaped = path.replace('\\', "\\\\").replace('\n', "\\n");
+            output.push_str(&format!("\\{}  {}\n", hash, escaped));
+        } else {
+            output.push_str(&format!("{}  {}\n", hash, path));
+        }
+    }
+    output
+}
+
+/// Parses a `sha256sum`-style manifest
+///
+/// Accepts both text (`hash  path`) and binary (`hash *path`) markers and
+/// skips blank lines and `#` comments
+pub fn parse_manifest(content: &str) -> Result<Vec<ManifestEntry>, DuplicateFinderError> {
+    let mut entries = Vec::new();
+
+    for (index, line) in content.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let invalid = || DuplicateFinderError::InvalidManifest { line: index + 1 };
+
+        let (escaped, line) = match line.strip_prefix('\\') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+
+        let (hash, rest) = line.split_once(' ').ok_or_else(invalid)?;
+        let path = rest
+            .strip_prefix(' ')
+            .or_else(|| rest.strip_prefix('*'))
+            .ok_or_else(invalid)?;
+
+        if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) || path.is_empty() {
+            return Err(invalid());
+        }
+
+        let path = if escaped { unescape(path) } else { path.to_string() };
+
+        entries.push(ManifestEntry {
+            hash: hash.to_lowercase(),
+            path: PathBuf::from(path),
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Compares freshly hashed files against manifest entries
+pub fn verify(entries: &[ManifestEntry], files: &[FileInfo], root: &Path) -> VerifyReport {
+    let expected: BTreeMap<&Path, &str> = entries
+        .iter()
+        .map(|e| (e.path.as_path(), e.hash.as_str()))
+        .collect();
+    let actual: BTreeMap<PathBuf, &str> = files
+        .iter()
+        .map(|f| (relative_path(&f.path, root).to_path_buf(), f.hash.as_str()))
+        .collect();
+
+    let mut report = VerifyReport::default();
+
+    for (path, hash) in &expected {
+        match actual.get(*path) {
+            None => report.missing.push(path.to_path_buf()),
+            Some(actual_hash) if actual_hash != hash => report.changed.push(path.to_path_buf()),
+            Some(_) => report.matched += 1,
+        }
+    }
+
+    report.extra = actual
+        .keys()
+        .filter(|path| !expected.contains_key(path.as_path()))
+        .cloned()
+        .collect();
+
+    report
+}
+
+/// Strips the scan root from a path, leaving other paths untouched
+fn relative_path<'a>(path: &'a Path, root: &Path) -> &'a Path {
+    path.strip_prefix(root).unwrap_or(path)
+}
+
+/// Reverses GNU coreutils' file name escaping (`\\` and `\n`)
+fn unescape(path: &str) -> String {
+    let mut result = String::with_capacity(path.len());
+    let mut chars = path.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') => result.push('\n'),
+                Some(other) => result.push(other),
+                None => result.push('\\'),
+            }
+        } else {
+            result.push(c);
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH_A: &str = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+    const HASH_B: &str = "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+
+    fn file(path: &str, hash: &str) -> FileInfo {
+        FileInfo {
+            path: PathBuf::from(path),
+            size: 1,
+            hash: hash.to_string(),
+            modified: std::time::UNIX_EPOCH,
+            created: None,
+            compression: None,
+        }
+    }
+
+    #[test]
+    fn test_manifest_round_trip() {
+        let files = vec![file("/root/b.txt", HASH_B), file("/root/dir/a\\b", HASH_A)];
+        let manifest = format_manifest(&files, Path::new("/root"));
+
+        assert_eq!(manifest, format!("{}  b.txt\n\\{}  dir/a\\\\b\n", HASH_B, HASH_A));
+
+        let entries = parse_manifest(&manifest).unwrap();
+        assert_eq!(entries[0].path, PathBuf::from("b.txt"));
+        assert_eq!(entries[1].path, PathBuf::from("dir/a\\b"));
+        assert_eq!(entries[1].hash, HASH_A);
+
+        assert!(matches!(
+            parse_manifest("not a manifest line"),
+            Err(DuplicateFinderError::InvalidManifest { line: 1 })
+        ));
+    }
+
+    #[test]
+    fn test_verify() {
+        let entries = parse_manifest(&format!(
+            "{HASH_A}  same.txt\n{HASH_A}  changed.txt\n{HASH_A} *gone.txt\n"
+        ))
+        .unwrap();
+        let files = vec![
+            file("/root/same.txt", HASH_A),
+            file("/root/changed.txt", HASH_B),
+            file("/root/new.txt", HASH_B),
+        ];
+
+        let report = verify(&entries, &files, Path::new("/root"));
+        assert_eq!(report.matched, 1);
+        assert_eq!(report.changed, vec![PathBuf::from("changed.txt")]);
+        assert_eq!(report.missing, vec![PathBuf::from("gone.txt")]);
+        assert_eq!(report.extra, vec![PathBuf::from("new.txt")]);
+        assert!(!report.is_clean());
+    }
+}
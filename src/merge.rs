@@ -0,0 +1,262 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tracing::{debug, info, warn};
+
+use crate::index::HashIndex;
+use crate::{FileInfo, FileScanner, HashAlgorithm, Mode, ScannerConfig};
+
+/// How unique content is transferred into the destination
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum TransferMode {
+    /// Copy files, leaving the sources untouched
+    #[default]
+    Copy,
+    /// Move files, removing them from the sources
+    Move,
+}
+
+/// Options for consolidating directories
+#[derive(Debug, Clone, Default)]
+pub struct MergeOptions {
+    /// Copy or move unique files
+    pub mode: TransferMode,
+
+    /// Hard-link content that already exists in the destination to the
+    /// source's relative path instead of skipping it
+    pub link_existing: bool,
+
+    /// Plan the merge without touching the filesystem
+    pub dry_run: bool,
+}
+
+/// What happened to one source file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MergeAction {
+    /// Unique content copied to the target path
+    Copied,
+    /// Unique content moved to the target path
+    Moved,
+    /// Content already present; the target path was hard-linked to `existing`
+    Linked { existing: PathBuf },
+    /// Content already present at `existing`, nothing transferred
+    Skipped { existing: PathBuf },
+    /// The target path holds different content; the file was left alone
+    Conflict,
+    /// Transferring failed
+    Failed { reason: String },
+}
+
+/// One planned or performed transfer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MergeEntry {
+    /// File in one of the source directories
+    pub source: PathBuf,
+
+    /// Path the file maps to inside the destination
+    pub target: PathBuf,
+
+    /// Size of the file in bytes
+    pub size: u64,
+
+    /// Outcome for this file
+    pub action: MergeAction,
+}
+
+/// Outcome of a merge, one entry per source file
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MergeReport {
+    pub entries: Vec<MergeEntry>,
+}
+
+impl MergeReport {
+    /// Entries whose action matches the predicate
+    pub fn with_action(&self, predicate: impl Fn(&MergeAction) -> bool) -> impl Iterator<Item = &MergeEntry> {
+        self.entries.iter().filter(move |e| predicate(&e.action))
+    }
+
+    /// Number of files whose target path held different content
+    pub fn conflicts(&self) -> usize {
+        self.with_action(|a| matches!(a, MergeAction::Conflict)).count()
+    }
+
+    /// Number of files that could not be transferred
+    pub fn failures(&self) -> usize {
+        self.with_action(|a| matches!(a, MergeAction::Failed { .. })).count()
+    }
+
+    /// Bytes transferred (or that would be, in a dry run)
+    pub fn transferred_bytes(&self) -> u64 {
+        self.with_action(|a| matches!(a, MergeAction::Copied | MergeAction::Moved))
+            .map(|e| e.size)
+            .sum()
+    }
+}
+
+/// Consolidates several source directories into one destination
+///
+/// The destination is hashed first. Each source file then maps to the same
+/// relative path inside the destination: unique content is copied or moved
+/// there, content that already exists anywhere in the destination is
+/// skipped (or hard-linked), and a target path occupied by different
+/// content is reported as a conflict and never overwritten. Sources are
+/// processed in order, so the first copy of any content wins. Content is
+/// always compared by its raw bytes' SHA-256 hash, whatever comparison mode
+/// or hash algorithm is configured
+///
+/// Runs in a `merge` span; every transfer is logged at debug level
+#[tracing::instrument(name = "merge", skip_all, fields(destination = %destination.display(), sources = sources.len()))]
+pub async fn merge(config: &ScannerConfig, sources: &[PathBuf], destination: &Path, options: &MergeOptions) -> Result<MergeReport> {
+    let mut index = if destination.exists() {
+        let dest_config = ScannerConfig { directory: destination.to_path_buf(), mode: Mode::Exact, hash_algo: HashAlgorithm::Sha256, normalize_text: false, decompress: false, scan_archives: Vec::new(), ..config.clone() };
+        FileScanner::new(dest_config).build_index().await?
+    } else {
+        HashIndex::new()
+    };
+    info!(files = index.len(), "Indexed the destination");
+
+    let mut report = MergeReport::default();
+    for source in sources {
+        let source_config = ScannerConfig { directory: source.clone(), mode: Mode::Exact, hash_algo: HashAlgorithm::Sha256, normalize_text: false, decompress: false, scan_archives: Vec::new(), ..config.clone() };
+        let mut files = FileScanner::new(source_config).collect_files().await?;
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        for file in files {
+            let relative = file.path.strip_prefix(source).unwrap_or(&file.path);
+            let target = destination.join(relative);
+            let action = plan_and_apply(&file, &target, &index, options).await;
+            debug!(source = %file.path.display(), target = %target.display(), ?action, "Merged file");
+
+            if matches!(action, MergeAction::Copied | MergeAction::Moved | MergeAction::Linked { .. }) {
+                index.insert(FileInfo { path: target.clone(), ..file.clone() });
+            }
+
+            report.entries.push(MergeEntry {
+                source: file.path,
+                target,
+                size: file.size,
+                action,
+            });
+        }
+    }
+
+    Ok(report)
+}
+
+/// Decides what to do with one source file and performs it unless dry-running
+async fn plan_and_apply(file: &FileInfo, target: &Path, index: &HashIndex, options: &MergeOptions) -> MergeAction {
+    let existing = index.find_hash(&file.hash).next().map(|f| f.path.clone());
+    let occupied = fs::symlink_metadata(target).await.is_ok();
+
+    let action = match existing {
+        Some(existing) if options.link_existing && !occupied => MergeAction::Linked { existing },
+        Some(existing) => MergeAction::Skipped { existing },
+        None if occupied => MergeAction::Conflict,
+        None if options.mode == TransferMode::Move => MergeAction::Moved,
+        None => MergeAction::Copied,
+    };
+
+    if options.dry_run {
+        return action;
+    }
+
+    let outcome = match &action {
+        MergeAction::Copied => copy_file(file, target).await,
+        MergeAction::Moved => move_file(file, target).await,
+        MergeAction::Linked { existing } => link_file(existing, target).await,
+        _ => Ok(()),
+    };
+
+    match outcome {
+        Ok(()) => action,
+        Err(e) => {
+            warn!("Cannot transfer {} to {}: {}", file.path.display(), target.display(), e);
+            MergeAction::Failed { reason: e.to_string() }
+        }
+    }
+}
+
+/// Copies a file, preserving its modification time
+async fn copy_file(file: &FileInfo, target: &Path) -> std::io::Result<()> {
+    create_parent(target).await?;
+    fs::copy(&file.path, target).await?;
+
+    let copy = std::fs::File::options().write(true).open(target)?;
+    copy.set_modified(file.modified)?;
+    Ok(())
+}
+
+/// Moves a file, falling back to copy-and-delete across filesystems
+async fn move_file(file: &FileInfo, target: &Path) -> std::io::Result<()> {
+    create_parent(target).await?;
+    if fs::rename(&file.path, target).await.is_ok() {
+        return Ok(());
+    }
+
+    copy_file(file, target).await?;
+    fs::remove_file(&file.path).await
+}
+
+/// Hard-links existing destination content to a new path
+async fn link_file(existing: &Path, target: &Path) -> std::io::Result<()> {
+    create_parent(target).await?;
+    fs::hard_link(existing, target).await
+}
+
+/// Creates the directory a target file will live in
+async fn create_parent(target: &Path) -> std::io::Result<()> {
+    match target.parent() {
+        Some(parent) => fs::create_dir_all(parent).await,
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_merge() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        for (path, content) in [
+            ("dest/kept.txt", "existing"),
+            ("dest/clash.txt", "destination version"),
+            ("a/copy-of-kept.txt", "existing"),
+            ("a/clash.txt", "source version"),
+            ("a/new/unique.txt", "unique"),
+            ("b/same-unique.txt", "unique"),
+        ] {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+
+        let config = ScannerConfig { quiet: true, ..Default::default() };
+        let sources = [root.join("a"), root.join("b")];
+        let destination = root.join("dest");
+
+        // A dry run plans everything but changes nothing
+        let options = MergeOptions { dry_run: true, ..Default::default() };
+        let report = merge(&config, &sources, &destination, &options).await.unwrap();
+        assert_eq!(report.conflicts(), 1);
+        assert!(!destination.join("new/unique.txt").exists());
+
+        let report = merge(&config, &sources, &destination, &MergeOptions::default()).await.unwrap();
+        let action = |name: &str| {
+            report.entries.iter().find(|e| e.source.ends_with(name)).map(|e| e.action.clone()).unwrap()
+        };
+        assert_eq!(action("a/new/unique.txt"), MergeAction::Copied);
+        assert_eq!(action("a/clash.txt"), MergeAction::Conflict);
+        assert!(matches!(action("a/copy-of-kept.txt"), MergeAction::Skipped { .. }));
+        // The first source already brought this content over
+        assert!(matches!(action("b/same-unique.txt"), MergeAction::Skipped { .. }));
+
+        assert_eq!(std::fs::read_to_string(destination.join("new/unique.txt")).unwrap(), "unique");
+        assert_eq!(std::fs::read_to_string(destination.join("clash.txt")).unwrap(), "destination version");
+        assert_eq!(report.transferred_bytes(), 6);
+    }
+}
@@ -0,0 +1,186 @@
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tracing::{debug, info, warn};
+
+use crate::ScanResult;
+
+/// Scan metrics exported in the Prometheus text format
+///
+/// Counters are updated by the scanner as files are hashed; gauges describe
+/// the most recent scan result (or watch-mode update). All fields are
+/// atomics so one instance can be shared between hashing tasks and the
+/// metrics endpoint
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Files hashed since the process started
+    files_hashed: AtomicU64,
+
+    /// Bytes hashed since the process started
+    bytes_hashed: AtomicU64,
+
+    /// Bytes hashed when the previous result was recorded
+    bytes_at_last_result: AtomicU64,
+
+    /// Number of results recorded (initial scan plus watch updates)
+    scans: AtomicU64,
+
+    /// Gauges from the latest result
+    files_scanned: AtomicU64,
+    duplicate_groups: AtomicU64,
+    duplicate_files: AtomicU64,
+    wasted_bytes: AtomicU64,
+    file_errors: AtomicU64,
+    throughput: AtomicU64,
+    last_scan_timestamp: AtomicU64,
+}
+
+impl Metrics {
+    /// Creates an empty, shareable metrics registry
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Records one successfully hashed file
+    pub fn record_file(&self, size: u64) {
+        self.files_hashed.fetch_add(1, Ordering::Relaxed);
+        self.bytes_hashed.fetch_add(size, Ordering::Relaxed);
+    }
+
+    /// Updates the gauges from a finished scan or watch update
+    ///
+    /// Hash throughput is computed from the bytes hashed since the previous
+    /// result over this result's scan duration
+    pub fn record_result(&self, scan_result: &ScanResult) {
+        let bytes_now = self.bytes_hashed.load(Ordering::Relaxed);
+        let bytes_before = self.bytes_at_last_result.swap(bytes_now, Ordering::Relaxed);
+        let seconds = scan_result.scan_duration.as_secs_f64();
+        let throughput = if seconds > 0.0 {
+            (bytes_now.saturating_sub(bytes_before) as f64 / seconds) as u64
+        } else {
+            0
+        };
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        self.scans.fetch_add(1, Ordering::Relaxed);
+        self.files_scanned.store(scan_result.total_files as u64, Ordering::Relaxed);
+        self.duplicate_groups.store(scan_result.group_count() as u64, Ordering::Relaxed);
+        self.duplicate_files.store(scan_result.total_duplicates as u64, Ordering::Relaxed);
+        self.wasted_bytes.store(scan_result.total_wasted_space, Ordering::Relaxed);
+        self.file_errors.store(scan_result.file_errors as u64, Ordering::Relaxed);
+        self.throughput.store(throughput, Ordering::Relaxed);
+        self.last_scan_timestamp.store(timestamp, Ordering::Relaxed);
+    }
+
+    /// Every metric as its name, kind, help text and current value
+    pub(crate) fn samples(&self) -> [(&'static str, &'static str, &'static str, u64); 11] {
+        let metrics: [(&str, &str, &str, &AtomicU64); 11] = [
+            ("files_hashed_total", "counter", "Files hashed since the process started", &self.files_hashed),
+            ("bytes_hashed_total", "counter", "Bytes hashed since the process started", &self.bytes_hashed),
+            ("scans_total", "counter", "Completed scans, including watch-mode updates", &self.scans),
+            ("files_scanned", "gauge", "Files included in the latest result", &self.files_scanned),
+            ("duplicate_groups", "gauge", "Duplicate groups in the latest result", &self.duplicate_groups),
+            ("duplicate_files", "gauge", "Redundant copies in the latest result", &self.duplicate_files),
+            ("wasted_bytes", "gauge", "Bytes taken by redundant copies in the latest result", &self.wasted_bytes),
+            ("file_errors", "gauge", "Files and directories that could not be read in the latest result", &self.file_errors),
+            ("hash_throughput_bytes_per_second", "gauge", "Hashing throughput of the latest scan", &self.throughput),
+            ("last_scan_timestamp_seconds", "gauge", "Unix time the latest result was produced", &self.last_scan_timestamp),
+            ("up", "gauge", "Whether the duplicate finder is running", &AtomicU64::new(1)),
+        ];
+        metrics.map(|(name, kind, help, value)| (name, kind, help, value.load(Ordering::Relaxed)))
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        for (name, kind, help, value) in self.samples() {
+            let _ = writeln!(output, "# HELP duplicate_finder_{} {}", name, help);
+            let _ = writeln!(output, "# TYPE duplicate_finder_{} {}", name, kind);
+            let _ = writeln!(output, "duplicate_finder_{} {}", name, value);
+        }
+        output
+    }
+}
+
+/// Serves metrics over HTTP at `/metrics` until the process exits
+///
+/// A deliberately tiny HTTP/1.0 responder: Prometheus only needs plain
+/// GET requests, so this avoids pulling in a full web server
+pub async fn serve(addr: SocketAddr, metrics: Arc<Metrics>) -> Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    info!("Serving Prometheus metrics on http://{}/metrics", listener.local_addr()?);
+
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, peer) = match listener.accept().await {
+                Ok(connection) => connection,
+                Err(e) => {
+                    warn!("Metrics connection failed: {}", e);
+                    continue;
+                }
+            };
+
+            let metrics = Arc::clone(&metrics);
+            tokio::spawn(async move {
+                let (reader, mut writer) = stream.split();
+                let mut request_line = String::new();
+                let mut reader = BufReader::new(reader);
+                if reader.read_line(&mut request_line).await.is_err() {
+                    return;
+                }
+                debug!("Metrics request from {}: {}", peer, request_line.trim_end());
+
+                let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+                let response = if path == "/metrics" || path == "/" {
+                    let body = metrics.render();
+                    format!(
+                        "HTTP/1.0 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                } else {
+                    "HTTP/1.0 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_string()
+                };
+
+                let _ = writer.write_all(response.as_bytes()).await;
+                let _ = writer.shutdown().await;
+            });
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new();
+        metrics.record_file(100);
+        metrics.record_file(50);
+        metrics.record_result(&ScanResult {
+            total_files: 2,
+            total_wasted_space: 50,
+            scan_duration: std::time::Duration::from_secs(1),
+            ..Default::default()
+        });
+
+        let rendered = metrics.render();
+        assert!(rendered.contains("# TYPE duplicate_finder_bytes_hashed_total counter\n"));
+        assert!(rendered.contains("duplicate_finder_bytes_hashed_total 150\n"));
+        assert!(rendered.contains("duplicate_finder_files_scanned 2\n"));
+        assert!(rendered.contains("duplicate_finder_wasted_bytes 50\n"));
+        assert!(rendered.contains("duplicate_finder_hash_throughput_bytes_per_second 150\n"));
+    }
+}
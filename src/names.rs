@@ -0,0 +1,140 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{DuplicateFinderError, DuplicateGroup, FileInfo};
+
+/// Reads a file's metadata without hashing it
+///
+/// The file name is the comparison key; it is normalized when grouping
+pub async fn name_file_info(path: &Path) -> Result<FileInfo, DuplicateFinderError> {
+    let mut file_info = FileInfo::stat(path).await?;
+    file_info.hash = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    Ok(file_info)
+}
+
+/// Reads a file's metadata and keys it by name and size, without reading it
+///
+/// Used by `--mode quick`: equal keys only suggest duplicates, so the key is
+/// marked `quick:` to keep it apart from content hashes
+pub async fn quick_file_info(path: &Path) -> Result<FileInfo, DuplicateFinderError> {
+    let mut file_info = FileInfo::stat(path).await?;
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    file_info.hash = format!("quick:{}:{}", file_info.size, name);
+    Ok(file_info)
+}
+
+/// Reads a file's metadata and keys it by size and modification time
+///
+/// Used by `--mode metadata` for storage where every read is expensive;
+/// equal keys are unverified candidates
+pub async fn metadata_file_info(path: &Path) -> Result<FileInfo, DuplicateFinderError> {
+    let mut file_info = FileInfo::stat(path).await?;
+    let modified = file_info.modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+    file_info.hash = format!("metadata:{}:{}.{:09}", file_info.size, modified.as_secs(), modified.subsec_nanos());
+    Ok(file_info)
+}
+
+/// Key under which file names collide
+///
+/// With `normalize_unicode` composed and decomposed spellings (as written by
+/// macOS and most other systems) compare equal; with `ignore_case` letter
+/// case is ignored, like on case-insensitive filesystems
+pub fn name_key(path: &Path, ignore_case: bool, normalize_unicode: bool) -> Option<String> {
+    let name = path.file_name()?.to_string_lossy();
+    let name: String = if normalize_unicode { name.nfc().collect() } else { name.into_owned() };
+    Some(if ignore_case { name.to_lowercase() } else { name })
+}
+
+/// Groups files whose names collide, whatever their content
+///
+/// Groups are keyed by the colliding name. Equal names say nothing about
+/// redundancy, so no space counts as wasted
+pub fn group_by_name(file_infos: Vec<FileInfo>, ignore_case: bool, normalize_unicode: bool) -> Vec<DuplicateGroup> {
+    let mut groups: HashMap<String, Vec<FileInfo>> = HashMap::new();
+    for file_info in file_infos {
+        if let Some(key) = name_key(&file_info.path, ignore_case, normalize_unicode) {
+            groups.entry(key).or_default().push(file_info);
+        }
+    }
+
+    groups.into_iter()
+        .map(|(name, mut files)| {
+            // Sort files by modification time (oldest first)
+            files.sort_by_key(|f| f.modified);
+            DuplicateGroup {
+                id: String::new(),
+                hash: name,
+                size: files[0].size,
+                total_size: files.iter().map(|f| f.size).sum(),
+                wasted_space: 0,
+                files,
+            }
+        })
+        .collect()
+}
+
+/// Groups files whose names collide but whose contents differ
+///
+/// `file_infos` must carry content hashes. A group is kept when its files
+/// hold at least two different contents: syncing them onto one
+/// case-insensitive or normalizing filesystem would overwrite one of them
+pub fn group_conflicts(file_infos: Vec<FileInfo>, ignore_case: bool, normalize_unicode: bool) -> Vec<DuplicateGroup> {
+    group_by_name(file_infos, ignore_case, normalize_unicode)
+        .into_iter()
+        .filter(|group| group.files.iter().map(|f| f.hash.as_str()).collect::<HashSet<_>>().len() > 1)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_name_collisions() {
+        let file = |path: &str, hash: &str| FileInfo {
+            path: PathBuf::from(path),
+            size: 10,
+            hash: hash.to_string(),
+            modified: UNIX_EPOCH,
+            created: None,
+            compression: None,
+        };
+        // "Café" spelled with a combining accent, as macOS writes it
+        let files = || vec![
+            file("/a/Café.txt", "x"),
+            file("/b/cafe\u{301}.txt", "y"),
+            file("/c/README", "z"),
+            file("/d/readme", "z"),
+        ];
+
+        assert!(group_by_name(files(), false, false).iter().all(|g| g.files.len() == 1));
+        let groups = group_by_name(files(), true, true);
+        assert_eq!(groups.iter().filter(|g| g.files.len() == 2).count(), 2);
+        assert!(groups.iter().all(|g| g.wasted_space == 0));
+
+        // Only the readme pair holds the same content
+        let conflicts = group_conflicts(files(), true, true);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].hash, "café.txt");
+    }
+
+    #[tokio::test]
+    async fn test_quick_key() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        std::fs::write(temp_dir.path().join("a.bin"), "same size").unwrap();
+        std::fs::write(temp_dir.path().join("b.bin"), "different").unwrap();
+
+        let a = quick_file_info(&temp_dir.path().join("a.bin")).await.unwrap();
+        assert_eq!(a.hash, "quick:9:a.bin");
+        assert_ne!(a.hash, quick_file_info(&temp_dir.path().join("b.bin")).await.unwrap().hash);
+
+        let modified = std::fs::File::open(temp_dir.path().join("a.bin")).unwrap();
+        modified.set_modified(UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000)).unwrap();
+        let a = metadata_file_info(&temp_dir.path().join("a.bin")).await.unwrap();
+        assert_eq!(a.hash, "metadata:9:1700000000.000000000");
+    }
+}
@@ -0,0 +1,95 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use opentelemetry::metrics::MeterProvider as _;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::{MetricExporter, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::metrics::{PeriodicReader, SdkMeterProvider};
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use tracing::{Subscriber, warn};
+use tracing_subscriber::Layer;
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::metrics::Metrics;
+
+/// Name the spans and metrics are exported under
+const SERVICE_NAME: &str = "duplicate-finder";
+
+/// How often metrics are exported
+const METRICS_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Exports spans and metrics to an OpenTelemetry collector over OTLP/HTTP
+/// (`--otel-endpoint`)
+///
+/// The `scan` span and its phase spans carry their timings and counts (see
+/// the crate docs); the metrics of a [`Metrics`] registry, file errors
+/// included, are exported every 15 seconds. Call [`Telemetry::shutdown`]
+/// before the process exits, or the last spans and metrics are lost
+pub struct Telemetry {
+    tracer_provider: SdkTracerProvider,
+    meter_provider: SdkMeterProvider,
+}
+
+impl Telemetry {
+    /// Exports to the collector at `endpoint`, e.g. `http://localhost:4318`
+    pub fn start(endpoint: &str, metrics: Arc<Metrics>) -> Result<Self> {
+        let endpoint = endpoint.trim_end_matches('/');
+        let resource = Resource::builder().with_service_name(SERVICE_NAME).build();
+
+        let spans = SpanExporter::builder().with_http().with_endpoint(format!("{}/v1/traces", endpoint)).build()?;
+        let tracer_provider = SdkTracerProvider::builder()
+            .with_batch_exporter(spans)
+            .with_resource(resource.clone())
+            .build();
+
+        let exporter = MetricExporter::builder().with_http().with_endpoint(format!("{}/v1/metrics", endpoint)).build()?;
+        let meter_provider = SdkMeterProvider::builder()
+            .with_reader(PeriodicReader::builder(exporter).with_interval(METRICS_INTERVAL).build())
+            .with_resource(resource)
+            .build();
+        observe(&meter_provider, metrics);
+
+        Ok(Self { tracer_provider, meter_provider })
+    }
+
+    /// Layer sending the spans of a tracing subscriber to the collector
+    pub fn layer<S>(&self) -> impl Layer<S> + Send + Sync
+    where
+        S: Subscriber + for<'span> LookupSpan<'span> + Send + Sync,
+    {
+        tracing_opentelemetry::layer().with_tracer(self.tracer_provider.tracer(SERVICE_NAME))
+    }
+
+    /// Sends what was not exported yet and stops exporting; failures are
+    /// logged as warnings
+    pub fn shutdown(&self) {
+        if let Err(e) = self.tracer_provider.shutdown() {
+            warn!(error = %e, "Cannot export the last spans");
+        }
+        if let Err(e) = self.meter_provider.shutdown() {
+            warn!(error = %e, "Cannot export the last metrics");
+        }
+    }
+}
+
+/// Registers an instrument per metric of `metrics`, read at every export
+fn observe(provider: &SdkMeterProvider, metrics: Arc<Metrics>) {
+    let meter = provider.meter(SERVICE_NAME);
+    for (index, (name, kind, help, _)) in metrics.samples().into_iter().enumerate() {
+        let metrics = Arc::clone(&metrics);
+        let name = format!("duplicate_finder.{}", name);
+        if kind == "counter" {
+            meter.u64_observable_counter(name)
+                .with_description(help)
+                .with_callback(move |observer| observer.observe(metrics.samples()[index].3, &[]))
+                .build();
+        } else {
+            meter.u64_observable_gauge(name)
+                .with_description(help)
+                .with_callback(move |observer| observer.observe(metrics.samples()[index].3, &[]))
+                .build();
+        }
+    }
+}
@@ -1,118 +1,543 @@
-use std::path::Path;
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
 use anyhow::Result;
 use chrono::{DateTime, Local};
+use serde::ser::{Error as _, SerializeSeq, SerializeStruct};
+use serde::{Serialize, Serializer};
 use serde_json;
-use tokio::fs;
 
-use crate::{Cli, DuplicateGroup, OutputFormat, ScanResult};
+use crate::{archives, atomic, directories};
+use crate::lock::StateLock;
+use crate::style::Color;
+use crate::{DuplicateGroup, GroupBy, OutputFormat, ScanResult, ScannerConfig, TextStyle};
 
 /// Output formatter for scan results
 ///
 /// This component is responsible for presenting results in various formats
 /// and providing a convenient user interface
 pub struct OutputFormatter<'a> {
-    config: &'a Cli,
+    config: &'a ScannerConfig,
+    style: TextStyle,
 }
 
 impl<'a> OutputFormatter<'a> {
     /// Creates a new formatter instance
-    pub fn new(config: &'a Cli) -> Self {
-        Self { config }
+    pub fn new(config: &'a ScannerConfig) -> Self {
+        Self {
+            config,
+            style: TextStyle::from_config(config),
+        }
     }
 
     /// Main function for displaying results
     ///
     /// Selects an output format based on configuration and displays results
     pub async fn display_results(&self, scan_result: &ScanResult) -> Result<()> {
+        let scan_result = &*self.report_view(scan_result)?;
         match self.config.output_format {
-            OutputFormat::Text => self.display_text_format(scan_result).await,
+            // A database can't be printed; show the human-readable report instead
+            OutputFormat::Text | OutputFormat::Sqlite => self.display_text_format(scan_result).await,
             OutputFormat::Json => self.display_json_format(scan_result).await,
+            OutputFormat::Fdupes => {
+                let mut stdout = std::io::stdout().lock();
+                self.write_fdupes(scan_result, &mut stdout)?;
+                stdout.flush()?;
+                Ok(())
+            }
+            OutputFormat::Markdown => {
+                let mut stdout = std::io::stdout().lock();
+                stdout.write_all(self.format_as_markdown(scan_result).as_bytes())?;
+                stdout.flush()?;
+                Ok(())
+            }
         }
     }
 
     /// Saves results to a file
+    ///
+    /// The file is locked while it is written, so two runs never write it
+    /// at once (see `--wait-for-lock`), and replaced in one step, so an
+    /// interrupted run never leaves a truncated file behind. JSON and fdupes
+    /// output is written as it is formatted, so spilled groups never have to
+    /// fit in memory
     pub async fn save_to_file(&self, scan_result: &ScanResult, output_path: &Path) -> Result<()> {
+        let (owned, wait) = (output_path.to_path_buf(), self.config.wait_for_lock);
+        let _lock = tokio::task::spawn_blocking(move || StateLock::acquire(&owned, wait)).await??;
+        let scan_result = &*self.report_view(scan_result)?;
         let content = match self.config.output_format {
-            OutputFormat::Text => self.format_as_text(scan_result),
-            OutputFormat::Json => serde_json::to_string_pretty(scan_result)?,
+            OutputFormat::Sqlite => return save_to_database(scan_result, output_path).await,
+            OutputFormat::Text => self.without_color().format_as_text(scan_result),
+            OutputFormat::Json => {
+                atomic::write_with(output_path, |writer| write_json(scan_result, writer))?;
+                return Ok(());
+            }
+            OutputFormat::Fdupes => {
+                atomic::write_with(output_path, |writer| self.write_fdupes(scan_result, writer))?;
+                return Ok(());
+            }
+            OutputFormat::Markdown => self.format_as_markdown(scan_result),
         };
 
-        fs::write(output_path, content).await?;
+        atomic::write_async(output_path, content.into_bytes()).await?;
         Ok(())
     }
 
+    /// Writes the paths of all redundant copies, suitable for piping
+    ///
+    /// Originals and files inside archives are never listed; sidecars of
+    /// redundant photos follow the copies. With `--report-archived` the
+    /// loose files already contained in archives are listed instead. Paths
+    /// are separated by newlines, or by NUL bytes when null separation is
+    /// requested (e.g. for `xargs -0 rm`)
+    pub fn write_duplicate_paths(&self, scan_result: &ScanResult, writer: &mut impl Write) -> std::io::Result<()> {
+        let separator: &[u8] = if self.config.null_separated { b"\0" } else { b"\n" };
+        let scan_result = &*self.report_view(scan_result)?;
+
+        if self.config.report_archived {
+            for file in &scan_result.archived_files {
+                writer.write_all(&path_bytes(&file.path))?;
+                writer.write_all(separator)?;
+            }
+            return writer.flush();
+        }
+
+        scan_result.for_each_group(|group| {
+            for file in group.redundant_files().filter(|f| !archives::is_member(&f.path)) {
+                writer.write_all(&path_bytes(&file.path))?;
+                writer.write_all(separator)?;
+            }
+            Ok(())
+        })?;
+        for sidecar in &scan_result.sidecars {
+            writer.write_all(&path_bytes(&sidecar.path))?;
+            writer.write_all(separator)?;
+        }
+
+        writer.flush()
+    }
+
+    /// Applies report filters to the results before rendering
+    ///
+    /// With `--top N` only the N groups wasting the most space are kept,
+    /// along with the sidecars of their photos; the summary totals still
+    /// describe the whole scan. Spilled groups are
+    /// read back once, holding no more than N of them in memory
+    fn report_view<'r>(&self, scan_result: &'r ScanResult) -> std::io::Result<Cow<'r, ScanResult>> {
+        if self.config.top == 0
+            || (scan_result.spilled_groups.is_none()
+                && scan_result.duplicate_groups.len() <= self.config.top
+                && scan_result.directory_groups.len() <= self.config.top
+                && scan_result.directory_overlaps.len() <= self.config.top
+                && scan_result.similar_pairs.len() <= self.config.top)
+        {
+            return Ok(Cow::Borrowed(scan_result));
+        }
+
+        let mut filtered = match &scan_result.spilled_groups {
+            Some(_) => ScanResult {
+                duplicate_groups: largest_groups(scan_result, self.config.top)?,
+                spilled_groups: None,
+                ..scan_result.clone()
+            },
+            None => scan_result.clone(),
+        };
+        filtered.duplicate_groups.sort_by_key(|g| Reverse(g.wasted_space));
+        filtered.duplicate_groups.truncate(self.config.top);
+        filtered.directory_groups.truncate(self.config.top);
+        filtered.directory_overlaps.truncate(self.config.top);
+        filtered.similar_pairs.truncate(self.config.top);
+        let kept = &filtered.duplicate_groups;
+        filtered.sidecars.retain(|sidecar| kept.iter().any(|group| group.files.iter().any(|file| file.path == sidecar.companion)));
+        Ok(Cow::Owned(filtered))
+    }
+
+    /// Returns a copy of this formatter that never emits color codes
+    fn without_color(&self) -> Self {
+        Self {
+            config: self.config,
+            style: self.style.without_color(),
+        }
+    }
+
     /// Display results in text format
     ///
     /// Creates a beautiful, human-readable report using Unicode symbols
     /// for better visual perception 
     async fn display_text_format(&self, scan_result: &ScanResult) -> Result<()> {
-        println!("{}", self.format_as_text(scan_result));
+        let mut stdout = std::io::stdout().lock();
+        writeln!(stdout, "{}", self.format_as_text(scan_result))?;
+        stdout.flush()?;
         Ok(())
     }
 
     /// Formats results as text
     fn format_as_text(&self, scan_result: &ScanResult) -> String {
+        let st = &self.style;
         let mut output = String::new();
 
         // Report header
-        output.push_str("📊 SCAN RESULTS\n");
-        output.push_str(&"═".repeat(50));
+        output.push_str(&format!("{}{}\n", st.icon("📊 "), st.paint("SCAN RESULTS", Color::Bold)));
+        output.push_str(&st.rule('═', '=', 50));
         output.push('\n');
+        if self.config.mode.is_unverified() {
+            output.push_str(&format!("{}{}\n", st.icon("⚠️  "), st.paint("UNVERIFIED: files were matched without reading their content", Color::Yellow)));
+        }
 
         // General statistics
-        output.push_str(&format!("📁 Scanned Directory: {}\n", scan_result.scanned_directory.display()));
-        output.push_str(&format!("⏱️  Scan Duration: {:?}\n", scan_result.scan_duration));
-        output.push_str(&format!("📄 Total Files: {}\n", scan_result.total_files));
-        output.push_str(&format!("🔄 Duplicate Files: {}\n", scan_result.total_duplicates));
-        output.push_str(&format!("📦 Duplicate Groups: {}\n", scan_result.duplicate_groups.len()));
-        output.push_str(&format!("💾 Wasted Space: {}\n", format_bytes(scan_result.total_wasted_space)));
+        output.push_str(&format!("{}Scanned Directory: {}\n", st.icon("📁 "), scan_result.scanned_directory.display()));
+        output.push_str(&format!("{}Scan Duration: {}\n", st.icon("⏱️  "), st.duration(scan_result.scan_duration)));
+        output.push_str(&format!("{}Total Files: {}\n", st.icon("📄 "), scan_result.total_files));
+        output.push_str(&format!("{}Duplicate Files: {}\n", st.icon("🔄 "), scan_result.total_duplicates));
+        output.push_str(&format!("{}Duplicate Groups: {}\n", st.icon("📦 "), scan_result.duplicate_groups.len()));
+        if !scan_result.directory_groups.is_empty() {
+            output.push_str(&format!("{}Duplicate Directories: {}\n", st.icon("🗂️  "), scan_result.directory_groups.len()));
+        }
+        output.push_str(&format!("{}Wasted Space: {}\n", st.icon("💾 "), st.paint(format_bytes(scan_result.total_wasted_space), Color::Red)));
         output.push('\n');
 
+        output.push_str(&self.format_empty_directories_section(scan_result));
+        output.push_str(&self.format_archived_section(scan_result));
+        output.push_str(&self.format_chunk_section(scan_result));
+
         if scan_result.duplicate_groups.is_empty() {
-            output.push_str("🎉 No duplicates found! Your file system is clean.\n");
+            output.push_str(&format!("{}{}\n", st.icon("🎉 "), st.paint("No duplicates found! Your file system is clean.", Color::Green)));
             return output;
         }
 
+        match self.config.group_by {
+            GroupBy::Hash => {
+                output.push_str(&self.format_directory_groups_section(scan_result));
+                output.push_str(&self.format_overlaps_section(scan_result));
+                output.push_str(&self.format_similar_pairs_section(scan_result));
+                output.push_str(&self.format_groups_section(scan_result));
+                output.push_str(&self.format_sidecars_section(scan_result));
+            }
+            GroupBy::Directory => output.push_str(&self.format_directories_section(scan_result)),
+        }
+
+        // Final recommendations (skipped in quiet mode)
+        if !self.config.quiet {
+            output.push_str(&self.generate_recommendations(scan_result));
+        }
+
+        output
+    }
+
+    /// Formats all duplicate groups, largest wasted space first
+    fn format_groups_section(&self, scan_result: &ScanResult) -> String {
+        let st = &self.style;
+        let mut output = String::new();
+
         // Sort duplicate groups by wasted space size (descending)
-        let mut sorted_groups = scan_result.duplicate_groups.clone();
-        sorted_groups.sort_by(|a, b| b.wasted_space.cmp(&a.wasted_space));
+        let mut sorted_groups: Vec<&DuplicateGroup> = scan_result.duplicate_groups.iter()
+            .filter(|g| !directories::is_covered(g, &scan_result.directory_groups))
+            .collect();
+        sorted_groups.sort_by_key(|g| std::cmp::Reverse(g.wasted_space));
 
         // Detailed information about duplicate groups
-        output.push_str("🔍 DUPLICATE GROUPS (sorted by wasted space)\n");
-        output.push_str(&"─".repeat(50));
+        output.push_str(&format!("{}{}\n", st.icon("🔍 "), st.paint("DUPLICATE GROUPS (sorted by wasted space)", Color::Bold)));
+        output.push_str(&st.rule('─', '-', 50));
         output.push('\n');
 
+        let hidden = scan_result.duplicate_groups.len() - sorted_groups.len();
+        if hidden > 0 {
+            output.push_str(&format!("{}{} groups inside duplicate directories are not listed\n\n", st.icon("🗂️  "), hidden));
+        }
+
         for (index, group) in sorted_groups.iter().enumerate() {
             output.push_str(&self.format_duplicate_group(group, index + 1));
             output.push('\n');
         }
 
-        // Final recommendations
-        output.push_str(&self.generate_recommendations(scan_result));
+        output
+    }
+
+    /// Formats groups of identical directory trees, if any were detected
+    fn format_directory_groups_section(&self, scan_result: &ScanResult) -> String {
+        let st = &self.style;
+        let mut output = String::new();
+        if scan_result.directory_groups.is_empty() {
+            return output;
+        }
+
+        output.push_str(&format!("{}{}\n", st.icon("🗂️  "), st.paint("DUPLICATE DIRECTORIES (sorted by wasted space)", Color::Bold)));
+        output.push_str(&st.rule('─', '-', 50));
+        output.push('\n');
+
+        for (index, group) in scan_result.directory_groups.iter().enumerate() {
+            output.push_str(&format!(
+                "{}{} ({} files, {})\n",
+                st.icon("📋 "),
+                st.paint(format!("Directory group #{}", index + 1), Color::Cyan),
+                group.file_count,
+                st.paint(format_bytes(group.size), Color::Magenta)
+            ));
+            output.push_str(&format!("   {}Wasted space: {}\n", st.icon("💰 "), st.paint(format_bytes(group.wasted_space), Color::Red)));
+
+            for (dir_index, dir) in group.directories.iter().enumerate() {
+                let (marker, path_color) = if dir_index == 0 {
+                    (st.pick("📌", "*"), Color::Green)
+                } else {
+                    (st.pick("🔄", "-"), Color::Yellow)
+                };
+                output.push_str(&format!("     {} {}\n", marker, st.paint(dir.display(), path_color)));
+            }
+            output.push('\n');
+        }
+
+        output
+    }
+
+    /// Formats directories largely contained in other directories, if requested
+    fn format_overlaps_section(&self, scan_result: &ScanResult) -> String {
+        let st = &self.style;
+        let mut output = String::new();
+        if scan_result.directory_overlaps.is_empty() {
+            return output;
+        }
+
+        output.push_str(&format!("{}{}\n", st.icon("🧩 "), st.paint("OVERLAPPING DIRECTORIES (sorted by overlap)", Color::Bold)));
+        output.push_str(&st.rule('─', '-', 50));
+        output.push('\n');
+
+        for overlap in &scan_result.directory_overlaps {
+            output.push_str(&format!(
+                "{}{} is {} contained in {}\n",
+                st.icon("📁 "),
+                st.paint(overlap.directory.display(), Color::Cyan),
+                st.paint(format!("{:.0}%", overlap.percent()), Color::Yellow),
+                st.paint(overlap.container.display(), Color::Green)
+            ));
+            output.push_str(&format!(
+                "   {}{} of {} files shared ({})\n",
+                st.icon("📊 "),
+                overlap.shared_files,
+                overlap.total_files,
+                format_bytes(overlap.shared_bytes)
+            ));
+        }
+        output.push('\n');
+
+        output
+    }
+
+    /// Formats pairs of similar text files found with `--mode text-similar`
+    fn format_similar_pairs_section(&self, scan_result: &ScanResult) -> String {
+        let st = &self.style;
+        let mut output = String::new();
+        if scan_result.similar_pairs.is_empty() {
+            return output;
+        }
+
+        output.push_str(&format!("{}{}\n", st.icon("📝 "), st.paint("SIMILAR TEXT FILES (sorted by similarity)", Color::Bold)));
+        output.push_str(&st.rule('─', '-', 50));
+        output.push('\n');
+
+        for pair in &scan_result.similar_pairs {
+            output.push_str(&format!(
+                "   {} {} ~ {} {}\n",
+                st.bullet(),
+                st.paint(pair.first.display(), Color::Cyan),
+                st.paint(pair.second.display(), Color::Cyan),
+                st.paint(format!("({}% similar)", pair.similarity), Color::Yellow)
+            ));
+        }
+        output.push('\n');
+
+        output
+    }
+
+    /// Formats the sidecars that go with redundant photos (`--companions`)
+    fn format_sidecars_section(&self, scan_result: &ScanResult) -> String {
+        let st = &self.style;
+        let mut output = String::new();
+        if scan_result.sidecars.is_empty() {
+            return output;
+        }
+
+        output.push_str(&format!("{}{}\n", st.icon("🏷️  "), st.paint("SIDECARS (remove together with their photos)", Color::Bold)));
+        output.push_str(&st.rule('─', '-', 50));
+        output.push('\n');
+
+        for sidecar in &scan_result.sidecars {
+            output.push_str(&format!(
+                "   {} {} {}\n",
+                st.bullet(),
+                st.paint(sidecar.path.display(), Color::Yellow),
+                st.paint(format!("(with {})", sidecar.companion.display()), Color::Dim)
+            ));
+        }
+        output.push('\n');
+
+        output
+    }
+
+    /// Formats the estimated chunk-level savings and the pairs of files
+    /// sharing chunks (`--chunk-analysis`)
+    fn format_chunk_section(&self, scan_result: &ScanResult) -> String {
+        let st = &self.style;
+        let mut output = String::new();
+        let Some(analysis) = &scan_result.chunk_analysis else {
+            return output;
+        };
+
+        output.push_str(&format!("{}{}\n", st.icon("🧩 "), st.paint("SHARED CHUNKS (large files that differ as a whole)", Color::Bold)));
+        output.push_str(&st.rule('─', '-', 50));
+        output.push('\n');
+        output.push_str(&format!(
+            "Files analyzed: {} ({})\n",
+            analysis.files_analyzed,
+            format_bytes(analysis.total_bytes)
+        ));
+        output.push_str(&format!(
+            "Estimated chunk-level dedup savings: {}\n",
+            st.paint(format_bytes(analysis.estimated_savings()), Color::Red)
+        ));
+        for pair in &analysis.shared_pairs {
+            output.push_str(&format!(
+                "   {} {} {} {} ({}, {}% of the smaller file)\n",
+                st.bullet(),
+                st.paint(pair.first.display(), Color::Yellow),
+                st.pick("↔", "<->"),
+                st.paint(pair.second.display(), Color::Yellow),
+                format_bytes(pair.shared_bytes),
+                pair.percent
+            ));
+        }
+        output.push('\n');
+
+        output
+    }
+
+    /// Formats loose files already contained in archives (`--report-archived`)
+    fn format_archived_section(&self, scan_result: &ScanResult) -> String {
+        let st = &self.style;
+        let mut output = String::new();
+        if scan_result.archived_files.is_empty() {
+            return output;
+        }
+
+        output.push_str(&format!("{}{}\n", st.icon("🗜️  "), st.paint("ALREADY IN ARCHIVES (loose copies can be deleted)", Color::Bold)));
+        output.push_str(&st.rule('─', '-', 50));
+        output.push('\n');
+
+        for file in &scan_result.archived_files {
+            output.push_str(&format!(
+                "{}{} ({})\n",
+                st.icon("📄 "),
+                st.paint(file.path.display(), Color::Yellow),
+                format_bytes(file.size)
+            ));
+            for copy in &file.copies {
+                output.push_str(&format!(
+                    "   {} in {} {}\n",
+                    st.bullet(),
+                    st.paint(copy.archive.display(), Color::Green),
+                    st.paint(format!("({})", copy.member.display()), Color::Dim)
+                ));
+            }
+        }
+        output.push('\n');
+
+        output
+    }
+
+    /// Formats the empty directories found with `--report-empty-dirs`
+    fn format_empty_directories_section(&self, scan_result: &ScanResult) -> String {
+        let st = &self.style;
+        let mut output = String::new();
+        if scan_result.empty_directories.is_empty() {
+            return output;
+        }
+
+        output.push_str(&format!("{}{}\n", st.icon("🕳️  "), st.paint("EMPTY DIRECTORIES", Color::Bold)));
+        output.push_str(&st.rule('─', '-', 50));
+        output.push('\n');
+
+        for dir in &scan_result.empty_directories {
+            let note = if dir.after_cleanup { st.paint(" (after removing duplicates)", Color::Dim) } else { String::new() };
+            output.push_str(&format!("   {} {}{}\n", st.bullet(), dir.path.display(), note));
+        }
+        output.push('\n');
+
+        output
+    }
+
+    /// Formats the directory-oriented view of the results
+    ///
+    /// Answers "what can I clean up in this folder" instead of listing
+    /// individual hash groups
+    fn format_directories_section(&self, scan_result: &ScanResult) -> String {
+        let st = &self.style;
+        let mut output = String::new();
+
+        output.push_str(&format!("{}{}\n", st.icon("📂 "), st.paint("DUPLICATES BY DIRECTORY (sorted by wasted space)", Color::Bold)));
+        output.push_str(&st.rule('─', '-', 50));
+        output.push('\n');
+
+        let twins = directory_twins(scan_result);
+
+        for stat in &scan_result.directory_stats {
+            output.push_str(&format!("{}{}\n", st.icon("📁 "), st.paint(stat.path.display(), Color::Cyan)));
+            output.push_str(&format!("   {}Files with duplicates: {}\n", st.icon("📄 "), stat.duplicate_files));
+            output.push_str(&format!("   {}Wasted space: {}\n", st.icon("💰 "), st.paint(format_bytes(stat.wasted_bytes), Color::Red)));
+
+            match twins.get(&stat.path).filter(|t| !t.is_empty()) {
+                None => output.push_str(&format!("   {}All copies live in this directory\n", st.icon("📍 "))),
+                Some(dir_twins) => {
+                    output.push_str(&format!("   {}Copies also found in:\n", st.icon("🔗 ")));
+                    for (twin, count) in dir_twins {
+                        output.push_str(&format!("      {} ({} shared)\n", twin.display(), count));
+                    }
+                }
+            }
+            output.push('\n');
+        }
 
         output
     }
 
     /// Formats one duplicate group
     fn format_duplicate_group(&self, group: &DuplicateGroup, group_number: usize) -> String {
+        let st = &self.style;
         let mut output = String::new();
 
-        output.push_str(&format!("📋 Group #{} ({})\n", group_number, format_bytes(group.size)));
-        output.push_str(&format!("   💰 Wasted space: {}\n", format_bytes(group.wasted_space)));
-        output.push_str(&format!("   🔐 Hash: {}...\n", &group.hash[..16])); // Show the first 16 characters of hash
-        output.push_str(&format!("   📊 {} duplicate files:\n", group.files.len()));
+        output.push_str(&format!(
+            "{}{} ({})\n",
+            st.icon("📋 "),
+            st.paint(format!("Group #{}", group_number), Color::Cyan),
+            st.paint(format_bytes(group.size), Color::Magenta)
+        ));
+        output.push_str(&format!("   {}Wasted space: {}\n", st.icon("💰 "), st.paint(format_bytes(group.wasted_space), Color::Red)));
+        if !group.id.is_empty() {
+            output.push_str(&format!("   {}ID: {}\n", st.icon("🆔 "), group.id));
+        }
+        output.push_str(&format!("   {}Hash: {}\n", st.icon("🔐 "), st.paint(short_key(&group.hash), Color::Dim)));
+        output.push_str(&format!("   {}{} duplicate files:\n", st.icon("📊 "), group.files.len()));
 
         for (file_index, file) in group.files.iter().enumerate() {
-            let file_marker = if file_index == 0 { "📌" } else { "🔄" }; // The first file is considered original
+            // The first file is considered original
+            let (file_marker, path_color) = if file_index == 0 {
+                (st.pick("📌", "*"), Color::Green)
+            } else {
+                (st.pick("🔄", "-"), Color::Yellow)
+            };
             let modified_time = format_system_time(file.modified);
 
-            output.push_str(&format!("     {} {}\n", file_marker, file.path.display()));
-            output.push_str(&format!("        📅 Modified: {}\n", modified_time));
+            let wrapper = match file.compression {
+                Some(compression) => st.paint(format!(" ({:?}, compared decompressed)", compression).to_lowercase(), Color::Dim),
+                None => String::new(),
+            };
+            output.push_str(&format!("     {} {}{}\n", file_marker, st.paint(file.path.display(), path_color), wrapper));
+            output.push_str(&format!("        {}Modified: {}\n", st.icon("📅 "), modified_time));
 
             if let Some(created) = file.created {
                 let created_time = format_system_time(created);
-                output.push_str(&format!("        🆕 Created: {}\n", created_time));
+                output.push_str(&format!("        {}Created: {}\n", st.icon("🆕 "), created_time));
             }
         }
 
@@ -121,14 +546,15 @@ impl<'a> OutputFormatter<'a> {
 
     /// Generates recommendations based on scan results
     fn generate_recommendations(&self, scan_result: &ScanResult) -> String {
+        let st = &self.style;
         let mut recommendations = String::new();
 
-        recommendations.push_str("💡 RECOMMENDATIONS\n");
-        recommendations.push_str(&"─".repeat(50));
+        recommendations.push_str(&format!("{}{}\n", st.icon("💡 "), st.paint("RECOMMENDATIONS", Color::Bold)));
+        recommendations.push_str(&st.rule('─', '-', 50));
         recommendations.push('\n');
 
         if scan_result.total_wasted_space == 0 {
-            recommendations.push_str("✨ Your file system is perfectly organized! No cleanup needed.\n");
+            recommendations.push_str(&format!("{}Your file system is perfectly organized! No cleanup needed.\n", st.icon("✨ ")));
             return recommendations;
         }
 
@@ -136,65 +562,469 @@ impl<'a> OutputFormatter<'a> {
         let savings_gb = scan_result.total_wasted_space as f64 / (1024.0 * 1024.0 * 1024.0);
 
         if savings_gb > 1.0 {
-            recommendations.push_str(&format!("🚨 High Impact: You can save {:.2} GB by removing duplicates!\n", savings_gb));
+            recommendations.push_str(&format!(
+                "{}{} You can save {:.2} GB by removing duplicates!\n",
+                st.icon("🚨 "),
+                st.paint("High Impact:", Color::Red),
+                savings_gb
+            ));
         } else if scan_result.total_wasted_space > 100 * 1024 * 1024 { // > 100 MB
-            recommendations.push_str("⚠️  Medium Impact: Consider cleaning up duplicate files.\n");
+            recommendations.push_str(&format!("{}{} Consider cleaning up duplicate files.\n", st.icon("⚠️  "), st.paint("Medium Impact:", Color::Yellow)));
         } else {
-            recommendations.push_str("ℹ️  Low Impact: Duplicates present but space savings are minimal.\n");
+            recommendations.push_str(&format!("{}{} Duplicates present but space savings are minimal.\n", st.icon("ℹ️  "), st.paint("Low Impact:", Color::Green)));
         }
 
         recommendations.push('\n');
-        recommendations.push_str("🛠️  Cleanup Strategy:\n");
+        recommendations.push_str(&format!("{}Cleanup Strategy:\n", st.icon("🛠️  ")));
         recommendations.push_str("   1. Review each group carefully before deletion\n");
-        recommendations.push_str("   2. Keep the oldest file (marked with 📌) as the original\n");
+        recommendations.push_str(&format!("   2. Keep the oldest file (marked with {}) as the original\n", st.pick("📌", "*")));
         recommendations.push_str("   3. Consider using hard links instead of deletion for safety\n");
         recommendations.push_str("   4. Always backup important data before cleanup\n");
 
         // Statistics by file types (if extensions exist)
-        let file_extensions = self.analyze_file_extensions(scan_result);
-        if !file_extensions.is_empty() {
+        if !scan_result.extension_stats.is_empty() {
             recommendations.push('\n');
-            recommendations.push_str("📈 File Types Analysis:\n");
-            for (extension, count) in file_extensions.iter().take(5) { // Top 5 file extensions
-                recommendations.push_str(&format!("   {} files: {}\n", extension, count));
+            recommendations.push_str(&format!("{}File Types Analysis:\n", st.icon("📈 ")));
+            for stat in scan_result.extension_stats.iter().take(5) { // Top 5 file extensions
+                recommendations.push_str(&format!(
+                    "   {} files: {} ({} wasted)\n",
+                    stat.extension,
+                    stat.files,
+                    format_bytes(stat.wasted_bytes)
+                ));
             }
         }
 
         recommendations
     }
 
-    /// Analyzes file extensions for statistics
-    fn analyze_file_extensions(&self, scan_result: &ScanResult) -> Vec<(String, usize)> {
-        use std::collections::HashMap;
+    /// Formats results exactly like `fdupes` prints them
+    ///
+    /// Each group lists one path per line and is followed by a blank line.
+    /// With `--sameline`, a group is printed on one line with spaces in
+    /// file names escaped as `\ `, matching `fdupes -1`
+    fn write_fdupes(&self, scan_result: &ScanResult, writer: &mut impl Write) -> std::io::Result<()> {
+        scan_result.for_each_group(|group| {
+            for file in &group.files {
+                let path = file.path.to_string_lossy();
+                if self.config.sameline {
+                    write!(writer, "{} ", path.replace(' ', "\\ "))?;
+                } else {
+                    writeln!(writer, "{}", path)?;
+                }
+            }
+            writeln!(writer)
+        })
+    }
 
-        let mut extension_counts: HashMap<String, usize> = HashMap::new();
+    /// Formats results as a Markdown report
+    ///
+    /// Produces a summary table followed by one section per duplicate group
+    /// (or a directory table with `--group-by directory`), using only
+    /// constructs that render on GitHub, GitLab and most wikis
+    fn format_as_markdown(&self, scan_result: &ScanResult) -> String {
+        let mut output = String::new();
 
-        for group in &scan_result.duplicate_groups {
-            for file in &group.files {
-                let extension = file.path
-                    .extension()
-                    .and_then(|ext| ext.to_str())
-                    .unwrap_or("(no extension)")
-                    .to_lowercase();
+        output.push_str("# Duplicate Finder Report\n\n");
+        if self.config.mode.is_unverified() {
+            output.push_str("> **Unverified:** files were matched without reading their content.\n\n");
+        }
+        output.push_str("| Metric | Value |\n");
+        output.push_str("| --- | --- |\n");
+        output.push_str(&format!("| Scanned directory | {} |\n", md_code(&scan_result.scanned_directory.to_string_lossy())));
+        output.push_str(&format!("| Scan duration | {:?} |\n", scan_result.scan_duration));
+        output.push_str(&format!("| Total files | {} |\n", scan_result.total_files));
+        output.push_str(&format!("| Duplicate files | {} |\n", scan_result.total_duplicates));
+        output.push_str(&format!("| Duplicate groups | {} |\n", scan_result.duplicate_groups.len()));
+        output.push_str(&format!("| Wasted space | {} |\n", format_bytes(scan_result.total_wasted_space)));
+        output.push('\n');
 
-                *extension_counts.entry(extension).or_insert(0) += 1;
+        if !scan_result.empty_directories.is_empty() {
+            output.push_str("## Empty directories\n\n");
+            for dir in &scan_result.empty_directories {
+                let note = if dir.after_cleanup { " (after removing duplicates)" } else { "" };
+                output.push_str(&format!("- {}{}\n", md_code(&dir.path.to_string_lossy()), note));
             }
+            output.push('\n');
         }
 
-        let mut extensions: Vec<(String, usize)> = extension_counts.into_iter().collect();
-        extensions.sort_by(|a, b| b.1.cmp(&a.1)); // Sort by count (descending)
+        if !scan_result.archived_files.is_empty() {
+            output.push_str("## Already in archives\n\n");
+            output.push_str("| File | Size | Archived as |\n");
+            output.push_str("| --- | --- | --- |\n");
+            for file in &scan_result.archived_files {
+                let copies = file.copies
+                    .iter()
+                    .map(|copy| format!("{} in {}", md_code(&copy.member.to_string_lossy()), md_code(&copy.archive.to_string_lossy())))
+                    .collect::<Vec<_>>()
+                    .join("<br>");
+                output.push_str(&format!(
+                    "| {} | {} | {} |\n",
+                    md_code(&file.path.to_string_lossy()),
+                    format_bytes(file.size),
+                    copies
+                ));
+            }
+            output.push('\n');
+        }
 
-        extensions
+        if let Some(analysis) = &scan_result.chunk_analysis {
+            output.push_str("## Shared chunks\n\n");
+            output.push_str(&format!(
+                "{} files analyzed ({}); estimated chunk-level dedup savings: **{}**\n\n",
+                analysis.files_analyzed,
+                format_bytes(analysis.total_bytes),
+                format_bytes(analysis.estimated_savings())
+            ));
+            if !analysis.shared_pairs.is_empty() {
+                output.push_str("| File | File | Shared | Of smaller file |\n");
+                output.push_str("| --- | --- | --- | --- |\n");
+                for pair in &analysis.shared_pairs {
+                    output.push_str(&format!(
+                        "| {} | {} | {} | {}% |\n",
+                        md_code(&pair.first.to_string_lossy()),
+                        md_code(&pair.second.to_string_lossy()),
+                        format_bytes(pair.shared_bytes),
+                        pair.percent
+                    ));
+                }
+                output.push('\n');
+            }
+        }
+
+        if scan_result.duplicate_groups.is_empty() {
+            output.push_str("No duplicates found.\n");
+            return output;
+        }
+
+        match self.config.group_by {
+            GroupBy::Hash => {
+                if !scan_result.directory_groups.is_empty() {
+                    output.push_str("## Duplicate directories\n\n");
+                    output.push_str("| Group | Files | Size | Wasted space | Directories |\n");
+                    output.push_str("| --- | --- | --- | --- | --- |\n");
+                    for (index, group) in scan_result.directory_groups.iter().enumerate() {
+                        let directories = group.directories
+                            .iter()
+                            .map(|dir| md_code(&dir.to_string_lossy()))
+                            .collect::<Vec<_>>()
+                            .join("<br>");
+                        output.push_str(&format!(
+                            "| {} | {} | {} | {} | {} |\n",
+                            index + 1,
+                            group.file_count,
+                            format_bytes(group.size),
+                            format_bytes(group.wasted_space),
+                            directories
+                        ));
+                    }
+                    output.push('\n');
+                }
+
+                if !scan_result.directory_overlaps.is_empty() {
+                    output.push_str("## Overlapping directories\n\n");
+                    output.push_str("| Directory | Contained in | Overlap | Shared files | Shared size |\n");
+                    output.push_str("| --- | --- | --- | --- | --- |\n");
+                    for overlap in &scan_result.directory_overlaps {
+                        output.push_str(&format!(
+                            "| {} | {} | {:.0}% | {} of {} | {} |\n",
+                            md_code(&overlap.directory.to_string_lossy()),
+                            md_code(&overlap.container.to_string_lossy()),
+                            overlap.percent(),
+                            overlap.shared_files,
+                            overlap.total_files,
+                            format_bytes(overlap.shared_bytes)
+                        ));
+                    }
+                    output.push('\n');
+                }
+
+                if !scan_result.similar_pairs.is_empty() {
+                    output.push_str("## Similar text files\n\n");
+                    output.push_str("| File | Similar to | Similarity |\n");
+                    output.push_str("| --- | --- | --- |\n");
+                    for pair in &scan_result.similar_pairs {
+                        output.push_str(&format!(
+                            "| {} | {} | {}% |\n",
+                            md_code(&pair.first.to_string_lossy()),
+                            md_code(&pair.second.to_string_lossy()),
+                            pair.similarity
+                        ));
+                    }
+                    output.push('\n');
+                }
+
+                let mut sorted_groups: Vec<&DuplicateGroup> = scan_result.duplicate_groups.iter()
+                    .filter(|g| !directories::is_covered(g, &scan_result.directory_groups))
+                    .collect();
+                sorted_groups.sort_by_key(|g| std::cmp::Reverse(g.wasted_space));
+
+                output.push_str("## Duplicate groups\n\n");
+                for (index, group) in sorted_groups.iter().enumerate() {
+                    output.push_str(&format!(
+                        "### Group {} ({} each, {} wasted)\n\n",
+                        index + 1,
+                        format_bytes(group.size),
+                        format_bytes(group.wasted_space)
+                    ));
+                    output.push_str(&format!("Hash: `{}`\n\n", group.hash));
+                    output.push_str("| Role | Path | Modified |\n");
+                    output.push_str("| --- | --- | --- |\n");
+                    for (file_index, file) in group.files.iter().enumerate() {
+                        let role = if file_index == 0 { "Original" } else { "Duplicate" };
+                        output.push_str(&format!(
+                            "| {} | {} | {} |\n",
+                            role,
+                            md_code(&file.path.to_string_lossy()),
+                            format_system_time(file.modified)
+                        ));
+                    }
+                    output.push('\n');
+                }
+
+                if !scan_result.sidecars.is_empty() {
+                    output.push_str("## Sidecars\n\n");
+                    output.push_str("| Sidecar | Belongs to |\n");
+                    output.push_str("| --- | --- |\n");
+                    for sidecar in &scan_result.sidecars {
+                        output.push_str(&format!(
+                            "| {} | {} |\n",
+                            md_code(&sidecar.path.to_string_lossy()),
+                            md_code(&sidecar.companion.to_string_lossy())
+                        ));
+                    }
+                    output.push('\n');
+                }
+            }
+            GroupBy::Directory => {
+                let twins = directory_twins(scan_result);
+
+                output.push_str("## Duplicates by directory\n\n");
+                output.push_str("| Directory | Files with duplicates | Wasted space | Copies also in |\n");
+                output.push_str("| --- | --- | --- | --- |\n");
+                for stat in &scan_result.directory_stats {
+                    let copies_in = twins
+                        .get(&stat.path)
+                        .map(|dir_twins| {
+                            dir_twins
+                                .keys()
+                                .map(|twin| md_code(&twin.to_string_lossy()))
+                                .collect::<Vec<_>>()
+                                .join("<br>")
+                        })
+                        .unwrap_or_default();
+                    output.push_str(&format!(
+                        "| {} | {} | {} | {} |\n",
+                        md_code(&stat.path.to_string_lossy()),
+                        stat.duplicate_files,
+                        format_bytes(stat.wasted_bytes),
+                        copies_in
+                    ));
+                }
+                output.push('\n');
+            }
+        }
+
+        if !self.config.quiet && !scan_result.extension_stats.is_empty() {
+            output.push_str("## File types\n\n");
+            output.push_str("| Extension | Files | Redundant copies | Wasted space |\n");
+            output.push_str("| --- | --- | --- | --- |\n");
+            for stat in &scan_result.extension_stats {
+                output.push_str(&format!(
+                    "| {} | {} | {} | {} |\n",
+                    md_code(&stat.extension),
+                    stat.files,
+                    stat.duplicate_files,
+                    format_bytes(stat.wasted_bytes)
+                ));
+            }
+        }
+
+        output
     }
 
     /// Display results in JSON format
     async fn display_json_format(&self, scan_result: &ScanResult) -> Result<()> {
-        let json_output = serde_json::to_string_pretty(scan_result)?;
-        println!("{}", json_output);
+        let mut stdout = std::io::stdout().lock();
+        write_json(scan_result, &mut stdout)?;
+        writeln!(stdout)?;
         Ok(())
     }
 }
 
+/// Writes a scan result as pretty-printed JSON, reading spilled groups
+/// back as they are written
+pub(crate) fn write_json(scan_result: &ScanResult, writer: &mut impl Write) -> std::io::Result<()> {
+    match scan_result.spilled_groups {
+        None => serde_json::to_writer_pretty(writer, scan_result)?,
+        Some(_) => serde_json::to_writer_pretty(writer, &StreamedResult(scan_result))?,
+    }
+    Ok(())
+}
+
+/// A scan result serialized exactly like [`ScanResult`], with its spilled
+/// groups following the ones in memory
+///
+/// Fields must be kept in the order they are declared in
+struct StreamedResult<'r>(&'r ScanResult);
+
+impl Serialize for StreamedResult<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let result = self.0;
+        let mut fields = serializer.serialize_struct("ScanResult", 21)?;
+        fields.serialize_field("format_version", &result.format_version)?;
+        fields.serialize_field("total_files", &result.total_files)?;
+        fields.serialize_field("duplicate_groups", &AllGroups(result))?;
+        fields.serialize_field("directory_groups", &result.directory_groups)?;
+        fields.serialize_field("directory_overlaps", &result.directory_overlaps)?;
+        fields.serialize_field("empty_directories", &result.empty_directories)?;
+        fields.serialize_field("similar_pairs", &result.similar_pairs)?;
+        fields.serialize_field("sidecars", &result.sidecars)?;
+        fields.serialize_field("archived_files", &result.archived_files)?;
+        fields.serialize_field("unique_files", &result.unique_files)?;
+        fields.serialize_field("chunk_analysis", &result.chunk_analysis)?;
+        fields.serialize_field("total_duplicates", &result.total_duplicates)?;
+        fields.serialize_field("total_wasted_space", &result.total_wasted_space)?;
+        fields.serialize_field("scan_duration", &result.scan_duration)?;
+        fields.serialize_field("scanned_directory", &result.scanned_directory)?;
+        fields.serialize_field("mode", &result.mode)?;
+        fields.serialize_field("hash_algo", &result.hash_algo)?;
+        fields.serialize_field("file_errors", &result.file_errors)?;
+        fields.serialize_field("partial", &result.partial)?;
+        fields.serialize_field("directory_stats", &result.directory_stats)?;
+        fields.serialize_field("extension_stats", &result.extension_stats)?;
+        fields.end()
+    }
+}
+
+/// Every duplicate group of a result, serialized as one sequence
+struct AllGroups<'r>(&'r ScanResult);
+
+impl Serialize for AllGroups<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut groups = serializer.serialize_seq(Some(self.0.group_count()))?;
+        for group in &self.0.duplicate_groups {
+            groups.serialize_element(group)?;
+        }
+        if let Some(spilled) = &self.0.spilled_groups {
+            for group in spilled.read().map_err(S::Error::custom)? {
+                groups.serialize_element(&group.map_err(S::Error::custom)?)?;
+            }
+        }
+        groups.end()
+    }
+}
+
+/// The `count` groups wasting the most space, read in one pass that holds
+/// no more than `count` of them
+fn largest_groups(scan_result: &ScanResult, count: usize) -> std::io::Result<Vec<DuplicateGroup>> {
+    // Smallest kept group on top, with the slot it is kept in
+    let mut smallest: BinaryHeap<Reverse<(u64, usize)>> = BinaryHeap::new();
+    let mut kept: Vec<DuplicateGroup> = Vec::new();
+    scan_result.for_each_group(|group| {
+        if kept.len() < count {
+            smallest.push(Reverse((group.wasted_space, kept.len())));
+            kept.push(group.clone());
+        } else if let Some(&Reverse((wasted_space, slot))) = smallest.peek()
+            && group.wasted_space > wasted_space
+        {
+            smallest.pop();
+            smallest.push(Reverse((group.wasted_space, slot)));
+            kept[slot] = group.clone();
+        }
+        Ok(())
+    })?;
+    Ok(kept)
+}
+
+/// Appends results to a SQLite database
+#[cfg(feature = "sqlite")]
+async fn save_to_database(scan_result: &ScanResult, output_path: &Path) -> Result<()> {
+    // rusqlite is blocking, so keep it off the async worker threads
+    let scan_result = scan_result.clone();
+    let output_path = output_path.to_path_buf();
+    tokio::task::spawn_blocking(move || crate::database::append_scan(&scan_result, &output_path)).await??;
+    Ok(())
+}
+
+/// Fallback when the binary was built without SQLite support
+#[cfg(not(feature = "sqlite"))]
+async fn save_to_database(_scan_result: &ScanResult, _output_path: &Path) -> Result<()> {
+    anyhow::bail!("SQLite output is not available: rebuild with the `sqlite` feature enabled")
+}
+
+/// Maps each directory to the other directories holding copies of its files
+///
+/// The inner map counts how many copies of the directory's files live in
+/// each twin directory
+fn directory_twins(scan_result: &ScanResult) -> BTreeMap<PathBuf, BTreeMap<PathBuf, usize>> {
+    let mut twins: BTreeMap<PathBuf, BTreeMap<PathBuf, usize>> = BTreeMap::new();
+
+    for group in &scan_result.duplicate_groups {
+        for file in &group.files {
+            let dir = parent_dir(&file.path);
+            let entry = twins.entry(dir.clone()).or_default();
+
+            for other in &group.files {
+                let other_dir = parent_dir(&other.path);
+                if other_dir != dir {
+                    *entry.entry(other_dir).or_insert(0) += 1;
+                }
+            }
+        }
+    }
+
+    twins
+}
+
+/// Returns the directory containing a path (or the path itself for bare names)
+fn parent_dir(path: &Path) -> PathBuf {
+    path.parent().map(Path::to_path_buf).unwrap_or_default()
+}
+
+/// Wraps text in a Markdown code span that is safe inside table cells
+///
+/// Pipes are escaped so they don't split the cell, and text containing
+/// backticks gets a longer fence as the CommonMark spec requires
+fn md_code(text: &str) -> String {
+    let escaped = text.replace('|', "\\|");
+    if escaped.contains('`') {
+        format!("`` {} ``", escaped)
+    } else {
+        format!("`{}`", escaped)
+    }
+}
+
+/// Returns the raw bytes of a path for machine-readable output
+///
+/// On Unix paths are written verbatim so non-UTF-8 names survive a round
+/// trip through `xargs`; elsewhere they are converted lossily
+fn path_bytes(path: &Path) -> Cow<'_, [u8]> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        Cow::Borrowed(path.as_os_str().as_bytes())
+    }
+    #[cfg(not(unix))]
+    {
+        match path.to_string_lossy() {
+            Cow::Borrowed(s) => Cow::Borrowed(s.as_bytes()),
+            Cow::Owned(s) => Cow::Owned(s.into_bytes()),
+        }
+    }
+}
+
+/// First 16 characters of a group key, followed by `...` when it is longer
+///
+/// Keys are digests in most modes, but file names in name modes and
+/// whatever callers supplied for groups formed outside a scan
+fn short_key(key: &str) -> String {
+    match key.char_indices().nth(16) {
+        Some((end, _)) => format!("{}...", &key[..end]),
+        None => key.to_string(),
+    }
+}
+
 /// Formats size in bytes into a human-readable format
 ///
 /// Converts large numbers into convenient units (KB, MB, GB, TB)
@@ -235,6 +1065,7 @@ fn format_system_time(time: std::time::SystemTime) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::Sidecar;
 
     #[test]
     fn test_format_bytes() {
@@ -246,6 +1077,229 @@ mod tests {
         assert_eq!(format_bytes(1024 * 1024 * 1024), "1.00 GB");
     }
 
+    fn file(path: &str) -> crate::FileInfo {
+        crate::FileInfo {
+            path: PathBuf::from(path),
+            size: 100,
+            hash: "abc".to_string(),
+            modified: std::time::UNIX_EPOCH,
+            created: None,
+            compression: None,
+        }
+    }
+
+    #[test]
+    fn test_directory_twins() {
+        let scan_result = ScanResult {
+            duplicate_groups: vec![DuplicateGroup {
+                id: String::new(),
+                hash: "abc".to_string(),
+                size: 100,
+                files: vec![file("/a/one.txt"), file("/b/two.txt"), file("/b/three.txt")],
+                total_size: 300,
+                wasted_space: 200,
+            }],
+            ..Default::default()
+        };
+
+        let twins = directory_twins(&scan_result);
+        assert_eq!(twins[Path::new("/a")].get(Path::new("/b")), Some(&2));
+        assert_eq!(twins[Path::new("/b")].get(Path::new("/a")), Some(&2));
+        assert!(!twins[Path::new("/b")].contains_key(Path::new("/b")));
+    }
+
+    #[test]
+    fn test_write_duplicate_paths() {
+        let scan_result = ScanResult {
+            duplicate_groups: vec![DuplicateGroup {
+                id: String::new(),
+                hash: "abc".to_string(),
+                size: 100,
+                files: vec![file("/a/one.txt"), file("/b/two.txt"), file("/b/three.txt")],
+                total_size: 300,
+                wasted_space: 200,
+            }],
+            ..Default::default()
+        };
+
+        let mut config = ScannerConfig::default();
+        let mut buffer = Vec::new();
+        OutputFormatter::new(&config).write_duplicate_paths(&scan_result, &mut buffer).unwrap();
+        assert_eq!(buffer, b"/b/two.txt\n/b/three.txt\n");
+
+        config.null_separated = true;
+        buffer.clear();
+        OutputFormatter::new(&config).write_duplicate_paths(&scan_result, &mut buffer).unwrap();
+        assert_eq!(buffer, b"/b/two.txt\0/b/three.txt\0");
+    }
+
+    #[tokio::test]
+    async fn test_format_name_modes_as_text() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        for dir in ["a", "b"] {
+            std::fs::create_dir(temp_dir.path().join(dir)).unwrap();
+        }
+        std::fs::write(temp_dir.path().join("a/1.txt"), "first").unwrap();
+        std::fs::write(temp_dir.path().join("b/1.txt"), "second").unwrap();
+
+        for mode in [crate::Mode::Name, crate::Mode::NameConflict] {
+            let config = ScannerConfig { directory: temp_dir.path().to_path_buf(), mode, quiet: true, ..Default::default() };
+            let scan_result = crate::FileScanner::new(config.clone()).scan().await.unwrap();
+            assert_eq!(scan_result.group_count(), 1);
+            // Files sharing a name are not copies of each other
+            assert_eq!((scan_result.total_duplicates, scan_result.total_wasted_space), (0, 0));
+            assert!(scan_result.directory_stats.is_empty() && scan_result.extension_stats.is_empty());
+
+            let text = OutputFormatter::new(&config).format_as_text(&scan_result);
+            assert!(text.contains("Hash: 1.txt\n"), "{}", text);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_format_quick_mode_as_text() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        for dir in ["a", "b"] {
+            std::fs::create_dir(temp_dir.path().join(dir)).unwrap();
+            std::fs::write(temp_dir.path().join(dir).join("x"), "1").unwrap();
+        }
+
+        let config = ScannerConfig { directory: temp_dir.path().to_path_buf(), mode: crate::Mode::Quick, quiet: true, ..Default::default() };
+        let scan_result = crate::FileScanner::new(config.clone()).scan().await.unwrap();
+        assert_eq!(scan_result.group_count(), 1);
+
+        // Keys of small files with short names are shorter than a digest
+        let text = OutputFormatter::new(&config).format_as_text(&scan_result);
+        assert!(text.contains("Hash: quick:1:x\n"), "{}", text);
+    }
+
+    #[test]
+    fn test_format_grouper_result_as_text() {
+        // Hashes computed elsewhere may be of any length
+        let grouper: crate::DuplicateGrouper = [file("/a/1.txt"), file("/b/1.txt")].into_iter()
+            .map(|f| crate::FileInfo { hash: "d1".to_string(), ..f })
+            .collect();
+        let scan_result = grouper.into_result("/imported".as_ref());
+
+        let config = ScannerConfig::default();
+        let text = OutputFormatter::new(&config).format_as_text(&scan_result);
+        assert!(text.contains("Hash: d1\n"), "{}", text);
+    }
+
+    #[test]
+    fn test_write_fdupes() {
+        let scan_result = ScanResult {
+            duplicate_groups: vec![DuplicateGroup {
+                id: String::new(),
+                hash: "abc".to_string(),
+                size: 100,
+                files: vec![file("/a/one.txt"), file("/b/two words.txt")],
+                total_size: 200,
+                wasted_space: 100,
+            }],
+            ..Default::default()
+        };
+
+        let mut config = ScannerConfig::default();
+        let mut formatted = Vec::new();
+        OutputFormatter::new(&config).write_fdupes(&scan_result, &mut formatted).unwrap();
+        assert_eq!(formatted, b"/a/one.txt\n/b/two words.txt\n\n");
+
+        config.sameline = true;
+        formatted.clear();
+        OutputFormatter::new(&config).write_fdupes(&scan_result, &mut formatted).unwrap();
+        assert_eq!(formatted, b"/a/one.txt /b/two\\ words.txt \n");
+    }
+
+    #[test]
+    fn test_format_as_markdown() {
+        let scan_result = ScanResult {
+            duplicate_groups: vec![DuplicateGroup {
+                id: String::new(),
+                hash: "abc".to_string(),
+                size: 100,
+                files: vec![file("/a/one.txt"), file("/b/pipe|name.txt")],
+                total_size: 200,
+                wasted_space: 100,
+            }],
+            total_wasted_space: 100,
+            ..Default::default()
+        };
+
+        let config = ScannerConfig::default();
+        let formatted = OutputFormatter::new(&config).format_as_markdown(&scan_result);
+        assert!(formatted.starts_with("# Duplicate Finder Report\n"));
+        assert!(formatted.contains("| Wasted space | 100 B |"));
+        assert!(formatted.contains("### Group 1 (100 B each, 100 B wasted)"));
+        assert!(formatted.contains("| Original | `/a/one.txt` |"));
+        assert!(formatted.contains("| Duplicate | `/b/pipe\\|name.txt` |"));
+        assert_eq!(md_code("a`b"), "`` a`b ``");
+    }
+
+    #[test]
+    fn test_report_view_top() {
+        let group = |hash: &str, wasted_space| DuplicateGroup {
+            id: String::new(),
+            hash: hash.to_string(),
+            size: wasted_space,
+            files: vec![file("/a/one.txt"), file(&format!("/b/{}.CR2", hash))],
+            total_size: wasted_space * 2,
+            wasted_space,
+        };
+        let sidecar = |hash: &str| Sidecar { path: format!("/b/{}.xmp", hash).into(), companion: format!("/b/{}.CR2", hash).into() };
+        let scan_result = ScanResult {
+            duplicate_groups: vec![group("small", 10), group("large", 300), group("medium", 20)],
+            sidecars: vec![sidecar("small"), sidecar("large")],
+            ..Default::default()
+        };
+
+        let mut config = ScannerConfig::default();
+        let formatter = OutputFormatter::new(&config);
+        assert!(matches!(formatter.report_view(&scan_result).unwrap(), Cow::Borrowed(_)));
+
+        config.top = 2;
+        let formatter = OutputFormatter::new(&config);
+        let view = formatter.report_view(&scan_result).unwrap();
+        let hashes: Vec<&str> = view.duplicate_groups.iter().map(|g| g.hash.as_str()).collect();
+        assert_eq!(hashes, ["large", "medium"]);
+
+        // Only the sidecars of kept groups are listed
+        let mut buffer = Vec::new();
+        formatter.write_duplicate_paths(&scan_result, &mut buffer).unwrap();
+        assert_eq!(buffer, b"/b/large.CR2\n/b/medium.CR2\n/b/large.xmp\n");
+    }
+
+    #[tokio::test]
+    async fn test_spilled_groups_output() {
+        let group = |hash: &str, wasted_space| DuplicateGroup {
+            id: String::new(),
+            hash: hash.to_string(),
+            size: wasted_space,
+            files: vec![file(&format!("/a/{}", hash)), file(&format!("/b/{}", hash))],
+            total_size: wasted_space * 2,
+            wasted_space,
+        };
+        let groups = vec![group("small", 10), group("large", 300), group("medium", 20)];
+        let in_memory = ScanResult { duplicate_groups: groups.clone(), total_files: 6, ..Default::default() };
+        let mut spool = crate::spill::GroupSpool::new(Some(1), None);
+        spool.push(groups).await.unwrap();
+        let crate::spill::Groups::Spilled(spilled) = spool.finish().await.unwrap() else {
+            panic!("groups were kept in memory beyond the limit");
+        };
+        let spilled = ScanResult { spilled_groups: Some(std::sync::Arc::new(spilled)), total_files: 6, ..Default::default() };
+
+        // Spilled groups are written exactly like groups in memory
+        let (mut expected, mut streamed) = (Vec::new(), Vec::new());
+        write_json(&in_memory, &mut expected).unwrap();
+        write_json(&spilled, &mut streamed).unwrap();
+        assert_eq!(String::from_utf8(streamed).unwrap(), String::from_utf8(expected).unwrap());
+
+        let config = ScannerConfig { top: 2, ..Default::default() };
+        let view = OutputFormatter::new(&config).report_view(&spilled).unwrap();
+        let hashes: Vec<&str> = view.duplicate_groups.iter().map(|g| g.hash.as_str()).collect();
+        assert_eq!(hashes, ["large", "medium"]);
+        assert!(view.spilled_groups.is_none());
+    }
+
     #[test]
     fn test_format_system_time() {
         let unix_epoch = std::time::UNIX_EPOCH;
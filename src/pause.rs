@@ -0,0 +1,209 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+/// How often blocked reads look whether the scan was resumed
+const RESUME_POLL: Duration = Duration::from_millis(100);
+
+/// Pauses a scan and resumes it later in the same process
+///
+/// While paused no file starts hashing, no further directory is listed and
+/// files being hashed stop at their next buffer, so the scan stops touching
+/// the disks within one read. Clones share the switch: the `p` key and
+/// SIGUSR1 toggle the one given to [`crate::FileScanner::with_pause`]
+#[derive(Debug, Clone)]
+pub struct PauseSwitch {
+    paused: Arc<watch::Sender<bool>>,
+}
+
+impl Default for PauseSwitch {
+    fn default() -> Self {
+        Self { paused: Arc::new(watch::Sender::new(false)) }
+    }
+}
+
+impl PauseSwitch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn pause(&self) {
+        self.paused.send_replace(true);
+    }
+
+    pub fn resume(&self) {
+        self.paused.send_replace(false);
+    }
+
+    /// Pauses a running scan or resumes a paused one, returning whether it
+    /// is paused now
+    pub fn toggle(&self) -> bool {
+        let mut paused = false;
+        self.paused.send_modify(|state| {
+            *state = !*state;
+            paused = *state;
+        });
+        paused
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    /// Notified of every pause and resume
+    pub(crate) fn subscribe(&self) -> watch::Receiver<bool> {
+        self.paused.subscribe()
+    }
+
+    /// Waits while the scan is paused
+    pub(crate) async fn wait(&self) {
+        // The sender lives in `self`, so the channel cannot close
+        let _ = self.subscribe().wait_for(|paused| !paused).await;
+    }
+
+    /// Blocks the thread while the scan is paused
+    ///
+    /// Only call this from blocking tasks
+    pub(crate) fn wait_blocking(&self) {
+        while self.is_paused() {
+            std::thread::sleep(RESUME_POLL);
+        }
+    }
+
+    /// Toggles the switch on every SIGUSR1 for the rest of the process,
+    /// calling `on_toggle` with the new state
+    #[cfg(unix)]
+    pub fn toggle_on_sigusr1(&self, on_toggle: impl Fn(bool) + Send + 'static) -> std::io::Result<()> {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let mut signals = signal(SignalKind::user_defined1())?;
+        let switch = self.clone();
+        tokio::spawn(async move {
+            while signals.recv().await.is_some() {
+                on_toggle(switch.toggle());
+            }
+        });
+        Ok(())
+    }
+}
+
+/// Toggles a [`PauseSwitch`] when a key is pressed on the terminal
+///
+/// Keys are read one by one without echo while the listener lives; the
+/// terminal gets its settings back when it is dropped. Ctrl+C, Ctrl+\ and
+/// Ctrl+Z still act as usual
+#[cfg(unix)]
+pub struct KeyListener {
+    stop: Arc<std::sync::atomic::AtomicBool>,
+    thread: Option<std::thread::JoinHandle<()>>,
+    saved: libc::termios,
+}
+
+#[cfg(unix)]
+impl KeyListener {
+    /// Listens for `key` on stdin, calling `on_toggle` with the new state
+    ///
+    /// Fails when stdin is not a terminal
+    pub fn start(switch: PauseSwitch, key: u8, on_toggle: impl Fn(bool) + Send + 'static) -> std::io::Result<Self> {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let mut saved = std::mem::MaybeUninit::uninit();
+        // SAFETY: tcgetattr fills the termios it is given when it succeeds
+        let saved = unsafe {
+            if libc::tcgetattr(libc::STDIN_FILENO, saved.as_mut_ptr()) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            saved.assume_init()
+        };
+        set_keys_mode(&saved)?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopping = Arc::clone(&stop);
+        let thread = std::thread::spawn(move || {
+            while !stopping.load(Ordering::Relaxed) {
+                let mut poll = libc::pollfd { fd: libc::STDIN_FILENO, events: libc::POLLIN, revents: 0 };
+                // Polled so the listener notices it is dropped
+                if unsafe { libc::poll(&mut poll, 1, RESUME_POLL.as_millis() as libc::c_int) } <= 0 {
+                    continue;
+                }
+                let mut byte = 0u8;
+                if unsafe { libc::read(libc::STDIN_FILENO, (&raw mut byte).cast(), 1) } != 1 {
+                    break;
+                }
+                if byte == key {
+                    on_toggle(switch.toggle());
+                } else if let Some(signal) = control_signal(byte) {
+                    // The signal may end or stop the process: the terminal
+                    // is left as it was found until it continues
+                    unsafe {
+                        libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &saved);
+                        libc::raise(signal);
+                    }
+                    let _ = set_keys_mode(&saved);
+                }
+            }
+        });
+        Ok(Self { stop, thread: Some(thread), saved })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for KeyListener {
+    fn drop(&mut self) {
+        self.stop.store(true, std::sync::atomic::Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.saved) };
+    }
+}
+
+/// Switches the terminal from `saved` to reading single keys without echo
+/// or signal keys, which the listener raises itself
+#[cfg(unix)]
+fn set_keys_mode(saved: &libc::termios) -> std::io::Result<()> {
+    let mut keys = *saved;
+    keys.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+    keys.c_cc[libc::VMIN] = 1;
+    keys.c_cc[libc::VTIME] = 0;
+    if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &keys) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// The signal a control key stands for
+#[cfg(unix)]
+fn control_signal(byte: u8) -> Option<libc::c_int> {
+    match byte {
+        0x03 => Some(libc::SIGINT),
+        0x1c => Some(libc::SIGQUIT),
+        0x1a => Some(libc::SIGTSTP),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_pause_switch() {
+        let switch = PauseSwitch::new();
+        assert!(!switch.is_paused());
+        assert!(switch.toggle());
+
+        // Waiters wake once the switch is resumed, through any clone
+        let waiting = tokio::spawn({
+            let switch = switch.clone();
+            async move { switch.wait().await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiting.is_finished());
+        switch.clone().resume();
+        tokio::time::timeout(Duration::from_secs(1), waiting).await.unwrap().unwrap();
+        assert!(!switch.is_paused());
+        switch.wait_blocking();
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use futures_util::{StreamExt, stream};
+use tracing::debug;
+use xxhash_rust::xxh3::xxh3_128;
+
+use crate::source::SourceEntry;
+
+/// Bytes compared at each end of a file
+const EDGE_SIZE: u64 = 64 * 1024;
+
+/// End of a file compared by a stage of the prefilter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edge {
+    Head,
+    Tail,
+}
+
+/// Splits discovered files into those to hash in full and those that
+/// cannot have a duplicate (`--prefilter`)
+///
+/// Each stage reads a little more than the one before: files are compared
+/// by size, then those of at least `min_size` by their first and then by
+/// their last 64 KiB. A file left alone in a stage has no duplicate. Files
+/// whose chunks cannot be read are hashed in full, which reports the error.
+/// Reads up to `concurrency` files at a time
+pub(crate) async fn split(entries: Vec<SourceEntry>, min_size: u64, concurrency: usize) -> (Vec<SourceEntry>, Vec<SourceEntry>) {
+    let mut hashed = Vec::new();
+    let mut unique = Vec::new();
+
+    let mut by_size: HashMap<u64, Vec<SourceEntry>> = HashMap::new();
+    for entry in entries {
+        by_size.entry(entry.size).or_default().push(entry);
+    }
+    let mut candidates = Vec::new();
+    for (size, files) in by_size {
+        if files.len() == 1 {
+            unique.extend(files);
+        } else if size < min_size {
+            hashed.extend(files);
+        } else {
+            candidates.push(files);
+        }
+    }
+
+    for edge in [Edge::Head, Edge::Tail] {
+        candidates = refine(candidates, edge, concurrency, &mut hashed, &mut unique).await;
+    }
+    hashed.extend(candidates.into_iter().flatten());
+    (hashed, unique)
+}
+
+/// Splits each set of same-size files by the digest of one of their ends
+///
+/// Returns the sets of files still alike; files left alone go to `unique`
+/// and unreadable ones to `hashed`
+async fn refine(
+    sets: Vec<Vec<SourceEntry>>,
+    edge: Edge,
+    concurrency: usize,
+    hashed: &mut Vec<SourceEntry>,
+    unique: &mut Vec<SourceEntry>,
+) -> Vec<Vec<SourceEntry>> {
+    let read: Vec<(usize, SourceEntry, std::io::Result<u128>)> = stream::iter(sets.into_iter().enumerate())
+        .flat_map(|(set, files)| stream::iter(files.into_iter().map(move |entry| (set, entry))))
+        .map(|(set, entry)| async move {
+            let (path, size) = (entry.path.clone(), entry.size);
+            let digest = tokio::task::spawn_blocking(move || edge_digest(&path, size, edge))
+                .await
+                .unwrap_or_else(|e| Err(std::io::Error::other(e)));
+            (set, entry, digest)
+        })
+        .buffered(concurrency.max(1))
+        .collect()
+        .await;
+
+    let mut alike: HashMap<(usize, u128), Vec<SourceEntry>> = HashMap::new();
+    for (set, entry, digest) in read {
+        match digest {
+            Ok(digest) => alike.entry((set, digest)).or_default().push(entry),
+            Err(e) => {
+                debug!(path = %entry.path.display(), error = %e, "Cannot read chunk, hashing the whole file");
+                hashed.push(entry);
+            }
+        }
+    }
+    alike.into_values()
+        .filter_map(|files| {
+            if files.len() == 1 {
+                unique.extend(files);
+                None
+            } else {
+                Some(files)
+            }
+        })
+        .collect()
+}
+
+/// Digest of the first or last 64 KiB of a file of `size` bytes
+///
+/// Only tells chunks apart: equal digests merely keep files in the running
+/// for a full hash
+fn edge_digest(path: &Path, size: u64, edge: Edge) -> std::io::Result<u128> {
+    let length = size.min(EDGE_SIZE);
+    let mut file = File::open(path)?;
+    if edge == Edge::Tail {
+        file.seek(SeekFrom::Start(size - length))?;
+    }
+    let mut buffer = vec![0u8; length as usize];
+    file.read_exact(&mut buffer)?;
+    Ok(xxh3_128(&buffer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[tokio::test]
+    async fn test_split() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let size = 3 * EDGE_SIZE as usize;
+        let entry = |name: &str, contents: &[u8]| {
+            let path = temp_dir.path().join(name);
+            std::fs::write(&path, contents).unwrap();
+            SourceEntry { path, size: contents.len() as u64, modified: std::time::SystemTime::UNIX_EPOCH, created: None, content_tag: None }
+        };
+        let mut other_tail = vec![0u8; size];
+        other_tail[size - 1] = 1;
+        let mut other_middle = vec![0u8; size];
+        other_middle[size / 2] = 1;
+        let entries = vec![
+            entry("copy1", &vec![0u8; size]),
+            entry("copy2", &vec![0u8; size]),
+            // Same header, different end
+            entry("tail", &other_tail),
+            // Only a full hash tells it apart
+            entry("middle", &other_middle),
+            entry("lonely", &[0u8; 10]),
+            entry("small1", &[1u8; 20]),
+            entry("small2", &[2u8; 20]),
+        ];
+
+        let names = |entries: &[SourceEntry]| {
+            let mut names: Vec<PathBuf> = entries.iter().map(|entry| entry.path.strip_prefix(temp_dir.path()).unwrap().to_path_buf()).collect();
+            names.sort();
+            names
+        };
+        let (hashed, unique) = split(entries, 1024, 4).await;
+        assert_eq!(names(&hashed), ["copy1", "copy2", "middle", "small1", "small2"].map(PathBuf::from));
+        assert_eq!(names(&unique), ["lonely", "tail"].map(PathBuf::from));
+    }
+}
@@ -0,0 +1,276 @@
+use std::fs::File;
+use std::io::{self, BufReader, Cursor, IsTerminal, Read};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use chrono::{DateTime, Local};
+use image::{ImageFormat, RgbImage};
+
+use crate::{ThumbnailProtocol, format_bytes, similarity, text};
+
+/// Lines shown from the beginning of a text file
+const TEXT_LINES: usize = 10;
+
+/// Bytes read to show the beginning of a text file
+const TEXT_HEAD: u64 = 4096;
+
+/// Largest width and height of a thumbnail, in pixels
+const THUMBNAIL_SIZE: u32 = 256;
+
+/// Base64 bytes per kitty graphics escape sequence
+const KITTY_CHUNK: usize = 4096;
+
+/// EXIF fields shown for images, when present
+const EXIF_TAGS: &[exif::Tag] = &[exif::Tag::DateTimeOriginal, exif::Tag::Make, exif::Tag::Model, exif::Tag::Software];
+
+/// Describes a file well enough to decide whether to keep it
+///
+/// Every file gets its size and modification time; images add their
+/// dimensions and main EXIF fields, text files their first lines
+pub fn describe(path: &Path) -> io::Result<Vec<String>> {
+    let metadata = std::fs::metadata(path)?;
+    let modified: DateTime<Local> = metadata.modified()?.into();
+    let mut lines = vec![format!("{}, modified {}", format_bytes(metadata.len()), modified.format("%Y-%m-%d %H:%M:%S"))];
+
+    if similarity::is_image(path) {
+        match image::image_dimensions(path) {
+            Ok((width, height)) => lines.push(format!("{} x {} pixels", width, height)),
+            Err(e) => lines.push(format!("Unreadable image: {}", e)),
+        }
+        lines.extend(exif_fields(path));
+        return Ok(lines);
+    }
+
+    let mut head = Vec::new();
+    File::open(path)?.take(TEXT_HEAD).read_to_end(&mut head)?;
+    if text::looks_like_text(&head) {
+        lines.extend(String::from_utf8_lossy(&head).lines().take(TEXT_LINES).map(|line| format!("| {}", line)));
+    } else {
+        lines.push("Binary file".to_string());
+    }
+    Ok(lines)
+}
+
+/// `Tag: value` lines for the EXIF fields of an image that has them
+fn exif_fields(path: &Path) -> Vec<String> {
+    let Ok(file) = File::open(path) else {
+        return Vec::new();
+    };
+    let Ok(exif) = exif::Reader::new().read_from_container(&mut BufReader::new(file)) else {
+        return Vec::new();
+    };
+    EXIF_TAGS
+        .iter()
+        .filter_map(|tag| exif.get_field(*tag, exif::In::PRIMARY))
+        .map(|field| format!("{}: {}", field.tag, field.display_value().with_unit(&exif)))
+        .collect()
+}
+
+/// The protocol thumbnails are drawn with, if any
+///
+/// `auto` picks kitty or iTerm2 when the terminal identifies itself as
+/// supporting them. Sixel support cannot be told from the environment, so
+/// it has to be asked for. Nothing is drawn unless stdout is a terminal
+pub fn thumbnail_protocol(choice: ThumbnailProtocol) -> Option<ThumbnailProtocol> {
+    if choice == ThumbnailProtocol::Off || !io::stdout().is_terminal() {
+        return None;
+    }
+    if choice != ThumbnailProtocol::Auto {
+        return Some(choice);
+    }
+    let var = |name: &str| std::env::var(name).unwrap_or_default();
+    if var("TERM") == "xterm-kitty" || !var("KITTY_WINDOW_ID").is_empty() || var("TERM_PROGRAM") == "ghostty" {
+        Some(ThumbnailProtocol::Kitty)
+    } else if matches!(var("TERM_PROGRAM").as_str(), "iTerm.app" | "WezTerm") {
+        Some(ThumbnailProtocol::Iterm)
+    } else {
+        None
+    }
+}
+
+/// Escape sequences drawing a thumbnail of an image with `protocol`
+///
+/// `None` when the file is not a readable image
+pub fn thumbnail(path: &Path, protocol: ThumbnailProtocol) -> Option<Vec<u8>> {
+    if !similarity::is_image(path) {
+        return None;
+    }
+    let mut image = image::open(path).ok()?;
+    if image.width() > THUMBNAIL_SIZE || image.height() > THUMBNAIL_SIZE {
+        image = image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+    }
+    let image = image.to_rgb8();
+    let png = || {
+        let mut png = Vec::new();
+        image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png).ok().map(|()| STANDARD.encode(png))
+    };
+    match protocol {
+        ThumbnailProtocol::Kitty => {
+            // Transmitted in chunks, each telling whether more follow
+            let data = png()?;
+            let chunks: Vec<&[u8]> = data.as_bytes().chunks(KITTY_CHUNK).collect();
+            let mut out = Vec::new();
+            for (i, chunk) in chunks.iter().enumerate() {
+                let more = u8::from(i + 1 < chunks.len());
+                let control = if i == 0 { format!("a=T,f=100,m={}", more) } else { format!("m={}", more) };
+                out.extend_from_slice(format!("\x1b_G{};", control).as_bytes());
+                out.extend_from_slice(chunk);
+                out.extend_from_slice(b"\x1b\\");
+            }
+            out.push(b'\n');
+            Some(out)
+        }
+        ThumbnailProtocol::Iterm => Some(format!("\x1b]1337;File=inline=1;preserveAspectRatio=1:{}\x07\n", png()?).into_bytes()),
+        ThumbnailProtocol::Sixel => Some(sixel(&image)),
+        ThumbnailProtocol::Auto | ThumbnailProtocol::Off => None,
+    }
+}
+
+/// Encodes an image as sixels with a 6x6x6 color cube palette
+///
+/// Sixels are columns of six pixels: each band of six rows is drawn once
+/// per color it uses, with repeated columns run-length encoded
+fn sixel(image: &RgbImage) -> Vec<u8> {
+    let level = |value: u8| (u16::from(value) * 5 + 127) / 255;
+    let color = |x: u32, y: u32| {
+        let [r, g, b] = image.get_pixel(x, y).0;
+        (level(r) * 36 + level(g) * 6 + level(b)) as usize
+    };
+    let (width, height) = image.dimensions();
+
+    let mut out = format!("\x1bPq\"1;1;{};{}", width, height);
+    for index in 0..216 {
+        let percent = |step: usize| step * 100 / 5;
+        out.push_str(&format!("#{};2;{};{};{}", index, percent(index / 36), percent(index / 6 % 6), percent(index % 6)));
+    }
+    for band in (0..height).step_by(6) {
+        let rows = (band..height.min(band + 6)).collect::<Vec<_>>();
+        let mut used = [false; 216];
+        for x in 0..width {
+            for &y in &rows {
+                used[color(x, y)] = true;
+            }
+        }
+        for index in (0..216).filter(|&index| used[index]) {
+            out.push_str(&format!("#{}", index));
+            let columns: Vec<u8> = (0..width)
+                .map(|x| 63 + rows.iter().enumerate().filter(|&(_, &y)| color(x, y) == index).map(|(bit, _)| 1 << bit).sum::<u8>())
+                .collect();
+            for run in columns.chunk_by(|a, b| a == b) {
+                match run.len() {
+                    1..=3 => run.iter().for_each(|&c| out.push(c as char)),
+                    n => out.push_str(&format!("!{}{}", n, run[0] as char)),
+                }
+            }
+            out.push('$');
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\\n");
+    out.into_bytes()
+}
+
+/// Opens a file with the default application for its type
+pub fn open(path: &Path) -> io::Result<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else {
+        Command::new("xdg-open")
+    };
+    launch(command.arg(path))
+}
+
+/// Shows a file in the file manager, selected where the platform allows it
+pub fn reveal(path: &Path) -> io::Result<()> {
+    if cfg!(target_os = "macos") {
+        launch(Command::new("open").arg("-R").arg(path))
+    } else if cfg!(windows) {
+        launch(Command::new("explorer").arg(format!("/select,{}", path.display())))
+    } else {
+        // Freedesktop file managers can select the file; otherwise its
+        // directory is opened
+        let uri = format!("file://{}", std::path::absolute(path)?.display());
+        let selected = Command::new("dbus-send")
+            .args(["--session", "--dest=org.freedesktop.FileManager1", "--type=method_call"])
+            .args(["/org/freedesktop/FileManager1", "org.freedesktop.FileManager1.ShowItems"])
+            .arg(format!("array:string:{}", uri))
+            .arg("string:")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        match selected {
+            Ok(status) if status.success() => Ok(()),
+            _ => open(path.parent().unwrap_or(Path::new("."))),
+        }
+    }
+}
+
+/// Runs the `--exec-preview` command on a file and waits for it
+///
+/// `{}` in the command stands for the path, which is appended when the
+/// command has none. The command runs in the shell with the terminal, so
+/// it can be a pager or an image viewer
+pub fn exec_preview(command: &str, path: &Path) -> io::Result<()> {
+    let status = if cfg!(windows) {
+        let quoted = format!("\"{}\"", path.display());
+        let line = if command.contains("{}") { command.replace("{}", &quoted) } else { format!("{} {}", command, quoted) };
+        Command::new("cmd").arg("/C").arg(line).status()?
+    } else {
+        // The path is passed as an argument, never spliced into the script
+        let script = if command.contains("{}") { command.replace("{}", "\"$1\"") } else { format!("{} \"$1\"", command) };
+        Command::new("sh").arg("-c").arg(script).arg("sh").arg(path).status()?
+    };
+    if status.success() { Ok(()) } else { Err(io::Error::other(format!("{} exited with {}", command, status))) }
+}
+
+/// Starts a helper without letting its output scribble over the prompt
+fn launch(command: &mut Command) -> io::Result<()> {
+    let status = command.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null()).status()?;
+    if status.success() { Ok(()) } else { Err(io::Error::other(format!("{:?} exited with {}", command.get_program(), status))) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let notes = temp_dir.path().join("notes.txt");
+        std::fs::write(&notes, "first\nsecond\n").unwrap();
+        let lines = describe(&notes).unwrap();
+        assert!(lines[0].starts_with("13 B, modified "));
+        assert_eq!(&lines[1..], ["| first", "| second"]);
+
+        let photo = temp_dir.path().join("photo.png");
+        image::RgbImage::new(3, 2).save(&photo).unwrap();
+        assert_eq!(describe(&photo).unwrap()[1], "3 x 2 pixels");
+        let kitty = thumbnail(&photo, ThumbnailProtocol::Kitty).unwrap();
+        assert!(kitty.starts_with(b"\x1b_Ga=T,f=100,m=0;") && kitty.ends_with(b"\x1b\\\n"));
+        // Two black rows: every column has the two lowest sixel bits set
+        let sixel = String::from_utf8(thumbnail(&photo, ThumbnailProtocol::Sixel).unwrap()).unwrap();
+        assert!(sixel.starts_with("\x1bPq\"1;1;3;2#0;2;0;0;0") && sixel.ends_with("#0BBB$-\x1b\\\n"), "{:?}", sixel);
+        assert!(thumbnail(&notes, ThumbnailProtocol::Kitty).is_none());
+
+        let data = temp_dir.path().join("data.bin");
+        std::fs::write(&data, [0u8, 1, 2]).unwrap();
+        assert_eq!(describe(&data).unwrap()[1], "Binary file");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_exec_preview() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let path = temp_dir.path().join("it's here.txt");
+        std::fs::write(&path, "").unwrap();
+        exec_preview("test -f", &path).unwrap();
+        exec_preview("test -f {} && test -d .", &path).unwrap();
+        assert!(exec_preview("test -d", &path).is_err());
+    }
+}
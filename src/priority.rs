@@ -0,0 +1,85 @@
+/// Nice value of a low-priority process
+#[cfg(unix)]
+const LOWEST_NICE: libc::c_int = 19;
+
+/// Lowers the CPU and I/O priority of the whole process
+///
+/// On Linux every thread gets nice value 19 and the idle I/O class, so
+/// the disks only serve the scan when nobody else needs them (like
+/// `nice -n 19 ionice -c 3`); threads started later inherit both. macOS
+/// moves the process to the background QoS band, which throttles CPU and
+/// I/O together. Windows switches to background processing mode and
+/// `IDLE_PRIORITY_CLASS`. Other Unix systems only get the nice value
+pub fn lower_priority() -> std::io::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        for task in std::fs::read_dir("/proc/self/task")? {
+            let Ok(thread) = task?.file_name().to_string_lossy().parse::<libc::id_t>() else {
+                continue;
+            };
+            // SAFETY: plain system calls on threads of this process
+            unsafe {
+                if libc::setpriority(libc::PRIO_PROCESS as _, thread, LOWEST_NICE) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                if libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, thread, IOPRIO_IDLE) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+        }
+        Ok(())
+    }
+    #[cfg(target_os = "macos")]
+    {
+        // SAFETY: plain system call on this process
+        if unsafe { libc::setpriority(libc::PRIO_DARWIN_PROCESS, 0, libc::PRIO_DARWIN_BG) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+    #[cfg(all(unix, not(any(target_os = "linux", target_os = "macos"))))]
+    {
+        // SAFETY: plain system call on this process
+        if unsafe { libc::setpriority(libc::PRIO_PROCESS as _, 0, LOWEST_NICE) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+    #[cfg(windows)]
+    {
+        // SAFETY: the pseudo handle of the current process needs no closing
+        unsafe {
+            let process = windows::GetCurrentProcess();
+            if windows::SetPriorityClass(process, windows::PROCESS_MODE_BACKGROUND_BEGIN) == 0
+                || windows::SetPriorityClass(process, windows::IDLE_PRIORITY_CLASS) == 0
+            {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "process priority cannot be changed on this system"))
+    }
+}
+
+/// `ioprio_set` target: a single thread
+#[cfg(target_os = "linux")]
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+
+/// Idle I/O scheduling class (3), shifted into the class bits
+#[cfg(target_os = "linux")]
+const IOPRIO_IDLE: libc::c_int = 3 << 13;
+
+#[cfg(windows)]
+mod windows {
+    pub const IDLE_PRIORITY_CLASS: u32 = 0x0000_0040;
+    pub const PROCESS_MODE_BACKGROUND_BEGIN: u32 = 0x0010_0000;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        pub fn GetCurrentProcess() -> isize;
+        pub fn SetPriorityClass(process: isize, priority_class: u32) -> i32;
+    }
+}
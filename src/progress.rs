@@ -0,0 +1,286 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "cli")]
+use std::time::Duration;
+
+#[cfg(feature = "cli")]
+use indicatif::{HumanDuration, ProgressBar, ProgressStyle};
+#[cfg(feature = "cli")]
+use tracing::info;
+
+#[cfg(feature = "cli")]
+use crate::format_bytes;
+
+/// How often the progress spinner moves while nothing else happens
+#[cfg(feature = "cli")]
+const SPINNER_TICK: Duration = Duration::from_millis(100);
+
+/// What the discovery phase of a scan found so far
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiscoveryProgress {
+    /// Directories listed
+    pub directories: u64,
+    /// Files found in them, before the size and comparison-mode filters
+    pub files: u64,
+}
+
+/// Follows a scan as it goes (see [`crate::FileScanner::with_observer`])
+///
+/// Methods are called from the tasks of the scan, so they should return
+/// quickly; they do nothing unless implemented. With the `cli` feature the
+/// scanner draws its progress bar through one of these unless it is quiet
+pub trait ProgressObserver: Send + Sync {
+    /// Another directory was listed; the counts are totals of all roots
+    fn discovered(&self, _progress: DiscoveryProgress) {}
+
+    /// Every root was listed
+    fn discovery_finished(&self) {}
+
+    /// Files of `bytes` bytes were queued for hashing; while discovery goes
+    /// on alongside hashing this is called once per directory
+    fn queued(&self, _bytes: u64) {}
+
+    /// `bytes` more of the queued bytes were read, or skipped because the
+    /// digest of their file was known
+    fn read(&self, _bytes: u64) {}
+
+    /// A queued file was hashed, taken from a checkpoint or failed
+    fn file_done(&self) {}
+
+    /// The scan was paused, or resumed
+    fn paused(&self, _paused: bool) {}
+
+    /// Discovery and hashing are over
+    fn finished(&self) {}
+}
+
+/// Tells several observers, in order
+pub(crate) struct Observers(pub(crate) Vec<Arc<dyn ProgressObserver>>);
+
+impl ProgressObserver for Observers {
+    fn discovered(&self, progress: DiscoveryProgress) {
+        self.0.iter().for_each(|observer| observer.discovered(progress));
+    }
+
+    fn discovery_finished(&self) {
+        self.0.iter().for_each(|observer| observer.discovery_finished());
+    }
+
+    fn queued(&self, bytes: u64) {
+        self.0.iter().for_each(|observer| observer.queued(bytes));
+    }
+
+    fn read(&self, bytes: u64) {
+        self.0.iter().for_each(|observer| observer.read(bytes));
+    }
+
+    fn file_done(&self) {
+        self.0.iter().for_each(|observer| observer.file_done());
+    }
+
+    fn paused(&self, paused: bool) {
+        self.0.iter().for_each(|observer| observer.paused(paused));
+    }
+
+    fn finished(&self) {
+        self.0.iter().for_each(|observer| observer.finished());
+    }
+}
+
+/// Counts the directories and files of a walk as they are listed and tells
+/// the observer
+pub(crate) struct Discovery {
+    directories: AtomicU64,
+    files: AtomicU64,
+    observer: Option<Arc<dyn ProgressObserver>>,
+}
+
+impl Discovery {
+    pub(crate) fn new(observer: Option<Arc<dyn ProgressObserver>>) -> Self {
+        Self { directories: AtomicU64::new(0), files: AtomicU64::new(0), observer }
+    }
+
+    /// Counts a directory holding `files` files
+    pub(crate) fn listed(&self, files: usize) {
+        let progress = DiscoveryProgress {
+            directories: self.directories.fetch_add(1, Ordering::Relaxed) + 1,
+            files: self.files.fetch_add(files as u64, Ordering::Relaxed) + files as u64,
+        };
+        if let Some(observer) = &self.observer {
+            observer.discovered(progress);
+        }
+    }
+}
+
+/// Progress bar of a scan on stderr
+///
+/// It starts as a spinner counting the directories and files found, which
+/// keeps moving while large directories are listed; once files are queued
+/// it turns into a bar counting bytes rather than files, so its ETA holds
+/// when a few huge files make up most of the scan. When stderr is not a
+/// terminal the bar is not drawn and a line is logged every `--heartbeat`
+/// instead
+#[cfg(feature = "cli")]
+pub(crate) struct ProgressBarObserver {
+    bar: ProgressBar,
+    files_hashed: Arc<AtomicU64>,
+    /// Message shown before the scan was paused
+    resumed: std::sync::Mutex<String>,
+}
+
+#[cfg(feature = "cli")]
+impl ProgressBarObserver {
+    pub(crate) fn start(heartbeat: Duration) -> Self {
+        let bar = ProgressBar::new(0);
+        bar.set_style(
+            ProgressStyle::default_spinner()
+                .template("{spinner:.green} [{elapsed_precise}] {msg}")
+                .expect("Invalid progress bar template"),
+        );
+        bar.set_message("Discovering files...");
+        bar.enable_steady_tick(SPINNER_TICK);
+        let files_hashed = Arc::new(AtomicU64::new(0));
+        if bar.is_hidden() {
+            spawn_heartbeat(bar.clone(), Arc::clone(&files_hashed), heartbeat);
+        }
+        Self { bar, files_hashed, resumed: Default::default() }
+    }
+
+    /// Switches the spinner to a bar of bytes
+    fn show_bytes(&self) {
+        self.bar.set_style(
+            ProgressStyle::default_bar()
+                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta}) {msg}")
+                .expect("Invalid progress bar template")
+                .progress_chars("#>-"),
+        );
+    }
+}
+
+#[cfg(feature = "cli")]
+impl ProgressObserver for ProgressBarObserver {
+    fn discovered(&self, progress: DiscoveryProgress) {
+        self.bar.set_message(format!("Discovering files: {} directories, {} files", progress.directories, progress.files));
+    }
+
+    fn discovery_finished(&self) {
+        self.bar.set_message("Processing files...");
+    }
+
+    fn queued(&self, bytes: u64) {
+        if self.bar.length() == Some(0) {
+            self.show_bytes();
+        }
+        self.bar.inc_length(bytes);
+    }
+
+    fn read(&self, bytes: u64) {
+        self.bar.inc(bytes);
+    }
+
+    fn file_done(&self) {
+        self.files_hashed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn paused(&self, paused: bool) {
+        let mut resumed = self.resumed.lock().unwrap_or_else(|e| e.into_inner());
+        if paused {
+            *resumed = self.bar.message();
+            self.bar.set_message("Paused (press p or send SIGUSR1 to resume)");
+        } else {
+            self.bar.set_message(resumed.clone());
+        }
+    }
+
+    fn finished(&self) {
+        self.bar.finish_with_message("File processing complete!");
+    }
+}
+
+/// Logs the progress of `bar` every `interval` until it is finished
+///
+/// For bars that are not drawn because stderr is not a terminal, so cron
+/// and CI logs show the scan is alive. It runs on a thread of its own, so
+/// scans outside an async runtime have it too
+#[cfg(feature = "cli")]
+fn spawn_heartbeat(bar: ProgressBar, files_hashed: Arc<AtomicU64>, interval: Duration) {
+    std::thread::spawn(move || {
+        loop {
+            std::thread::sleep(interval);
+            if bar.is_finished() {
+                break;
+            }
+            info!("{}", status_line(&bar, files_hashed.load(Ordering::Relaxed)));
+        }
+    });
+}
+
+/// One line telling how far the scan of `bar` got
+#[cfg(feature = "cli")]
+fn status_line(bar: &ProgressBar, files_hashed: u64) -> String {
+    let total = bar.length().unwrap_or_default();
+    if total == 0 {
+        // Still discovering: the message counts what was found
+        return bar.message();
+    }
+    let rate = bar.per_sec();
+    let eta = if rate > 0.0 {
+        format!("about {} left", HumanDuration(bar.eta()))
+    } else {
+        "no estimate yet".to_string()
+    };
+    format!(
+        "Hashed {} files, {} of {} ({}/s, {})",
+        files_hashed,
+        format_bytes(bar.position()),
+        format_bytes(total),
+        format_bytes(rate as u64),
+        eta
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    use crate::{FileScanner, ScannerConfig};
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<DiscoveryProgress>>);
+
+    impl ProgressObserver for Recorder {
+        fn discovered(&self, progress: DiscoveryProgress) {
+            self.0.lock().unwrap().push(progress);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_discovery_progress() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        std::fs::create_dir(temp_dir.path().join("sub")).unwrap();
+        std::fs::write(temp_dir.path().join("a.txt"), "same").unwrap();
+        std::fs::write(temp_dir.path().join("sub/b.txt"), "same").unwrap();
+        std::fs::write(temp_dir.path().join("sub/c.txt"), "other").unwrap();
+
+        let recorder = Arc::new(Recorder::default());
+        let config = ScannerConfig { directory: temp_dir.path().to_path_buf(), quiet: true, ..Default::default() };
+        FileScanner::new(config).with_observer(Arc::clone(&recorder) as Arc<dyn ProgressObserver>).scan().await.unwrap();
+
+        let seen = recorder.0.lock().unwrap();
+        assert_eq!(seen.len(), 2);
+        assert_eq!(seen.last(), Some(&DiscoveryProgress { directories: 2, files: 3 }));
+    }
+
+    #[cfg(feature = "cli")]
+    #[test]
+    fn test_status_line() {
+        let bar = ProgressBar::hidden();
+        bar.set_message("Discovering files: 2 directories, 5 files");
+        assert_eq!(status_line(&bar, 0), "Discovering files: 2 directories, 5 files");
+
+        bar.set_length(2048);
+        bar.inc(1024);
+        assert!(status_line(&bar, 3).starts_with("Hashed 3 files, 1.00 KB of 2.00 KB ("), "{}", status_line(&bar, 3));
+    }
+}
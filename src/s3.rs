@@ -0,0 +1,161 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures_util::TryStreamExt;
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path as ObjectPath;
+use object_store::{ObjectMeta, ObjectStore};
+use tokio_util::io::StreamReader;
+
+use crate::DuplicateFinderError;
+use crate::source::{FileSource, ListOptions, Listing, SourceEntry, SourceReader};
+
+/// URL scheme of S3-compatible object storage locations
+pub const SCHEME: &str = "s3://";
+
+/// A bucket prefix in S3-compatible object storage
+///
+/// Objects are reported as `s3://bucket/key` URLs. Credentials, region and
+/// endpoint (for S3-compatible services such as MinIO) are read from the
+/// usual `AWS_*` environment variables
+pub struct S3Source {
+    store: Arc<dyn ObjectStore>,
+    url: PathBuf,
+    bucket: String,
+    prefix: Option<ObjectPath>,
+}
+
+impl S3Source {
+    /// Connects to the bucket named by an `s3://bucket/prefix` URL
+    pub fn open(url: &Path) -> Result<Self, DuplicateFinderError> {
+        let (bucket, prefix) = parse_url(url)?;
+        let store = AmazonS3Builder::from_env()
+            .with_bucket_name(&bucket)
+            .build()
+            .map_err(|e| DuplicateFinderError::ObjectStorage(e.to_string()))?;
+        Ok(Self::with_store(Arc::new(store), bucket, prefix))
+    }
+
+    /// Scans `prefix` of a bucket served by an existing store
+    pub fn with_store(store: Arc<dyn ObjectStore>, bucket: String, prefix: Option<String>) -> Self {
+        let url = PathBuf::from(format!("{}{}/{}", SCHEME, bucket, prefix.as_deref().unwrap_or_default()));
+        Self { store, url, bucket, prefix: prefix.map(ObjectPath::from) }
+    }
+
+    /// Describes a listed object
+    ///
+    /// Only single-part ETags are content tags: multipart uploads get
+    /// `<digest>-<parts>` ETags that depend on the part size, so equal
+    /// content may carry different tags
+    fn entry(&self, meta: ObjectMeta) -> SourceEntry {
+        SourceEntry {
+            path: PathBuf::from(format!("{}{}/{}", SCHEME, self.bucket, meta.location)),
+            size: meta.size,
+            modified: meta.last_modified.into(),
+            created: None,
+            content_tag: meta.e_tag.filter(|tag| !tag.contains('-')),
+        }
+    }
+
+    /// Key of the object a reported path names
+    fn key(&self, path: &Path) -> Result<ObjectPath, DuplicateFinderError> {
+        let bucket_url = format!("{}{}/", SCHEME, self.bucket);
+        path.to_str()
+            .and_then(|p| p.strip_prefix(&bucket_url))
+            .map(ObjectPath::from)
+            .ok_or_else(|| DuplicateFinderError::ObjectStorage(format!("{} is not in this bucket", path.display())))
+    }
+
+    /// Key of an object relative to the scanned prefix
+    fn relative_key<'a>(&self, key: &'a str) -> &'a str {
+        let prefix = self.prefix.as_ref().map(ObjectPath::as_ref).unwrap_or_default();
+        key.strip_prefix(prefix).unwrap_or(key).trim_start_matches('/')
+    }
+}
+
+#[async_trait]
+impl FileSource for S3Source {
+    fn root(&self) -> &Path {
+        &self.url
+    }
+
+    /// Lists the objects below the prefix
+    ///
+    /// Key segments stand in for directories: hidden segments and the
+    /// depth limit apply to them like to local paths
+    async fn list(&self, options: &ListOptions) -> Result<Listing, DuplicateFinderError> {
+        let objects: Vec<ObjectMeta> = self.store.list(self.prefix.as_ref())
+            .try_collect()
+            .await
+            .map_err(|e| DuplicateFinderError::ObjectStorage(e.to_string()))?;
+
+        let entries = objects.into_iter()
+            .filter(|meta| {
+                let relative = self.relative_key(meta.location.as_ref());
+                let depth = relative.matches('/').count();
+                (options.include_hidden || !relative.split('/').any(|part| part.starts_with('.')))
+                    && (options.max_depth == 0 || depth < options.max_depth)
+            })
+            .map(|meta| self.entry(meta))
+            .collect();
+        Ok(Listing { entries, errors: 0 })
+    }
+
+    async fn metadata(&self, path: &Path) -> Result<SourceEntry, DuplicateFinderError> {
+        let meta = self.store.head(&self.key(path)?).await
+            .map_err(|e| DuplicateFinderError::ObjectStorage(format!("{}: {}", path.display(), e)))?;
+        Ok(self.entry(meta))
+    }
+
+    /// Streams an object's body
+    async fn open(&self, path: &Path) -> Result<SourceReader, DuplicateFinderError> {
+        let body = self.store.get(&self.key(path)?).await
+            .map_err(|e| DuplicateFinderError::ObjectStorage(format!("{}: {}", path.display(), e)))?
+            .into_stream()
+            .map_err(std::io::Error::other);
+        Ok(Box::new(StreamReader::new(body)))
+    }
+}
+
+/// Splits an `s3://bucket/prefix` URL into bucket and prefix
+fn parse_url(url: &Path) -> Result<(String, Option<String>), DuplicateFinderError> {
+    let invalid = || DuplicateFinderError::ObjectStorage(format!("invalid S3 URL: {}", url.display()));
+    let rest = url.to_str().and_then(|u| u.strip_prefix(SCHEME)).ok_or_else(invalid)?;
+    let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+    if bucket.is_empty() {
+        return Err(invalid());
+    }
+    let prefix = prefix.trim_matches('/');
+    Ok((bucket.to_string(), (!prefix.is_empty()).then(|| prefix.to_string())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::hash_entry;
+    use object_store::PutPayload;
+    use object_store::memory::InMemory;
+    use sha2::{Digest, Sha256};
+
+    #[tokio::test]
+    async fn test_s3_source() {
+        let store = Arc::new(InMemory::new());
+        for key in ["backup/photos/a.jpg", "backup/.trash/a.jpg", "other/b.jpg"] {
+            store.put(&ObjectPath::from(key), PutPayload::from_static(b"hello")).await.unwrap();
+        }
+        let source = S3Source::with_store(store, "bucket".to_string(), Some("backup".to_string()));
+
+        let listing = source.list(&ListOptions::default()).await.unwrap();
+        assert_eq!(listing.entries.len(), 1);
+        let path = &listing.entries[0].path;
+        assert_eq!(path, Path::new("s3://bucket/backup/photos/a.jpg"));
+        assert_eq!(source.metadata(path).await.unwrap().size, 5);
+        assert_eq!(hash_entry(&source, path, &crate::HashAlgorithm::Sha256).await.unwrap(), format!("{:x}", Sha256::digest(b"hello")));
+        assert!(source.list(&ListOptions { max_depth: 1, ..Default::default() }).await.unwrap().entries.is_empty());
+        assert!(source.open(Path::new("s3://elsewhere/x")).await.is_err());
+
+        assert_eq!(parse_url(Path::new("s3://bucket/a/b/")).unwrap(), ("bucket".to_string(), Some("a/b".to_string())));
+        assert!(parse_url(Path::new("s3:///x")).is_err());
+    }
+}
@@ -1,15 +1,49 @@
-use std::collections::HashMap;
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::Instant;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
-use indicatif::{ProgressBar, ProgressStyle};
 use tokio::fs;
-use tokio::sync::Semaphore;
-use tracing::{debug, error, info, warn};
-
-use crate::{Cli, DuplicateFinderError, DuplicateGroup, FileInfo, ScanResult};
+use tokio::io::AsyncReadExt;
+use tokio::sync::{Semaphore, mpsc};
+use futures_util::{StreamExt, stream};
+use tracing::field::Empty;
+use tracing::{Instrument, debug, debug_span, error, info, info_span, warn};
+
+use crate::checkpoint::Checkpoint;
+use crate::discovery::FileFilter;
+use crate::grouping::DuplicateGrouper;
+use crate::{archives, chunks, companions, compression, directories, documents, empty, hashing, names, prefilter, storage};
+use crate::hashing::{Cancel, HashFunction, ReadOptions, ReadProgress};
+use crate::index::HashIndex;
+use crate::io_backend::{self, LocalIo};
+use crate::metrics::Metrics;
+use crate::pause::PauseSwitch;
+#[cfg(feature = "cli")]
+use crate::progress::ProgressBarObserver;
+use crate::progress::{Discovery, Observers, ProgressObserver};
+use crate::{audio, similarity, text, video};
+use crate::storage::{DeviceLimits, StorageKind};
+use crate::throttle::{Throttle, TokenBucket};
+use crate::source::{self, FileSource, ListOptions, Listing, LocalSource, SourceEntry, is_remote_url};
+use crate::spill::{FileSpool, GroupSpool, Groups, SpilledGroups};
+use crate::text::TextNormalizer;
+use crate::xattr::{Cached, XattrCache};
+use crate::{DirStat, DuplicateFinderError, DuplicateGroup, ExtensionStat, FileInfo, GroupScope, HashAlgorithm, IoMode, Mode, OutputFormat, ScanResult, ScannerConfig, Scope};
+
+/// Fingerprint tasks spawned ahead of the files being hashed (at least
+/// one per permit), which bounds the memory taken by waiting tasks
+const PENDING_FILES: usize = 256;
+
+/// Wait before the first retry of a transient read error; doubled for
+/// every further retry
+const RETRY_DELAY: Duration = Duration::from_millis(250);
+
+/// Directories whose files may wait between discovery and hashing
+const PENDING_DIRECTORIES: usize = 64;
 
 /// Main file scanner
 ///
@@ -17,33 +51,202 @@ use crate::{Cli, DuplicateFinderError, DuplicateGroup, FileInfo, ScanResult};
 /// configuration that affects the duplicate search process
 pub struct FileScanner {
     /// Configuration from CLI
-    config: Cli,
+    config: ScannerConfig,
 
     /// Semaphore to limit the number of concurrent operations
     /// This prevents file descriptor exhaustion
     semaphore: Arc<Semaphore>,
 
-    /// Progress bar for user interface
-    progress_bar: Option<ProgressBar>,
+    /// Limits of each device on top of `semaphore`; `None` when the hash
+    /// concurrency was set explicitly and no device limit was asked for
+    device_limits: Option<Arc<DeviceLimits>>,
+
+    /// Bytes per second all local reads together may take (`--max-throughput`)
+    throughput: Option<Arc<TokenBucket>>,
+
+    /// Single permit for multi-threaded hashing of huge files, which
+    /// already keeps every core busy
+    parallel_lane: Arc<Semaphore>,
+
+    /// Told about the progress of the scan: the progress bar and
+    /// `observer`, once the scan started
+    progress: Option<Arc<dyn ProgressObserver>>,
+
+    /// Tells `progress` when the scan is paused or resumed
+    pause_watch: Option<tokio::task::AbortHandle>,
+
+    /// Optional metrics registry updated as files are hashed
+    metrics: Option<Arc<Metrics>>,
+
+    /// Told about the progress of the scan as it goes
+    observer: Option<Arc<dyn ProgressObserver>>,
+
+    /// Number of files and directories that could not be read
+    file_errors: Arc<AtomicUsize>,
+
+    /// Canonicalization applied to text files before hashing (`--normalize-text`)
+    normalizer: Option<Arc<TextNormalizer>>,
+
+    /// Where files are listed and read; `None` opens the source named by
+    /// the configured directory
+    source: Option<Arc<dyn FileSource>>,
+
+    /// Digest of exact comparisons (`--hash-algo` unless replaced with
+    /// [`FileScanner::with_hasher`])
+    hasher: Arc<dyn HashFunction>,
+
+    /// How local files are stated and read (`--io-backend`)
+    io: Arc<dyn LocalIo>,
+
+    /// Storage of every local scan root
+    root_storage: Arc<[(PathBuf, StorageKind)]>,
+
+    /// Where hashed files are recorded for `--resume`, once a scan opened it
+    checkpoint: Option<Arc<Checkpoint>>,
+
+    /// Set at `--deadline` or `--stop-at`: no further files are hashed
+    stopped: Arc<AtomicBool>,
+
+    /// Holds discovery and hashing while the scan is paused
+    pause: PauseSwitch,
+
+    /// Digests kept with the files (`--xattr-cache`); only for the digests
+    /// of `--hash-algo`
+    xattr_cache: Option<Arc<XattrCache>>,
 }
 
 impl FileScanner {
     /// Creates a new scanner instance
-    pub fn new(config: Cli) -> Self {
-        // Determine thread count
-        let thread_count = if config.threads == 0 {
-            num_cpus::get() * 2 // Default: number of CPU cores * 2
+    pub fn new(config: ScannerConfig) -> Self {
+        let root_storage: Arc<[(PathBuf, StorageKind)]> = config.roots()
+            .filter(|root| !is_remote_url(root))
+            .map(|root| {
+                let kind = storage::detect(root);
+                info!("{} is on {:?} storage", root.display(), kind);
+                (root.to_path_buf(), kind)
+            })
+            .collect();
+
+        // Determine how many files are hashed at once
+        let hash_concurrency = if config.hash_concurrency == 0 && config.nice {
+            // A quarter of the usual readers leaves room for everyone else
+            storage_concurrency(&root_storage).div_ceil(4)
+        } else if config.hash_concurrency == 0 {
+            storage_concurrency(&root_storage)
         } else {
-            config.threads
+            config.hash_concurrency
         };
+        let device_cap = (config.hash_concurrency == 0 || config.device_concurrency > 0).then_some(config.device_concurrency);
+        let device_limits = (device_cap.is_some() || config.device_max_throughput.is_some())
+            .then(|| Arc::new(DeviceLimits::new(device_cap, config.device_max_throughput)));
 
-        info!("Using {} threads for file processing", thread_count);
+        info!("Hashing up to {} files at a time, listing up to {} directories", hash_concurrency, config.walk_concurrency);
 
         Self {
+            normalizer: TextNormalizer::from_config(&config).map(Arc::new),
+            hasher: Arc::new(config.hash_algo),
+            io: io_backend::open_backend(config.io_backend),
+            throughput: config.max_throughput.map(|rate| Arc::new(TokenBucket::new(rate))),
+            xattr_cache: config.xattr_cache.then(|| Arc::new(XattrCache::new(config.hash_algo, config.cache_verify))),
             config,
-            semaphore: Arc::new(Semaphore::new(thread_count)),
-            progress_bar: None,
+            semaphore: Arc::new(Semaphore::new(hash_concurrency)),
+            device_limits,
+            root_storage,
+            parallel_lane: Arc::new(Semaphore::new(1)),
+            progress: None,
+            pause_watch: None,
+            metrics: None,
+            observer: None,
+            file_errors: Arc::new(AtomicUsize::new(0)),
+            source: None,
+            checkpoint: None,
+            stopped: Arc::new(AtomicBool::new(false)),
+            pause: PauseSwitch::default(),
+        }
+    }
+
+    /// Reports hashing progress to a metrics registry
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Tells `observer` about the progress of the scan as it goes
+    pub fn with_observer(mut self, observer: Arc<dyn ProgressObserver>) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Pauses and resumes the scan with `pause`
+    ///
+    /// Discovery and hashing of local files wait while it is paused; the
+    /// progress bar says so
+    pub fn with_pause(mut self, pause: PauseSwitch) -> Self {
+        self.pause = pause;
+        self
+    }
+
+    /// Lists and reads files through a custom source instead of the
+    /// configured directory
+    ///
+    /// Sources that are not local only support exact comparison
+    pub fn with_source(mut self, source: Arc<dyn FileSource>) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// Hashes file contents with a custom digest instead of `--hash-algo`
+    ///
+    /// Used wherever exact mode compares raw, decompressed or normalized
+    /// contents. Other comparison modes keep their own keys
+    pub fn with_hasher(mut self, hasher: Arc<dyn HashFunction>) -> Self {
+        self.hasher = hasher;
+        // Stored digests are named after the algorithm this one replaces
+        self.xattr_cache = None;
+        self
+    }
+
+    /// Computes comparison keys as configured
+    pub(crate) fn fingerprinter(&self) -> Fingerprinter {
+        Fingerprinter {
+            mode: self.config.mode,
+            hasher: Arc::clone(&self.hasher),
+            io_mode: self.config.io_mode,
+            io: Arc::clone(&self.io),
+            normalizer: self.normalizer.clone(),
+            decompress: self.config.decompress,
+            buffer_size: self.config.buffer_size,
+            cache_hints: !self.config.keep_cache,
+            root_storage: Arc::clone(&self.root_storage),
+            throughput: self.throughput.clone(),
+            device_limits: self.device_limits.clone(),
+            file_timeout: self.config.file_timeout,
+            retries: self.config.retries,
+            xattr_cache: self.xattr_cache.clone(),
+            pause: self.pause.clone(),
+            progress: None,
+        }
+    }
+
+    /// Configuration this scanner was created with
+    pub(crate) fn config(&self) -> &ScannerConfig {
+        &self.config
+    }
+
+    /// Number of files and directories skipped because they could not be read
+    pub fn file_errors(&self) -> usize {
+        self.file_errors.load(Ordering::Relaxed)
+    }
+
+    /// Hashes a single file, recording it in the metrics registry
+    pub(crate) async fn hash_file(&self, path: &Path) -> Result<FileInfo, DuplicateFinderError> {
+        let file_info = self.fingerprinter().fingerprint(path).await.inspect_err(|_| {
+            self.file_errors.fetch_add(1, Ordering::Relaxed);
+        })?;
+        if let Some(metrics) = &self.metrics {
+            metrics.record_file(file_info.size);
         }
+        Ok(file_info)
     }
 
     /// Main entry point for scanning
@@ -54,76 +257,752 @@ impl FileScanner {
     /// 3. Hash calculation
     /// 4. Duplicate grouping
     /// 5. Result formation
+    ///
+    /// Runs in a `scan` span holding the phases' `discovery`, `hashing` and
+    /// `grouping` spans, and records its totals on it
+    #[tracing::instrument(
+        name = "scan",
+        skip_all,
+        fields(root = %self.config.directory.display(), mode = ?self.config.mode, files = Empty, groups = Empty, wasted_bytes = Empty),
+    )]
     pub async fn scan(&mut self) -> Result<ScanResult> {
+        #[cfg(feature = "threads")]
+        if self.runs_on_threads() {
+            return self.scan_on_threads_in_place();
+        }
         let start_time = Instant::now();
+        let deadline = self.start_deadline();
+
+        let mut scanned = None;
+        let mut chunk_candidates = Vec::new();
+        let mut result = if self.groups_in_partitions() {
+            self.scan_partitioned(start_time).await?
+        } else {
+            // Remote files without a possible duplicate are never downloaded,
+            // unless unique files are reported with their hashes
+            let compared_sizes = (!self.config.include_unique).then(HashSet::new);
+            let mut file_infos = self.collect_files_sized(|_| true, compared_sizes.as_ref()).await?;
+            scanned = self.config.include_unique.then(|| file_infos.clone());
+            if self.config.chunk_analysis {
+                chunk_candidates = file_infos.iter().filter(|f| f.size >= self.config.chunk_min_size).cloned().collect();
+            }
+
+            // Phase 3: Grouping duplicates
+            match self.config.reference.clone() {
+                None => info_span!("grouping", files = file_infos.len()).in_scope(|| {
+                    info!("Grouping duplicates");
+                    let (directory_groups, directory_overlaps) = directories::analyze(&file_infos, &self.config);
+                    ScanResult {
+                        directory_groups,
+                        directory_overlaps,
+                        ..build_result(file_infos, &self.config, self.file_errors(), start_time)
+                    }
+                }),
+                Some(reference) => {
+                    // A reference inside the scan root was already covered by the main scan
+                    file_infos.retain(|f| !f.path.starts_with(&reference));
+                    let total_files = file_infos.len();
+
+                    info!(reference = %reference.display(), "Hashing reference directory");
+                    let reference_files = self.collect_reference(&reference, &file_infos).await?;
+                    file_infos.extend(reference_files);
+
+                    info_span!("grouping", files = file_infos.len()).in_scope(|| {
+                        info!("Grouping duplicates");
+                        let groups = match_reference(group_files(file_infos, &self.config), &reference);
+                        result_from_groups(groups, total_files, &self.config.directory, self.file_errors(), start_time)
+                    })
+                }
+            }
+        };
+        if let Some(deadline) = deadline {
+            deadline.abort();
+        }
+        result.partial = self.stopped.load(Ordering::Relaxed);
+        result.mode = self.config.mode;
+        result.hash_algo = self.config.hash_algo;
+
+        if self.config.verify_matches && self.compares_raw_bytes() && !result.partial {
+            info!(groups = result.duplicate_groups.len(), "Verifying duplicate groups byte by byte");
+            let groups = std::mem::take(&mut result.duplicate_groups);
+            let (mut groups, errors) = hashing::verify_groups(groups, self.semaphore.available_permits()).await;
+            if let Some(reference) = &self.config.reference {
+                groups = match_reference(groups, reference);
+            }
+            result.duplicate_groups = groups;
+            result.file_errors += errors;
+            refresh_statistics(&mut result);
+        }
+
+        if let Some(scanned) = scanned {
+            let grouped: HashSet<&Path> = result.duplicate_groups.iter()
+                .flat_map(|g| &g.files)
+                .map(|f| f.path.as_path())
+                .collect();
+            result.unique_files = scanned.into_iter().filter(|f| !grouped.contains(f.path.as_path())).collect();
+        }
 
+        if self.config.chunk_analysis && !result.partial {
+            info!("Analyzing shared chunks");
+            let (analysis, errors) = chunks::analyze(
+                &chunk_candidates,
+                self.config.chunk_min_size,
+                self.compares_raw_bytes(),
+                self.semaphore.available_permits(),
+            ).await;
+            result.chunk_analysis = Some(analysis);
+            result.file_errors += errors;
+        }
+
+        if self.config.companions {
+            companions::apply(&mut result).await;
+        }
+
+        if self.config.report_archived {
+            result.archived_files = archives::find_archived_files(&result.duplicate_groups);
+        }
+
+        if self.config.report_empty_dirs && self.source.is_none() && !is_remote_url(&self.config.directory) {
+            let redundant: HashSet<&Path> = result.duplicate_groups.iter()
+                .flat_map(|g| g.redundant_files())
+                .map(|f| f.path.as_path())
+                .chain(result.sidecars.iter().map(|s| s.path.as_path()))
+                .collect();
+            for root in self.config.roots() {
+                result.empty_directories.extend(
+                    empty::find_empty_directories(root, self.config.include_hidden, &redundant).await?
+                );
+            }
+        }
+
+        let span = tracing::Span::current();
+        span.record("files", result.total_files);
+        span.record("groups", result.group_count());
+        span.record("wasted_bytes", result.total_wasted_space);
+        info!(
+            duration = ?result.scan_duration,
+            groups = result.group_count(),
+            wasted_bytes = result.total_wasted_space,
+            "Scan completed"
+        );
+
+        Ok(result)
+    }
+
+    /// Stops hashing at `--deadline` or `--stop-at`
+    ///
+    /// Files being hashed at that moment are finished, files still waiting
+    /// are dropped and discovery ends, so the scan goes on to group what it
+    /// has. Returns the timer, to be aborted once hashing is over
+    fn start_deadline(&self) -> Option<tokio::task::JoinHandle<()>> {
+        let time_left = time_left(&self.config)?;
+        info!("Wrapping up the scan in {:?}", time_left);
+        let stopped = Arc::clone(&self.stopped);
+        if time_left.is_zero() {
+            stopped.store(true, Ordering::Relaxed);
+            return None;
+        }
+        Some(tokio::spawn(async move {
+            tokio::time::sleep(time_left).await;
+            info!("Deadline reached, finishing the files being hashed");
+            stopped.store(true, Ordering::Relaxed);
+        }))
+    }
+
+    /// Returns true when a scan can group its files without holding the
+    /// whole tree in memory
+    ///
+    /// Needs local roots that are walked from scratch, a comparison key that
+    /// groups files by equality, and none of the reports that look at every
+    /// scanned file at once
+    fn groups_in_partitions(&self) -> bool {
+        let exact_key = matches!(
+            self.config.mode,
+            Mode::Exact | Mode::ImagePixels | Mode::Audio | Mode::Document | Mode::Quick | Mode::Metadata
+        );
+        exact_key
+            && self.source.is_none()
+            && self.config.files_from.is_none()
+            && !self.config.roots().any(is_remote_url)
+            && self.config.reference.is_none()
+            && !self.config.include_unique
+            && !self.config.chunk_analysis
+            && !self.config.duplicate_dirs
+            && self.config.dir_overlap.is_none()
+            && self.config.scan_archives.is_empty()
+            && !self.prefilters()
+    }
+
+    /// Scans local roots with bounded memory
+    ///
+    /// Roots are walked while their files are hashed: the files of each
+    /// directory flow through a bounded channel into the fingerprint window,
+    /// so discovery waits for hashing instead of listing the whole tree
+    /// ahead of it. Hashed files go to a [`FileSpool`], which spills them to
+    /// temporary files beyond `--spill-threshold` and groups them one
+    /// partition at a time
+    async fn scan_partitioned(&mut self, start_time: Instant) -> Result<ScanResult> {
         info!("Starting file system scan");
+        self.validate_config()?;
+        self.open_checkpoint()?;
+        self.start_progress();
+
+        // Phases 1 and 2 overlap
+        info!("Discovering files while calculating hashes");
+        let (sender, receiver) = mpsc::channel(PENDING_DIRECTORIES);
+        let discovery = self.spawn_discovery(sender);
+        let discovered = stream::unfold(receiver, |mut receiver| async move {
+            receiver.recv().await.map(|directory| (directory, receiver))
+        });
+
+        let parallel_min_size = self.parallel_min_size();
+        let window = PENDING_FILES.max(self.semaphore.available_permits());
+        let mut outcomes = std::pin::pin!(discovered
+            .flat_map(|(root, entries)| stream::iter(self.accept_walked(&root, entries)))
+            .map(|entry| match parallel_min_size {
+                Some(min) if entry.size >= min => self.spawn_fingerprint(entry, Arc::clone(&self.parallel_lane), true),
+                _ => self.spawn_fingerprint(entry, Arc::clone(&self.semaphore), false),
+            })
+            .buffered(window));
 
-        // Configuration validation
+        let mut spool = FileSpool::new(self.config.spill_threshold, self.config.spill_dir.clone());
+        async {
+            while let Some(outcome) = outcomes.next().await {
+                if let Some(file_info) = self.task_output(outcome) {
+                    spool.push(file_info).await?;
+                }
+            }
+            tracing::Span::current().record("files", spool.len());
+            anyhow::Ok(())
+        }
+        .instrument(info_span!("hashing", files = Empty))
+        .await?;
+        discovery.await?;
+        if let Some(checkpoint) = &self.checkpoint {
+            checkpoint.flush();
+        }
+
+        self.finish_progress();
+
+        let total_files = spool.len();
+        let grouping = info_span!("grouping", files = total_files);
+        grouping.in_scope(|| info!("Grouping duplicates"));
+        let limit = self.streams_groups().then_some(self.config.spill_threshold);
+        let groups = GroupSpool::new(limit, self.config.spill_dir.clone());
+        match spool.group_with(|files| group_files(files, &self.config), groups).instrument(grouping).await? {
+            Groups::Memory(groups) => {
+                Ok(result_from_groups(groups, total_files, &self.config.directory, self.file_errors(), start_time))
+            }
+            Groups::Spilled(spilled) => {
+                let result = result_from_groups(Vec::new(), total_files, &self.config.directory, self.file_errors(), start_time);
+                let spilled = Arc::new(spilled);
+                // Statistics read every group back, so keep that off the workers
+                tokio::task::spawn_blocking(move || with_spilled_groups(result, spilled)).await?.map_err(Into::into)
+            }
+        }
+    }
+
+    /// Returns true when every use of the result reads its duplicate groups
+    /// one at a time, so they may be spilled to disk
+    ///
+    /// JSON, fdupes and path lists are written out as the groups are read
+    /// back, and `--top` only keeps the largest groups; text and Markdown
+    /// reports sort every group, and verification, sidecars, archived files
+    /// and empty directories work on all of them at once
+    fn streams_groups(&self) -> bool {
+        let streamed = self.config.top > 0 || matches!(self.config.output_format, OutputFormat::Json | OutputFormat::Fdupes);
+        (streamed || self.config.print_duplicates && self.config.output_file.is_none())
+            && !self.config.verify_matches
+            && !self.config.companions
+            && !self.config.report_archived
+            && !self.config.report_empty_dirs
+    }
+
+    /// Walks every root on a background task, sending the files of each
+    /// directory to `sender` together with their root
+    ///
+    /// Up to `--walk-concurrency` roots are walked at a time; walks wait
+    /// while the channel is full
+    fn spawn_discovery(&self, sender: mpsc::Sender<(PathBuf, Vec<SourceEntry>)>) -> tokio::task::JoinHandle<()> {
+        let mut roots: Vec<PathBuf> = self.config.roots().map(Path::to_path_buf).collect();
+        roots.sort();
+        roots.dedup();
+        let io = Arc::clone(&self.io);
+        let options = self.walk_options(roots.len());
+        let file_errors = Arc::clone(&self.file_errors);
+        let stopped = Arc::clone(&self.stopped);
+        let pause = self.pause.clone();
+        let discovery = self.discovery();
+        let progress = self.progress.clone();
+        let walk_concurrency = self.config.walk_concurrency.max(1);
+        let span = info_span!("discovery", roots = roots.len());
+
+        tokio::spawn(async move {
+            stream::iter(roots)
+                .for_each_concurrent(walk_concurrency, |root| {
+                    let source = LocalSource::new(root.clone()).with_io(Arc::clone(&io)).with_pause(pause.clone()).with_discovery(Arc::clone(&discovery));
+                    let sender = sender.clone();
+                    let file_errors = Arc::clone(&file_errors);
+                    let stopped = Arc::clone(&stopped);
+                    async move {
+                        let errors = source.walk_each(0, &options, |entries| {
+                            let sender = sender.clone();
+                            let root = root.clone();
+                            let stopped = Arc::clone(&stopped);
+                            async move {
+                                // The receiver is only gone when the scan failed
+                                !stopped.load(Ordering::Relaxed) && sender.send((root, entries)).await.is_ok()
+                            }
+                        }).await;
+                        file_errors.fetch_add(errors, Ordering::Relaxed);
+                    }
+                })
+                .await;
+            if let Some(progress) = &progress {
+                progress.discovery_finished();
+            }
+        }.instrument(span))
+    }
+
+    /// Applies the size and comparison-mode filters to the files of one
+    /// directory below `root`, queueing them on the progress observers
+    ///
+    /// Files below a nested root are left to the walk of that root, so each
+    /// file is hashed once
+    fn accept_walked(&self, root: &Path, entries: Vec<SourceEntry>) -> Vec<SourceEntry> {
+        let accepted: Vec<SourceEntry> = entries.into_iter()
+            .filter(|entry| {
+                self.config.root_of(&entry.path) == Some(root)
+                    && self.file_passes_size_filter(entry.size)
+                    && self.mode_accepts(&entry.path)
+            })
+            .collect();
+        if let Some(progress) = &self.progress {
+            progress.queued(accepted.iter().map(|entry| entry.size).sum());
+        }
+        accepted
+    }
+
+    /// Blocking version of [`FileScanner::scan`], for applications without
+    /// an async runtime
+    ///
+    /// The scan runs on a runtime of its own for the duration of the call
+    ///
+    /// # Panics
+    ///
+    /// When called from within an async runtime; await [`FileScanner::scan`]
+    /// there instead
+    pub fn scan_blocking(&mut self) -> Result<ScanResult> {
+        #[cfg(feature = "threads")]
+        if self.runs_on_threads() {
+            return self.scan_on_threads();
+        }
+        crate::block_on(self.scan())?
+    }
+
+    /// Returns true when the `threads` backend can run the scan
+    ///
+    /// Needs local roots that are walked from scratch, hashes of the raw
+    /// file contents, and none of the options that wait on the async
+    /// pipeline: reference trees, archives, checkpoints, stored digests,
+    /// duplicate groups spilled to disk and the reports that read files
+    /// again after grouping
+    #[cfg(feature = "threads")]
+    fn runs_on_threads(&self) -> bool {
+        self.compares_raw_bytes()
+            && !self.streams_groups()
+            && self.source.is_none()
+            && self.config.files_from.is_none()
+            && !self.config.roots().any(is_remote_url)
+            && self.config.reference.is_none()
+            && self.config.scan_archives.is_empty()
+            && self.config.checkpoint.is_none()
+            && self.config.resume.is_none()
+            && self.xattr_cache.is_none()
+            && !self.prefilters()
+            && !self.config.include_unique
+            && !self.config.chunk_analysis
+            && !self.config.verify_matches
+            && !self.config.companions
+            && !self.config.report_empty_dirs
+    }
+
+    /// Runs [`FileScanner::scan_on_threads`] from within a runtime, moving
+    /// the tasks of a multi-threaded one to other workers meanwhile
+    #[cfg(feature = "threads")]
+    fn scan_on_threads_in_place(&mut self) -> Result<ScanResult> {
+        match tokio::runtime::Handle::current().runtime_flavor() {
+            tokio::runtime::RuntimeFlavor::MultiThread => tokio::task::block_in_place(|| self.scan_on_threads()),
+            _ => self.scan_on_threads(),
+        }
+    }
+
+    /// Scans on a thread pool of `--hash-concurrency` threads instead of
+    /// tokio (`threads` feature)
+    ///
+    /// Roots are listed one directory per task and their files hashed in
+    /// parallel on the calling thread's pool; every hashed file is kept in
+    /// memory until it is grouped. Throughput limits, pauses and deadlines
+    /// apply as on tokio, while `--file-timeout`, `--retries` and the
+    /// per-device concurrency limits do not
+    #[cfg(feature = "threads")]
+    fn scan_on_threads(&mut self) -> Result<ScanResult> {
+        use rayon::prelude::*;
+
+        let start_time = Instant::now();
+        info!("Starting file system scan on threads");
         self.validate_config()?;
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.semaphore.available_permits().max(1))
+            .thread_name(|index| format!("duplicate-finder-{}", index))
+            .build()?;
+        let deadline = time_left(&self.config).map(|left| Instant::now() + left);
+        self.start_progress();
+
+        // Phase 1: Discovering files
+        let files = info_span!("discovery", roots = self.config.roots().count()).in_scope(|| {
+            info!("Discovering files");
+            let options = ListOptions::from_config(&self.config);
+            let discovery = self.discovery();
+            let mut seen = HashSet::new();
+            let mut files = Vec::new();
+            for root in self.config.roots() {
+                let listing = crate::threads::walk(&pool, root, &options, &discovery, &self.pause);
+                files.extend(self.accept_listing(listing).into_iter().filter(|entry| seen.insert(entry.path.clone())));
+            }
+            if let Some(progress) = &self.progress {
+                progress.discovery_finished();
+                progress.queued(files.iter().map(|entry| entry.size).sum());
+            }
+            files
+        });
+
+        // Phase 2: Calculating hashes
+        let file_infos: Vec<FileInfo> = info_span!("hashing", files = files.len()).in_scope(|| {
+            info!("Calculating hashes for {} files", files.len());
+            pool.install(|| files.into_par_iter().filter_map(|entry| self.hash_on_thread(&entry, deadline)).collect())
+        });
+        self.finish_progress();
 
-        // Create a progress bar if not running in quiet mode
-        if !self.config.verbose {
-            self.setup_progress_bar();
+        // Phase 3: Grouping duplicates
+        let mut result = info_span!("grouping", files = file_infos.len()).in_scope(|| {
+            info!("Grouping duplicates");
+            let (directory_groups, directory_overlaps) = directories::analyze(&file_infos, &self.config);
+            ScanResult {
+                directory_groups,
+                directory_overlaps,
+                ..build_result(file_infos, &self.config, self.file_errors(), start_time)
+            }
+        });
+        result.partial = self.stopped.load(Ordering::Relaxed);
+        if self.config.report_archived {
+            result.archived_files = archives::find_archived_files(&result.duplicate_groups);
+        }
+
+        let span = tracing::Span::current();
+        span.record("files", result.total_files);
+        span.record("groups", result.group_count());
+        span.record("wasted_bytes", result.total_wasted_space);
+        info!(
+            duration = ?result.scan_duration,
+            groups = result.group_count(),
+            wasted_bytes = result.total_wasted_space,
+            "Scan completed"
+        );
+        Ok(result)
+    }
+
+    /// Hashes one file on a thread of the pool, unless the scan was stopped
+    /// or reached its deadline while it waited
+    ///
+    /// Failures are logged and counted as file errors
+    #[cfg(feature = "threads")]
+    fn hash_on_thread(&self, entry: &SourceEntry, deadline: Option<Instant>) -> Option<FileInfo> {
+        self.pause.wait_blocking();
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) && !self.stopped.swap(true, Ordering::Relaxed) {
+            info!("Deadline reached, finishing the files being hashed");
+        }
+        if self.stopped.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        let progress = self.progress.clone().map(|observer| Arc::new(ReadProgress::new(observer, entry.size)));
+        let fingerprinter = Fingerprinter { progress: progress.clone(), ..self.fingerprinter() };
+        let options = fingerprinter.read_options(&entry.path, &Cancel::default());
+        let outcome = crate::threads::hash_file(entry, self.hasher.as_ref(), &options);
+        if let (Some(progress), Some(observer)) = (progress, &self.progress) {
+            progress.finish();
+            observer.file_done();
+        }
+
+        match outcome {
+            Ok(file_info) => {
+                if let Some(metrics) = &self.metrics {
+                    metrics.record_file(file_info.size);
+                }
+                Some(file_info)
+            }
+            Err(e) => {
+                error!(path = %entry.path.display(), error = %e, "Failed to process file");
+                self.file_errors.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    /// Discovers and hashes every file matching the configured filters
+    ///
+    /// Runs the first two scan phases without grouping, for callers that
+    /// need per-file hashes (e.g. manifest generation)
+    pub async fn collect_files(&mut self) -> Result<Vec<FileInfo>> {
+        self.collect_files_sized(|_| true, None).await
+    }
+
+    /// Blocking version of [`FileScanner::collect_files`]
+    ///
+    /// # Panics
+    ///
+    /// When called from within an async runtime
+    pub fn collect_files_blocking(&mut self) -> Result<Vec<FileInfo>> {
+        crate::block_on(self.collect_files())?
+    }
+
+    /// Lists the local files a scan would hash, without reading them
+    pub async fn list_files(&self) -> Result<Vec<PathBuf>> {
+        Ok(self.discover_roots().await?.into_iter().map(|entry| entry.path).collect())
+    }
+
+    /// Discovers and hashes every file into a queryable [`HashIndex`]
+    pub async fn build_index(&mut self) -> Result<HashIndex> {
+        Ok(HashIndex::from_files(self.collect_files().await?))
+    }
+
+    /// Discovers every file and hashes those whose size is accepted by `wanted`
+    ///
+    /// Sizes are known after discovery, so rejecting a size here skips the
+    /// expensive hashing of that file entirely. When scanning object storage,
+    /// `compared_sizes` lists the sizes of local files the objects are
+    /// compared against (see [`source::plan_downloads`]); `None` hashes every object.
+    /// An empty set, as plain scans pass, also lets `--prefilter` skip local
+    /// files that cannot have a duplicate
+    async fn collect_files_sized(&mut self, wanted: impl Fn(u64) -> bool, compared_sizes: Option<&HashSet<u64>>) -> Result<Vec<FileInfo>> {
+        info!("Starting file system scan");
+
+        // Configuration validation
+        self.validate_config()?;
+        self.open_checkpoint()?;
+
+        self.start_progress();
+
+        if self.config.files_from.is_none()
+            && let Some(source) = self.remote_source().await?
+        {
+            // A local reference tree is compared against the remote files too
+            let mut compared_sizes = compared_sizes.cloned();
+            if let (Some(sizes), Some(reference)) = (&mut compared_sizes, &self.config.reference)
+                && !is_remote_url(reference)
+            {
+                sizes.extend(self.discover_files(reference.clone(), 0).await?.into_iter().map(|entry| entry.size));
+            }
+            let file_infos = self.collect_from_source(source, wanted, compared_sizes.as_ref()).await?;
+            self.finish_progress();
+            return Ok(file_infos);
         }
 
         // Phase 1: File system scanning
-        info!("Phase 1: Discovering files");
-        let file_paths = self.discover_files().await?;
+        let discovery = info_span!("discovery", files = Empty);
+        let discovered = async {
+            info!("Discovering files");
+            anyhow::Ok(match &self.config.files_from {
+                Some(list) => self.listed_files(list).await?,
+                None => match &self.source {
+                    Some(source) => {
+                        let listing = source.list(&ListOptions::from_config(&self.config)).await?;
+                        self.accept_listing(listing)
+                    }
+                    None => self.discover_roots().await?,
+                },
+            })
+        }
+        .instrument(discovery.clone())
+        .await?;
+        let mut files: Vec<SourceEntry> = discovered
+            .into_iter()
+            .filter(|entry| wanted(entry.size))
+            .collect();
+        discovery.record("files", files.len());
+        discovery.in_scope(|| info!(files = files.len(), "Found files to process"));
+
+        if let Some(progress) = &self.progress {
+            progress.discovery_finished();
+        }
 
-        info!("Found {} files to process", file_paths.len());
+        // Files that cannot have a duplicate are reported without a digest
+        let mut unhashed = Vec::new();
+        if compared_sizes.is_some_and(HashSet::is_empty) && self.prefilters() {
+            let prefiltering = info_span!("prefilter", files = files.len(), skipped = Empty);
+            let (hashed, unique) = prefilter::split(files, self.config.prefilter_min_size, self.semaphore.available_permits())
+                .instrument(prefiltering.clone())
+                .await;
+            prefiltering.record("skipped", unique.len());
+            prefiltering.in_scope(|| info!(files = hashed.len(), skipped = unique.len(), "Prefiltered files by size, head and tail"));
+            (files, unhashed) = (hashed, unique);
+        }
 
-        if let Some(pb) = &self.progress_bar {
-            pb.set_length(file_paths.len() as u64);
-            pb.set_message("Processing files...");
+        if let Some(progress) = &self.progress {
+            progress.queued(files.iter().map(|entry| entry.size).sum());
         }
 
         // Phase 2: Process files and calculate hashes
-        info!("Phase 2: Processing files and calculating hashes");
-        let file_infos = self.process_files(file_paths).await?;
+        let hashing = info_span!("hashing", files = files.len(), bytes = files.iter().map(|entry| entry.size).sum::<u64>());
+        hashing.in_scope(|| info!("Calculating hashes"));
+        let mut file_infos = self.process_files(files).instrument(hashing).await?;
+        if let Some(checkpoint) = &self.checkpoint {
+            checkpoint.flush();
+        }
+        for entry in unhashed {
+            if let Some(metrics) = &self.metrics {
+                metrics.record_file(entry.size);
+            }
+            file_infos.push(FileInfo {
+                hash: source::unhashed_digest(&entry),
+                path: entry.path,
+                size: entry.size,
+                modified: entry.modified,
+                created: entry.created,
+                compression: None,
+            });
+        }
 
-        if let Some(pb) = &self.progress_bar {
-            pb.finish_with_message("File processing complete!");
+        self.finish_progress();
+
+        Ok(file_infos)
+    }
+
+    /// Finds every copy of the given needle files within the scanned tree
+    ///
+    /// Only files with the same size as a needle are hashed, which is much
+    /// cheaper than a full scan. Each returned group starts with its needle
+    /// (so the needle is never reported as redundant) followed by its copies;
+    /// needles with identical content share one group
+    pub async fn find_copies(&mut self, needles: &[PathBuf]) -> Result<ScanResult> {
+        let start_time = Instant::now();
+
+        let mut groups: HashMap<String, DuplicateGroup> = HashMap::new();
+        let mut needle_paths = HashSet::new();
+        for needle in needles {
+            let file_info = self.hash_file(needle).await?;
+            needle_paths.insert(fs::canonicalize(needle).await?);
+
+            let group = groups.entry(file_info.hash.clone()).or_insert_with(|| DuplicateGroup {
+                id: String::new(),
+                hash: file_info.hash.clone(),
+                size: file_info.size,
+                files: Vec::new(),
+                total_size: 0,
+                wasted_space: 0,
+            });
+            group.files.push(file_info);
         }
 
-        // Phase 3: Grouping duplicates
-        info!("Phase 3: Grouping duplicates");
-        let duplicate_groups = self.group_duplicates(file_infos);
-
-        let scan_duration = start_time.elapsed();
-
-        // Form the final result
-        let result = ScanResult {
-            total_files: duplicate_groups.iter().map(|g| g.files.len()).sum(),
-            total_duplicates: duplicate_groups.iter()
-                .map(|g| if g.files.len() > 1 { g.files.len() - 1 } else { 0 })
-                .sum(),
-            total_wasted_space: duplicate_groups.iter()
-                .map(|g| g.wasted_space)
-                .sum(),
-            duplicate_groups: duplicate_groups.into_iter()
-                .filter(|g| g.files.len() > 1) // Only real duplicates
-                .collect(),
-            scan_duration,
-            scanned_directory: self.config.directory.clone(),
-        };
+        // Only exact comparison of raw bytes implies equal sizes
+        let sizes: HashSet<u64> = groups.values().map(|g| g.size).collect();
+        let exact = self.compares_raw_bytes();
+        let candidates = self.collect_files_sized(|size| !exact || sizes.contains(&size), Some(&sizes)).await?;
+        let total_files = candidates.len();
 
-        info!("Scan completed in {:?}", scan_duration);
-        info!("Found {} duplicate groups", result.duplicate_groups.len());
-        info!("Total wasted space: {} bytes", result.total_wasted_space);
+        for file_info in candidates {
+            let Some(group) = groups.get_mut(&file_info.hash) else {
+                continue;
+            };
 
-        Ok(result)
+            // The needle itself may live inside the haystack
+            if let Ok(canonical) = fs::canonicalize(&file_info.path).await
+                && needle_paths.contains(&canonical)
+            {
+                continue;
+            }
+            group.files.push(file_info);
+        }
+
+        let groups: Vec<DuplicateGroup> = groups.into_values()
+            .map(|mut group| {
+                let count = group.files.len() as u64;
+                group.total_size = group.size * count;
+                group.wasted_space = group.size * count.saturating_sub(1);
+                group
+            })
+            .collect();
+        let groups = assign_ids(groups, &self.config);
+
+        info!("Found copies of {} needle(s)", groups.iter().filter(|g| g.files.len() > 1).count());
+        Ok(result_from_groups(groups, total_files, &self.config.directory, self.file_errors(), start_time))
+    }
+
+    /// Finds scanned files whose content does not exist in the reference tree
+    ///
+    /// Answers "is anything here unique?" before deleting an old copy of a
+    /// tree. Requires a reference directory; files inside it are ignored
+    pub async fn find_missing(&mut self) -> Result<Vec<FileInfo>> {
+        let reference = self.config.reference.clone()
+            .ok_or(DuplicateFinderError::ReferenceRequired)?;
+
+        let mut file_infos = self.collect_files().await?;
+        file_infos.retain(|f| !f.path.starts_with(&reference));
+
+        info!("Hashing reference directory: {}", reference.display());
+        let known = HashIndex::from_files(self.collect_reference(&reference, &file_infos).await?);
+
+        let mut missing: Vec<FileInfo> = file_infos.into_iter()
+            .filter(|f| !known.contains_hash(&f.hash))
+            .collect();
+        missing.sort_by(|a, b| a.path.cmp(&b.path));
+
+        info!("{} files have no copy in the reference directory", missing.len());
+        Ok(missing)
     }
 
     /// Configuration validation before starting the scan
     fn validate_config(&self) -> Result<(), DuplicateFinderError> {
         // Verify that the directory exists
-        if !self.config.directory.exists() {
+        if self.source.is_none() && !self.config.directory.exists() && !is_remote_url(&self.config.directory) {
             return Err(DuplicateFinderError::PathNotFound {
                 path: self.config.directory.clone(),
             });
         }
 
+        for root in &self.config.extra_roots {
+            if is_remote_url(root) || is_remote_url(&self.config.directory) || self.source.is_some() {
+                return Err(DuplicateFinderError::RemoteRoot { path: root.clone() });
+            }
+            if !root.exists() {
+                return Err(DuplicateFinderError::PathNotFound { path: root.clone() });
+            }
+        }
+
+        if let Some(reference) = &self.config.reference
+            && !reference.exists()
+            && !is_remote_url(reference)
+        {
+            return Err(DuplicateFinderError::PathNotFound {
+                path: reference.clone(),
+            });
+        }
+
+        let remote = self.source.as_ref().map_or(is_remote_url(&self.config.directory), |s| !s.is_local())
+            || self.config.reference.as_deref().is_some_and(is_remote_url);
+        if remote && (self.config.mode != Mode::Exact || self.normalizer.is_some()) {
+            return Err(DuplicateFinderError::RemoteRequiresExactMode);
+        }
+
+        if self.config.mode == Mode::Video {
+            video::check_tools()?;
+        }
+
+        if !self.config.scan_archives.is_empty() && (self.config.mode != Mode::Exact || self.normalizer.is_some()) {
+            return Err(DuplicateFinderError::ArchivesRequireExactMode);
+        }
+
         // Check file size filters
         if self.config.max_size > 0 && self.config.min_size > self.config.max_size {
             return Err(DuplicateFinderError::InvalidSizeFilter {
@@ -135,127 +1014,331 @@ impl FileScanner {
         Ok(())
     }
 
-    /// Configure a progress bar for visual feedback
-    fn setup_progress_bar(&mut self) {
-        let pb = ProgressBar::new(0);
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} {msg}")
-                .expect("Invalid progress bar template")
-                .progress_chars("#>-"),
-        );
-        self.progress_bar = Some(pb);
+    /// Opens the checkpoint of `--checkpoint` or `--resume`
+    fn open_checkpoint(&mut self) -> Result<(), DuplicateFinderError> {
+        if self.checkpoint.is_some() {
+            return Ok(());
+        }
+        let checkpoint = match (&self.config.checkpoint, &self.config.resume) {
+            (_, Some(path)) => Checkpoint::resume(path, &self.config)?,
+            (Some(path), None) => Checkpoint::create(path, &self.config)?,
+            (None, None) => return Ok(()),
+        };
+        self.checkpoint = Some(Arc::new(checkpoint));
+        Ok(())
+    }
+
+    /// Returns true when file hashes are digests of the raw file contents
+    fn compares_raw_bytes(&self) -> bool {
+        self.config.mode == Mode::Exact && self.normalizer.is_none() && !self.config.decompress
+    }
+
+    /// Returns true when `--prefilter` applies: local files compared by
+    /// their raw bytes, whose unique files need no digest
+    fn prefilters(&self) -> bool {
+        self.config.prefilter
+            && self.compares_raw_bytes()
+            && self.source.is_none()
+            && !self.config.roots().any(is_remote_url)
+            && self.config.reference.is_none()
+            && !self.config.include_unique
+            && self.config.scan_archives.is_empty()
     }
 
-    /// Iterative recursion file discovery in a directory
+    /// Starts telling the progress bar and the observer about the scan
     ///
-    /// Uses an iterative approach instead of pure recursion to avoid
-    /// async recursion issues and better stack usage control
-    async fn discover_files(&self) -> Result<Vec<PathBuf>> {
-        let mut file_paths = Vec::new();
+    /// The bar is drawn unless verbose logs or quiet mode would clash with
+    /// it, and only with the `cli` feature
+    fn start_progress(&mut self) {
+        let mut observers: Vec<Arc<dyn ProgressObserver>> = Vec::new();
+        #[cfg(feature = "cli")]
+        if !self.config.verbose && !self.config.quiet {
+            observers.push(Arc::new(ProgressBarObserver::start(self.config.heartbeat)));
+        }
+        observers.extend(self.observer.clone());
+        if let Some(watch) = self.pause_watch.take() {
+            watch.abort();
+        }
+        if observers.is_empty() {
+            self.progress = None;
+            return;
+        }
+        let progress: Arc<dyn ProgressObserver> = Arc::new(Observers(observers));
+
+        // The observers hear when the scan is paused, until it is finished;
+        // scans on threads outside a runtime go without
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let mut paused = self.pause.subscribe();
+            let watched = Arc::clone(&progress);
+            self.pause_watch = Some(runtime.spawn(async move {
+                while paused.changed().await.is_ok() {
+                    watched.paused(*paused.borrow_and_update());
+                }
+            }).abort_handle());
+        }
+        self.progress = Some(progress);
+    }
 
-        // Use a stack to imitate recursion
-        // Each element contains (directory_path, current_depth)
-        let mut dir_stack = vec![(self.config.directory.clone(), 0)];
+    /// Tells the progress bar and the observer that the scan is over
+    fn finish_progress(&self) {
+        if let Some(watch) = &self.pause_watch {
+            watch.abort();
+        }
+        if let Some(progress) = &self.progress {
+            progress.finished();
+        }
+    }
 
-        // Debug and monitoring statistics
-        let mut directories_processed = 0;
-        let mut max_stack_size = 0;
+    /// Discovers the local files below `root`, which lies `root_depth`
+    /// levels below the scan root
+    ///
+    /// Returns each file that passes the filters with the metadata read
+    /// while walking, which hashing reuses instead of reading it again
+    async fn discover_files(&self, root: PathBuf, root_depth: usize) -> Result<Vec<SourceEntry>> {
+        let listing = LocalSource::new(root)
+            .with_io(Arc::clone(&self.io))
+            .with_pause(self.pause.clone())
+            .with_discovery(self.discovery())
+            .walk(root_depth, &ListOptions::from_config(&self.config))
+            .await;
+        Ok(self.accept_listing(listing))
+    }
 
-        while let Some((current_dir, current_depth)) = dir_stack.pop() {
-            directories_processed += 1;
-            max_stack_size = max_stack_size.max(dir_stack.len());
+    /// Discovers the files below the scan root and every `--root`
+    ///
+    /// Up to `--walk-concurrency` roots are walked concurrently. A file
+    /// below nested roots is listed once
+    async fn discover_roots(&self) -> Result<Vec<SourceEntry>> {
+        let roots: Vec<&Path> = self.config.roots().collect();
+        let options = self.walk_options(roots.len());
+        let discovery = self.discovery();
+        let listings: Vec<Listing> = stream::iter(roots)
+            .map(|root| {
+                let source = LocalSource::new(root)
+                    .with_io(Arc::clone(&self.io))
+                    .with_pause(self.pause.clone())
+                    .with_discovery(Arc::clone(&discovery));
+                async move { source.walk(0, &options).await }
+            })
+            .buffered(self.config.walk_concurrency.max(1))
+            .collect()
+            .await;
 
-            // Protection against accidental infinite recursion
-            const MAX_PENDING_DIRS: usize = 10000;
-            if dir_stack.len() > MAX_PENDING_DIRS {
-                warn!(
-                    "Too many pending directories ({}), possible deep directory structure. Limiting scan.",
-                    dir_stack.len()
-                );
-                break;
-            }
+        let mut seen = HashSet::new();
+        Ok(listings.into_iter()
+            .flat_map(|listing| self.accept_listing(listing))
+            .filter(|entry| seen.insert(entry.path.clone()))
+            .collect())
+    }
 
-            // Check depth limit
-            if self.config.max_depth > 0 && current_depth >= self.config.max_depth {
-                debug!("Max depth {} reached at {}", self.config.max_depth, current_dir.display());
+    /// Counts the directories and files of one discovery for the progress
+    /// observers, or for `observer` when no scan started them
+    fn discovery(&self) -> Arc<Discovery> {
+        Arc::new(Discovery::new(self.progress.clone().or_else(|| self.observer.clone())))
+    }
+
+    /// Discovery rules for walking `roots` trees, which share the
+    /// `--walk-concurrency` directories listed at a time
+    fn walk_options(&self, roots: usize) -> ListOptions {
+        let mut options = ListOptions::from_config(&self.config);
+        let walked_together = roots.clamp(1, options.walk_concurrency.max(1));
+        options.walk_concurrency = (options.walk_concurrency / walked_together).max(1);
+        options
+    }
+
+    /// Applies the size and comparison-mode filters to a listing of local
+    /// files, counting its errors
+    fn accept_listing(&self, listing: Listing) -> Vec<SourceEntry> {
+        self.file_errors.fetch_add(listing.errors, Ordering::Relaxed);
+        listing.entries.into_iter()
+            .filter(|entry| self.file_passes_size_filter(entry.size) && self.mode_accepts(&entry.path))
+            .collect()
+    }
+
+    /// Reads the files named in a `--files-from` list instead of traversing
+    ///
+    /// Entries are separated by newlines, or by NUL bytes with `-0`; `-`
+    /// reads the list from standard input. Listed files bypass the
+    /// hidden-file, symlink and depth rules of discovery, while size and
+    /// comparison-mode filters still apply. Missing files count as file errors
+    async fn listed_files(&self, list: &Path) -> Result<Vec<SourceEntry>> {
+        let data = if list == Path::new("-") {
+            let mut data = Vec::new();
+            tokio::io::stdin().read_to_end(&mut data).await?;
+            data
+        } else {
+            fs::read(list).await?
+        };
+
+        let separator = if self.config.null_separated { b'\0' } else { b'\n' };
+        let mut seen = HashSet::new();
+        let mut files = Vec::new();
+        for entry in data.split(|&b| b == separator) {
+            let entry = if separator == b'\n' { entry.strip_suffix(b"\r").unwrap_or(entry) } else { entry };
+            if entry.is_empty() {
+                continue;
+            }
+            let path = path_from_bytes(entry);
+            if !seen.insert(path.clone()) {
                 continue;
             }
 
-            debug!("Scanning directory: {} (depth: {}, stack: {})",
-                current_dir.display(), current_depth, dir_stack.len());
-
-            // Try to read directory contents
-            let mut read_dir = match fs::read_dir(&current_dir).await {
-                Ok(rd) => rd,
+            match fs::metadata(&path).await {
+                Ok(metadata) if metadata.is_file() => {
+                    if self.file_passes_size_filter(metadata.len()) && self.mode_accepts(&path) {
+                        files.push(source::local_entry(path, &metadata));
+                    }
+                }
+                Ok(_) => debug!("Skipping listed path that is not a file: {}", path.display()),
                 Err(e) => {
-                    warn!("Cannot read directory {}: {}", current_dir.display(), e);
-                    continue; // Continue with other directories
+                    warn!("Cannot read listed file {}: {}", path.display(), e);
+                    self.file_errors.fetch_add(1, Ordering::Relaxed);
                 }
-            };
+            }
+        }
 
-            // Buffer new directories before adding them to the stack
-            // This helps with performance when dealing with a large number of subdirectories
-            let mut new_directories = Vec::new();
+        info!("Read {} files from {}", files.len(), list.display());
+        Ok(files)
+    }
 
-            // Process each entry in the directory
-            while let Some(entry_result) = read_dir.next_entry().await.transpose() {
-                let entry = match entry_result {
-                    Ok(entry) => entry,
-                    Err(e) => {
-                        warn!("Error reading directory entry in {}: {}", current_dir.display(), e);
-                        continue;
-                    }
-                };
+    /// Checks whether a single file would be picked up by discovery
+    ///
+    /// Applies the hidden-file, symlink and size filters to a path below the
+    /// scan root, for callers that learn about files without a full traversal
+    pub(crate) fn accepts_file(&self, path: &Path, metadata: &std::fs::Metadata) -> bool {
+        if !metadata.is_file() || !self.file_passes_size_filter(metadata.len()) || !self.mode_accepts(path) {
+            return false;
+        }
+
+        if path.is_symlink() && !self.config.follow_symlinks {
+            return false;
+        }
 
-                let path = entry.path();
+        !self.is_below_hidden(path)
+    }
 
-                // Check if hidden files should be skipped
-                if !self.config.include_hidden && self.is_hidden(&path) {
-                    debug!("Skipping hidden path: {}", path.display());
-                    continue;
-                }
+    /// Checks whether a path lies in a hidden part of the tree that discovery skips
+    pub(crate) fn is_below_hidden(&self, path: &Path) -> bool {
+        if self.config.include_hidden {
+            return false;
+        }
+
+        // Discovery never descends into hidden directories, so check every component
+        let relative = self.config.root_of(path).and_then(|root| path.strip_prefix(root).ok()).unwrap_or(path);
+        relative.components().any(|c| self.is_hidden(Path::new(c.as_os_str())))
+    }
+
+    /// Discovers and hashes files below one subdirectory of the scan root
+    ///
+    /// Used for incremental updates; `depth` is the subdirectory's depth
+    /// below the root so the max-depth limit still applies
+    pub(crate) async fn collect_files_below(&self, dir: &Path, depth: usize) -> Result<Vec<FileInfo>> {
+        let files = self.discover_files(dir.to_path_buf(), depth).await?;
+        self.process_files(files).await
+    }
+
+    /// Hashes the reference tree of `--reference`
+    ///
+    /// `scanned` are the files already hashed from the scan root; for a
+    /// remote reference only files that could match one of them are
+    /// downloaded
+    async fn collect_reference(&self, reference: &Path, scanned: &[FileInfo]) -> Result<Vec<FileInfo>> {
+        if is_remote_url(reference) {
+            let sizes: HashSet<u64> = scanned.iter().map(|f| f.size).collect();
+            return self.collect_from_source(source::open_source(reference).await?, |_| true, Some(&sizes)).await;
+        }
+        self.collect_files_below(reference, 0).await
+    }
+
+    /// Lists and hashes the files of a source that is not local
+    ///
+    /// Files pass the same size filter as local files (the source applies
+    /// the hidden-file and depth rules). Only files that could have a
+    /// duplicate are read, once per distinct content (see
+    /// [`source::plan_downloads`]); files that cannot be read count as file
+    /// errors
+    async fn collect_from_source(&self, source: Arc<dyn FileSource>, wanted: impl Fn(u64) -> bool, compared_sizes: Option<&HashSet<u64>>) -> Result<Vec<FileInfo>> {
+        info!("Listing files in {}", source.root().display());
+        let listing = source.list(&ListOptions::from_config(&self.config)).await?;
+        self.file_errors.fetch_add(listing.errors, Ordering::Relaxed);
+
+        let entries: Vec<SourceEntry> = listing.entries.into_iter()
+            .filter(|entry| self.file_passes_size_filter(entry.size) && wanted(entry.size))
+            .collect();
+        let plan = source::plan_downloads(&entries, compared_sizes);
+
+        let downloads: BTreeSet<usize> = plan.iter().flatten().copied().collect();
+        info!("Found {} files, reading {}", entries.len(), downloads.len());
+        if let Some(progress) = &self.progress {
+            progress.discovery_finished();
+            progress.queued(downloads.iter().map(|&index| entries[index].size).sum());
+        }
 
-                // Check symbolic links
-                if path.is_symlink() && !self.config.follow_symlinks {
-                    debug!("Skipping symlink: {}", path.display());
-                    continue;
+        let mut tasks = Vec::new();
+        for index in downloads {
+            let path = entries[index].path.clone();
+            let size = entries[index].size;
+            let source = Arc::clone(&source);
+            let hasher = Arc::clone(&self.hasher);
+            let semaphore = Arc::clone(&self.semaphore);
+            let progress = self.progress.clone();
+            tasks.push(tokio::spawn(async move {
+                let _permit = semaphore.acquire().await
+                    .expect("Semaphore should not be closed");
+                let result = source::hash_entry(source.as_ref(), &path, hasher.as_ref()).await;
+                if let Some(progress) = &progress {
+                    progress.read(size);
+                    progress.file_done();
                 }
+                (index, result)
+            }));
+        }
 
-                let metadata = match entry.metadata().await {
-                    Ok(metadata) => metadata,
-                    Err(e) => {
-                        warn!("Cannot read metadata for {}: {}", path.display(), e);
-                        continue;
-                    }
-                };
-
-                if metadata.is_dir() {
-                    // Buffer the directory for later scanning
-                    new_directories.push((path, current_depth + 1));
-                } else if metadata.is_file() {
-                    // Check file size filters
-                    if self.file_passes_size_filter(metadata.len()) {
-                        file_paths.push(path);
-                    }
+        let mut digests = HashMap::new();
+        for task in tasks {
+            match task.await {
+                Ok((index, Ok(hash))) => {
+                    digests.insert(index, hash);
                 }
+                Ok((index, Err(e))) => warn!("Cannot read {}: {}", entries[index].path.display(), e),
+                Err(e) => error!("Task panicked: {}", e),
             }
+        }
 
-            // Add new directories to the stack
-            // Reverse order for breadth-first traversal
-            for dir_entry in new_directories.into_iter().rev() {
-                dir_stack.push(dir_entry);
+        let mut file_infos = Vec::with_capacity(entries.len());
+        for (entry, representative) in entries.into_iter().zip(plan) {
+            let hash = match representative {
+                None => source::unhashed_digest(&entry),
+                Some(representative) => match digests.get(&representative) {
+                    Some(hash) => hash.clone(),
+                    None => {
+                        self.file_errors.fetch_add(1, Ordering::Relaxed);
+                        continue;
+                    }
+                },
+            };
+            if let Some(metrics) = &self.metrics {
+                metrics.record_file(entry.size);
             }
+            file_infos.push(FileInfo {
+                path: entry.path,
+                size: entry.size,
+                hash,
+                modified: entry.modified,
+                created: None,
+                compression: None,
+            });
         }
+        Ok(file_infos)
+    }
 
-        info!(
-            "Directory scan completed: {} directories processed, {} files found, max stack size: {}",
-            directories_processed,
-            file_paths.len(),
-            max_stack_size
-        );
-
-        Ok(file_paths)
+    /// The source of the scanned files, unless it is the local filesystem
+    async fn remote_source(&self) -> Result<Option<Arc<dyn FileSource>>> {
+        Ok(match &self.source {
+            Some(source) => (!source.is_local()).then(|| Arc::clone(source)),
+            None if is_remote_url(&self.config.directory) => Some(source::open_source(&self.config.directory).await?),
+            None => None,
+        })
     }
 
     /// Checks if a path is hidden
@@ -268,115 +1351,1096 @@ impl FileScanner {
 
     /// Checks if a file passes a size filter
     fn file_passes_size_filter(&self, size: u64) -> bool {
-        // Check empty files exclusion
-        if self.config.exclude_empty && size == 0 {
-            return false;
-        }
+        FileFilter::from_config(&self.config).passes_size(size)
+    }
 
-        // Check minimum size
-        if size < self.config.min_size {
-            return false;
+    /// Checks if the comparison mode can handle a file (e.g. images only)
+    fn mode_accepts(&self, path: &Path) -> bool {
+        FileFilter::from_config(&self.config).mode_accepts(path)
+    }
+
+    /// Process files and calculate their hashes
+    ///
+    /// Uses controlled parallelism through semaphore for efficient
+    /// processing of large numbers of files without a system overload.
+    /// Up to [`PENDING_FILES`] tasks are spawned ahead of the permits, and
+    /// each finished file lets the next one in, so a slow file never holds
+    /// up the others. Reading and digesting happen on the blocking pool.
+    /// Files of at least `--parallel-hash-min-size` are hashed with several
+    /// threads when the digest allows it, one at a time and outside the
+    /// window, so they neither hold a regular permit nor a window slot
+    async fn process_files(&self, files: Vec<SourceEntry>) -> Result<Vec<FileInfo>> {
+        let mut file_infos = Vec::new();
+
+        let parallel_min_size = self.parallel_min_size();
+        let (huge, regular): (Vec<_>, Vec<_>) = files.iter()
+            .partition(|entry| parallel_min_size.is_some_and(|min| entry.size >= min));
+        if !huge.is_empty() {
+            info!("Hashing {} huge files with multiple threads", huge.len());
+        }
+        let huge_tasks: Vec<_> = huge.into_iter()
+            .map(|entry| self.spawn_fingerprint(entry.clone(), Arc::clone(&self.parallel_lane), true))
+            .collect();
+
+        let window = PENDING_FILES.max(self.semaphore.available_permits());
+        let outcomes: Vec<_> = stream::iter(regular)
+            .map(|entry| self.spawn_fingerprint(entry.clone(), Arc::clone(&self.semaphore), false))
+            .buffered(window)
+            .collect()
+            .await;
+        for outcome in outcomes {
+            file_infos.extend(self.task_output(outcome));
+        }
+        for task in huge_tasks {
+            file_infos.extend(self.task_output(task.await));
         }
 
-        // Check maximum size (0 means no limit)
-        if self.config.max_size > 0 && size > self.config.max_size {
-            return false;
+        if !self.config.scan_archives.is_empty() {
+            let file_paths: Vec<PathBuf> = files.into_iter().map(|entry| entry.path).collect();
+            file_infos.extend(self.expand_archives(&file_paths).await);
         }
 
-        true
+        Ok(file_infos)
     }
 
-    /// Process files and calculate their hashes
+    /// Smallest file hashed with several threads, if the digest allows it
+    fn parallel_min_size(&self) -> Option<u64> {
+        (self.compares_raw_bytes() && self.hasher.hashes_in_parallel()).then_some(self.config.parallel_hash_min_size)
+    }
+
+    /// Fingerprints one file on its own task once `semaphore` grants a permit
     ///
-    /// Uses controlled parallelism through semaphore for efficient
-    /// processing of large numbers of files without a system overload
-    async fn process_files(&self, file_paths: Vec<PathBuf>) -> Result<Vec<FileInfo>> {
-        let mut file_infos = Vec::new();
-        let mut tasks = Vec::new();
+    /// The file's device grants a permit first, so files waiting on a busy
+    /// device hold no permit other devices could use. With `parallel` the
+    /// file is hashed with several threads (exact mode only). Files an
+    /// interrupted scan already hashed are taken from its checkpoint. Files
+    /// wait to start while the scan is paused; once the scan was stopped,
+    /// files that did not start yet are dropped.
+    /// The metadata of `entry` was read during discovery and is not read again
+    fn spawn_fingerprint(&self, entry: SourceEntry, semaphore: Arc<Semaphore>, parallel: bool) -> tokio::task::JoinHandle<Option<FileInfo>> {
+        let observer = self.progress.clone();
+        let progress = observer.clone().map(|observer| Arc::new(ReadProgress::new(observer, entry.size)));
+        let fingerprinter = Fingerprinter { progress: progress.clone(), ..self.fingerprinter() };
+        let metrics = self.metrics.clone();
+        let file_errors = Arc::clone(&self.file_errors);
+        let device_limits = self.device_limits.clone();
+        let checkpoint = self.checkpoint.clone();
+        let stopped = Arc::clone(&self.stopped);
+        let pause = self.pause.clone();
+        let span = debug_span!("hash_file", path = %entry.path.display(), size = entry.size);
+
+        tokio::spawn(async move {
+            let path = entry.path.clone();
+            if let Some(checkpoint) = &checkpoint
+                && let Some(file_info) = checkpoint.completed(&entry)
+            {
+                if let (Some(progress), Some(observer)) = (&progress, &observer) {
+                    progress.finish();
+                    observer.file_done();
+                }
+                return Some(file_info);
+            }
 
-        // Process files in batches to manage memory
-        const BATCH_SIZE: usize = 100;
+            let lane = device_limits.map(|limits| limits.lane(&path)).unwrap_or_default();
+            let _device_permit = match &lane.permits {
+                Some(permits) => Some(permits.acquire().await.expect("Semaphore should not be closed")),
+                None => None,
+            };
+            // Acquire permission from the semaphore
+            let _permit = semaphore.acquire().await
+                .expect("Semaphore should not be closed");
+            pause.wait().await;
+            if stopped.load(Ordering::Relaxed) {
+                return None;
+            }
 
-        for batch in file_paths.chunks(BATCH_SIZE) {
-            // Create tasks for the current batch
-            for path in batch {
-                let path_owned = path.clone();
-                let semaphore = Arc::clone(&self.semaphore);
-                let progress_bar = self.progress_bar.clone();
+            let result = if parallel {
+                fingerprinter.fingerprint_parallel(&entry).await
+            } else {
+                fingerprinter.fingerprint_discovered(&entry).await
+            };
 
-                let task = tokio::spawn(async move {
-                    // Acquire permission from the semaphore
-                    let _permit = semaphore.acquire().await
-                        .expect("Semaphore should not be closed");
+            if let (Ok(file_info), Some(metrics)) = (&result, &metrics) {
+                metrics.record_file(file_info.size);
+            }
+            if let (Ok(file_info), Some(checkpoint)) = (&result, &checkpoint) {
+                checkpoint.record(file_info, &entry);
+            }
 
-                    let result = FileInfo::from_path(&path_owned).await;
+            // Whatever was not read (cached digests, decoded media) still
+            // counts as done
+            if let (Some(progress), Some(observer)) = (&progress, &observer) {
+                progress.finish();
+                observer.file_done();
+            }
 
-                    // Update the progress bar
-                    if let Some(pb) = &progress_bar {
-                        pb.inc(1);
-                    }
+            match result {
+                Ok(file_info) => {
+                    debug!("Processed file");
+                    Some(file_info)
+                }
+                Err(e) => {
+                    error!(path = %path.display(), error = %e, "Failed to process file");
+                    file_errors.fetch_add(1, Ordering::Relaxed);
+                    None
+                }
+            }
+        }.instrument(span))
+    }
 
-                    match result {
-                        Ok(file_info) => {
-                            debug!("Processed file: {}", path_owned.display());
-                            Some(file_info)
-                        }
-                        Err(e) => {
-                            error!("Failed to process file {}: {}", path_owned.display(), e);
-                            None
-                        }
-                    }
-                });
+    /// The file hashed by a fingerprint task, if it succeeded
+    fn task_output(&self, outcome: Result<Option<FileInfo>, tokio::task::JoinError>) -> Option<FileInfo> {
+        match outcome {
+            Ok(file_info) => file_info, // None when file processing failed
+            Err(e) => {
+                error!("Task panicked: {}", e);
+                self.file_errors.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    /// Hashes the members of archives selected with `--scan-archives`
+    ///
+    /// Members pass the same size and hidden-file filters as regular files.
+    /// Unreadable archives count as file errors
+    async fn expand_archives(&self, file_paths: &[PathBuf]) -> Vec<FileInfo> {
+        let mut tasks = Vec::new();
+        for path in file_paths {
+            let Some(format) = archives::archive_format(path).filter(|f| self.config.scan_archives.contains(f)) else {
+                continue;
+            };
+            let path = path.clone();
+            let hasher = Arc::clone(&self.hasher);
+            let semaphore = Arc::clone(&self.semaphore);
+            tasks.push(tokio::spawn(async move {
+                let _permit = semaphore.acquire().await
+                    .expect("Semaphore should not be closed");
+                let result = archives::archive_members(&path, format, hasher).await;
+                (path, result)
+            }));
+        }
 
-                tasks.push(task);
+        let mut members = Vec::new();
+        for task in tasks {
+            match task.await {
+                Ok((path, Ok(found))) => {
+                    debug!("Read {} members from {}", found.len(), path.display());
+                    members.extend(found.into_iter().filter(|member| {
+                        let inner = member.path.strip_prefix(&path).unwrap_or(&member.path);
+                        self.file_passes_size_filter(member.size)
+                            && (self.config.include_hidden || !inner.iter().any(|c| c.to_string_lossy().starts_with('.')))
+                    }));
+                }
+                Ok((path, Err(e))) => {
+                    warn!("Cannot read archive {}: {}", path.display(), e);
+                    self.file_errors.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(e) => {
+                    error!("Task panicked: {}", e);
+                    self.file_errors.fetch_add(1, Ordering::Relaxed);
+                }
             }
+        }
+
+        info!("Found {} files inside archives", members.len());
+        members
+    }
+}
+
+/// Groups hashed files into a complete scan result
+///
+/// Shared by full scans and incremental updates (watch mode) so both
+/// produce identical statistics. `start_time` marks the beginning of the
+/// work being reported
+pub(crate) fn build_result(
+    file_infos: Vec<FileInfo>,
+    config: &ScannerConfig,
+    file_errors: usize,
+    start_time: Instant,
+) -> ScanResult {
+    let total_files = file_infos.len();
+    let duplicate_groups = group_files(file_infos, config);
+    let mut result = result_from_groups(duplicate_groups, total_files, &config.directory, file_errors, start_time);
+    result.mode = config.mode;
+    result.hash_algo = config.hash_algo;
+    if config.mode == Mode::TextSimilar {
+        result.similar_pairs = text::similar_pairs(&result.duplicate_groups, config.min_similarity);
+    }
+    result
+}
+
+/// Computes the comparison keys of local files
+///
+/// Cheap to clone, so every hashing task gets its own
+#[derive(Clone)]
+pub(crate) struct Fingerprinter {
+    mode: Mode,
+    hasher: Arc<dyn HashFunction>,
+    io_mode: IoMode,
+    io: Arc<dyn LocalIo>,
+    normalizer: Option<Arc<TextNormalizer>>,
+    decompress: bool,
+    buffer_size: u64,
+    cache_hints: bool,
+    root_storage: Arc<[(PathBuf, StorageKind)]>,
+    throughput: Option<Arc<TokenBucket>>,
+    device_limits: Option<Arc<DeviceLimits>>,
+    file_timeout: Option<Duration>,
+    retries: u32,
+    xattr_cache: Option<Arc<XattrCache>>,
+    pause: PauseSwitch,
+    /// Bytes read of the one file being fingerprinted
+    progress: Option<Arc<ReadProgress>>,
+}
+
+impl Fingerprinter {
+    /// Computes the comparison key of one file for the comparison mode
+    ///
+    /// The key is stored in [`FileInfo::hash`]: a digest of the file by
+    /// the hash function, a SHA-256 digest of its decoded pixels, untagged
+    /// audio data or document content, or a perceptual signature for image,
+    /// video and text similarity. In exact mode compressed files may be
+    /// hashed decompressed and a normalizer canonicalizes text files before
+    /// they are hashed; other files are read as the I/O mode and backend say
+    pub(crate) async fn fingerprint(&self, path: &Path) -> Result<FileInfo, DuplicateFinderError> {
+        self.retrying(path, |cancel| self.comparison_key(path, None, cancel)).await
+    }
+
+    /// Like [`Fingerprinter::fingerprint`], taking the size and times of a
+    /// file from its discovery instead of reading its metadata again
+    ///
+    /// Only raw contents skip the metadata call; other comparison modes
+    /// read it along with the file
+    pub(crate) async fn fingerprint_discovered(&self, entry: &SourceEntry) -> Result<FileInfo, DuplicateFinderError> {
+        self.retrying(&entry.path, |cancel| self.comparison_key(&entry.path, Some(entry), cancel)).await
+    }
+
+    async fn comparison_key(&self, path: &Path, known: Option<&SourceEntry>, cancel: Cancel) -> Result<FileInfo, DuplicateFinderError> {
+        let compression = compression::compression_of(path).filter(|_| self.decompress);
+        match self.mode {
+            Mode::Exact => match (compression, self.normalizer.as_deref()) {
+                (Some(format), _) => compression::decompressed_file_info(path, format, Arc::clone(&self.hasher)).await,
+                (None, Some(normalizer)) => text::normalized_file_info(path, normalizer, self.hasher.as_ref()).await,
+                (None, None) => self.raw_file_info(path, known, &cancel).await,
+            },
+            Mode::ImageSimilar => similarity::perceptual_file_info(path).await,
+            Mode::ImagePixels => similarity::pixel_file_info(path).await,
+            Mode::Audio => audio::audio_file_info(path).await,
+            Mode::Video => video::video_file_info(path).await,
+            Mode::TextSimilar => text::text_file_info(path).await,
+            Mode::Document => documents::document_file_info(path).await,
+            Mode::Name => names::name_file_info(path).await,
+            Mode::NameConflict => self.raw_file_info(path, known, &cancel).await,
+            Mode::Quick => names::quick_file_info(path).await,
+            Mode::Metadata => names::metadata_file_info(path).await,
+        }
+    }
+
+    /// Hashes the raw contents of a huge discovered file with several threads
+    pub(crate) async fn fingerprint_parallel(&self, entry: &SourceEntry) -> Result<FileInfo, DuplicateFinderError> {
+        let file_info = FileInfo::from_entry(entry);
+        let cached = self.cached(&entry.path).await;
+        if let Some(hash) = cached.as_ref().and_then(Cached::trusted) {
+            return Ok(FileInfo { hash: hash.to_string(), ..file_info });
+        }
+        let path = &entry.path;
+        let file_info = self.retrying(path, |cancel| {
+            hashing::parallel_file_info(file_info.clone(), Arc::clone(&self.hasher), self.io_mode, self.read_options(path, &cancel))
+        }).await?;
+        self.remember(cached, &file_info).await;
+        Ok(file_info)
+    }
+
+    async fn raw_file_info(&self, path: &Path, known: Option<&SourceEntry>, cancel: &Cancel) -> Result<FileInfo, DuplicateFinderError> {
+        let file_info = match known {
+            Some(entry) => FileInfo::from_entry(entry),
+            None => FileInfo::stat(path).await?,
+        };
+        let cached = self.cached(path).await;
+        if let Some(hash) = cached.as_ref().and_then(Cached::trusted) {
+            return Ok(FileInfo { hash: hash.to_string(), ..file_info });
+        }
+        let file_info = hashing::raw_file_info(file_info, &self.hasher, self.io_mode, self.io.as_ref(), self.read_options(path, cancel)).await?;
+        self.remember(cached, &file_info).await;
+        Ok(file_info)
+    }
+
+    /// What `--xattr-cache` knows about a file before it is hashed
+    async fn cached(&self, path: &Path) -> Option<Cached> {
+        let cache = self.xattr_cache.clone()?;
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || cache.lookup(&path)).await.ok().flatten()
+    }
+
+    /// Stores the digest of a freshly hashed file for `--xattr-cache`
+    async fn remember(&self, cached: Option<Cached>, file_info: &FileInfo) {
+        if let (Some(cache), Some(cached)) = (self.xattr_cache.clone(), cached) {
+            let file_info = file_info.clone();
+            let _ = tokio::task::spawn_blocking(move || cache.store(&cached, &file_info)).await;
+        }
+    }
 
-            // Wait for all tasks in the current batch to complete
-            for task in tasks.drain(..) {
-                match task.await {
-                    Ok(Some(file_info)) => file_infos.push(file_info),
-                    Ok(None) => {} // File processing failed, skipping
-                    Err(e) => error!("Task panicked: {}", e),
+    /// Runs `attempt` until it succeeds, fails for good or failed
+    /// transiently `--retries` times in a row
+    ///
+    /// Every attempt gets its own `--file-timeout`; a file that timed out
+    /// is not retried, as it would most likely hang again
+    async fn retrying<F, Fut>(&self, path: &Path, mut attempt: F) -> Result<FileInfo, DuplicateFinderError>
+    where
+        F: FnMut(Cancel) -> Fut,
+        Fut: Future<Output = Result<FileInfo, DuplicateFinderError>>,
+    {
+        let mut delay = RETRY_DELAY;
+        let mut retries = 0;
+        loop {
+            let cancel = Cancel::default();
+            match self.within_timeout(path, &cancel, attempt(cancel.clone())).await {
+                Err(e) if e.is_transient() && retries < self.retries => {
+                    retries += 1;
+                    warn!("Retrying {} in {:?} ({} of {}): {}", path.display(), delay, retries, self.retries, e);
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
                 }
+                result => return result,
             }
         }
+    }
 
-        Ok(file_infos)
+    /// Gives up on a file after `--file-timeout`
+    ///
+    /// Reads of raw contents stop at their next block once `cancel` is set;
+    /// the work of other comparison modes is abandoned and finishes unseen
+    async fn within_timeout(
+        &self,
+        path: &Path,
+        cancel: &Cancel,
+        work: impl Future<Output = Result<FileInfo, DuplicateFinderError>>,
+    ) -> Result<FileInfo, DuplicateFinderError> {
+        let Some(limit) = self.file_timeout else {
+            return work.await;
+        };
+        tokio::time::timeout(limit, work).await.unwrap_or_else(|_| {
+            cancel.cancel();
+            Err(DuplicateFinderError::FileTimeout { path: path.to_path_buf(), after: limit })
+        })
+    }
+
+    /// Reads for a file, sized by `--buffer-size` or the storage of the
+    /// deepest scan root holding it, and limited by the global and device
+    /// throughput limits
+    fn read_options(&self, path: &Path, cancel: &Cancel) -> ReadOptions {
+        let storage = self.root_storage.iter()
+            .filter(|(root, _)| path.starts_with(root))
+            .max_by_key(|(root, _)| root.components().count())
+            .map_or(StorageKind::Unknown, |(_, kind)| *kind);
+        let device_bucket = self.device_limits.as_ref().and_then(|limits| limits.lane(path).bucket);
+        let throttle = Throttle::new(self.throughput.iter().cloned().chain(device_bucket)).with_pause(self.pause.clone());
+        ReadOptions::new(self.buffer_size, storage, self.cache_hints)
+            .with_throttle(throttle)
+            .with_cancel(cancel.clone())
+            .with_progress(self.progress.clone())
+    }
+}
+
+/// Time until the scan has to wrap up (`--deadline` or `--stop-at`)
+///
+/// A `--stop-at` time that already passed today means tomorrow
+fn time_left(config: &ScannerConfig) -> Option<Duration> {
+    if let Some(deadline) = config.deadline {
+        return Some(deadline);
+    }
+    let now = chrono::Local::now().naive_local();
+    let mut end = now.date().and_time(config.stop_at?);
+    if end <= now {
+        end += chrono::Duration::days(1);
+    }
+    (end - now).to_std().ok()
+}
+
+/// Files read at the same time when `--hash-concurrency` is 0
+///
+/// Every local scan root gets the default of its storage and the fastest
+/// storage sets the count; device limits keep slower storage within its own
+fn storage_concurrency(root_storage: &[(PathBuf, StorageKind)]) -> usize {
+    let cpus = num_cpus::get();
+    root_storage.iter()
+        .map(|(_, kind)| kind.default_concurrency(cpus))
+        .max()
+        .unwrap_or(cpus * 2)
+}
+
+/// Converts a raw path from a file list into a path
+pub(crate) fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+    }
+    #[cfg(not(unix))]
+    {
+        PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+    }
+}
+
+/// Groups hashed files according to the configured comparison mode
+///
+/// With `--group-scope per-root` the files of each root are grouped on their
+/// own; files outside every root (e.g. of a reference tree) are compared
+/// with each root
+fn group_files(file_infos: Vec<FileInfo>, config: &ScannerConfig) -> Vec<DuplicateGroup> {
+    if config.group_scope == GroupScope::Combined || config.extra_roots.is_empty() {
+        return assign_ids(apply_scope(group_by_mode(file_infos, config), config), config);
+    }
+
+    let mut by_root: BTreeMap<&Path, Vec<FileInfo>> = BTreeMap::new();
+    let mut shared = Vec::new();
+    for file in file_infos {
+        match config.root_of(&file.path) {
+            Some(root) => by_root.entry(root).or_default().push(file),
+            None => shared.push(file),
+        }
+    }
+    let groups = by_root.into_values()
+        .flat_map(|mut files| {
+            files.extend(shared.iter().cloned());
+            group_by_mode(files, config)
+        })
+        .collect();
+    assign_ids(apply_scope(groups, config), config)
+}
+
+/// Gives every group its stable ID (see [`DuplicateGroup::id`])
+fn assign_ids(mut groups: Vec<DuplicateGroup>, config: &ScannerConfig) -> Vec<DuplicateGroup> {
+    let settings = comparison_settings(config);
+    let per_root = config.group_scope == GroupScope::PerRoot && !config.extra_roots.is_empty();
+    for group in &mut groups {
+        // Groups of other roots or locations may share the key
+        let confined_to = match (config.scope, group.files.first()) {
+            (Scope::SameDir, Some(file)) => Some(scope_location(&file.path, config)),
+            _ if per_root => group.files.iter().find_map(|f| config.root_of(&f.path)).map(Path::to_path_buf),
+            _ => None,
+        };
+        group.id = group_id(&settings, &group.hash, confined_to.as_deref());
     }
+    groups
+}
 
-    /// Groups files by their hashes to find duplicates
-    fn group_duplicates(&self, file_infos: Vec<FileInfo>) -> Vec<DuplicateGroup> {
-        let mut groups: HashMap<String, Vec<FileInfo>> = HashMap::new();
-
-        // Group files by hash
-        for file_info in file_infos {
-            groups.entry(file_info.hash.clone())
-                .or_insert_with(Vec::new)
-                .push(file_info);
-        }
-
-        // Convert to DuplicateGroup
-        groups.into_iter()
-            .map(|(hash, mut files)| {
-                // Sort files by modification time (oldest first)
-                files.sort_by_key(|f| f.modified);
-
-                let size = files.first().map(|f| f.size).unwrap_or(0);
-                let total_size = size * files.len() as u64;
-                let wasted_space = if files.len() > 1 {
-                    size * (files.len() as u64 - 1)
-                } else {
-                    0
-                };
-
-                DuplicateGroup {
-                    hash,
-                    size,
-                    files,
-                    total_size,
-                    wasted_space,
+/// Stable ID of a group: the first 16 hex digits of a digest of the
+/// comparison settings, the group's key and the root or location the group
+/// is confined to
+pub(crate) fn group_id(settings: &str, key: &str, confined_to: Option<&Path>) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(settings.as_bytes());
+    hasher.update(b"\0");
+    hasher.update(key.as_bytes());
+    if let Some(location) = confined_to {
+        hasher.update(b"\0");
+        hasher.update(location.as_os_str().as_encoded_bytes());
+    }
+    hasher.finalize().to_hex()[..16].to_string()
+}
+
+/// ID a group with this key gets from a scan with default settings, for
+/// groups of results whose settings are unknown
+pub(crate) fn default_group_id(key: &str) -> String {
+    group_id(&comparison_settings(&ScannerConfig::default()), key, None)
+}
+
+/// Settings that change the comparison key of a file, and with it the
+/// IDs of groups and whether a checkpoint can be resumed
+pub(crate) fn comparison_settings(config: &ScannerConfig) -> String {
+    format!(
+        "mode={:?} hash={:?} decompress={} normalize_text={} trim_trailing_whitespace={}",
+        config.mode, config.hash_algo, config.decompress, config.normalize_text, config.trim_trailing_whitespace
+    )
+}
+
+/// Drops the groups or copies that `--scope` excludes
+///
+/// For `same-dir` every group is split by location and only locations
+/// holding at least two copies are kept. Totals are recomputed from the
+/// remaining groups afterwards
+fn apply_scope(groups: Vec<DuplicateGroup>, config: &ScannerConfig) -> Vec<DuplicateGroup> {
+    match config.scope {
+        Scope::All => groups,
+        Scope::CrossDir => groups.into_iter()
+            .filter(|group| {
+                let locations: HashSet<PathBuf> = group.files.iter().map(|f| scope_location(&f.path, config)).collect();
+                locations.len() > 1
+            })
+            .collect(),
+        Scope::SameDir => groups.into_iter()
+            .flat_map(|group| {
+                let mut by_location: BTreeMap<PathBuf, Vec<FileInfo>> = BTreeMap::new();
+                for file in group.files {
+                    by_location.entry(scope_location(&file.path, config)).or_default().push(file);
                 }
+                let hash = group.hash;
+                by_location.into_values()
+                    .filter(|files| files.len() > 1)
+                    .map(move |files| {
+                        let size = files[0].size;
+                        let total_size: u64 = files.iter().map(|f| f.size).sum();
+                        DuplicateGroup { id: String::new(), hash: hash.clone(), size, files, total_size, wasted_space: total_size - size }
+                    })
             })
-            .collect()
+            .collect(),
+    }
+}
+
+/// Directory that stands for a file's location when applying `--scope`
+///
+/// With a `--scope-depth` of N, the file's directory is cut to N levels
+/// below its root, so copies anywhere in the same subtree share a location
+fn scope_location(path: &Path, config: &ScannerConfig) -> PathBuf {
+    let parent = path.parent().unwrap_or(path);
+    let Some(root) = config.root_of(path).filter(|_| config.scope_depth > 0) else {
+        return parent.to_path_buf();
+    };
+    let relative = parent.strip_prefix(root).unwrap_or(parent);
+    root.join(relative.components().take(config.scope_depth).collect::<PathBuf>())
+}
+
+/// Groups files by the comparison key of the configured mode
+fn group_by_mode(file_infos: Vec<FileInfo>, config: &ScannerConfig) -> Vec<DuplicateGroup> {
+    match config.mode {
+        Mode::Exact | Mode::ImagePixels | Mode::Audio | Mode::Document | Mode::Quick | Mode::Metadata => group_duplicates(file_infos),
+        Mode::ImageSimilar => similarity::group_similar(file_infos, config.max_distance),
+        Mode::Video => video::group_similar(file_infos, config.max_distance),
+        Mode::TextSimilar => text::group_similar(file_infos, config.min_similarity),
+        Mode::Name => names::group_by_name(file_infos, config.ignore_case, config.normalize_unicode),
+        Mode::NameConflict => names::group_conflicts(file_infos, config.ignore_case, config.normalize_unicode),
+    }
+}
+
+/// Forms a scan result from already grouped files
+///
+/// Groups with a single file are dropped; `total_files` is the number of
+/// files that were scanned, which may include files outside any group
+pub(crate) fn result_from_groups(
+    duplicate_groups: Vec<DuplicateGroup>,
+    total_files: usize,
+    directory: &Path,
+    file_errors: usize,
+    start_time: Instant,
+) -> ScanResult {
+    let scan_duration = start_time.elapsed();
+
+    // Form the final result
+    let mut result = ScanResult {
+        format_version: crate::FORMAT_VERSION,
+        total_files,
+        total_duplicates: 0,
+        total_wasted_space: 0,
+        duplicate_groups,
+        directory_groups: Vec::new(),
+        directory_overlaps: Vec::new(),
+        empty_directories: Vec::new(),
+        similar_pairs: Vec::new(),
+        sidecars: Vec::new(),
+        archived_files: Vec::new(),
+        unique_files: Vec::new(),
+        chunk_analysis: None,
+        scan_duration,
+        scanned_directory: directory.to_path_buf(),
+        mode: Mode::default(),
+        hash_algo: HashAlgorithm::default(),
+        file_errors,
+        partial: false,
+        directory_stats: Vec::new(),
+        extension_stats: Vec::new(),
+        spilled_groups: None,
+    };
+    refresh_statistics(&mut result);
+    result
+}
+
+/// Recomputes totals and statistics after the duplicate groups changed
+///
+/// Groups left with a single file are dropped
+pub(crate) fn refresh_statistics(result: &mut ScanResult) {
+    result.duplicate_groups.retain(|g| g.files.len() > 1); // Only real duplicates
+    result.total_duplicates = result.duplicate_groups.iter().filter(|g| g.holds_copies()).map(|g| g.files.len() - 1).sum();
+    result.total_wasted_space = result.duplicate_groups.iter().map(|g| g.wasted_space).sum();
+    result.directory_stats = compute_directory_stats(&result.duplicate_groups);
+    result.extension_stats = compute_extension_stats(&result.duplicate_groups);
+}
+
+/// Attaches spilled duplicate groups to a result without any groups in
+/// memory, reading them back for the totals and statistics
+fn with_spilled_groups(mut result: ScanResult, spilled: Arc<SpilledGroups>) -> std::io::Result<ScanResult> {
+    // The statistics take infallible iterators: reading stops at the first
+    // error, which is returned once they are done
+    let failed = std::cell::Cell::new(None);
+    let read = || -> std::io::Result<_> {
+        Ok(spilled.read()?.map_while(|group| group.map_err(|e| failed.set(Some(e))).ok()))
+    };
+    let (mut duplicates, mut wasted) = (0, 0);
+    result.directory_stats = compute_directory_stats(read()?.inspect(|group| {
+        if group.holds_copies() {
+            duplicates += group.files.len() - 1;
+        }
+        wasted += group.wasted_space;
+    }));
+    result.extension_stats = compute_extension_stats(read()?);
+    if let Some(e) = failed.into_inner() {
+        return Err(e);
+    }
+    result.total_duplicates = duplicates;
+    result.total_wasted_space = wasted;
+    result.spilled_groups = Some(spilled);
+    Ok(result)
+}
+
+/// Restricts duplicate groups to copies of files in a reference tree
+///
+/// Only groups that contain both reference files and scanned files are
+/// kept. Each one is rebuilt with the oldest reference file as the original
+/// followed by the scanned copies, so reference files are never reported
+/// as redundant
+pub(crate) fn match_reference(groups: Vec<DuplicateGroup>, reference: &Path) -> Vec<DuplicateGroup> {
+    groups.into_iter()
+        .filter_map(|group| {
+            let (references, copies): (Vec<FileInfo>, Vec<FileInfo>) = group.files
+                .into_iter()
+                .partition(|f| f.path.starts_with(reference));
+
+            // Files were sorted oldest first, so the first reference is the oldest
+            let original = references.into_iter().next()?;
+            if copies.is_empty() {
+                return None;
+            }
+
+            let mut files = vec![original];
+            files.extend(copies);
+
+            Some(DuplicateGroup {
+                id: group.id,
+                hash: group.hash,
+                size: group.size,
+                total_size: group.size * files.len() as u64,
+                wasted_space: group.size * (files.len() as u64 - 1),
+                files,
+            })
+        })
+        .collect()
+}
+
+/// Groups files by their hashes to find duplicates, single files included
+pub(crate) fn group_duplicates(file_infos: Vec<FileInfo>) -> Vec<DuplicateGroup> {
+    file_infos.into_iter().collect::<DuplicateGrouper>().all_groups()
+}
+
+/// Rolls duplicate groups up into per-directory statistics
+///
+/// The first file of every group is treated as the original; all other
+/// copies count towards the wasted bytes of their directory. Groups of
+/// files that are not copies (see [`DuplicateGroup::holds_copies`]) are
+/// left out
+pub(crate) fn compute_directory_stats(groups: impl IntoIterator<Item = impl Borrow<DuplicateGroup>>) -> Vec<DirStat> {
+    let mut stats: BTreeMap<PathBuf, DirStat> = BTreeMap::new();
+
+    for group in groups.into_iter().filter(|g| g.borrow().files.len() > 1 && g.borrow().holds_copies()) {
+        let group = group.borrow();
+        for (index, file) in group.files.iter().enumerate() {
+            let dir = file.path.parent().map(Path::to_path_buf).unwrap_or_default();
+            let stat = stats.entry(dir.clone()).or_insert_with(|| DirStat {
+                path: dir,
+                duplicate_files: 0,
+                wasted_bytes: 0,
+            });
+
+            stat.duplicate_files += 1;
+            if index > 0 {
+                stat.wasted_bytes += file.size;
+            }
+        }
+    }
+
+    let mut stats: Vec<DirStat> = stats.into_values().collect();
+    stats.sort_by(|a, b| {
+        b.wasted_bytes
+            .cmp(&a.wasted_bytes)
+            .then_with(|| b.duplicate_files.cmp(&a.duplicate_files))
+    });
+    stats
+}
+
+/// Rolls duplicate groups up into per-extension statistics
+pub(crate) fn compute_extension_stats(groups: impl IntoIterator<Item = impl Borrow<DuplicateGroup>>) -> Vec<ExtensionStat> {
+    let mut stats: HashMap<String, ExtensionStat> = HashMap::new();
+
+    for group in groups.into_iter().filter(|g| g.borrow().files.len() > 1 && g.borrow().holds_copies()) {
+        let group = group.borrow();
+        for (index, file) in group.files.iter().enumerate() {
+            let extension = file.path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .unwrap_or("(no extension)")
+                .to_lowercase();
+
+            let stat = stats.entry(extension.clone()).or_insert_with(|| ExtensionStat {
+                extension,
+                files: 0,
+                duplicate_files: 0,
+                wasted_bytes: 0,
+            });
+
+            stat.files += 1;
+            if index > 0 {
+                stat.duplicate_files += 1;
+                stat.wasted_bytes += file.size;
+            }
+        }
+    }
+
+    let mut stats: Vec<ExtensionStat> = stats.into_values().collect();
+    stats.sort_by(|a, b| {
+        b.files
+            .cmp(&a.files)
+            .then_with(|| a.extension.cmp(&b.extension))
+    });
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str) -> FileInfo {
+        FileInfo {
+            path: PathBuf::from(path),
+            size: 10,
+            hash: "abc".to_string(),
+            modified: std::time::UNIX_EPOCH,
+            created: None,
+            compression: None,
+        }
+    }
+
+    #[test]
+    fn test_compute_directory_stats() {
+        let groups = vec![DuplicateGroup {
+            id: String::new(),
+            hash: "abc".to_string(),
+            size: 10,
+            files: vec![file("/a/1"), file("/b/2"), file("/b/3")],
+            total_size: 30,
+            wasted_space: 20,
+        }];
+
+        let stats = compute_directory_stats(&groups);
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0], DirStat { path: PathBuf::from("/b"), duplicate_files: 2, wasted_bytes: 20 });
+        assert_eq!(stats[1], DirStat { path: PathBuf::from("/a"), duplicate_files: 1, wasted_bytes: 0 });
+    }
+
+    #[test]
+    fn test_match_reference() {
+        let group = |paths: &[&str]| DuplicateGroup {
+            id: String::new(),
+            hash: "abc".to_string(),
+            size: 10,
+            files: paths.iter().map(|p| file(p)).collect(),
+            total_size: 10 * paths.len() as u64,
+            wasted_space: 10 * (paths.len() as u64 - 1),
+        };
+        let groups = vec![
+            group(&["/ref/1", "/scan/2", "/ref/3", "/scan/4"]),
+            group(&["/scan/5", "/scan/6"]),
+            group(&["/ref/7", "/ref/8"]),
+        ];
+
+        let matched = match_reference(groups, Path::new("/ref"));
+        assert_eq!(matched.len(), 1);
+        let paths: Vec<&Path> = matched[0].files.iter().map(|f| f.path.as_path()).collect();
+        assert_eq!(paths, [Path::new("/ref/1"), Path::new("/scan/2"), Path::new("/scan/4")]);
+        assert_eq!(matched[0].wasted_space, 20);
+    }
+
+    #[test]
+    fn test_scope() {
+        let group = |paths: &[&str]| DuplicateGroup {
+            id: String::new(),
+            hash: "abc".to_string(),
+            size: 10,
+            files: paths.iter().map(|p| file(p)).collect(),
+            total_size: 10 * paths.len() as u64,
+            wasted_space: 10 * (paths.len() as u64 - 1),
+        };
+        let groups = || vec![
+            group(&["/root/exports/v1.pdf", "/root/exports/v2.pdf"]),
+            group(&["/root/photos/2020/a.jpg", "/root/photos/2021/a.jpg"]),
+            group(&["/root/photos/a.jpg", "/root/backup/a.jpg"]),
+        ];
+        let config = ScannerConfig { directory: PathBuf::from("/root"), scope: Scope::CrossDir, ..Default::default() };
+
+        assert_eq!(apply_scope(groups(), &ScannerConfig::default()).len(), 3);
+        assert_eq!(apply_scope(groups(), &config).len(), 2);
+        // Copies within one top-level folder only count as one location
+        let shallow = apply_scope(groups(), &ScannerConfig { scope_depth: 1, ..config });
+        assert_eq!(shallow.len(), 1);
+        assert_eq!(shallow[0].files[1].path, Path::new("/root/backup/a.jpg"));
+
+        let mut mixed = groups();
+        mixed[1].files.push(file("/root/photos/2021/a (1).jpg"));
+        let same_dir = apply_scope(mixed, &ScannerConfig { scope: Scope::SameDir, ..ScannerConfig::default() });
+        assert_eq!(same_dir.len(), 2);
+        assert_eq!(same_dir[1].files.len(), 2);
+        assert_eq!(same_dir[1].wasted_space, 10);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_scan_blocking() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        std::fs::write(temp_dir.path().join("a.txt"), "same").unwrap();
+        std::fs::write(temp_dir.path().join("b.txt"), "same").unwrap();
+
+        // No runtime here: the blocking calls bring their own
+        let config = ScannerConfig { directory: temp_dir.path().to_path_buf(), quiet: true, ..Default::default() };
+        let result = FileScanner::new(config).scan_blocking().unwrap();
+        assert_eq!(result.duplicate_groups.len(), 1);
+        let file_info = FileInfo::from_path_blocking(&temp_dir.path().join("a.txt")).unwrap();
+        assert_eq!(file_info.hash, result.duplicate_groups[0].hash);
+    }
+
+    #[tokio::test]
+    async fn test_find_missing() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let source = temp_dir.path().join("source");
+        let reference = temp_dir.path().join("reference");
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::create_dir_all(&reference).unwrap();
+        std::fs::write(source.join("backed-up.txt"), "same").unwrap();
+        std::fs::write(source.join("unique.txt"), "only here").unwrap();
+        std::fs::write(reference.join("renamed.txt"), "same").unwrap();
+
+        let config = ScannerConfig {
+            directory: source.clone(),
+            reference: Some(reference),
+            quiet: true,
+            ..Default::default()
+        };
+        let missing = FileScanner::new(config).find_missing().await.unwrap();
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].path, source.join("unique.txt"));
+
+        let mut scanner = FileScanner::new(ScannerConfig { directory: source, quiet: true, ..Default::default() });
+        assert!(scanner.find_missing().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_find_copies() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let needle = temp_dir.path().join("needle.txt");
+        std::fs::write(&needle, "needle").unwrap();
+        std::fs::create_dir_all(temp_dir.path().join("sub")).unwrap();
+        std::fs::write(temp_dir.path().join("sub").join("copy.txt"), "needle").unwrap();
+        std::fs::write(temp_dir.path().join("same-size.txt"), "eldeen").unwrap();
+        std::fs::write(temp_dir.path().join("other.txt"), "unrelated").unwrap();
+
+        let config = ScannerConfig {
+            directory: temp_dir.path().to_path_buf(),
+            quiet: true,
+            ..Default::default()
+        };
+        let result = FileScanner::new(config).find_copies(std::slice::from_ref(&needle)).await.unwrap();
+
+        // Only same-size files were hashed, and the needle stays the original
+        assert_eq!(result.total_files, 3);
+        assert_eq!(result.duplicate_groups.len(), 1);
+        let paths: Vec<&Path> = result.duplicate_groups[0].files.iter().map(|f| f.path.as_path()).collect();
+        assert_eq!(paths, [needle.as_path(), temp_dir.path().join("sub").join("copy.txt").as_path()]);
+    }
+
+    #[tokio::test]
+    async fn test_files_from_list() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        for name in ["a.txt", "b.txt", "unlisted.txt", ".hidden.txt"] {
+            std::fs::write(root.join(name), "same").unwrap();
+        }
+        let list = root.join("list");
+        let entries = [root.join("a.txt"), root.join(".hidden.txt"), root.join("a.txt"), root.join("missing.txt"), root.to_path_buf()];
+        let mut data = Vec::new();
+        for entry in &entries {
+            data.extend_from_slice(entry.to_string_lossy().as_bytes());
+            data.push(0);
+        }
+        std::fs::write(&list, data).unwrap();
+
+        let config = ScannerConfig {
+            directory: root.to_path_buf(),
+            files_from: Some(list),
+            null_separated: true,
+            quiet: true,
+            ..Default::default()
+        };
+        let mut scanner = FileScanner::new(config);
+        let result = scanner.scan().await.unwrap();
+
+        // Duplicate entries collapse; the directory is skipped and the missing file is an error
+        assert_eq!(result.total_files, 2);
+        assert_eq!(result.file_errors, 1);
+        let paths: HashSet<&Path> = result.duplicate_groups[0].files.iter().map(|f| f.path.as_path()).collect();
+        assert_eq!(paths, HashSet::from([root.join("a.txt").as_path(), root.join(".hidden.txt").as_path()]));
+    }
+
+    #[tokio::test]
+    async fn test_group_ids() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        std::fs::write(temp_dir.path().join("a.txt"), "same").unwrap();
+        std::fs::write(temp_dir.path().join("b.txt"), "same").unwrap();
+        let config = ScannerConfig { directory: temp_dir.path().to_path_buf(), quiet: true, ..Default::default() };
+        let before = FileScanner::new(config.clone()).scan().await.unwrap();
+
+        // The group keeps its ID as copies come and go
+        std::fs::write(temp_dir.path().join("c.txt"), "same").unwrap();
+        std::fs::remove_file(temp_dir.path().join("a.txt")).unwrap();
+        let after = FileScanner::new(config.clone()).scan().await.unwrap();
+        assert_eq!(after.duplicate_groups[0].id, before.duplicate_groups[0].id);
+        assert_eq!(after.duplicate_groups[0].id.len(), 16);
+        assert_eq!(after.duplicate_groups[0].id, default_group_id(&after.duplicate_groups[0].hash));
+
+        // Another digest makes another group
+        let blake3 = FileScanner::new(ScannerConfig { hash_algo: crate::HashAlgorithm::Blake3, ..config }).scan().await.unwrap();
+        assert_ne!(blake3.duplicate_groups[0].id, before.duplicate_groups[0].id);
+        assert_ne!(group_id("", "abc", Some(Path::new("/a"))), group_id("", "abc", Some(Path::new("/b"))));
+    }
+
+    #[tokio::test]
+    async fn test_prefilter() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let log = |end: &str| format!("{}{}", "header\n".repeat(1000), end);
+        std::fs::write(temp_dir.path().join("a.log"), log("same")).unwrap();
+        std::fs::write(temp_dir.path().join("b.log"), log("same")).unwrap();
+        std::fs::write(temp_dir.path().join("c.log"), log("diff")).unwrap();
+        std::fs::write(temp_dir.path().join("d.txt"), "small").unwrap();
+        std::fs::write(temp_dir.path().join("e.txt"), "small").unwrap();
+        let config = ScannerConfig { directory: temp_dir.path().to_path_buf(), quiet: true, ..Default::default() };
+        let hashed = FileScanner::new(config.clone()).scan().await.unwrap();
+
+        // Files left alone by size or tail are not hashed, and the result is the same
+        let prefiltered = ScannerConfig { prefilter: true, prefilter_min_size: 1024, ..config };
+        let prefiltered = FileScanner::new(prefiltered).scan().await.unwrap();
+        assert_eq!(prefiltered.total_files, 5);
+        let hashes = |result: &ScanResult| result.duplicate_groups.iter().map(|g| g.hash.clone()).collect::<BTreeSet<_>>();
+        assert_eq!(hashes(&prefiltered), hashes(&hashed));
+        assert_eq!((prefiltered.total_duplicates, prefiltered.total_wasted_space), (hashed.total_duplicates, hashed.total_wasted_space));
+
+        // Where it does not apply, the scan keeps to bounded memory
+        let quick = FileScanner::new(ScannerConfig { mode: Mode::Quick, prefilter: true, ..ScannerConfig::default() });
+        assert!(!quick.prefilters() && quick.groups_in_partitions());
+    }
+
+    #[tokio::test]
+    async fn test_multiple_roots() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let (first, second) = (temp_dir.path().join("first"), temp_dir.path().join("second"));
+        std::fs::create_dir_all(first.join("nested")).unwrap();
+        std::fs::create_dir_all(&second).unwrap();
+        std::fs::write(first.join("a.txt"), "shared").unwrap();
+        std::fs::write(first.join("nested").join("b.txt"), "local").unwrap();
+        std::fs::write(first.join("c.txt"), "local").unwrap();
+        std::fs::write(second.join("a.txt"), "shared").unwrap();
+
+        // The nested root is not scanned twice
+        let config = ScannerConfig {
+            directory: first.clone(),
+            extra_roots: vec![second.clone(), first.join("nested")],
+            quiet: true,
+            ..Default::default()
+        };
+        let combined = FileScanner::new(config.clone()).scan().await.unwrap();
+        assert_eq!(combined.total_files, 4);
+        assert_eq!(combined.duplicate_groups.len(), 2);
+
+        // Spilling every file to disk groups them the same way
+        let spilled = FileScanner::new(ScannerConfig { spill_threshold: 1, ..config.clone() }).scan().await.unwrap();
+        assert_eq!(spilled.total_files, 4);
+        assert_eq!(spilled.duplicate_groups.len(), 2);
+
+        // Groups spill too when the output is written as they are read back
+        let streamed = ScannerConfig { spill_threshold: 1, output_format: OutputFormat::Json, ..config.clone() };
+        let streamed = FileScanner::new(streamed).scan().await.unwrap();
+        assert!(streamed.duplicate_groups.is_empty());
+        assert_eq!(streamed.group_count(), 2);
+        assert_eq!((streamed.total_duplicates, streamed.total_wasted_space), (combined.total_duplicates, combined.total_wasted_space));
+        // Which copy is the original is arbitrary for files written in the same instant
+        let directories = |result: &ScanResult| -> BTreeMap<PathBuf, usize> {
+            result.directory_stats.iter().map(|stat| (stat.path.clone(), stat.duplicate_files)).collect()
+        };
+        assert_eq!(directories(&streamed), directories(&combined));
+        let mut files = 0;
+        streamed.for_each_group(|group| {
+            files += group.files.len();
+            Ok(())
+        }).unwrap();
+        assert_eq!(files, 4);
+
+        let per_root = FileScanner::new(ScannerConfig { group_scope: GroupScope::PerRoot, ..config }).scan().await.unwrap();
+        assert!(per_root.duplicate_groups.is_empty());
+
+        let missing = ScannerConfig { extra_roots: vec![temp_dir.path().join("missing")], quiet: true, ..Default::default() };
+        assert!(FileScanner::new(missing).scan().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_file_timeout() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let path = temp_dir.path().join("slow.bin");
+        std::fs::write(&path, vec![0u8; 64 * 1024]).unwrap();
+
+        // 4 KiB reads at 4 KiB/s would take 16 seconds
+        let config = ScannerConfig {
+            directory: temp_dir.path().to_path_buf(),
+            buffer_size: 4096,
+            max_throughput: Some(4096),
+            file_timeout: Some(Duration::from_secs(1)),
+            quiet: true,
+            ..Default::default()
+        };
+        let scanner = FileScanner::new(config);
+        let start = Instant::now();
+        let result = scanner.hash_file(&path).await;
+        assert!(matches!(result, Err(DuplicateFinderError::FileTimeout { .. })), "{:?}", result);
+        assert!(start.elapsed() < Duration::from_secs(3));
+        assert_eq!(scanner.file_errors(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_deadline() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        std::fs::write(temp_dir.path().join("a.txt"), "same").unwrap();
+        std::fs::write(temp_dir.path().join("b.txt"), "same").unwrap();
+        let config = ScannerConfig { directory: temp_dir.path().to_path_buf(), quiet: true, ..Default::default() };
+
+        let expired = ScannerConfig { deadline: Some(Duration::ZERO), ..config.clone() };
+        let result = FileScanner::new(expired).scan().await.unwrap();
+        assert!(result.partial);
+        assert_eq!(result.total_files, 0);
+
+        let relaxed = ScannerConfig { deadline: Some(Duration::from_secs(3600)), ..config };
+        let result = FileScanner::new(relaxed).scan().await.unwrap();
+        assert!(!result.partial);
+        assert_eq!(result.duplicate_groups.len(), 1);
+    }
+
+    #[test]
+    fn test_compute_extension_stats() {
+        let groups = vec![DuplicateGroup {
+            id: String::new(),
+            hash: "abc".to_string(),
+            size: 10,
+            files: vec![file("/a/1.JPG"), file("/b/2.jpg"), file("/b/3")],
+            total_size: 30,
+            wasted_space: 20,
+        }];
+
+        let stats = compute_extension_stats(&groups);
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0], ExtensionStat { extension: "jpg".to_string(), files: 2, duplicate_files: 1, wasted_bytes: 10 });
+        assert_eq!(stats[1].extension, "(no extension)");
+    }
+}
@@ -0,0 +1,295 @@
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use ssh2::{CheckResult, FileStat, KnownHostFileKind, Session, Sftp};
+use tracing::{debug, warn};
+
+use crate::DuplicateFinderError;
+use crate::source::{self, FileSource, ListOptions, Listing, SourceEntry, SourceReader};
+
+/// URL scheme of remote directories reached over SSH
+pub const SCHEME: &str = "sftp://";
+
+/// Port used when the URL does not name one
+const DEFAULT_PORT: u16 = 22;
+
+/// Private keys tried, in order, when the SSH agent cannot authenticate
+const KEY_FILES: &[&str] = &["id_ed25519", "id_ecdsa", "id_rsa"];
+
+/// A remote directory named by an `sftp://[user@]host[:port]/path` URL
+///
+/// The path is absolute; `sftp://host/~/photos` names `photos` in the
+/// remote user's home directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SftpLocation {
+    user: String,
+    host: String,
+    port: u16,
+    root: PathBuf,
+
+    /// `[user@]host[:port]` exactly as given, used when reporting paths
+    authority: String,
+}
+
+impl SftpLocation {
+    /// Parses an `sftp://` URL; the user defaults to the local `$USER`
+    pub fn parse(url: &Path) -> Result<Self, DuplicateFinderError> {
+        let invalid = || DuplicateFinderError::Sftp(format!("invalid SFTP URL: {}", url.display()));
+        let rest = url.to_str().and_then(|u| u.strip_prefix(SCHEME)).ok_or_else(invalid)?;
+        let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+
+        let (user, host_port) = match authority.rsplit_once('@') {
+            Some((user, host_port)) => (user.to_string(), host_port),
+            None => (std::env::var("USER").unwrap_or_default(), authority),
+        };
+        let (host, port) = match host_port.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| invalid())?),
+            None => (host_port, DEFAULT_PORT),
+        };
+        if host.is_empty() || user.is_empty() {
+            return Err(invalid());
+        }
+
+        let root = match path.strip_prefix('~') {
+            Some(home) => PathBuf::from(".").join(home.trim_start_matches('/')),
+            None => Path::new("/").join(path),
+        };
+        Ok(Self { user, host: host.to_string(), port, root, authority: authority.to_string() })
+    }
+
+    /// Remote path of a file reported under [`SftpLocation::url`]
+    fn remote_path(&self, url: &Path) -> Result<PathBuf, DuplicateFinderError> {
+        let base = format!("{}{}", SCHEME, self.authority);
+        let path = url.to_str()
+            .and_then(|u| u.strip_prefix(&base))
+            .filter(|p| p.starts_with('/'))
+            .ok_or_else(|| DuplicateFinderError::Sftp(format!("{} is not on this server", url.display())))?;
+        Ok(match path.strip_prefix("/~") {
+            Some(home) => PathBuf::from(".").join(home.trim_start_matches('/')),
+            None => PathBuf::from(path),
+        })
+    }
+
+    /// URL under which a remote file is reported, e.g. `sftp://nas/srv/a.jpg`
+    pub fn url(&self, path: &Path) -> PathBuf {
+        match path.strip_prefix(".") {
+            Ok(relative) => PathBuf::from(format!("{}{}/~/{}", SCHEME, self.authority, relative.display())),
+            Err(_) => PathBuf::from(format!("{}{}{}", SCHEME, self.authority, path.display())),
+        }
+    }
+}
+
+/// An authenticated SFTP session
+///
+/// libssh2 is blocking, so every method must run off the async worker
+/// threads (e.g. in `spawn_blocking`)
+pub struct SftpConnection {
+    location: SftpLocation,
+    sftp: Sftp,
+    _session: Session,
+}
+
+impl SftpConnection {
+    /// Connects and authenticates with the SSH agent or the default keys
+    /// in `~/.ssh`
+    ///
+    /// The server's host key must already be listed in `~/.ssh/known_hosts`
+    pub fn connect(location: SftpLocation) -> Result<Self, DuplicateFinderError> {
+        let failed = |e: ssh2::Error| DuplicateFinderError::Sftp(format!("{}: {}", location.host, e));
+        let tcp = TcpStream::connect((location.host.as_str(), location.port)).map_err(|e| {
+            DuplicateFinderError::Sftp(format!("cannot connect to {}:{}: {}", location.host, location.port, e))
+        })?;
+        let mut session = Session::new().map_err(failed)?;
+        session.set_tcp_stream(tcp);
+        session.handshake().map_err(failed)?;
+
+        let ssh_dir = PathBuf::from(std::env::var_os("HOME").unwrap_or_default()).join(".ssh");
+        verify_host_key(&session, &location, &ssh_dir.join("known_hosts"))?;
+
+        if let Err(e) = session.userauth_agent(&location.user) {
+            debug!("SSH agent authentication failed: {}", e);
+        }
+        for name in KEY_FILES {
+            let key = ssh_dir.join(name);
+            if session.authenticated() || !key.exists() {
+                continue;
+            }
+            if let Err(e) = session.userauth_pubkey_file(&location.user, None, &key, None) {
+                debug!("Key {} was not accepted: {}", key.display(), e);
+            }
+        }
+        if !session.authenticated() {
+            return Err(DuplicateFinderError::Sftp(format!(
+                "authentication failed for {}@{} (tried the SSH agent and ~/.ssh keys)",
+                location.user, location.host
+            )));
+        }
+
+        let sftp = session.sftp().map_err(failed)?;
+        Ok(Self { location, sftp, _session: session })
+    }
+
+    /// Lists every regular file below the remote directory
+    ///
+    /// Symbolic links are skipped. Subdirectories that cannot be read are
+    /// logged and counted as listing errors
+    fn list(&self, options: &ListOptions) -> Result<Listing, DuplicateFinderError> {
+        let mut listing = Listing::default();
+        let mut pending = vec![(self.location.root.clone(), 0)];
+
+        while let Some((dir, depth)) = pending.pop() {
+            let entries = match self.sftp.readdir(&dir) {
+                Ok(entries) => entries,
+                // The scanned directory itself must be readable
+                Err(e) if depth == 0 => return Err(DuplicateFinderError::Sftp(format!("{}: {}", dir.display(), e))),
+                Err(e) => {
+                    warn!("Cannot read remote directory {}: {}", dir.display(), e);
+                    listing.errors += 1;
+                    continue;
+                }
+            };
+
+            for (path, stat) in entries {
+                let hidden = path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.'));
+                if hidden && !options.include_hidden {
+                    continue;
+                }
+                if stat.is_dir() {
+                    if options.max_depth == 0 || depth + 1 < options.max_depth {
+                        pending.push((path, depth + 1));
+                    }
+                } else if stat.is_file() {
+                    listing.entries.push(self.entry(&path, &stat));
+                }
+            }
+        }
+        Ok(listing)
+    }
+
+    fn entry(&self, path: &Path, stat: &FileStat) -> SourceEntry {
+        SourceEntry {
+            path: self.location.url(path),
+            size: stat.size.unwrap_or(0),
+            modified: UNIX_EPOCH + Duration::from_secs(stat.mtime.unwrap_or(0)),
+            created: None,
+            content_tag: None,
+        }
+    }
+}
+
+/// A directory on a server reached over SFTP
+///
+/// Files are reported as `sftp://[user@]host[:port]/path` URLs and read over
+/// a single SSH session
+pub struct SftpSource {
+    url: PathBuf,
+    connection: Arc<SftpConnection>,
+}
+
+impl SftpSource {
+    /// Connects to the server named by an `sftp://` URL
+    pub async fn connect(url: &Path) -> Result<Self, DuplicateFinderError> {
+        let location = SftpLocation::parse(url)?;
+        let connection = tokio::task::spawn_blocking(move || SftpConnection::connect(location))
+            .await
+            .map_err(|e| DuplicateFinderError::Io(std::io::Error::other(e)))??;
+        Ok(Self { url: url.to_path_buf(), connection: Arc::new(connection) })
+    }
+
+    /// Runs a blocking operation on the connection
+    async fn blocking<T: Send + 'static>(
+        &self,
+        operation: impl FnOnce(&SftpConnection) -> Result<T, DuplicateFinderError> + Send + 'static,
+    ) -> Result<T, DuplicateFinderError> {
+        let connection = Arc::clone(&self.connection);
+        tokio::task::spawn_blocking(move || operation(&connection))
+            .await
+            .map_err(|e| DuplicateFinderError::Io(std::io::Error::other(e)))?
+    }
+}
+
+#[async_trait]
+impl FileSource for SftpSource {
+    fn root(&self) -> &Path {
+        &self.url
+    }
+
+    async fn list(&self, options: &ListOptions) -> Result<Listing, DuplicateFinderError> {
+        let options = *options;
+        self.blocking(move |connection| connection.list(&options)).await
+    }
+
+    async fn metadata(&self, path: &Path) -> Result<SourceEntry, DuplicateFinderError> {
+        let remote = self.connection.location.remote_path(path)?;
+        self.blocking(move |connection| {
+            let stat = connection.sftp.stat(&remote)
+                .map_err(|e| DuplicateFinderError::Sftp(format!("{}: {}", remote.display(), e)))?;
+            Ok(connection.entry(&remote, &stat))
+        })
+        .await
+    }
+
+    async fn open(&self, path: &Path) -> Result<SourceReader, DuplicateFinderError> {
+        let remote = self.connection.location.remote_path(path)?;
+        let file = self.blocking(move |connection| {
+            connection.sftp.open(&remote)
+                .map_err(|e| DuplicateFinderError::Sftp(format!("{}: {}", remote.display(), e)))
+        })
+        .await?;
+        Ok(source::blocking_reader(file))
+    }
+}
+
+/// Rejects servers whose host key is unknown or changed
+fn verify_host_key(session: &Session, location: &SftpLocation, known_hosts_file: &Path) -> Result<(), DuplicateFinderError> {
+    let failed = |e: ssh2::Error| DuplicateFinderError::Sftp(format!("{}: {}", location.host, e));
+    let mut known_hosts = session.known_hosts().map_err(failed)?;
+    if known_hosts_file.exists() {
+        known_hosts.read_file(known_hosts_file, KnownHostFileKind::OpenSSH).map_err(failed)?;
+    }
+    let (key, _) = session.host_key()
+        .ok_or_else(|| DuplicateFinderError::Sftp(format!("{} sent no host key", location.host)))?;
+
+    match known_hosts.check_port(&location.host, location.port, key) {
+        CheckResult::Match => Ok(()),
+        CheckResult::NotFound => Err(DuplicateFinderError::Sftp(format!(
+            "host key of {} is not in {}; connect once with ssh to verify it",
+            location.host,
+            known_hosts_file.display()
+        ))),
+        CheckResult::Mismatch => Err(DuplicateFinderError::Sftp(format!(
+            "host key of {} does not match {}",
+            location.host,
+            known_hosts_file.display()
+        ))),
+        CheckResult::Failure => Err(DuplicateFinderError::Sftp(format!("cannot check the host key of {}", location.host))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sftp_locations() {
+        let location = SftpLocation::parse(Path::new("sftp://backup@nas.local:2222/srv/photos")).unwrap();
+        assert_eq!((location.user.as_str(), location.host.as_str(), location.port), ("backup", "nas.local", 2222));
+        assert_eq!(location.root, Path::new("/srv/photos"));
+        let url = location.url(Path::new("/srv/photos/a.jpg"));
+        assert_eq!(url, Path::new("sftp://backup@nas.local:2222/srv/photos/a.jpg"));
+        assert_eq!(location.remote_path(&url).unwrap(), Path::new("/srv/photos/a.jpg"));
+
+        let home = SftpLocation::parse(Path::new("sftp://me@host/~/photos")).unwrap();
+        assert_eq!(home.root, Path::new("./photos"));
+        let url = home.url(Path::new("./photos/a.jpg"));
+        assert_eq!(url, Path::new("sftp://me@host/~/photos/a.jpg"));
+        assert_eq!(home.remote_path(&url).unwrap(), Path::new("./photos/a.jpg"));
+        assert!(home.remote_path(Path::new("sftp://other/x")).is_err());
+
+        assert!(SftpLocation::parse(Path::new("sftp://me@host:port/x")).is_err());
+        assert!(SftpLocation::parse(Path::new("sftp:///x")).is_err());
+    }
+}
@@ -0,0 +1,236 @@
+use std::path::Path;
+
+use image::DynamicImage;
+use image::imageops::FilterType;
+use sha2::{Digest, Sha256};
+use tracing::debug;
+
+use crate::{DuplicateFinderError, DuplicateGroup, FileInfo};
+
+/// Extensions of image formats that can be decoded for perceptual hashing
+pub const IMAGE_EXTENSIONS: &[&str] = &["bmp", "gif", "jpeg", "jpg", "png", "tif", "tiff", "webp"];
+
+/// Returns true when the path looks like a decodable image
+pub fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// Reads an image's metadata and perceptual hash
+///
+/// The hash is stored as 16 hex digits in [`FileInfo::hash`]. Decoding is
+/// CPU-bound, so it runs on the blocking thread pool
+pub async fn perceptual_file_info(path: &Path) -> Result<FileInfo, DuplicateFinderError> {
+    let mut file_info = FileInfo::stat(path).await?;
+
+    let owned = path.to_path_buf();
+    let hash = tokio::task::spawn_blocking(move || difference_hash(&owned))
+        .await
+        .map_err(|e| DuplicateFinderError::Io(std::io::Error::other(e)))?
+        .ok_or_else(|| DuplicateFinderError::HashCalculationError {
+            path: path.to_path_buf(),
+        })?;
+
+    file_info.hash = format!("{:016x}", hash);
+    Ok(file_info)
+}
+
+/// Reads an image's metadata and a SHA-256 digest of its decoded pixels
+///
+/// Metadata segments (EXIF, XMP, ICC profiles, comments) never reach the
+/// digest, so a photo whose tags were rewritten still matches the original.
+/// The dimensions and pixel layout are part of the digest
+pub async fn pixel_file_info(path: &Path) -> Result<FileInfo, DuplicateFinderError> {
+    let mut file_info = FileInfo::stat(path).await?;
+
+    let owned = path.to_path_buf();
+    file_info.hash = tokio::task::spawn_blocking(move || pixel_digest(&owned))
+        .await
+        .map_err(|e| DuplicateFinderError::Io(std::io::Error::other(e)))?
+        .ok_or_else(|| DuplicateFinderError::HashCalculationError {
+            path: path.to_path_buf(),
+        })?;
+
+    Ok(file_info)
+}
+
+/// Decodes an image file, logging files that are not valid images
+fn decode(path: &Path) -> Option<DynamicImage> {
+    image::open(path)
+        .inspect_err(|e| debug!("Cannot decode image {}: {}", path.display(), e))
+        .ok()
+}
+
+/// Hashes the decoded pixels of an image file
+fn pixel_digest(path: &Path) -> Option<String> {
+    let image = decode(path)?;
+
+    let mut hasher = Sha256::new();
+    hasher.update(image.width().to_le_bytes());
+    hasher.update(image.height().to_le_bytes());
+    hasher.update(format!("{:?}", image.color()).as_bytes());
+    hasher.update(image.as_bytes());
+    Some(format!("{:x}", hasher.finalize()))
+}
+
+/// Computes a 64-bit difference hash (dHash) of an image file
+///
+/// The image is reduced to a 9x8 grayscale thumbnail and each bit records
+/// whether a pixel is brighter than its right neighbour. Resizing,
+/// re-encoding and small edits flip only a few bits, so visually identical
+/// images end up within a small Hamming distance
+fn difference_hash(path: &Path) -> Option<u64> {
+    let image = decode(path)?;
+    let thumbnail = image.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    Some(thumbnail_hash(thumbnail.as_raw()))
+}
+
+/// Difference hash of a 9x8 row-major grayscale thumbnail
+pub(crate) fn thumbnail_hash(pixels: &[u8]) -> u64 {
+    let mut hash = 0u64;
+    for row in pixels.chunks_exact(9).take(8) {
+        for pair in row.windows(2) {
+            hash = (hash << 1) | u64::from(pair[0] > pair[1]);
+        }
+    }
+    hash
+}
+
+/// Number of differing bits between two perceptual hashes
+fn distance(a: &str, b: &str) -> Option<u32> {
+    let a = u64::from_str_radix(a, 16).ok()?;
+    let b = u64::from_str_radix(b, 16).ok()?;
+    Some((a ^ b).count_ones())
+}
+
+/// Groups images whose perceptual hashes differ by at most `max_distance` bits
+///
+/// See [`cluster`] for how groups are formed and ordered
+pub fn group_similar(file_infos: Vec<FileInfo>, max_distance: u32) -> Vec<DuplicateGroup> {
+    cluster(file_infos, |a, b| distance(&a.hash, &b.hash).is_some_and(|d| d <= max_distance))
+}
+
+/// Groups files connected by a similarity relation
+///
+/// Similarity is transitive within a group: two files end up together when
+/// a chain of close matches connects them. Sizes differ within a group, so
+/// the largest file (usually the best quality) comes first and is treated
+/// as the original; `size` is that file's size and `wasted_space` counts
+/// every other file
+pub(crate) fn cluster(file_infos: Vec<FileInfo>, similar: impl Fn(&FileInfo, &FileInfo) -> bool) -> Vec<DuplicateGroup> {
+    // Union-find over all pairs; fine for the tens of thousands of media
+    // files in a typical library
+    let mut parent: Vec<usize> = (0..file_infos.len()).collect();
+    fn find(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+
+    for i in 0..file_infos.len() {
+        for j in (i + 1)..file_infos.len() {
+            if similar(&file_infos[i], &file_infos[j]) {
+                let (a, b) = (find(&mut parent, i), find(&mut parent, j));
+                parent[a] = b;
+            }
+        }
+    }
+
+    let mut clusters: std::collections::HashMap<usize, Vec<FileInfo>> = std::collections::HashMap::new();
+    for (index, file_info) in file_infos.into_iter().enumerate() {
+        let root = find(&mut parent, index);
+        clusters.entry(root).or_default().push(file_info);
+    }
+
+    clusters
+        .into_values()
+        .map(|mut files| {
+            files.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.modified.cmp(&b.modified)));
+            let size = files[0].size;
+            let total_size: u64 = files.iter().map(|f| f.size).sum();
+            DuplicateGroup {
+                id: String::new(),
+                hash: files[0].hash.clone(),
+                size,
+                total_size,
+                wasted_space: total_size - size,
+                files,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Rgb};
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_similar_images_group_together() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let gradient = ImageBuffer::from_fn(128, 96, |x, y| Rgb([(x * 2) as u8, (y * 2) as u8, ((x + y) % 256) as u8]));
+        let stripes = ImageBuffer::from_fn(128, 96, |x, _| if (x / 8) % 2 == 0 { Rgb([255u8, 255, 255]) } else { Rgb([0, 0, 0]) });
+
+        let original = temp_dir.path().join("original.png");
+        let resized = temp_dir.path().join("resized.jpg");
+        let different = temp_dir.path().join("different.png");
+        gradient.save(&original).unwrap();
+        image::imageops::resize(&gradient, 64, 48, FilterType::Triangle).save(&resized).unwrap();
+        stripes.save(&different).unwrap();
+
+        let mut files = Vec::new();
+        for path in [&original, &resized, &different] {
+            files.push(perceptual_file_info(path).await.unwrap());
+        }
+        assert!(perceptual_file_info(&temp_dir.path().join("missing.png")).await.is_err());
+
+        let groups: Vec<DuplicateGroup> = group_similar(files, 6).into_iter().filter(|g| g.files.len() > 1).collect();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].files.len(), 2);
+        assert_eq!(groups[0].files[0].path, original);
+        assert!(is_image(&resized) && !is_image(Path::new("notes.txt")));
+    }
+
+    #[tokio::test]
+    async fn test_pixel_digest_ignores_metadata() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let pixels = ImageBuffer::from_fn(16, 16, |x, y| Rgb([x as u8 * 16, y as u8 * 16, 0]));
+
+        let original = temp_dir.path().join("original.png");
+        pixels.save(&original).unwrap();
+
+        // Same pixels with an extra text chunk, as a photo manager might add
+        let bytes = std::fs::read(&original).unwrap();
+        let mut chunk = b"tEXtComment\0retagged".to_vec();
+        let length = (chunk.len() as u32 - 4).to_be_bytes();
+        let crc = crc32(&chunk).to_be_bytes();
+        let mut tagged = bytes[..33].to_vec();
+        tagged.extend_from_slice(&length);
+        tagged.append(&mut chunk);
+        tagged.extend_from_slice(&crc);
+        tagged.extend_from_slice(&bytes[33..]);
+        let retagged = temp_dir.path().join("retagged.png");
+        std::fs::write(&retagged, tagged).unwrap();
+
+        let a = pixel_file_info(&original).await.unwrap();
+        let b = pixel_file_info(&retagged).await.unwrap();
+        assert_ne!(a.size, b.size);
+        assert_eq!(a.hash, b.hash);
+    }
+
+    /// CRC-32 as used by PNG chunks
+    fn crc32(data: &[u8]) -> u32 {
+        let mut crc = 0xffff_ffffu32;
+        for &byte in data {
+            crc ^= u32::from(byte);
+            for _ in 0..8 {
+                crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+            }
+        }
+        !crc
+    }
+}
@@ -0,0 +1,558 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::StreamExt;
+use futures_util::stream::FuturesUnordered;
+use tokio::fs;
+use tokio::io::AsyncRead;
+use tokio_util::io::StreamReader;
+use tracing::{debug, info, warn};
+
+use crate::hashing::{self, HashFunction};
+use crate::io_backend::{LocalIo, TokioIo};
+use crate::pause::PauseSwitch;
+use crate::progress::Discovery;
+use crate::{DuplicateFinderError, ScannerConfig};
+
+/// A file reported by a [`FileSource`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceEntry {
+    /// Path under which the file is reported and opened, e.g.
+    /// `/home/me/a.jpg` or `s3://bucket/photos/a.jpg`
+    pub path: PathBuf,
+    /// Size in bytes
+    pub size: u64,
+    /// Last modification time
+    pub modified: SystemTime,
+    /// Creation time, where the source knows it
+    pub created: Option<SystemTime>,
+    /// Identifier that is equal for equal contents, when the source knows
+    /// one (e.g. the MD5 ETag of a single-part S3 upload)
+    pub content_tag: Option<String>,
+}
+
+/// Discovery rules shared by every source
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ListOptions {
+    /// Include files and directories whose name starts with a dot
+    pub include_hidden: bool,
+    /// Follow symbolic links (local files only)
+    pub follow_symlinks: bool,
+    /// Maximum directory depth (0 for unlimited)
+    pub max_depth: usize,
+    /// Directories of one tree listed at the same time (local files only;
+    /// 0 reads them one by one)
+    pub walk_concurrency: usize,
+}
+
+impl ListOptions {
+    /// Discovery rules requested on the command line
+    pub fn from_config(config: &ScannerConfig) -> Self {
+        Self {
+            include_hidden: config.include_hidden,
+            follow_symlinks: config.follow_symlinks,
+            max_depth: config.max_depth,
+            walk_concurrency: config.walk_concurrency,
+        }
+    }
+}
+
+/// Files found by [`FileSource::list`]
+#[derive(Debug, Default)]
+pub struct Listing {
+    /// Every regular file passing the discovery rules
+    pub entries: Vec<SourceEntry>,
+    /// Number of directories and entries that could not be read
+    pub errors: usize,
+}
+
+/// Streaming reader over a file's contents
+pub type SourceReader = Box<dyn AsyncRead + Send + Unpin>;
+
+/// Where files are discovered and read from
+///
+/// The scanner lists a source once, then opens the files whose contents it
+/// needs. [`LocalSource`] reads the local filesystem; the `s3` and `sftp`
+/// features add object storage and SSH servers, and other crates can plug in
+/// their own sources with [`FileScanner::with_source`](crate::FileScanner::with_source)
+#[async_trait]
+pub trait FileSource: Send + Sync {
+    /// Location this source scans, as given by the user
+    fn root(&self) -> &Path;
+
+    /// True when entry paths are local files that content-aware comparison
+    /// modes may read directly; other sources only support exact comparison
+    fn is_local(&self) -> bool {
+        false
+    }
+
+    /// Lists every regular file below the root
+    async fn list(&self, options: &ListOptions) -> Result<Listing, DuplicateFinderError>;
+
+    /// Reads the metadata of a single file
+    async fn metadata(&self, path: &Path) -> Result<SourceEntry, DuplicateFinderError>;
+
+    /// Opens a file for streaming reads
+    async fn open(&self, path: &Path) -> Result<SourceReader, DuplicateFinderError>;
+}
+
+/// The local filesystem
+pub struct LocalSource {
+    root: PathBuf,
+    io: Arc<dyn LocalIo>,
+    pause: PauseSwitch,
+    discovery: Option<Arc<Discovery>>,
+}
+
+impl LocalSource {
+    /// Scans the directory tree below `root`
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into(), io: Arc::new(TokioIo), pause: PauseSwitch::default(), discovery: None }
+    }
+
+    /// Stats directory entries through another I/O backend
+    pub(crate) fn with_io(mut self, io: Arc<dyn LocalIo>) -> Self {
+        self.io = io;
+        self
+    }
+
+    /// Lists no further directories while `pause` is paused
+    pub fn with_pause(mut self, pause: PauseSwitch) -> Self {
+        self.pause = pause;
+        self
+    }
+
+    /// Counts every directory listed and the files in it
+    pub(crate) fn with_discovery(mut self, discovery: Arc<Discovery>) -> Self {
+        self.discovery = Some(discovery);
+        self
+    }
+
+    /// Iterative directory traversal starting `root_depth` levels below
+    /// the scan root, so the depth limit still applies to subtrees
+    pub(crate) async fn walk(&self, root_depth: usize, options: &ListOptions) -> Listing {
+        let mut entries = Vec::new();
+        let errors = self.walk_each(root_depth, options, |found| {
+            entries.extend(found);
+            std::future::ready(true)
+        }).await;
+        Listing { entries, errors }
+    }
+
+    /// Traverses the tree like [`LocalSource::walk`], handing the files of
+    /// each directory to `emit` as soon as it is read; the walk ends early
+    /// when `emit` returns false
+    ///
+    /// Uses a stack instead of recursion to avoid async recursion issues
+    /// and keep stack usage under control. Up to
+    /// [`ListOptions::walk_concurrency`] directories from the stack are read
+    /// at the same time, so wide trees on high-latency storage keep several
+    /// listings in flight. Returns the number of directories and entries
+    /// that could not be read
+    pub(crate) async fn walk_each<F>(&self, root_depth: usize, options: &ListOptions, mut emit: impl FnMut(Vec<SourceEntry>) -> F) -> usize
+    where
+        F: Future<Output = bool>,
+    {
+        let mut errors = 0;
+        let mut files_found = 0;
+        let fanout = options.walk_concurrency.max(1);
+
+        // Each element contains (directory_path, current_depth)
+        let mut dir_stack = vec![(self.root.clone(), root_depth)];
+        let mut reading = FuturesUnordered::new();
+
+        // Debug and monitoring statistics
+        let mut directories_processed = 0;
+        let mut max_stack_size = 0;
+
+        loop {
+            // Protection against accidental infinite recursion
+            const MAX_PENDING_DIRS: usize = 10000;
+            if dir_stack.len() > MAX_PENDING_DIRS {
+                warn!(
+                    "Too many pending directories ({}), possible deep directory structure. Limiting scan.",
+                    dir_stack.len()
+                );
+                break;
+            }
+
+            self.pause.wait().await;
+            while reading.len() < fanout
+                && let Some((current_dir, current_depth)) = dir_stack.pop()
+            {
+                directories_processed += 1;
+                max_stack_size = max_stack_size.max(dir_stack.len());
+
+                // Check depth limit
+                if options.max_depth > 0 && current_depth >= options.max_depth {
+                    debug!("Max depth {} reached at {}", options.max_depth, current_dir.display());
+                    continue;
+                }
+
+                debug!("Scanning directory: {} (depth: {}, stack: {})",
+                    current_dir.display(), current_depth, dir_stack.len());
+                reading.push(self.read_directory(current_dir, current_depth, options));
+            }
+
+            let Some(directory) = reading.next().await else {
+                break;
+            };
+            errors += directory.errors;
+            if let Some(discovery) = &self.discovery {
+                discovery.listed(directory.files.len());
+            }
+            if !directory.files.is_empty() {
+                files_found += directory.files.len();
+                if !emit(directory.files).await {
+                    break;
+                }
+            }
+
+            // Add new directories to the stack
+            // Reverse order for breadth-first traversal
+            for dir_entry in directory.directories.into_iter().rev() {
+                dir_stack.push(dir_entry);
+            }
+        }
+
+        info!(
+            root = %self.root.display(),
+            directories = directories_processed,
+            files = files_found,
+            max_stack_size,
+            "Directory scan completed"
+        );
+
+        errors
+    }
+
+    /// Lists one directory `depth` levels below the scan root
+    async fn read_directory(&self, current_dir: PathBuf, current_depth: usize, options: &ListOptions) -> DirectoryListing {
+        let mut listing = DirectoryListing::default();
+
+        // Try to read directory contents
+        let mut read_dir = match fs::read_dir(&current_dir).await {
+            Ok(rd) => rd,
+            Err(e) => {
+                warn!("Cannot read directory {}: {}", current_dir.display(), e);
+                listing.errors += 1;
+                return listing; // Continue with other directories
+            }
+        };
+
+        // Entries are stated together once the directory is read
+        let mut candidates = Vec::new();
+
+        // Process each entry in the directory
+        while let Some(entry_result) = read_dir.next_entry().await.transpose() {
+            let entry = match entry_result {
+                Ok(entry) => entry,
+                Err(e) => {
+                    warn!("Error reading directory entry in {}: {}", current_dir.display(), e);
+                    listing.errors += 1;
+                    continue;
+                }
+            };
+
+            let path = entry.path();
+
+            // Check if hidden files should be skipped
+            if !options.include_hidden && is_hidden(&path) {
+                debug!("Skipping hidden path: {}", path.display());
+                continue;
+            }
+
+            // The entry type usually comes with the directory listing,
+            // so only files need a stat call of their own
+            let file_type = match entry.file_type().await {
+                Ok(file_type) => Some(file_type),
+                Err(e) => {
+                    debug!("Cannot read the type of {}: {}", path.display(), e);
+                    None
+                }
+            };
+
+            // Check symbolic links
+            if file_type.is_some_and(|t| t.is_symlink()) && !options.follow_symlinks {
+                debug!("Skipping symlink: {}", path.display());
+                continue;
+            }
+
+            if file_type.is_some_and(|t| t.is_dir()) {
+                listing.directories.push((path, current_depth + 1));
+            } else {
+                candidates.push(path);
+            }
+        }
+
+        let stats = match self.io.stat_batch(candidates.clone()).await {
+            Ok(stats) => stats,
+            Err(e) => {
+                warn!("Cannot read metadata in {}: {}", current_dir.display(), e);
+                listing.errors += 1;
+                return listing;
+            }
+        };
+        for (path, stat) in candidates.into_iter().zip(stats) {
+            let stat = match stat {
+                Ok(stat) => stat,
+                Err(e) => {
+                    warn!("Cannot read metadata for {}: {}", path.display(), e);
+                    listing.errors += 1;
+                    continue;
+                }
+            };
+
+            if stat.is_dir {
+                // Buffer the directory for later scanning
+                listing.directories.push((path, current_depth + 1));
+            } else if stat.is_file {
+                listing.files.push(SourceEntry { path, size: stat.len, modified: stat.modified, created: stat.created, content_tag: None });
+            }
+        }
+        listing
+    }
+}
+
+/// What [`LocalSource::read_directory`] found in one directory
+#[derive(Default)]
+struct DirectoryListing {
+    files: Vec<SourceEntry>,
+    /// Subdirectories with their depth below the scan root
+    directories: Vec<(PathBuf, usize)>,
+    errors: usize,
+}
+
+#[async_trait]
+impl FileSource for LocalSource {
+    fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn is_local(&self) -> bool {
+        true
+    }
+
+    async fn list(&self, options: &ListOptions) -> Result<Listing, DuplicateFinderError> {
+        if !fs::try_exists(&self.root).await? {
+            return Err(DuplicateFinderError::PathNotFound { path: self.root.clone() });
+        }
+        Ok(self.walk(0, options).await)
+    }
+
+    async fn metadata(&self, path: &Path) -> Result<SourceEntry, DuplicateFinderError> {
+        let metadata = fs::metadata(path).await?;
+        Ok(local_entry(path.to_path_buf(), &metadata))
+    }
+
+    async fn open(&self, path: &Path) -> Result<SourceReader, DuplicateFinderError> {
+        Ok(Box::new(fs::File::open(path).await?))
+    }
+}
+
+pub(crate) fn local_entry(path: PathBuf, metadata: &std::fs::Metadata) -> SourceEntry {
+    SourceEntry {
+        path,
+        size: metadata.len(),
+        modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+        created: metadata.created().ok(),
+        content_tag: None,
+    }
+}
+
+/// Checks if a path is hidden
+pub(crate) fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with('.'))
+}
+
+/// Returns true when a path is an `s3://bucket/prefix` URL rather than a local path
+pub fn is_s3_url(path: &Path) -> bool {
+    path.to_str().is_some_and(|p| p.starts_with("s3://"))
+}
+
+/// Returns true when a path names remote storage (`s3://` or `sftp://`)
+pub fn is_remote_url(path: &Path) -> bool {
+    is_s3_url(path) || path.to_str().is_some_and(|p| p.starts_with("sftp://"))
+}
+
+/// Opens the source a scan location names: an `s3://` or `sftp://` URL,
+/// or else a local directory
+pub async fn open_source(location: &Path) -> Result<Arc<dyn FileSource>, DuplicateFinderError> {
+    if is_s3_url(location) {
+        #[cfg(feature = "s3")]
+        return Ok(Arc::new(crate::s3::S3Source::open(location)?));
+        #[cfg(not(feature = "s3"))]
+        return Err(DuplicateFinderError::ObjectStorage(
+            "S3 scanning is not available: rebuild with the `s3` feature enabled".to_string(),
+        ));
+    }
+    if is_remote_url(location) {
+        #[cfg(feature = "sftp")]
+        return Ok(Arc::new(crate::sftp::SftpSource::connect(location).await?));
+        #[cfg(not(feature = "sftp"))]
+        return Err(DuplicateFinderError::Sftp(
+            "SFTP scanning is not available: rebuild with the `sftp` feature enabled".to_string(),
+        ));
+    }
+    Ok(Arc::new(LocalSource::new(location)))
+}
+
+/// Digest of a file read through its source
+pub async fn hash_entry(source: &dyn FileSource, path: &Path, function: &dyn HashFunction) -> Result<String, DuplicateFinderError> {
+    let mut reader = source.open(path).await?;
+    Ok(hashing::hash_stream(&mut reader, function).await?.0)
+}
+
+/// Placeholder digest of a file that was not read
+///
+/// Unique per path, so such files never form a group
+pub fn unhashed_digest(entry: &SourceEntry) -> String {
+    format!("unhashed:{}", entry.path.display())
+}
+
+/// Decides which listed files must be read to be compared
+///
+/// Returns, for every entry, the index of the entry whose contents are
+/// hashed in its place, or `None` when it need not be read at all:
+///
+/// * Entries whose size matches no other entry and no local file
+///   (`local_sizes`) cannot have a duplicate. With `local_sizes` of `None`
+///   every entry is read, e.g. for checksum manifests.
+/// * Entries sharing size and content tag have the same content, so one
+///   read serves all of them.
+/// * Within a size, entries whose content tags all differ only need to be
+///   read when a local file of that size could match them
+pub fn plan_downloads(entries: &[SourceEntry], local_sizes: Option<&HashSet<u64>>) -> Vec<Option<usize>> {
+    let mut by_size: HashMap<u64, Vec<usize>> = HashMap::new();
+    for (index, entry) in entries.iter().enumerate() {
+        by_size.entry(entry.size).or_default().push(index);
+    }
+
+    let mut plan = vec![None; entries.len()];
+    for (size, indices) in by_size {
+        let local = local_sizes.is_none_or(|sizes| sizes.contains(&size));
+        if indices.len() == 1 && !local {
+            continue;
+        }
+
+        let mut representatives: HashMap<&str, usize> = HashMap::new();
+        for &index in &indices {
+            plan[index] = Some(match entries[index].content_tag.as_deref() {
+                Some(tag) => *representatives.entry(tag).or_insert(index),
+                None => index,
+            });
+        }
+
+        // Distinct content tags prove the entries differ from each other
+        let all_distinct = indices.iter().all(|&i| entries[i].content_tag.is_some() && plan[i] == Some(i));
+        if all_distinct && !local {
+            indices.iter().for_each(|&i| plan[i] = None);
+        }
+    }
+    plan
+}
+
+/// Adapts a blocking reader (e.g. a file on an SFTP server) to
+/// [`SourceReader`], reading it on the blocking thread pool
+pub fn blocking_reader(mut reader: impl Read + Send + 'static) -> SourceReader {
+    let (sender, receiver) = tokio::sync::mpsc::channel::<std::io::Result<Bytes>>(4);
+    tokio::task::spawn_blocking(move || {
+        let mut buffer = vec![0u8; 65536];
+        loop {
+            let chunk = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(bytes_read) => Ok(Bytes::copy_from_slice(&buffer[..bytes_read])),
+                Err(e) => Err(e),
+            };
+            let failed = chunk.is_err();
+            if sender.blocking_send(chunk).is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    let chunks = futures_util::stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    });
+    Box::new(StreamReader::new(Box::pin(chunks)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::{Digest, Sha256};
+    use std::time::UNIX_EPOCH;
+    use tokio::io::AsyncReadExt;
+    use tempfile::tempdir;
+
+    fn entry(path: &str, size: u64, tag: Option<&str>) -> SourceEntry {
+        SourceEntry { path: path.into(), size, modified: UNIX_EPOCH, created: None, content_tag: tag.map(str::to_string) }
+    }
+
+    #[tokio::test]
+    async fn test_sources_and_download_plan() {
+        let entries = [
+            entry("a", 10, Some("aaa")),
+            entry("b", 10, Some("aaa")),
+            entry("c", 10, Some("ccc")),
+            entry("unique", 99, Some("uuu")),
+            entry("untagged-1", 20, None),
+            entry("untagged-2", 20, None),
+            entry("distinct-1", 30, Some("d1")),
+            entry("distinct-2", 30, Some("d2")),
+        ];
+        let plan = plan_downloads(&entries, Some(&HashSet::new()));
+        assert_eq!(plan, [Some(0), Some(0), Some(2), None, Some(4), Some(5), None, None]);
+
+        // A local file of the same size needs the contents to compare against
+        let plan = plan_downloads(&entries, Some(&HashSet::from([30, 99])));
+        assert_eq!(&plan[3..], [Some(3), Some(4), Some(5), Some(6), Some(7)]);
+        assert!(plan_downloads(&entries, None).iter().all(Option::is_some));
+
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        std::fs::create_dir_all(temp_dir.path().join("sub/.git")).unwrap();
+        std::fs::write(temp_dir.path().join("sub/a.txt"), "hello").unwrap();
+        std::fs::write(temp_dir.path().join("sub/.git/config"), "hidden").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(temp_dir.path().join("sub"), temp_dir.path().join("link")).unwrap();
+
+        let source = open_source(temp_dir.path()).await.unwrap();
+        let listing = source.list(&ListOptions::default()).await.unwrap();
+        assert_eq!(listing.entries.len(), 1);
+        assert_eq!(listing.entries[0].size, 5);
+        // Hashing trusts the metadata read while walking
+        let metadata = std::fs::metadata(temp_dir.path().join("sub/a.txt")).unwrap();
+        assert_eq!(listing.entries[0].modified, metadata.modified().unwrap());
+        assert_eq!(listing.entries[0].created, metadata.created().ok());
+        let expected = format!("{:x}", Sha256::digest(b"hello"));
+        assert_eq!(hash_entry(source.as_ref(), &listing.entries[0].path, &crate::HashAlgorithm::Sha256).await.unwrap(), expected);
+        assert_eq!(source.list(&ListOptions { include_hidden: true, ..Default::default() }).await.unwrap().entries.len(), 2);
+        assert_eq!(source.list(&ListOptions { max_depth: 1, ..Default::default() }).await.unwrap().entries.len(), 0);
+
+        // Directories listed concurrently find the same files
+        for index in 0..50 {
+            let directory = temp_dir.path().join(format!("wide/{}/deeper", index));
+            std::fs::create_dir_all(&directory).unwrap();
+            std::fs::write(directory.join("file"), "x").unwrap();
+        }
+        let sequential = source.list(&ListOptions::default()).await.unwrap();
+        let concurrent = source.list(&ListOptions { walk_concurrency: 8, ..Default::default() }).await.unwrap();
+        let paths = |listing: Listing| listing.entries.into_iter().map(|e| e.path).collect::<HashSet<_>>();
+        assert_eq!(concurrent.errors, 0);
+        assert_eq!(paths(concurrent), paths(sequential));
+        let limited = source.list(&ListOptions { walk_concurrency: 8, max_depth: 3, ..Default::default() }).await.unwrap();
+        assert_eq!(limited.entries.len(), 1);
+
+        let mut reader = blocking_reader(std::io::Cursor::new(b"hello".to_vec()));
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content).await.unwrap();
+        assert_eq!(content, b"hello");
+    }
+}
@@ -0,0 +1,411 @@
+use std::fs::File;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::scanner::path_from_bytes;
+use crate::{DuplicateGroup, FileInfo};
+
+/// Number of spill files; each holds the files of a slice of comparison keys
+const PARTITIONS: usize = 64;
+
+/// Hashed files of a scan, kept in memory up to a limit and spilled to
+/// temporary files beyond it
+///
+/// Spilled files are partitioned by comparison key, so every file that
+/// could be grouped with another ends up in the same partition and each
+/// partition can be grouped on its own. The spill files are removed when
+/// the spool is dropped
+pub(crate) struct FileSpool {
+    limit: usize,
+    directory: Option<PathBuf>,
+    memory: Vec<FileInfo>,
+    partitions: Option<Partitions>,
+    len: usize,
+}
+
+impl FileSpool {
+    /// Keeps up to `limit` files in memory and spills the rest below
+    /// `directory` (the system temporary directory when `None`)
+    pub(crate) fn new(limit: usize, directory: Option<PathBuf>) -> Self {
+        Self { limit: limit.max(1), directory, memory: Vec::new(), partitions: None, len: 0 }
+    }
+
+    /// Number of files added so far
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) async fn push(&mut self, file: FileInfo) -> io::Result<()> {
+        self.memory.push(file);
+        self.len += 1;
+        if self.memory.len() >= self.limit {
+            self.spill().await?;
+        }
+        Ok(())
+    }
+
+    /// Moves the files in memory to the spill files on the blocking pool
+    async fn spill(&mut self) -> io::Result<()> {
+        let files = std::mem::take(&mut self.memory);
+        let mut partitions = match self.partitions.take() {
+            Some(partitions) => partitions,
+            None => {
+                let partitions = Partitions::create(self.directory.as_deref())?;
+                info!("More than {} files hashed, spilling them to {}", self.limit, partitions.directory.path().display());
+                partitions
+            }
+        };
+        let partitions = tokio::task::spawn_blocking(move || partitions.write(files).map(|()| partitions))
+            .await
+            .map_err(io::Error::other)??;
+        self.partitions = Some(partitions);
+        Ok(())
+    }
+
+    /// Groups the files with `group`, holding one partition in memory at a
+    /// time, and collects the duplicate groups in `groups`
+    pub(crate) async fn group_with(
+        mut self,
+        group: impl Fn(Vec<FileInfo>) -> Vec<DuplicateGroup>,
+        mut groups: GroupSpool,
+    ) -> io::Result<Groups> {
+        if self.partitions.is_none() {
+            groups.push(group(self.memory)).await?;
+            return groups.finish().await;
+        }
+        self.spill().await?;
+        let partitions = Arc::new(self.partitions.take().expect("files were just spilled"));
+
+        for index in 0..PARTITIONS {
+            let partitions = Arc::clone(&partitions);
+            let files = tokio::task::spawn_blocking(move || partitions.read(index))
+                .await
+                .map_err(io::Error::other)??;
+            groups.push(group(files)).await?;
+        }
+        groups.finish().await
+    }
+}
+
+/// Duplicate groups of a scan, kept in memory until they hold a given
+/// number of files and spilled to a temporary file beyond it
+///
+/// Groups of a single file are dropped on the way in: most files of a large
+/// tree have no duplicate, and only real groups are worth keeping
+pub(crate) struct GroupSpool {
+    limit: usize,
+    directory: Option<PathBuf>,
+    memory: Vec<DuplicateGroup>,
+    held: usize,
+    spilled: Option<SpilledGroups>,
+}
+
+/// Duplicate groups collected by a [`GroupSpool`]
+pub(crate) enum Groups {
+    Memory(Vec<DuplicateGroup>),
+    Spilled(SpilledGroups),
+}
+
+impl GroupSpool {
+    /// Keeps groups of up to `limit` files in memory and spills them below
+    /// `directory` (the system temporary directory when `None`) beyond it;
+    /// `None` keeps every group in memory
+    pub(crate) fn new(limit: Option<usize>, directory: Option<PathBuf>) -> Self {
+        let limit = limit.map_or(usize::MAX, |limit| limit.max(1));
+        Self { limit, directory, memory: Vec::new(), held: 0, spilled: None }
+    }
+
+    pub(crate) async fn push(&mut self, groups: Vec<DuplicateGroup>) -> io::Result<()> {
+        for group in groups.into_iter().filter(|g| g.files.len() > 1) {
+            self.held += group.files.len();
+            self.memory.push(group);
+        }
+        if self.held >= self.limit || self.spilled.is_some() {
+            self.spill().await?;
+        }
+        Ok(())
+    }
+
+    /// Moves the groups in memory to the spill file on the blocking pool
+    async fn spill(&mut self) -> io::Result<()> {
+        let groups = std::mem::take(&mut self.memory);
+        self.held = 0;
+        let mut spilled = match self.spilled.take() {
+            Some(spilled) => spilled,
+            None => {
+                let spilled = SpilledGroups::create(self.directory.as_deref())?;
+                info!("Duplicate groups hold more than {} files, spilling them to {}", self.limit, spilled.file.path().display());
+                spilled
+            }
+        };
+        let spilled = tokio::task::spawn_blocking(move || spilled.write(groups).map(|()| spilled))
+            .await
+            .map_err(io::Error::other)??;
+        self.spilled = Some(spilled);
+        Ok(())
+    }
+
+    pub(crate) async fn finish(mut self) -> io::Result<Groups> {
+        if self.spilled.is_none() {
+            return Ok(Groups::Memory(self.memory));
+        }
+        self.spill().await?;
+        let mut spilled = self.spilled.take().expect("groups were just spilled");
+        spilled.writer = None;
+        Ok(Groups::Spilled(spilled))
+    }
+}
+
+/// Duplicate groups of a scan written to a temporary file, which is removed
+/// when the last clone of the scan result is dropped
+///
+/// Groups are read back one at a time with [`SpilledGroups::read`], so a
+/// result with more groups than fit in memory can still be written out
+/// (see [`ScanResult::for_each_group`](crate::ScanResult::for_each_group))
+#[derive(Debug)]
+pub struct SpilledGroups {
+    file: tempfile::NamedTempFile,
+    writer: Option<BufWriter<File>>,
+    len: usize,
+}
+
+/// Fields of a spilled group; its files follow as records of their own
+#[derive(Serialize, Deserialize)]
+struct GroupHeader {
+    id: String,
+    hash: String,
+    size: u64,
+    total_size: u64,
+    wasted_space: u64,
+    files: usize,
+}
+
+impl SpilledGroups {
+    fn create(parent: Option<&Path>) -> io::Result<Self> {
+        let mut builder = tempfile::Builder::new();
+        builder.prefix("duplicate-finder-").suffix(".groups");
+        let file = match parent {
+            Some(parent) => builder.tempfile_in(parent)?,
+            None => builder.tempfile()?,
+        };
+        let writer = Some(BufWriter::new(file.reopen()?));
+        Ok(Self { file, writer, len: 0 })
+    }
+
+    /// Appends groups to the file
+    fn write(&mut self, groups: Vec<DuplicateGroup>) -> io::Result<()> {
+        let writer = self.writer.as_mut().expect("groups are only spilled until the spool is finished");
+        for group in groups {
+            let header = GroupHeader {
+                id: group.id,
+                hash: group.hash,
+                size: group.size,
+                total_size: group.total_size,
+                wasted_space: group.wasted_space,
+                files: group.files.len(),
+            };
+            write_record(writer, Path::new(""), &header)?;
+            for mut file in group.files {
+                let path = std::mem::take(&mut file.path);
+                write_record(writer, &path, &file)?;
+            }
+            self.len += 1;
+        }
+        writer.flush()
+    }
+
+    /// Number of spilled groups
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Reads the groups back in the order they were spilled
+    pub fn read(&self) -> io::Result<impl Iterator<Item = io::Result<DuplicateGroup>>> {
+        let mut reader = BufReader::new(self.file.reopen()?);
+        let mut remaining = self.len;
+        Ok(std::iter::from_fn(move || {
+            remaining = remaining.checked_sub(1)?;
+            Some(read_group(&mut reader))
+        }))
+    }
+}
+
+fn read_group(reader: &mut impl BufRead) -> io::Result<DuplicateGroup> {
+    let (_, header) = read_record::<GroupHeader>(reader)?.ok_or(io::ErrorKind::UnexpectedEof)?;
+    let files = (0..header.files)
+        .map(|_| {
+            let (path, file) = read_record::<FileInfo>(reader)?.ok_or(io::ErrorKind::UnexpectedEof)?;
+            Ok(FileInfo { path, ..file })
+        })
+        .collect::<io::Result<_>>()?;
+    Ok(DuplicateGroup {
+        id: header.id,
+        hash: header.hash,
+        size: header.size,
+        files,
+        total_size: header.total_size,
+        wasted_space: header.wasted_space,
+    })
+}
+
+/// Spill files below a temporary directory
+struct Partitions {
+    directory: tempfile::TempDir,
+    writers: Vec<BufWriter<File>>,
+}
+
+impl Partitions {
+    fn create(parent: Option<&Path>) -> io::Result<Self> {
+        let directory = match parent {
+            Some(parent) => tempfile::Builder::new().prefix("duplicate-finder-").tempdir_in(parent)?,
+            None => tempfile::Builder::new().prefix("duplicate-finder-").tempdir()?,
+        };
+        let writers = (0..PARTITIONS)
+            .map(|index| File::create(partition_path(directory.path(), index)).map(BufWriter::new))
+            .collect::<io::Result<_>>()?;
+        Ok(Self { directory, writers })
+    }
+
+    /// Appends files to their partitions
+    fn write(&mut self, files: Vec<FileInfo>) -> io::Result<()> {
+        for mut file in files {
+            let mut hasher = DefaultHasher::new();
+            file.hash.hash(&mut hasher);
+            let writer = &mut self.writers[hasher.finish() as usize % PARTITIONS];
+            let path = std::mem::take(&mut file.path);
+            write_record(writer, &path, &file)?;
+        }
+        self.writers.iter_mut().try_for_each(Write::flush)
+    }
+
+    fn read(&self, index: usize) -> io::Result<Vec<FileInfo>> {
+        let mut reader = BufReader::new(File::open(partition_path(self.directory.path(), index))?);
+        let mut files = Vec::new();
+        while let Some((path, file)) = read_record::<FileInfo>(&mut reader)? {
+            files.push(FileInfo { path, ..file });
+        }
+        Ok(files)
+    }
+}
+
+fn partition_path(directory: &Path, index: usize) -> PathBuf {
+    directory.join(format!("{:02}.spill", index))
+}
+
+/// Writes a record of a file: its raw path, a NUL byte and `fields` as a
+/// JSON line, so paths that are not valid UTF-8 survive the round trip
+pub(crate) fn write_record(writer: &mut impl Write, path: &Path, fields: &impl Serialize) -> io::Result<()> {
+    writer.write_all(&path_bytes(path))?;
+    writer.write_all(b"\0")?;
+    serde_json::to_writer(&mut *writer, fields)?;
+    writer.write_all(b"\n")
+}
+
+/// Reads a record written by [`write_record`]; `None` at the end of the input
+///
+/// A record cut short (e.g. by a crash while it was written) is an error
+pub(crate) fn read_record<T: DeserializeOwned>(reader: &mut impl BufRead) -> io::Result<Option<(PathBuf, T)>> {
+    let mut path = Vec::new();
+    if reader.read_until(b'\0', &mut path)? == 0 {
+        return Ok(None);
+    }
+    let mut fields = Vec::new();
+    reader.read_until(b'\n', &mut fields)?;
+    let (Some(path), Some(fields)) = (path.strip_suffix(b"\0"), fields.strip_suffix(b"\n")) else {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    };
+    Ok(Some((path_from_bytes(path), serde_json::from_slice(fields)?)))
+}
+
+/// Raw bytes of a path; lossy outside Unix, where paths are rarely invalid Unicode
+fn path_bytes(path: &Path) -> Vec<u8> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        path.as_os_str().as_bytes().to_vec()
+    }
+    #[cfg(not(unix))]
+    {
+        path.to_string_lossy().into_owned().into_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::group_duplicates;
+
+    fn file(path: &str, hash: &str) -> FileInfo {
+        FileInfo {
+            path: PathBuf::from(path),
+            size: 4,
+            hash: hash.to_string(),
+            modified: std::time::UNIX_EPOCH,
+            created: None,
+            compression: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_spilled_grouping() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let mut spool = FileSpool::new(3, Some(temp_dir.path().to_path_buf()));
+        for index in 0..20 {
+            spool.push(file(&format!("/data/line\nbreak-{}", index), &format!("hash-{}", index % 5))).await.unwrap();
+        }
+        assert_eq!(spool.len(), 20);
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+
+        let Groups::Memory(mut groups) = spool.group_with(group_duplicates, GroupSpool::new(None, None)).await.unwrap() else {
+            panic!("groups were spilled without a limit");
+        };
+        groups.sort_by(|a, b| a.hash.cmp(&b.hash));
+        assert_eq!(groups.len(), 5);
+        assert!(groups.iter().all(|g| g.files.len() == 4));
+        assert!(groups[0].files.iter().any(|f| f.path == Path::new("/data/line\nbreak-5")));
+        // Spill files are gone with the spool
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+
+        let mut small = FileSpool::new(10, Some(temp_dir.path().to_path_buf()));
+        small.push(file("/a", "x")).await.unwrap();
+        small.push(file("/b", "x")).await.unwrap();
+        small.push(file("/c", "y")).await.unwrap();
+        let groups = small.group_with(group_duplicates, GroupSpool::new(Some(10), None)).await.unwrap();
+        assert!(matches!(groups, Groups::Memory(groups) if groups.len() == 1));
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_spilled_groups() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let mut spool = FileSpool::new(3, Some(temp_dir.path().to_path_buf()));
+        for index in 0..20 {
+            spool.push(file(&format!("/data/{}", index), &format!("hash-{}", index % 6))).await.unwrap();
+        }
+        let groups = GroupSpool::new(Some(4), Some(temp_dir.path().to_path_buf()));
+        let Groups::Spilled(spilled) = spool.group_with(group_duplicates, groups).await.unwrap() else {
+            panic!("groups were kept in memory beyond the limit");
+        };
+        assert_eq!(spilled.len(), 6);
+
+        let mut groups: Vec<DuplicateGroup> = spilled.read().unwrap().collect::<io::Result<_>>().unwrap();
+        groups.sort_by(|a, b| a.hash.cmp(&b.hash));
+        assert_eq!(groups.iter().map(|g| g.files.len()).collect::<Vec<_>>(), [4, 4, 3, 3, 3, 3]);
+        assert!(groups[0].files.iter().all(|f| f.hash == "hash-0" && f.path.starts_with("/data")));
+        // Reading does not consume the groups
+        assert_eq!(spilled.read().unwrap().count(), 6);
+
+        drop(spilled);
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::Semaphore;
+use tracing::info;
+
+use crate::throttle::TokenBucket;
+
+/// Kind of storage a scan root lives on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageKind {
+    /// Spinning disks, where concurrent reads turn into seeks
+    Rotational,
+    /// SSDs, NVMe drives and memory-backed filesystems
+    SolidState,
+    /// Network filesystems, where each request waits on a round trip
+    Network,
+    /// Storage that could not be identified (e.g. overlay filesystems)
+    Unknown,
+}
+
+impl StorageKind {
+    /// Files read at the same time when `--hash-concurrency` and
+    /// `--device-concurrency` are left at 0
+    ///
+    /// Spinning disks get two readers, so one request is queued while the
+    /// other seeks; solid-state drives are fastest with deep queues and
+    /// network mounts with enough requests to hide latency
+    pub fn default_concurrency(self, cpus: usize) -> usize {
+        match self {
+            StorageKind::Rotational => 2,
+            StorageKind::SolidState => (cpus * 2).max(16),
+            StorageKind::Network => 16,
+            StorageKind::Unknown => cpus * 2,
+        }
+    }
+}
+
+impl StorageKind {
+    /// Bytes read at a time when `--buffer-size` is left at 0
+    ///
+    /// Large reads save seeks on spinning disks and round trips on network
+    /// mounts; SSDs gain little beyond a few hundred KiB
+    pub fn default_buffer_size(self) -> usize {
+        match self {
+            StorageKind::Rotational | StorageKind::Network => 1024 * 1024,
+            StorageKind::SolidState => 256 * 1024,
+            StorageKind::Unknown => 64 * 1024,
+        }
+    }
+}
+
+/// Per-device limits on files read at the same time and on read throughput
+///
+/// Each device gets `--device-concurrency` permits, or the default of its
+/// storage kind, and may be read at `--device-max-throughput`. Devices are
+/// told apart by the device number of a file's directory, which is looked
+/// up once per directory
+pub(crate) struct DeviceLimits {
+    /// Permits of every device; 0 picks them by storage kind and `None`
+    /// leaves the number of readers alone
+    cap: Option<usize>,
+    /// Bytes per second every device may be read at
+    rate: Option<u64>,
+    cpus: usize,
+    lanes: Mutex<HashMap<u64, DeviceLane>>,
+    directories: Mutex<HashMap<PathBuf, u64>>,
+}
+
+/// Limits of one device
+#[derive(Clone, Default)]
+pub(crate) struct DeviceLane {
+    pub permits: Option<Arc<Semaphore>>,
+    pub bucket: Option<Arc<TokenBucket>>,
+}
+
+impl DeviceLimits {
+    pub(crate) fn new(cap: Option<usize>, rate: Option<u64>) -> Self {
+        Self {
+            cap,
+            rate,
+            cpus: num_cpus::get(),
+            lanes: Mutex::new(HashMap::new()),
+            directories: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Limits of the device holding `path`; none when the device is unknown
+    pub(crate) fn lane(&self, path: &Path) -> DeviceLane {
+        let Some(directory) = path.parent() else {
+            return DeviceLane::default();
+        };
+        let Some(device) = self.device_of(directory) else {
+            return DeviceLane::default();
+        };
+        let mut lanes = self.lanes.lock().expect("device lanes lock poisoned");
+        let lane = lanes.entry(device).or_insert_with(|| {
+            let permits = self.cap.map(|cap| {
+                let permits = if cap > 0 {
+                    cap
+                } else {
+                    let kind = detect(directory);
+                    let permits = kind.default_concurrency(self.cpus);
+                    info!("Reading {:?} storage of {} with {} files at a time", kind, directory.display(), permits);
+                    permits
+                };
+                Arc::new(Semaphore::new(permits))
+            });
+            DeviceLane { permits, bucket: self.rate.map(|rate| Arc::new(TokenBucket::new(rate))) }
+        });
+        lane.clone()
+    }
+
+    fn device_of(&self, directory: &Path) -> Option<u64> {
+        if let Some(device) = self.directories.lock().expect("device cache lock poisoned").get(directory) {
+            return Some(*device);
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let device = std::fs::metadata(directory).ok()?.dev();
+            self.directories.lock().expect("device cache lock poisoned").insert(directory.to_path_buf(), device);
+            Some(device)
+        }
+        #[cfg(not(unix))]
+        {
+            None
+        }
+    }
+}
+
+/// Filesystems served over the network (FUSE types without their `fuse.` prefix)
+const NETWORK_FILESYSTEMS: &[&str] = &[
+    "nfs", "nfs4", "cifs", "smb3", "smbfs", "ncpfs", "afs", "9p", "ceph", "glusterfs",
+    "lustre", "gpfs", "beegfs", "sshfs", "rclone", "s3fs", "davfs",
+];
+
+/// Filesystems kept in memory
+const MEMORY_FILESYSTEMS: &[&str] = &["tmpfs", "ramfs"];
+
+/// Detects the storage holding a path
+///
+/// Looks up the mount the path is on and, for block devices, whether the
+/// kernel reports the device (or a disk below a partition, RAID array or
+/// device-mapper volume) as rotational. Always [`StorageKind::Unknown`]
+/// on other systems than Linux
+pub fn detect(path: &Path) -> StorageKind {
+    #[cfg(target_os = "linux")]
+    {
+        linux::detect(path).unwrap_or(StorageKind::Unknown)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = path;
+        StorageKind::Unknown
+    }
+}
+
+/// Classifies a mount by its filesystem type and the rotational flag of its device
+fn classify(fstype: &str, rotational: Option<bool>) -> StorageKind {
+    let fstype = fstype.strip_prefix("fuse.").unwrap_or(fstype);
+    if NETWORK_FILESYSTEMS.contains(&fstype) {
+        return StorageKind::Network;
+    }
+    if MEMORY_FILESYSTEMS.contains(&fstype) {
+        return StorageKind::SolidState;
+    }
+    match rotational {
+        Some(true) => StorageKind::Rotational,
+        Some(false) => StorageKind::SolidState,
+        None => StorageKind::Unknown,
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::os::unix::fs::MetadataExt;
+    use std::path::{Path, PathBuf};
+
+    use super::{StorageKind, classify};
+
+    /// A line of `/proc/self/mountinfo`
+    #[derive(Debug, PartialEq, Eq)]
+    pub(super) struct Mount<'a> {
+        pub device: (u32, u32),
+        pub fstype: &'a str,
+        pub source: &'a str,
+    }
+
+    pub(super) fn detect(path: &Path) -> Option<StorageKind> {
+        let device = std::fs::metadata(path).ok()?.dev();
+        let device = (libc::major(device), libc::minor(device));
+        let mountinfo = std::fs::read_to_string("/proc/self/mountinfo").ok()?;
+        // Later mounts hide earlier ones on the same device number
+        let mount = mountinfo.lines().rev().filter_map(parse_mount).find(|m| m.device == device)?;
+
+        let mut rotational = is_rotational(&sysfs_device(mount.device));
+        // Filesystems like btrfs report an anonymous device number; the
+        // mount source names the real device
+        if rotational.is_none()
+            && mount.source.starts_with("/dev/")
+            && let Ok(metadata) = std::fs::metadata(mount.source)
+        {
+            rotational = is_rotational(&sysfs_device((libc::major(metadata.rdev()), libc::minor(metadata.rdev()))));
+        }
+        Some(classify(mount.fstype, rotational))
+    }
+
+    /// Parses a mountinfo line:
+    /// `id parent major:minor root mount-point options [tags...] - fstype source super-options`
+    pub(super) fn parse_mount(line: &str) -> Option<Mount<'_>> {
+        let (mount, filesystem) = line.split_once(" - ")?;
+        let (major, minor) = mount.split(' ').nth(2)?.split_once(':')?;
+        let mut filesystem = filesystem.split(' ');
+        Some(Mount {
+            device: (major.parse().ok()?, minor.parse().ok()?),
+            fstype: filesystem.next()?,
+            source: filesystem.next()?,
+        })
+    }
+
+    fn sysfs_device((major, minor): (u32, u32)) -> PathBuf {
+        PathBuf::from(format!("/sys/dev/block/{}:{}", major, minor))
+    }
+
+    /// Whether a block device is rotational; `None` without a block device
+    fn is_rotational(device: &Path) -> Option<bool> {
+        // Partitions have no queue of their own; their disk is the parent
+        if device.join("partition").exists() {
+            return is_rotational(&device.join(".."));
+        }
+        // RAID arrays and device-mapper volumes are as slow as their slowest disk
+        if let Ok(slaves) = std::fs::read_dir(device.join("slaves")) {
+            let disks: Vec<Option<bool>> = slaves.flatten().map(|slave| is_rotational(&slave.path())).collect();
+            if !disks.is_empty() {
+                return Some(disks.contains(&Some(true)));
+            }
+        }
+        let flag = std::fs::read_to_string(device.join("queue/rotational")).ok()?;
+        Some(flag.trim() == "1")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_device_limits() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let limits = DeviceLimits::new(Some(3), None);
+        let first = limits.lane(&temp_dir.path().join("a")).permits.unwrap();
+        let second = limits.lane(&temp_dir.path().join("b")).permits.unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(first.available_permits(), 3);
+        assert!(limits.lane(Path::new("/nonexistent/dir/file")).permits.is_none());
+
+        let throttled = DeviceLimits::new(None, Some(1024)).lane(&temp_dir.path().join("a"));
+        assert!(throttled.permits.is_none() && throttled.bucket.is_some());
+    }
+
+    #[test]
+    fn test_classify_storage() {
+        assert_eq!(classify("nfs4", None), StorageKind::Network);
+        assert_eq!(classify("fuse.sshfs", None), StorageKind::Network);
+        assert_eq!(classify("tmpfs", None), StorageKind::SolidState);
+        assert_eq!(classify("ext4", Some(true)), StorageKind::Rotational);
+        assert_eq!(classify("xfs", Some(false)), StorageKind::SolidState);
+        assert_eq!(classify("overlay", None), StorageKind::Unknown);
+        assert_eq!(StorageKind::Rotational.default_concurrency(32), 2);
+        assert_eq!(StorageKind::SolidState.default_concurrency(2), 16);
+
+        #[cfg(target_os = "linux")]
+        {
+            let line = "36 35 98:0 /mnt1 /mnt/parent rw,noatime master:1 - ext3 /dev/root rw,errors=continue";
+            assert_eq!(linux::parse_mount(line), Some(linux::Mount { device: (98, 0), fstype: "ext3", source: "/dev/root" }));
+            assert_eq!(linux::parse_mount("garbage"), None);
+            // Whatever the sandbox runs on, detection must not fail
+            let _ = detect(Path::new("."));
+        }
+    }
+}
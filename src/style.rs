@@ -0,0 +1,139 @@
+use std::io::IsTerminal;
+
+use crate::{ColorChoice, ScannerConfig};
+
+/// ANSI colors used in text output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Bold,
+    Dim,
+    Red,
+    Green,
+    Yellow,
+    Cyan,
+    Magenta,
+}
+
+impl Color {
+    /// ANSI SGR parameters for this color
+    fn code(self) -> &'static str {
+        match self {
+            Color::Bold => "1",
+            Color::Dim => "2",
+            Color::Red => "31",
+            Color::Green => "32",
+            Color::Yellow => "33",
+            Color::Cyan => "1;36",
+            Color::Magenta => "35",
+        }
+    }
+}
+
+/// Text decoration settings
+///
+/// Centralizes the choice between Unicode/emoji decoration and plain ASCII
+/// so every human-readable output path renders consistently
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TextStyle {
+    /// Replace emoji and box-drawing characters with plain ASCII
+    pub ascii: bool,
+
+    /// Wrap highlighted text in ANSI color codes
+    pub color: bool,
+}
+
+impl TextStyle {
+    /// Creates a style from CLI configuration for output written to stdout
+    pub fn from_config(config: &ScannerConfig) -> Self {
+        Self {
+            ascii: config.ascii,
+            color: color_enabled(config.color),
+        }
+    }
+
+    /// Returns the same style with colors turned off (e.g. for file output)
+    pub fn without_color(self) -> Self {
+        Self { color: false, ..self }
+    }
+
+    /// Returns an emoji prefix, or nothing in ASCII mode
+    ///
+    /// The argument should include its trailing spacing, e.g. `"📁 "`
+    pub fn icon<'a>(&self, fancy: &'a str) -> &'a str {
+        self.pick(fancy, "")
+    }
+
+    /// Returns the decorated variant or its plain ASCII replacement
+    pub fn pick<'a>(&self, fancy: &'a str, plain: &'a str) -> &'a str {
+        if self.ascii { plain } else { fancy }
+    }
+
+    /// Wraps text in the given color when colors are enabled
+    pub fn paint(&self, text: impl std::fmt::Display, color: Color) -> String {
+        if self.color {
+            format!("\x1b[{}m{}\x1b[0m", color.code(), text)
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// Builds a horizontal rule of the given width
+    pub fn rule(&self, fancy: char, plain: char, width: usize) -> String {
+        let ch = if self.ascii { plain } else { fancy };
+        std::iter::repeat_n(ch, width).collect()
+    }
+
+    /// Formats a duration, spelling microseconds as "us" in ASCII mode
+    pub fn duration(&self, duration: std::time::Duration) -> String {
+        let formatted = format!("{:?}", duration);
+        if self.ascii { formatted.replace('µ', "u") } else { formatted }
+    }
+
+    /// Bullet used in plain lists
+    pub fn bullet(&self) -> &'static str {
+        self.pick("•", "-")
+    }
+}
+
+/// Resolves a color choice against the environment
+///
+/// `auto` enables colors only when stdout is a terminal and the
+/// `NO_COLOR` convention (https://no-color.org) is not in effect
+pub fn color_enabled(choice: ColorChoice) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+            !no_color && std::io::stdout().is_terminal()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ascii_style_strips_decoration() {
+        let style = TextStyle { ascii: true, color: false };
+        assert_eq!(style.icon("📁 "), "");
+        assert_eq!(style.pick("📌", "*"), "*");
+        assert_eq!(style.rule('═', '=', 3), "===");
+        assert!(style.bullet().is_ascii());
+        assert_eq!(style.duration(std::time::Duration::from_micros(5)), "5us");
+
+        let style = TextStyle::default();
+        assert_eq!(style.icon("📁 "), "📁 ");
+        assert_eq!(style.rule('═', '=', 2), "══");
+    }
+
+    #[test]
+    fn test_paint() {
+        let style = TextStyle { ascii: false, color: true };
+        assert_eq!(style.paint("x", Color::Red), "\x1b[31mx\x1b[0m");
+        assert_eq!(style.without_color().paint("x", Color::Red), "x");
+        assert!(!color_enabled(ColorChoice::Never));
+        assert!(color_enabled(ColorChoice::Always));
+    }
+}
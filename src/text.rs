@@ -0,0 +1,297 @@
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+use crate::similarity;
+use crate::hashing::{self, HashFunction};
+use crate::{DuplicateFinderError, DuplicateGroup, FileInfo, ScannerConfig, SimilarPair};
+
+/// Files larger than this are never treated as text and are compared exactly
+const MAX_TEXT_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Number of leading bytes inspected to tell text from binary data
+const SNIFF_LENGTH: usize = 8192;
+
+/// Consecutive words forming one shingle
+const SHINGLE_WORDS: usize = 3;
+
+/// Number of MinHash slots in a signature
+const SIGNATURE_SLOTS: usize = 64;
+
+/// Returns true when the data looks like text
+///
+/// Text has no NUL bytes and its beginning is valid UTF-8; a multi-byte
+/// character cut off by the sniffing window is tolerated
+pub fn looks_like_text(data: &[u8]) -> bool {
+    let head = &data[..data.len().min(SNIFF_LENGTH)];
+    if head.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none() && head.len() == SNIFF_LENGTH,
+    }
+}
+
+/// One step of text canonicalization
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Normalization {
+    /// Removes a leading UTF-8 byte order mark
+    StripBom,
+    /// Converts CRLF and lone CR line endings to LF
+    UnixLineEndings,
+    /// Removes spaces and tabs at the end of every line
+    TrimTrailingWhitespace,
+}
+
+impl Normalization {
+    /// Applies this step to the bytes of a text file
+    fn apply(self, data: Vec<u8>) -> Vec<u8> {
+        match self {
+            Self::StripBom => match data.strip_prefix(b"\xef\xbb\xbf") {
+                Some(rest) => rest.to_vec(),
+                None => data,
+            },
+            Self::UnixLineEndings => {
+                let mut out = Vec::with_capacity(data.len());
+                let mut bytes = data.iter().peekable();
+                while let Some(&byte) = bytes.next() {
+                    if byte == b'\r' {
+                        bytes.next_if_eq(&&b'\n');
+                        out.push(b'\n');
+                    } else {
+                        out.push(byte);
+                    }
+                }
+                out
+            }
+            Self::TrimTrailingWhitespace => {
+                let mut out = Vec::with_capacity(data.len());
+                for (index, line) in data.split(|&b| b == b'\n').enumerate() {
+                    if index > 0 {
+                        out.push(b'\n');
+                    }
+                    let end = line.iter().rposition(|&b| b != b' ' && b != b'\t').map_or(0, |i| i + 1);
+                    out.extend_from_slice(&line[..end]);
+                }
+                out
+            }
+        }
+    }
+}
+
+/// Canonicalizes text files before hashing, so that the "same" file saved
+/// by different editors or platforms gets the same digest
+///
+/// Steps run in order; binary files are never touched
+#[derive(Debug, Clone, Default)]
+pub struct TextNormalizer {
+    steps: Vec<Normalization>,
+}
+
+impl TextNormalizer {
+    /// Creates a normalizer running the given steps in order
+    pub fn new(steps: Vec<Normalization>) -> Self {
+        Self { steps }
+    }
+
+    /// Builds the normalizer requested with `--normalize-text`, if any
+    pub fn from_config(config: &ScannerConfig) -> Option<Self> {
+        if !config.normalize_text {
+            return None;
+        }
+        let mut steps = vec![Normalization::StripBom, Normalization::UnixLineEndings];
+        if config.trim_trailing_whitespace {
+            steps.push(Normalization::TrimTrailingWhitespace);
+        }
+        Some(Self::new(steps))
+    }
+
+    /// Returns the canonical form of a text file's bytes
+    pub fn normalize(&self, data: Vec<u8>) -> Vec<u8> {
+        self.steps.iter().fold(data, |data, step| step.apply(data))
+    }
+}
+
+/// Reads a file's metadata and a digest of its normalized contents
+///
+/// Binary and very large files are hashed as they are
+pub async fn normalized_file_info(path: &Path, normalizer: &TextNormalizer, function: &dyn HashFunction) -> Result<FileInfo, DuplicateFinderError> {
+    let mut file_info = FileInfo::stat(path).await?;
+    if file_info.size > MAX_TEXT_SIZE {
+        return FileInfo::from_path_with(path, function).await;
+    }
+
+    let mut data = tokio::fs::read(path).await?;
+    if looks_like_text(&data) {
+        data = normalizer.normalize(data);
+    }
+    file_info.hash = hashing::digest(function, &data);
+    Ok(file_info)
+}
+
+/// Reads a file's metadata and its text similarity signature
+///
+/// Text files get a MinHash signature of their word shingles; binary and
+/// very large files get a plain SHA-256 digest, so they are still grouped
+/// when byte-identical
+pub async fn text_file_info(path: &Path) -> Result<FileInfo, DuplicateFinderError> {
+    let mut file_info = FileInfo::stat(path).await?;
+    if file_info.size > MAX_TEXT_SIZE {
+        return FileInfo::from_path(path).await;
+    }
+
+    let data = tokio::fs::read(path).await?;
+    file_info.hash = if looks_like_text(&data) {
+        signature(&String::from_utf8_lossy(&data))
+    } else {
+        format!("{:x}", Sha256::digest(&data))
+    };
+    Ok(file_info)
+}
+
+/// Computes the MinHash signature of a text
+///
+/// The text is split into lowercased words and every run of
+/// [`SHINGLE_WORDS`] words becomes a shingle. Each slot keeps the lowest
+/// hash of any shingle under its own hash function, so the share of equal
+/// slots between two signatures estimates how many shingles the texts have
+/// in common. Slots store 16 bits each, encoded as 4 hex digits
+fn signature(text: &str) -> String {
+    let words: Vec<String> = text.split_whitespace().map(|w| w.to_lowercase()).collect();
+    let mut slots = [u64::MAX; SIGNATURE_SLOTS];
+
+    for shingle in words.windows(SHINGLE_WORDS.min(words.len()).max(1)) {
+        let base = fnv1a(shingle);
+        for (seed, slot) in slots.iter_mut().enumerate() {
+            *slot = (*slot).min(mix(base ^ (seed as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)));
+        }
+    }
+
+    slots.iter().map(|slot| format!("{:04x}", slot >> 48)).collect()
+}
+
+/// Decodes the slots of a signature created by [`signature`]
+fn decode(hash: &str) -> Option<Vec<u16>> {
+    if hash.len() != SIGNATURE_SLOTS * 4 {
+        return None;
+    }
+    (0..hash.len())
+        .step_by(4)
+        .map(|i| u16::from_str_radix(hash.get(i..i + 4)?, 16).ok())
+        .collect()
+}
+
+/// Estimated percentage of shared content between two files
+///
+/// `None` when either file is not a text file
+pub fn similarity(a: &FileInfo, b: &FileInfo) -> Option<u8> {
+    let (a, b) = (decode(&a.hash)?, decode(&b.hash)?);
+    let equal = a.iter().zip(&b).filter(|(x, y)| x == y).count();
+    Some((equal * 100 / SIGNATURE_SLOTS) as u8)
+}
+
+/// Groups text files that are at least `min_similarity` percent alike
+///
+/// Groups are formed like similar images (see [`similarity::cluster`]):
+/// lightly edited copies chain together and the largest file comes first.
+/// Files without a text signature only match byte-identical copies
+pub fn group_similar(file_infos: Vec<FileInfo>, min_similarity: u8) -> Vec<DuplicateGroup> {
+    similarity::cluster(file_infos, |a, b| match similarity(a, b) {
+        Some(percent) => percent >= min_similarity,
+        None => a.hash == b.hash,
+    })
+}
+
+/// Lists the pairs of files within each group that are at least
+/// `min_similarity` percent alike, most similar first
+pub fn similar_pairs(groups: &[DuplicateGroup], min_similarity: u8) -> Vec<SimilarPair> {
+    let mut pairs = Vec::new();
+    for group in groups {
+        for (i, a) in group.files.iter().enumerate() {
+            for b in &group.files[i + 1..] {
+                if let Some(percent) = similarity(a, b).filter(|&p| p >= min_similarity) {
+                    pairs.push(SimilarPair {
+                        first: a.path.clone(),
+                        second: b.path.clone(),
+                        similarity: percent,
+                    });
+                }
+            }
+        }
+    }
+
+    pairs.sort_by(|a, b| b.similarity.cmp(&a.similarity).then_with(|| a.first.cmp(&b.first)));
+    pairs
+}
+
+/// 64-bit FNV-1a hash of a shingle; stable across runs and platforms
+fn fnv1a(words: &[String]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for byte in words.iter().flat_map(|w| w.bytes().chain([b' '])) {
+        hash = (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// SplitMix64 finalizer, deriving independent hash functions from one hash
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_similar_text_files() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let words: Vec<String> = (0..300).map(|i| format!("word{}", i)).collect();
+        let original = words.join(" ");
+        let mut edited_words = words.clone();
+        edited_words[150] = "changed".to_string();
+        let edited = edited_words.join("\n");
+
+        let write = |name: &str, data: &[u8]| {
+            let path = temp_dir.path().join(name);
+            std::fs::write(&path, data).unwrap();
+            path
+        };
+        let mut files = Vec::new();
+        for (name, data) in [
+            ("original.txt", original.as_bytes()),
+            ("edited.md", edited.as_bytes()),
+            ("unrelated.txt", b"something else entirely, with no words in common at all".as_slice()),
+            ("a.bin", b"\0\x01binary".as_slice()),
+            ("b.bin", b"\0\x01binary".as_slice()),
+        ] {
+            files.push(text_file_info(&write(name, data)).await.unwrap());
+        }
+
+        assert!(similarity(&files[0], &files[1]).unwrap() >= 90);
+        assert_eq!(similarity(&files[3], &files[4]), None);
+
+        let groups: Vec<DuplicateGroup> = group_similar(files, 80).into_iter().filter(|g| g.files.len() > 1).collect();
+        assert_eq!(groups.len(), 2);
+
+        let pairs = similar_pairs(&groups, 80);
+        assert_eq!(pairs.len(), 1);
+        assert!(pairs[0].first.ends_with("original.txt") || pairs[0].second.ends_with("original.txt"));
+        assert!(!looks_like_text(b"PNG\0\0") && looks_like_text("héllo".as_bytes()));
+    }
+
+    #[test]
+    fn test_text_normalizer() {
+        let normalizer = TextNormalizer::new(vec![Normalization::StripBom, Normalization::UnixLineEndings]);
+        let unix = b"key = value\nother = 1 \n".to_vec();
+        assert_eq!(normalizer.normalize(b"\xef\xbb\xbfkey = value\r\nother = 1 \r\n".to_vec()), unix);
+        assert_eq!(normalizer.normalize(b"key = value\rother = 1 \r".to_vec()), unix);
+
+        let trimming = TextNormalizer::new(vec![Normalization::UnixLineEndings, Normalization::TrimTrailingWhitespace]);
+        assert_eq!(trimming.normalize(b"a \t\r\nb  ".to_vec()), b"a\nb");
+        assert_eq!(normalizer.normalize(b"a \t\nb".to_vec()), b"a \t\nb");
+    }
+}
@@ -0,0 +1,122 @@
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rayon::{Scope, ThreadPool};
+use tracing::{debug, info, warn};
+
+use crate::hashing::{self, ReadOptions};
+use crate::pause::PauseSwitch;
+use crate::progress::Discovery;
+use crate::source::{self, ListOptions, Listing, SourceEntry};
+use crate::{DuplicateFinderError, FileInfo, HashFunction};
+
+/// One walk of a directory tree on the threads of a pool
+struct Walk<'a> {
+    options: &'a ListOptions,
+    discovery: &'a Discovery,
+    pause: &'a PauseSwitch,
+    entries: Mutex<Vec<SourceEntry>>,
+    errors: AtomicUsize,
+}
+
+/// Lists the files below `root` like [`source::LocalSource`], one directory
+/// per task on `pool`
+///
+/// Directories are listed as soon as a thread is free rather than in
+/// breadth-first order, so files come out in no particular order
+pub(crate) fn walk(pool: &ThreadPool, root: &Path, options: &ListOptions, discovery: &Discovery, pause: &PauseSwitch) -> Listing {
+    let walk = Walk { options, discovery, pause, entries: Mutex::new(Vec::new()), errors: AtomicUsize::new(0) };
+    pool.scope(|scope| list_directory(scope, root.to_path_buf(), 0, &walk));
+
+    let entries = walk.entries.into_inner().unwrap_or_else(|e| e.into_inner());
+    info!(root = %root.display(), files = entries.len(), "Directory scan completed");
+    Listing { entries, errors: walk.errors.into_inner() }
+}
+
+/// Lists one directory `depth` levels below the root, spawning a task for
+/// each of its subdirectories
+fn list_directory<'s>(scope: &Scope<'s>, dir: PathBuf, depth: usize, walk: &'s Walk<'s>) {
+    if walk.options.max_depth > 0 && depth >= walk.options.max_depth {
+        debug!("Max depth {} reached at {}", walk.options.max_depth, dir.display());
+        return;
+    }
+    walk.pause.wait_blocking();
+
+    let read_dir = match std::fs::read_dir(&dir) {
+        Ok(read_dir) => read_dir,
+        Err(e) => {
+            warn!("Cannot read directory {}: {}", dir.display(), e);
+            walk.errors.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+    };
+
+    let mut files = Vec::new();
+    for entry in read_dir {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                warn!("Error reading directory entry in {}: {}", dir.display(), e);
+                walk.errors.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+        };
+        let path = entry.path();
+        if !walk.options.include_hidden && source::is_hidden(&path) {
+            continue;
+        }
+        let is_symlink = entry.file_type().is_ok_and(|file_type| file_type.is_symlink());
+        if is_symlink && !walk.options.follow_symlinks {
+            continue;
+        }
+
+        match std::fs::metadata(&path) {
+            Ok(metadata) if metadata.is_dir() => scope.spawn(move |scope| list_directory(scope, path, depth + 1, walk)),
+            Ok(metadata) if metadata.is_file() => files.push(source::local_entry(path, &metadata)),
+            Ok(_) => {}
+            Err(e) => {
+                warn!("Cannot read metadata for {}: {}", path.display(), e);
+                walk.errors.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    walk.discovery.listed(files.len());
+    walk.entries.lock().unwrap_or_else(|e| e.into_inner()).extend(files);
+}
+
+/// Hashes the contents of a listed file on the calling thread
+pub(crate) fn hash_file(entry: &SourceEntry, function: &dyn HashFunction, options: &ReadOptions) -> Result<FileInfo, DuplicateFinderError> {
+    let mut file_info = FileInfo::from_entry(entry);
+    file_info.hash = hashing::hash_sized_local(&entry.path, function, entry.size, options)
+        .map_err(|_| DuplicateFinderError::HashCalculationError { path: entry.path.clone() })?;
+    Ok(file_info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FileScanner, ScannerConfig};
+
+    #[test]
+    fn test_scan_on_threads() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        std::fs::create_dir_all(temp_dir.path().join("sub/deeper")).unwrap();
+        std::fs::write(temp_dir.path().join("a.txt"), "same").unwrap();
+        std::fs::write(temp_dir.path().join("sub/b.txt"), "same").unwrap();
+        std::fs::write(temp_dir.path().join("sub/deeper/c.txt"), "same").unwrap();
+        std::fs::write(temp_dir.path().join(".hidden"), "same").unwrap();
+
+        let config = ScannerConfig { directory: temp_dir.path().to_path_buf(), quiet: true, ..Default::default() };
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+        let options = ListOptions { max_depth: 2, ..ListOptions::from_config(&config) };
+        let listing = walk(&pool, temp_dir.path(), &options, &Discovery::new(None), &PauseSwitch::new());
+        assert_eq!((listing.entries.len(), listing.errors), (2, 0));
+
+        let result = FileScanner::new(config).scan_blocking().unwrap();
+        assert_eq!(result.total_files, 3);
+        assert_eq!(result.duplicate_groups.len(), 1);
+        assert_eq!(result.duplicate_groups[0].files.len(), 3);
+    }
+}
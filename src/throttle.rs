@@ -0,0 +1,112 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::pause::PauseSwitch;
+
+/// Token bucket limiting the bytes read per second
+///
+/// Readers take tokens after each read and may run the bucket into debt;
+/// a reader then sleeps until the debt it caused would be paid back, so
+/// concurrent readers queue up behind each other instead of all bursting
+/// at once
+pub(crate) struct TokenBucket {
+    /// Bytes per second
+    rate: f64,
+    /// Most tokens saved up while nobody reads (a tenth of a second)
+    capacity: f64,
+    state: Mutex<BucketState>,
+}
+
+struct BucketState {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    pub(crate) fn new(bytes_per_second: u64) -> Self {
+        let rate = bytes_per_second.max(1) as f64;
+        Self {
+            rate,
+            capacity: rate / 10.0,
+            state: Mutex::new(BucketState { tokens: rate / 10.0, updated: Instant::now() }),
+        }
+    }
+
+    /// Takes `bytes` tokens and returns how long the reader has to wait
+    fn take(&self, bytes: usize) -> Duration {
+        let mut state = self.state.lock().expect("token bucket lock poisoned");
+        let now = Instant::now();
+        let refill = now.duration_since(state.updated).as_secs_f64() * self.rate;
+        state.tokens = (state.tokens + refill).min(self.capacity) - bytes as f64;
+        state.updated = now;
+        if state.tokens < 0.0 {
+            Duration::from_secs_f64(-state.tokens / self.rate)
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
+/// Rate limits of one file's reads: the global one of `--max-throughput`
+/// and the one of its device (`--device-max-throughput`), and the switch
+/// pausing them
+#[derive(Clone, Default)]
+pub(crate) struct Throttle {
+    buckets: Vec<Arc<TokenBucket>>,
+    pause: Option<PauseSwitch>,
+}
+
+impl std::fmt::Debug for Throttle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Throttle").field("limits", &self.buckets.len()).finish()
+    }
+}
+
+impl Throttle {
+    pub(crate) fn new(buckets: impl IntoIterator<Item = Arc<TokenBucket>>) -> Self {
+        Self { buckets: buckets.into_iter().collect(), pause: None }
+    }
+
+    /// Holds reads while the scan is paused
+    pub(crate) fn with_pause(mut self, pause: PauseSwitch) -> Self {
+        self.pause = Some(pause);
+        self
+    }
+
+    /// Accounts for `bytes` just read, sleeping while any limit is exceeded
+    /// or the scan is paused
+    ///
+    /// Blocks the thread: only call this from blocking tasks
+    pub(crate) fn consume(&self, bytes: usize) {
+        let wait = self.buckets.iter().map(|bucket| bucket.take(bytes)).max().unwrap_or_default();
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+        if let Some(pause) = &self.pause {
+            pause.wait_blocking();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        // 10 MB/s with 1 MB saved up: the first megabyte is free, the next
+        // two take about 200 ms
+        let throttle = Throttle::new([Arc::new(TokenBucket::new(10 * 1024 * 1024))]);
+        let start = Instant::now();
+        for _ in 0..3 {
+            throttle.consume(1024 * 1024);
+        }
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(150), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(2), "{:?}", elapsed);
+
+        let start = Instant::now();
+        Throttle::default().consume(usize::MAX);
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+}
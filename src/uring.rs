@@ -0,0 +1,223 @@
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::os::fd::AsRawFd;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use io_uring::{IoUring, Probe, opcode, types};
+
+use crate::hashing::{self, HashFunction, ReadOptions};
+use crate::io_backend::{EntryStat, LocalIo};
+
+/// Submission queue size of each ring, and the most statx calls issued
+/// with one system call
+const RING_ENTRIES: u32 = 64;
+
+/// Reads of one file in flight at once
+const READ_DEPTH: usize = 8;
+
+thread_local! {
+    /// One ring per blocking-pool thread, set up on first use
+    static RING: RefCell<Option<IoUring>> = const { RefCell::new(None) };
+}
+
+/// Runs an operation on this thread's ring
+fn with_ring<T>(operation: impl FnOnce(&mut IoUring) -> std::io::Result<T>) -> std::io::Result<T> {
+    RING.with(|cell| {
+        let mut ring = cell.borrow_mut();
+        if ring.is_none() {
+            *ring = Some(IoUring::new(RING_ENTRIES)?);
+        }
+        operation(ring.as_mut().expect("ring was just set up"))
+    })
+}
+
+/// Reads through io_uring (Linux 5.6 or later)
+///
+/// Discovery stats up to [`RING_ENTRIES`] directory entries per system
+/// call, and hashing keeps [`READ_DEPTH`] reads of a file in flight, so
+/// files of up to a few hundred KiB are read with a single submission
+pub(crate) struct UringIo;
+
+impl UringIo {
+    /// Checks that io_uring can be used and supports the needed operations
+    pub(crate) fn new() -> std::io::Result<Self> {
+        let ring = IoUring::new(2)?;
+        let mut probe = Probe::new();
+        ring.submitter().register_probe(&mut probe)?;
+        if !probe.is_supported(opcode::Statx::CODE) || !probe.is_supported(opcode::Read::CODE) {
+            return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "kernel lacks io_uring statx or read"));
+        }
+        Ok(Self)
+    }
+}
+
+#[async_trait]
+impl LocalIo for UringIo {
+    async fn stat_batch(&self, paths: Vec<PathBuf>) -> std::io::Result<Vec<std::io::Result<EntryStat>>> {
+        tokio::task::spawn_blocking(move || with_ring(|ring| stat_all(ring, &paths)))
+            .await
+            .map_err(std::io::Error::other)?
+    }
+
+    async fn hash_file(&self, path: &Path, size: u64, function: Arc<dyn HashFunction>, options: ReadOptions) -> std::io::Result<String> {
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || with_ring(|ring| hash_file(ring, &path, size, function.as_ref(), options)))
+            .await
+            .map_err(std::io::Error::other)?
+    }
+}
+
+/// Submits the queued entries and hands each of `count` completions to
+/// `on_completion` as `(user data, result)`
+fn complete(ring: &mut IoUring, count: usize, mut on_completion: impl FnMut(usize, i32)) -> std::io::Result<()> {
+    let mut done = 0;
+    while done < count {
+        match ring.submit_and_wait(count - done) {
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+            Ok(_) => {}
+        }
+        for entry in ring.completion() {
+            on_completion(entry.user_data() as usize, entry.result());
+            done += 1;
+        }
+    }
+    Ok(())
+}
+
+fn stat_all(ring: &mut IoUring, paths: &[PathBuf]) -> std::io::Result<Vec<std::io::Result<EntryStat>>> {
+    let mut stats = Vec::with_capacity(paths.len());
+    for batch in paths.chunks(RING_ENTRIES as usize) {
+        let names = batch.iter()
+            .map(|path| CString::new(path.as_os_str().as_bytes()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(std::io::Error::other)?;
+        // SAFETY: statx is plain data, for which all zeroes is a valid value
+        let mut buffers: Vec<libc::statx> = vec![unsafe { std::mem::zeroed() }; batch.len()];
+
+        for (index, (name, buffer)) in names.iter().zip(buffers.iter_mut()).enumerate() {
+            let entry = opcode::Statx::new(types::Fd(libc::AT_FDCWD), name.as_ptr(), (buffer as *mut libc::statx).cast())
+                .flags(libc::AT_SYMLINK_NOFOLLOW)
+                .mask(libc::STATX_TYPE | libc::STATX_SIZE | libc::STATX_MTIME | libc::STATX_BTIME)
+                .build()
+                .user_data(index as u64);
+            // SAFETY: the names and buffers outlive the submission, which
+            // completes before this batch ends
+            unsafe { ring.submission().push(&entry) }.map_err(std::io::Error::other)?;
+        }
+        let mut results = vec![0; batch.len()];
+        complete(ring, batch.len(), |index, result| results[index] = result)?;
+
+        stats.extend(results.into_iter().zip(&buffers).map(|(result, stat)| {
+            if result < 0 {
+                return Err(std::io::Error::from_raw_os_error(-result));
+            }
+            let kind = u32::from(stat.stx_mode) & libc::S_IFMT;
+            Ok(EntryStat {
+                is_dir: kind == libc::S_IFDIR,
+                is_file: kind == libc::S_IFREG,
+                len: stat.stx_size,
+                modified: UNIX_EPOCH + Duration::new(stat.stx_mtime.tv_sec.max(0) as u64, stat.stx_mtime.tv_nsec),
+                // Not every filesystem records a birth time
+                created: (stat.stx_mask & libc::STATX_BTIME != 0)
+                    .then(|| UNIX_EPOCH + Duration::new(stat.stx_btime.tv_sec.max(0) as u64, stat.stx_btime.tv_nsec)),
+            })
+        }));
+    }
+    Ok(stats)
+}
+
+/// Hashes a file with several reads in flight
+///
+/// Enough reads are issued to reach the expected end plus one beyond it,
+/// so an unchanged file needs no extra round to see its end. A short read
+/// followed by an empty one marks the end; any other short read restarts
+/// the next round right after it
+fn hash_file(ring: &mut IoUring, path: &Path, size: u64, function: &dyn HashFunction, options: ReadOptions) -> std::io::Result<String> {
+    let buffer_size = options.buffer_size(size);
+    let file = hashing::open_sequential(path, options.hints)?;
+    let fd = types::Fd(file.as_raw_fd());
+    let mut hasher = function.hasher();
+    let mut buffers = vec![vec![0u8; buffer_size]; READ_DEPTH];
+    let mut offset = 0;
+
+    loop {
+        let remaining = size.saturating_sub(offset) / buffer_size as u64;
+        let count = (remaining as usize).saturating_add(2).min(READ_DEPTH);
+        for (index, buffer) in buffers[..count].iter_mut().enumerate() {
+            let entry = opcode::Read::new(fd, buffer.as_mut_ptr(), buffer_size as u32)
+                .offset(offset + (index * buffer_size) as u64)
+                .build()
+                .user_data(index as u64);
+            // SAFETY: the buffers and the file outlive the submission,
+            // which completes before they are read
+            unsafe { ring.submission().push(&entry) }.map_err(std::io::Error::other)?;
+        }
+        let mut results = vec![0; count];
+        complete(ring, count, |index, result| results[index] = result)?;
+
+        for (index, &result) in results.iter().enumerate() {
+            if result < 0 {
+                return Err(std::io::Error::from_raw_os_error(-result));
+            }
+            let length = result as usize;
+            options.account(length)?;
+            hasher.update(&buffers[index][..length]);
+            offset += length as u64;
+            if length == 0 || (length < buffer_size && results.get(index + 1) == Some(&0)) {
+                hashing::release_cache(&file, options.hints);
+                return Ok(hasher.finalize());
+            }
+            if length < buffer_size {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HashAlgorithm;
+    use crate::hashing::BUFFER_SIZE;
+    use crate::storage::StorageKind;
+    use crate::io_backend::TokioIo;
+
+    #[tokio::test]
+    async fn test_uring_io() {
+        // Containers often forbid io_uring; the backend then falls back
+        let Ok(uring) = UringIo::new() else {
+            return;
+        };
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let mut paths = vec![temp_dir.path().join("missing")];
+        for (name, size) in [("empty", 0), ("small", 100), ("block", BUFFER_SIZE), ("large", BUFFER_SIZE * 20 + 7)] {
+            let path = temp_dir.path().join(name);
+            std::fs::write(&path, (0..size).map(|i| (i % 251) as u8).collect::<Vec<u8>>()).unwrap();
+            paths.push(path);
+        }
+        paths.push(temp_dir.path().to_path_buf());
+
+        let stats = uring.stat_batch(paths.clone()).await.unwrap();
+        let expected = TokioIo.stat_batch(paths.clone()).await.unwrap();
+        assert!(stats[0].is_err());
+        for (stat, expected) in stats.iter().zip(&expected).skip(1) {
+            assert_eq!(stat.as_ref().unwrap(), expected.as_ref().unwrap());
+        }
+
+        let function: Arc<dyn HashFunction> = Arc::new(HashAlgorithm::Sha256);
+        for (path, stat) in paths.iter().zip(&stats).skip(1).filter(|(_, stat)| stat.as_ref().is_ok_and(|s| s.is_file)) {
+            let size = stat.as_ref().unwrap().len;
+            let digest = uring.hash_file(path, size, Arc::clone(&function), ReadOptions::default()).await.unwrap();
+            assert_eq!(digest, TokioIo.hash_file(path, size, Arc::clone(&function), ReadOptions::default()).await.unwrap(), "{}", path.display());
+            // A stale size hint still reads the whole file
+            assert_eq!(digest, uring.hash_file(path, 0, Arc::clone(&function), ReadOptions::default()).await.unwrap());
+            assert_eq!(digest, uring.hash_file(path, size, Arc::clone(&function), ReadOptions::new(4096, StorageKind::Unknown, false)).await.unwrap());
+        }
+        assert!(uring.hash_file(&paths[0], 0, function, ReadOptions::default()).await.is_err());
+    }
+}
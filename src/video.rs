@@ -0,0 +1,218 @@
+use std::path::Path;
+use std::process::Stdio;
+
+use serde::Deserialize;
+use tokio::process::Command;
+use tracing::debug;
+
+use crate::similarity::{self, thumbnail_hash};
+use crate::{DuplicateFinderError, DuplicateGroup, FileInfo};
+
+/// Extensions of video containers that are sampled
+pub const VIDEO_EXTENSIONS: &[&str] = &[
+    "avi", "flv", "m2ts", "m4v", "mkv", "mov", "mp4", "mpeg", "mpg", "mts", "ts", "webm", "wmv",
+];
+
+/// Number of frames sampled per video, evenly spread over its duration
+const SAMPLED_FRAMES: u32 = 4;
+
+/// Durations within this many milliseconds are considered equal
+///
+/// Re-containering and re-encoding shift the reported duration slightly
+const DURATION_TOLERANCE_MS: u64 = 1000;
+
+/// Returns true when the path looks like a video file
+pub fn is_video(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| VIDEO_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// Checks that the external tools used to sample videos are installed
+///
+/// Video decoding is delegated to `ffprobe` and `ffmpeg` from FFmpeg
+pub fn check_tools() -> Result<(), DuplicateFinderError> {
+    for tool in ["ffprobe", "ffmpeg"] {
+        let available = std::process::Command::new(tool)
+            .arg("-version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success());
+        if !available {
+            return Err(DuplicateFinderError::ToolNotFound { tool: tool.to_string() });
+        }
+    }
+    Ok(())
+}
+
+/// A video's content signature
+///
+/// Stored in [`FileInfo::hash`] as `<duration ms>:<aspect>:<frame hashes>`,
+/// where the aspect ratio is width/height in thousandths and every sampled
+/// frame contributes a 16-digit difference hash
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Signature {
+    duration_ms: u64,
+    aspect: u64,
+    frames: Vec<u64>,
+}
+
+impl Signature {
+    fn encode(&self) -> String {
+        let frames: String = self.frames.iter().map(|f| format!("{:016x}", f)).collect();
+        format!("{}:{}:{}", self.duration_ms, self.aspect, frames)
+    }
+
+    fn decode(text: &str) -> Option<Self> {
+        let mut parts = text.splitn(3, ':');
+        let duration_ms = parts.next()?.parse().ok()?;
+        let aspect = parts.next()?.parse().ok()?;
+        let frames = parts.next()?;
+        if frames.len() % 16 != 0 {
+            return None;
+        }
+        let frames = (0..frames.len())
+            .step_by(16)
+            .map(|i| u64::from_str_radix(&frames[i..i + 16], 16).ok())
+            .collect::<Option<Vec<u64>>>()?;
+        Some(Self { duration_ms, aspect, frames })
+    }
+
+    /// True when both signatures describe the same footage
+    ///
+    /// Durations must agree within [`DURATION_TOLERANCE_MS`], aspect ratios
+    /// within 2% (so rescaled copies match) and every sampled frame within
+    /// `max_distance` bits
+    fn matches(&self, other: &Self, max_distance: u32) -> bool {
+        self.duration_ms.abs_diff(other.duration_ms) <= DURATION_TOLERANCE_MS
+            && self.aspect.abs_diff(other.aspect) * 50 <= self.aspect.max(other.aspect)
+            && self.frames.len() == other.frames.len()
+            && self.frames.iter().zip(&other.frames).all(|(a, b)| (a ^ b).count_ones() <= max_distance)
+    }
+}
+
+#[derive(Deserialize)]
+struct Probe {
+    format: ProbeFormat,
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+}
+
+#[derive(Deserialize)]
+struct ProbeFormat {
+    duration: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ProbeStream {
+    width: Option<u32>,
+    height: Option<u32>,
+}
+
+/// Reads a video's metadata and content signature
+///
+/// Duration and resolution come from `ffprobe`; [`SAMPLED_FRAMES`] frames
+/// spread over the video are decoded by `ffmpeg` straight into 9x8
+/// grayscale thumbnails and reduced to difference hashes
+pub async fn video_file_info(path: &Path) -> Result<FileInfo, DuplicateFinderError> {
+    let mut file_info = FileInfo::stat(path).await?;
+    let failed = || DuplicateFinderError::HashCalculationError {
+        path: path.to_path_buf(),
+    };
+
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-select_streams", "v:0", "-show_entries", "format=duration:stream=width,height", "-of", "json"])
+        .arg(path)
+        .output()
+        .await?;
+    if !output.status.success() {
+        debug!("ffprobe failed for {}: {}", path.display(), String::from_utf8_lossy(&output.stderr).trim());
+        return Err(failed());
+    }
+
+    let probe: Probe = serde_json::from_slice(&output.stdout).map_err(|_| failed())?;
+    let duration: f64 = probe.format.duration.and_then(|d| d.parse().ok()).ok_or_else(failed)?;
+    let stream = probe.streams.first().ok_or_else(failed)?;
+    let (width, height) = stream.width.zip(stream.height).filter(|(_, h)| *h > 0).ok_or_else(failed)?;
+
+    let mut frames = Vec::new();
+    for index in 1..=SAMPLED_FRAMES {
+        let at = duration * index as f64 / (SAMPLED_FRAMES + 1) as f64;
+        let output = Command::new("ffmpeg")
+            .args(["-v", "error", "-ss", &format!("{:.3}", at), "-i"])
+            .arg(path)
+            .args(["-frames:v", "1", "-vf", "scale=9:8,format=gray", "-f", "rawvideo", "-"])
+            .output()
+            .await?;
+        if !output.status.success() || output.stdout.len() < 72 {
+            debug!("ffmpeg could not sample {} at {:.1}s", path.display(), at);
+            return Err(failed());
+        }
+        frames.push(thumbnail_hash(&output.stdout[..72]));
+    }
+
+    file_info.hash = Signature {
+        duration_ms: (duration * 1000.0).round() as u64,
+        aspect: (width as u64 * 1000) / height as u64,
+        frames,
+    }
+    .encode();
+    Ok(file_info)
+}
+
+/// Groups videos whose signatures match within `max_distance` bits per frame
+///
+/// Groups are formed and ordered like similar images (see
+/// [`similarity::group_similar`]): the largest copy comes first
+pub fn group_similar(file_infos: Vec<FileInfo>, max_distance: u32) -> Vec<DuplicateGroup> {
+    similarity::cluster(file_infos, |a, b| {
+        match (Signature::decode(&a.hash), Signature::decode(&b.hash)) {
+            (Some(a), Some(b)) => a.matches(&b, max_distance),
+            _ => a.hash == b.hash,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn video(path: &str, size: u64, duration_ms: u64, aspect: u64, frames: [u64; 4]) -> FileInfo {
+        FileInfo {
+            path: path.into(),
+            size,
+            hash: Signature { duration_ms, aspect, frames: frames.to_vec() }.encode(),
+            modified: std::time::UNIX_EPOCH,
+            created: None,
+            compression: None,
+        }
+    }
+
+    #[test]
+    fn test_signature_matching() {
+        let signature = Signature { duration_ms: 61_000, aspect: 1777, frames: vec![0xdead_beef, 1, u64::MAX, 0] };
+        assert_eq!(Signature::decode(&signature.encode()), Some(signature));
+        assert_eq!(Signature::decode("garbage"), None);
+
+        let groups = group_similar(
+            vec![
+                video("/v/original.mkv", 900, 61_000, 1777, [0xff00, 0xf0f0, 0x0ff0, 0xaaaa]),
+                // Re-encoded at a lower resolution: slightly off frames and duration
+                video("/v/small.mp4", 300, 61_400, 1778, [0xff01, 0xf0f0, 0x0ff0, 0xaaab]),
+                // Same length, different footage
+                video("/v/other.mp4", 500, 61_000, 1777, [0x00ff, 0x0f0f, 0xf00f, 0x5555]),
+                // Same footage cut short
+                video("/v/trailer.mp4", 100, 30_000, 1777, [0xff00, 0xf0f0, 0x0ff0, 0xaaaa]),
+            ],
+            6,
+        );
+
+        let group = groups.iter().find(|g| g.files.len() > 1).unwrap();
+        assert_eq!(groups.len(), 3);
+        assert_eq!(group.files[0].path, Path::new("/v/original.mkv"));
+        assert_eq!(group.files[1].path, Path::new("/v/small.mp4"));
+        assert_eq!(group.wasted_space, 300);
+        assert!(is_video(Path::new("clip.MKV")) && !is_video(Path::new("song.mp3")));
+    }
+}
@@ -0,0 +1,282 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use notify::event::ModifyKind;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::fs;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+use crate::directories;
+use crate::index::HashIndex;
+use crate::scanner::{self, FileScanner};
+use crate::{FileInfo, ScanResult};
+
+/// How long to wait for further events before applying a batch of changes
+///
+/// Editors and copy tools touch a file several times in a row; collecting
+/// them avoids re-hashing the same file over and over
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Long-lived incremental scanner that follows filesystem changes
+///
+/// Performs one full scan on creation and then keeps an in-memory index of
+/// every hashed file. Filesystem notifications (inotify, FSEvents or
+/// ReadDirectoryChangesW) trigger re-hashing of just the changed paths
+pub struct WatchEngine {
+    /// Scanner providing filters, discovery and hashing
+    scanner: FileScanner,
+
+    /// Scan root exactly as configured (prefix of every indexed path)
+    root: PathBuf,
+
+    /// Absolute form of the root, as reported by notification events
+    canonical_root: PathBuf,
+
+    /// Output file that must not trigger updates when results are saved
+    ignored: Option<PathBuf>,
+
+    /// Every file that passed the filters
+    files: HashIndex,
+
+    /// Keeps the OS watch registered for as long as the engine lives
+    _watcher: RecommendedWatcher,
+
+    /// Raw notifications forwarded from the watcher thread
+    events: mpsc::UnboundedReceiver<notify::Result<Event>>,
+}
+
+impl WatchEngine {
+    /// Starts watching the scanner's directory and runs the initial scan
+    ///
+    /// The watch is registered before scanning so changes made while the
+    /// initial scan runs are not lost
+    pub async fn start(mut scanner: FileScanner) -> Result<(Self, ScanResult)> {
+        let start_time = Instant::now();
+        let config = scanner.config();
+        let root = config.directory.clone();
+        let canonical_root = fs::canonicalize(&root).await?;
+        let ignored = match &config.output_file {
+            Some(output) => Some(absolute_path(output).await),
+            None => None,
+        };
+
+        let (sender, events) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            // The receiver only disappears when the engine is dropped
+            let _ = sender.send(event);
+        })?;
+        watcher.watch(&canonical_root, RecursiveMode::Recursive)?;
+
+        let files = scanner.build_index().await?;
+
+        let engine = Self {
+            scanner,
+            root,
+            canonical_root,
+            ignored,
+            files,
+            _watcher: watcher,
+            events,
+        };
+
+        let result = engine.snapshot(start_time);
+        Ok((engine, result))
+    }
+
+    /// Waits for filesystem changes and returns the updated results
+    ///
+    /// Events that don't affect any scanned file (hidden paths, filtered
+    /// sizes, metadata-only changes) are absorbed silently. Returns `None`
+    /// when the watcher stops delivering events
+    pub async fn next_update(&mut self) -> Result<Option<ScanResult>> {
+        loop {
+            let Some(first) = self.events.recv().await else {
+                return Ok(None);
+            };
+
+            let start_time = Instant::now();
+            let mut changes = BTreeMap::new();
+            self.record(first, &mut changes);
+
+            // Keep collecting until the filesystem goes quiet
+            while let Ok(Some(event)) = tokio::time::timeout(DEBOUNCE, self.events.recv()).await {
+                self.record(event, &mut changes);
+            }
+
+            let mut changed = false;
+            for (path, structural) in changes {
+                changed |= self.apply(&path, structural).await;
+            }
+
+            if changed {
+                return Ok(Some(self.snapshot(start_time)));
+            }
+        }
+    }
+
+    /// Current results built from the in-memory index
+    fn snapshot(&self, start_time: Instant) -> ScanResult {
+        let files: Vec<FileInfo> = self.files.files().cloned().collect();
+        let (directory_groups, directory_overlaps) = directories::analyze(&files, self.scanner.config());
+
+        ScanResult {
+            directory_groups,
+            directory_overlaps,
+            ..scanner::build_result(files, self.scanner.config(), self.scanner.file_errors(), start_time)
+        }
+    }
+
+    /// Adds the paths touched by an event to the pending change set
+    ///
+    /// The flag marks structural changes (creations and renames), the only
+    /// ones that require walking a directory
+    fn record(&self, event: notify::Result<Event>, changes: &mut BTreeMap<PathBuf, bool>) {
+        let event = match event {
+            Ok(event) => event,
+            Err(e) => {
+                warn!("Filesystem watch error: {}", e);
+                return;
+            }
+        };
+
+        let structural = match event.kind {
+            EventKind::Access(_) => return,
+            EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(_)) => true,
+            _ => false,
+        };
+
+        for path in event.paths {
+            if self.ignored.as_deref() == Some(path.as_path()) {
+                continue;
+            }
+            let path = self.to_scan_path(&path);
+            *changes.entry(path).or_insert(false) |= structural;
+        }
+    }
+
+    /// Brings the index up to date for one changed path
+    ///
+    /// Returns true when the set of indexed files or their hashes changed
+    async fn apply(&mut self, path: &Path, structural: bool) -> bool {
+        let metadata = match fs::metadata(path).await {
+            Ok(metadata) => metadata,
+            Err(_) => return self.forget(path),
+        };
+
+        if metadata.is_dir() {
+            if !structural || self.scanner.is_below_hidden(path) {
+                return false;
+            }
+
+            let depth = path.strip_prefix(&self.root).map(|p| p.components().count()).unwrap_or(0);
+            debug!("Rescanning directory: {}", path.display());
+            let removed = self.forget(path);
+            return match self.scanner.collect_files_below(path, depth).await {
+                Ok(files) => {
+                    let added = !files.is_empty();
+                    for file in files {
+                        self.files.insert(file);
+                    }
+                    removed || added
+                }
+                Err(e) => {
+                    warn!("Cannot rescan directory {}: {}", path.display(), e);
+                    removed
+                }
+            };
+        }
+
+        if !self.scanner.accepts_file(path, &metadata) {
+            return self.files.remove(path).is_some();
+        }
+
+        match self.scanner.hash_file(path).await {
+            Ok(file_info) => {
+                debug!("Re-hashed file: {}", path.display());
+                let hash = file_info.hash.clone();
+                let previous = self.files.insert(file_info);
+                previous.map(|p| p.hash != hash).unwrap_or(true)
+            }
+            Err(e) => {
+                warn!("Failed to process file {}: {}", path.display(), e);
+                self.files.remove(path).is_some()
+            }
+        }
+    }
+
+    /// Drops a path and everything below it from the index
+    fn forget(&mut self, path: &Path) -> bool {
+        let removed = self.files.remove_below(path) > 0;
+        if removed {
+            info!("Removed from index: {}", path.display());
+        }
+        removed
+    }
+
+    /// Rewrites an absolute event path into the form used by the index
+    fn to_scan_path(&self, path: &Path) -> PathBuf {
+        match path.strip_prefix(&self.canonical_root) {
+            Ok(relative) if relative.as_os_str().is_empty() => self.root.clone(),
+            Ok(relative) => self.root.join(relative),
+            Err(_) => path.to_path_buf(),
+        }
+    }
+}
+
+/// Resolves a possibly not-yet-existing file to an absolute path
+async fn absolute_path(path: &Path) -> PathBuf {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    match (fs::canonicalize(parent).await, path.file_name()) {
+        (Ok(parent), Some(name)) => parent.join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ScannerConfig;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_apply_tracks_changes() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        std::fs::write(temp_dir.path().join("a.txt"), "same").unwrap();
+
+        let config = ScannerConfig {
+            directory: temp_dir.path().to_path_buf(),
+            quiet: true,
+            ..Default::default()
+        };
+        let (mut engine, initial) = WatchEngine::start(FileScanner::new(config)).await.unwrap();
+        assert!(initial.duplicate_groups.is_empty());
+
+        // A new copy creates a duplicate group
+        let copy = temp_dir.path().join("b.txt");
+        std::fs::write(&copy, "same").unwrap();
+        assert!(engine.apply(&copy, true).await);
+        assert_eq!(engine.snapshot(Instant::now()).duplicate_groups.len(), 1);
+
+        // Touching a file without changing it is not an update
+        assert!(!engine.apply(&copy, false).await);
+
+        // Deleting the copy resolves the group
+        std::fs::remove_file(&copy).unwrap();
+        assert!(engine.apply(&copy, false).await);
+        assert!(engine.snapshot(Instant::now()).duplicate_groups.is_empty());
+
+        // New directories are discovered recursively
+        let nested = temp_dir.path().join("nested");
+        std::fs::create_dir_all(nested.join("deeper")).unwrap();
+        std::fs::write(nested.join("deeper").join("c.txt"), "same").unwrap();
+        assert!(engine.apply(&nested, true).await);
+        assert_eq!(engine.snapshot(Instant::now()).total_duplicates, 1);
+    }
+}
@@ -0,0 +1,325 @@
+use std::hash::{BuildHasher, RandomState};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use tracing::{debug, warn};
+
+use crate::{FileInfo, HashAlgorithm};
+
+/// Longest attribute value read back; stored values are far shorter
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+const MAX_VALUE: usize = 256;
+
+/// Digests kept in an extended attribute of every hashed file (`--xattr-cache`)
+///
+/// The attribute `user.duplicate-finder.<algorithm>` holds the [`CacheKey`]
+/// the file had before it was hashed, followed by its digest. A later scan
+/// trusts the digest while the key is unchanged. The attribute moves with
+/// the file when it is renamed and needs no cache file, but only
+/// filesystems with user attributes keep it (Linux and macOS)
+pub(crate) struct XattrCache {
+    name: String,
+    /// Percentage of cached digests checked by hashing anyway (`--cache-verify`)
+    verify_percent: f64,
+    /// Picks the checked files differently on every run
+    sampler: RandomState,
+}
+
+/// Cached digests found on a set of files (`cache stats`)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct CacheSummary {
+    /// Files looked at
+    pub files: usize,
+
+    /// Files with a digest for their current contents
+    pub current: usize,
+
+    /// Files with a digest from before they last changed
+    pub stale: usize,
+}
+
+/// What the cache knows about a file before it is hashed
+pub(crate) struct Cached {
+    key: CacheKey,
+    /// Digest stored for the file's current key
+    hash: Option<String>,
+    /// Whether the stored digest is checked by hashing the file anyway
+    verify: bool,
+}
+
+impl Cached {
+    /// The stored digest, unless it is to be checked
+    pub(crate) fn trusted(&self) -> Option<&str> {
+        self.hash.as_deref().filter(|_| !self.verify)
+    }
+}
+
+impl XattrCache {
+    pub(crate) fn new(algorithm: HashAlgorithm, verify_percent: f64) -> Self {
+        if !cfg!(any(target_os = "linux", target_os = "macos")) {
+            warn!("Extended attributes are not supported on this system, --xattr-cache has no effect");
+        }
+        Self { name: attribute_name(algorithm), verify_percent, sampler: RandomState::new() }
+    }
+
+    /// The current key of a file and the digest stored for it; `None` when
+    /// the file cannot be stated, which hashing reports
+    ///
+    /// Blocks on system calls: run it on the blocking pool
+    pub(crate) fn lookup(&self, path: &Path) -> Option<Cached> {
+        let key = CacheKey::of(&std::fs::metadata(path).ok()?);
+        let hash = match get(path, &self.name) {
+            Ok(value) => String::from_utf8(value).ok().and_then(|value| {
+                let (stored, hash) = value.rsplit_once(' ')?;
+                (stored == key.0).then(|| hash.to_string())
+            }),
+            Err(e) => {
+                if !is_missing(&e) {
+                    debug!("Cannot read {} of {}: {}", self.name, path.display(), e);
+                }
+                None
+            }
+        };
+        let verify = hash.is_some() && self.sampled(path);
+        Some(Cached { key, hash, verify })
+    }
+
+    /// Stores the digest of a file hashed after `cached` was looked up;
+    /// files that cannot carry attributes are skipped
+    ///
+    /// Blocks on a system call: run it on the blocking pool
+    pub(crate) fn store(&self, cached: &Cached, file: &FileInfo) {
+        match cached.hash.as_deref() {
+            Some(hash) if hash == file.hash => return,
+            Some(_) => warn!(
+                "Cached hash of {} was stale: its contents changed without a new size, inode or modification time",
+                file.path.display()
+            ),
+            None => {}
+        }
+        let value = format!("{} {}", cached.key.0, file.hash);
+        if let Err(e) = set(&file.path, &self.name, value.as_bytes()) {
+            debug!("Cannot write {} of {}: {}", self.name, file.path.display(), e);
+        }
+    }
+
+    /// Whether a file falls into the `--cache-verify` sample of this run
+    fn sampled(&self, path: &Path) -> bool {
+        self.verify_percent > 0.0 && (self.sampler.hash_one(path) as f64 / u64::MAX as f64) * 100.0 < self.verify_percent
+    }
+}
+
+/// Name of the attribute holding the digests of `algorithm`
+fn attribute_name(algorithm: HashAlgorithm) -> String {
+    let algorithm = match algorithm {
+        HashAlgorithm::Sha256 => "sha256",
+        HashAlgorithm::Blake3 => "blake3",
+        HashAlgorithm::Xxh3 => "xxh3",
+    };
+    format!("user.duplicate-finder.{}", algorithm)
+}
+
+/// Counts the files holding a digest of `algorithm`, and how many of those
+/// digests are outdated
+///
+/// Blocks on system calls: run it on the blocking pool
+pub fn summarize(paths: &[PathBuf], algorithm: HashAlgorithm) -> CacheSummary {
+    let name = attribute_name(algorithm);
+    let mut summary = CacheSummary { files: paths.len(), ..CacheSummary::default() };
+    for path in paths {
+        let (Ok(metadata), Ok(value)) = (std::fs::metadata(path), get(path, &name)) else {
+            continue;
+        };
+        let key = CacheKey::of(&metadata);
+        match String::from_utf8_lossy(&value).rsplit_once(' ') {
+            Some((stored, _)) if stored == key.0 => summary.current += 1,
+            _ => summary.stale += 1,
+        }
+    }
+    summary
+}
+
+/// Removes the digests of every algorithm from the files, returning how
+/// many files had any; files that cannot be changed are logged and skipped
+///
+/// Blocks on system calls: run it on the blocking pool
+pub fn clear(paths: &[PathBuf]) -> usize {
+    let names = [HashAlgorithm::Sha256, HashAlgorithm::Blake3, HashAlgorithm::Xxh3].map(attribute_name);
+    let mut cleared = 0;
+    for path in paths {
+        let mut had_any = false;
+        for name in &names {
+            match remove(path, name) {
+                Ok(()) => had_any = true,
+                Err(e) if is_missing(&e) => {}
+                Err(e) => warn!("Cannot remove {} of {}: {}", name, path.display(), e),
+            }
+        }
+        cleared += usize::from(had_any);
+    }
+    cleared
+}
+
+/// Metadata a stored digest belongs to: device and inode, size and
+/// modification time in nanoseconds, e.g. `2049:1312 1024 1700000000.123456789`
+///
+/// A file restored from a backup or copied over keeps neither its inode
+/// nor, usually, its exact modification time. The change time is left out:
+/// writing the attribute changes it
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CacheKey(String);
+
+impl CacheKey {
+    fn of(metadata: &std::fs::Metadata) -> Self {
+        #[cfg(unix)]
+        let (device, inode) = {
+            use std::os::unix::fs::MetadataExt;
+            (metadata.dev(), metadata.ino())
+        };
+        #[cfg(not(unix))]
+        let (device, inode) = (0, 0);
+        let modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+        let since_epoch = modified.duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO);
+        Self(format!(
+            "{}:{} {} {}.{:09}",
+            device, inode, metadata.len(), since_epoch.as_secs(), since_epoch.subsec_nanos()
+        ))
+    }
+}
+
+/// Whether a failed read means the attribute or attribute support is absent
+fn is_missing(error: &io::Error) -> bool {
+    #[cfg(target_os = "linux")]
+    let absent = libc::ENODATA;
+    #[cfg(target_os = "macos")]
+    let absent = libc::ENOATTR;
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    {
+        error.raw_os_error() == Some(absent) || error.kind() == io::ErrorKind::Unsupported
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    {
+        error.kind() == io::ErrorKind::Unsupported
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn get(path: &Path, name: &str) -> io::Result<Vec<u8>> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+    let name = std::ffi::CString::new(name)?;
+    let mut value = vec![0u8; MAX_VALUE];
+    // SAFETY: both names are NUL-terminated and the value buffer is as long
+    // as the length passed along
+    #[cfg(target_os = "linux")]
+    let length = unsafe { libc::getxattr(path.as_ptr(), name.as_ptr(), value.as_mut_ptr().cast(), value.len()) };
+    // SAFETY: as above
+    #[cfg(target_os = "macos")]
+    let length = unsafe { libc::getxattr(path.as_ptr(), name.as_ptr(), value.as_mut_ptr().cast(), value.len(), 0, 0) };
+    if length < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    value.truncate(length as usize);
+    Ok(value)
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn set(path: &Path, name: &str, value: &[u8]) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+    let name = std::ffi::CString::new(name)?;
+    // SAFETY: both names are NUL-terminated and the value is as long as the
+    // length passed along
+    #[cfg(target_os = "linux")]
+    let result = unsafe { libc::setxattr(path.as_ptr(), name.as_ptr(), value.as_ptr().cast(), value.len(), 0) };
+    // SAFETY: as above
+    #[cfg(target_os = "macos")]
+    let result = unsafe { libc::setxattr(path.as_ptr(), name.as_ptr(), value.as_ptr().cast(), value.len(), 0, 0) };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn remove(path: &Path, name: &str) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+    let name = std::ffi::CString::new(name)?;
+    // SAFETY: both names are NUL-terminated
+    #[cfg(target_os = "linux")]
+    let result = unsafe { libc::removexattr(path.as_ptr(), name.as_ptr()) };
+    // SAFETY: as above
+    #[cfg(target_os = "macos")]
+    let result = unsafe { libc::removexattr(path.as_ptr(), name.as_ptr(), 0) };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn get(_path: &Path, _name: &str) -> io::Result<Vec<u8>> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn set(_path: &Path, _name: &str, _value: &[u8]) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn remove(_path: &Path, _name: &str) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xattr_cache() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let path = temp_dir.path().join("data.txt");
+        std::fs::write(&path, "contents").unwrap();
+        let file = FileInfo {
+            path: path.clone(),
+            size: 8,
+            hash: "abc".to_string(),
+            modified: std::time::UNIX_EPOCH,
+            created: None,
+            compression: None,
+        };
+        let cache = XattrCache::new(HashAlgorithm::Sha256, 0.0);
+        let cached = cache.lookup(&path).unwrap();
+        assert_eq!(cached.trusted(), None);
+        cache.store(&cached, &file);
+        // Filesystems without user attributes (e.g. some tmpfs mounts) keep nothing
+        if get(&path, &cache.name).is_err() {
+            return;
+        }
+        assert_eq!(cache.lookup(&path).unwrap().trusted(), Some("abc"));
+        assert_eq!(XattrCache::new(HashAlgorithm::Blake3, 0.0).lookup(&path).unwrap().trusted(), None);
+        // Every cached digest is checked at 100%
+        let verified = XattrCache::new(HashAlgorithm::Sha256, 100.0).lookup(&path).unwrap();
+        assert!(verified.verify && verified.trusted().is_none());
+
+        // A restored copy has another inode, even with the same size and time
+        let restored = temp_dir.path().join("restored.txt");
+        std::fs::copy(&path, &restored).unwrap();
+        std::fs::rename(&restored, &path).unwrap();
+        assert_eq!(cache.lookup(&path).unwrap().trusted(), None);
+
+        std::fs::write(&path, "new contents").unwrap();
+        assert_eq!(cache.lookup(&path).unwrap().trusted(), None);
+
+        let paths = [path.clone()];
+        cache.store(&cache.lookup(&path).unwrap(), &FileInfo { size: 12, ..file.clone() });
+        assert_eq!(summarize(&paths, HashAlgorithm::Sha256), CacheSummary { files: 1, current: 1, stale: 0 });
+        std::fs::write(&path, "newer contents").unwrap();
+        assert_eq!(summarize(&paths, HashAlgorithm::Sha256).stale, 1);
+        assert_eq!(clear(&paths), 1);
+        assert_eq!(summarize(&paths, HashAlgorithm::Sha256), CacheSummary { files: 1, current: 0, stale: 0 });
+    }
+}